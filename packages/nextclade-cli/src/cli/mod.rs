@@ -1,9 +1,14 @@
+pub mod nextclade_auto_dataset;
+pub mod nextclade_cache;
 pub mod nextclade_cli;
 pub mod nextclade_dataset_get;
 pub mod nextclade_dataset_list;
+pub mod nextclade_journal;
 pub mod nextclade_loop;
 pub mod nextclade_ordered_writer;
 pub mod nextclade_read_annotation;
+pub mod nextclade_segmented_loop;
 pub mod nextclade_seq_sort;
+pub mod nextclade_seq_sort_build_index;
 pub mod print_help_markdown;
 pub mod verbosity;
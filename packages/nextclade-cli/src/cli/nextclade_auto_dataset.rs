@@ -0,0 +1,234 @@
+use crate::cli::nextclade_cli::{nextclade_get_output_filenames, NextcladeRunArgs};
+use crate::cli::nextclade_dataset_get::dataset_http_get;
+use crate::cli::nextclade_loop::{nextclade_run_dataset, NextcladeRecord};
+use crate::cli::nextclade_seq_sort::minimizer_index_load;
+use crate::dataset::dataset_download::{dataset_dir_load, dataset_zip_bytes_load, dataset_zip_fetch, dataset_zip_load};
+use crate::io::http_client::{HttpClient, ProxyConfig};
+use eyre::{Report, WrapErr};
+use indexmap::IndexMap;
+use itertools::Itertools;
+use log::{info, LevelFilter};
+use nextclade::io::csv::CsvStructFileWriter;
+use nextclade::io::fasta::{FastaReader, FastaRecord};
+use nextclade::io::file::is_path_stdin;
+use nextclade::io::fs::{add_extension, has_extension};
+use nextclade::make_error;
+use nextclade::qc::qc_run::QcStatus;
+use nextclade::run::nextclade_wasm::{AnalysisOutput, NextcladeParams};
+use nextclade::sort::minimizer_index_binary::MinimizerIndexBinary;
+use nextclade::sort::minimizer_search::run_minimizer_search;
+use nextclade::sort::params::NextcladeSeqSortParams;
+use nextclade::utils::error::report_to_string;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+/// Indices of input sequences assigned to each of the datasets
+type DatasetSelections = IndexMap<String, BTreeSet<usize>>;
+
+/// Row of the summary table of the `--auto-dataset` mode
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AutoDatasetSummaryEntry {
+  index: usize,
+  seq_name: String,
+  dataset: Option<String>,
+  score: Option<f64>,
+  num_hits: Option<u64>,
+  clade: Option<String>,
+  #[serde(rename = "qc.overallScore")]
+  qc_overall_score: Option<f64>,
+  #[serde(rename = "qc.overallStatus")]
+  qc_overall_status: Option<QcStatus>,
+  coverage: Option<f64>,
+  errors: Option<String>,
+}
+
+/// Runs analysis of input sequences, each with its own automatically detected dataset.
+///
+/// Datasets are detected in a first pass over the inputs. Then, for each of the detected datasets, the dataset is
+/// loaded and the inputs are read again, this time analyzing only the sequences assigned to this dataset.
+pub fn nextclade_run_auto_dataset(run_args: &NextcladeRunArgs) -> Result<(), Report> {
+  check_args(run_args)?;
+
+  let output_all = run_args.outputs.output_all.clone().unwrap_or_default();
+  let output_basename = run_args.outputs.output_basename.as_deref().unwrap_or("nextclade");
+
+  let verbose = log::max_level() > LevelFilter::Info;
+
+  let (minimizer_index, _) = minimizer_index_load(
+    run_args.inputs.input_minimizer_index.as_deref(),
+    &run_args.inputs.server,
+    &ProxyConfig::default(),
+    verbose,
+  )
+  .wrap_err("When loading minimizer index for dataset detection")?;
+
+  let (mut summary, selections) =
+    detect_datasets(run_args, &minimizer_index).wrap_err("When detecting datasets for input sequences")?;
+
+  let http = HttpClient::new(&run_args.inputs.server, &ProxyConfig::default(), verbose)?;
+
+  for (dataset_name, selection) in selections {
+    info!(
+      "Analyzing {} sequence(s) using dataset '{dataset_name}'",
+      selection.len()
+    );
+
+    let inputs = auto_dataset_load(run_args, &http, &dataset_name)
+      .wrap_err_with(|| format!("When loading dataset '{dataset_name}'"))?;
+
+    let mut dataset_run_args = run_args.clone();
+    dataset_run_args.outputs.output_all = Some(output_all.join(dataset_output_subdir(&dataset_name)));
+    nextclade_get_output_filenames(&mut dataset_run_args)
+      .wrap_err_with(|| format!("When deducing output filenames for dataset '{dataset_name}'"))?;
+
+    nextclade_run_dataset(dataset_run_args, inputs, Some(&Arc::new(selection)), |record| {
+      summary_add_result(&mut summary[record.index], record);
+    })
+    .wrap_err_with(|| format!("When analyzing sequences using dataset '{dataset_name}'"))?;
+  }
+
+  let summary_path = add_extension(output_all.join(output_basename), "summary.tsv");
+  let mut summary_writer = CsvStructFileWriter::new(summary_path, b'\t')?;
+  for entry in &summary {
+    summary_writer.write(entry)?;
+  }
+
+  Ok(())
+}
+
+fn check_args(run_args: &NextcladeRunArgs) -> Result<(), Report> {
+  let outputs = &run_args.outputs;
+
+  if outputs.output_all.is_none() {
+    return make_error!("The `--auto-dataset` mode requires `--output-all` argument, because outputs for each of the detected datasets are written into a separate subdirectory of the output directory");
+  }
+
+  let individual_outputs = [
+    ("--output-fasta", outputs.output_fasta.is_some()),
+    ("--output-translations", outputs.output_translations.is_some()),
+    ("--output-ndjson", outputs.output_ndjson.is_some()),
+    ("--output-json", outputs.output_json.is_some()),
+    ("--output-csv", outputs.output_csv.is_some()),
+    ("--output-tsv", outputs.output_tsv.is_some()),
+    ("--output-parquet", outputs.output_parquet.is_some()),
+    ("--output-vcf", outputs.output_vcf.is_some()),
+    ("--output-sam", outputs.output_sam.is_some()),
+    (
+      "--output-mutational-spectrum",
+      outputs.output_mutational_spectrum.is_some(),
+    ),
+    ("--output-tree", outputs.output_tree.is_some()),
+    ("--output-tree-nwk", outputs.output_tree_nwk.is_some()),
+    ("--output-jplace", outputs.output_jplace.is_some()),
+    ("--output-graph", outputs.output_graph.is_some()),
+    ("--output-annotation-gff", outputs.output_annotation_gff.is_some()),
+    ("--output-annotation-tbl", outputs.output_annotation_tbl.is_some()),
+  ]
+  .into_iter()
+  .filter_map(|(arg, is_provided)| is_provided.then_some(arg))
+  .collect_vec();
+
+  if !individual_outputs.is_empty() {
+    return make_error!(
+      "The `--auto-dataset` mode only supports `--output-all` argument, optionally with `--output-selection` and `--output-basename`, because outputs of different datasets cannot be written into the same files. Please remove the following arguments: {}",
+      individual_outputs.join(", ")
+    );
+  }
+
+  let input_fastas = &run_args.inputs.input_fastas;
+  if input_fastas.is_empty() || input_fastas.iter().any(is_path_stdin) {
+    return make_error!("The `--auto-dataset` mode requires paths to input FASTA files: reading from standard input is not supported in this mode, because the inputs are read multiple times");
+  }
+
+  Ok(())
+}
+
+/// Finds the best matching dataset for each of the input sequences. Returns summary entries for all sequences, and
+/// indices of sequences assigned to each dataset, in order of first appearance of the datasets in the inputs.
+fn detect_datasets(
+  run_args: &NextcladeRunArgs,
+  minimizer_index: &MinimizerIndexBinary,
+) -> Result<(Vec<AutoDatasetSummaryEntry>, DatasetSelections), Report> {
+  let search_params = NextcladeSeqSortParams::default();
+
+  let mut summary = vec![];
+  let mut selections = DatasetSelections::new();
+
+  let mut reader = FastaReader::from_paths(&run_args.inputs.input_fastas)?;
+  loop {
+    let mut record = FastaRecord::default();
+    reader.read(&mut record)?;
+    if record.is_empty() {
+      break;
+    }
+
+    let result = run_minimizer_search(&record, minimizer_index, &search_params)
+      .wrap_err_with(|| format!("When processing sequence #{} '{}'", record.index, record.seq_name))?;
+
+    let mut entry = AutoDatasetSummaryEntry {
+      index: record.index,
+      seq_name: record.seq_name,
+      ..AutoDatasetSummaryEntry::default()
+    };
+
+    if let Some(dataset) = result.datasets.first() {
+      selections.entry(dataset.name.clone()).or_default().insert(record.index);
+      entry.dataset = Some(dataset.name.clone());
+      entry.score = Some(dataset.score);
+      entry.num_hits = Some(dataset.n_hits);
+    } else {
+      info!(
+        "No suitable dataset found for sequence #{} '{}'. This sequence will not be analyzed.",
+        entry.index, entry.seq_name
+      );
+    }
+
+    summary.push(entry);
+  }
+
+  Ok((summary, selections))
+}
+
+fn summary_add_result(entry: &mut AutoDatasetSummaryEntry, record: &NextcladeRecord) {
+  match &record.outputs_or_err {
+    Ok(AnalysisOutput { analysis_result, .. }) => {
+      entry.clade.clone_from(&analysis_result.clade);
+      entry.qc_overall_score = Some(analysis_result.qc.overall_score);
+      entry.qc_overall_status = Some(analysis_result.qc.overall_status.clone());
+      entry.coverage = Some(analysis_result.coverage);
+    }
+    Err(report) => {
+      entry.errors = Some(report_to_string(report));
+    }
+  }
+}
+
+/// Loads a detected dataset. Datasets which are not found locally are downloaded from the dataset server.
+fn auto_dataset_load(
+  run_args: &NextcladeRunArgs,
+  http: &HttpClient,
+  dataset_name: &str,
+) -> Result<NextcladeParams, Report> {
+  let path = Path::new(dataset_name);
+  if path.is_dir() {
+    dataset_dir_load(run_args, path, &None)
+  } else if path.is_file() && has_extension(path, "zip") {
+    dataset_zip_load(run_args, path, &None)
+  } else {
+    let dataset = dataset_http_get(http, dataset_name, &None)?;
+    let dataset_zip = dataset_zip_fetch(http, &dataset, &None)?;
+    dataset_zip_bytes_load(run_args, dataset_zip, dataset_name, &None)
+  }
+}
+
+/// Output subdirectory for a dataset. Dataset names can be paths (e.g. local datasets in a custom index), which are
+/// kept relative to the output directory.
+fn dataset_output_subdir(dataset_name: &str) -> PathBuf {
+  Path::new(dataset_name)
+    .components()
+    .filter(|component| matches!(component, Component::Normal(_)))
+    .collect()
+}
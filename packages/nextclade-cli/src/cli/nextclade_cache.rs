@@ -0,0 +1,256 @@
+use crate::cli::nextclade_cli::{NextcladeCacheInfoArgs, NextcladeCachePruneArgs, NextcladeCacheVerifyArgs};
+use comfy_table::modifiers::{UTF8_ROUND_CORNERS, UTF8_SOLID_INNER_BORDERS};
+use comfy_table::presets::UTF8_FULL;
+use comfy_table::{ContentArrangement, Table};
+use eyre::{Report, WrapErr};
+use log::{info, warn};
+use nextclade::io::fs::filename_maybe;
+use nextclade::io::json::{json_stringify, JsonPretty};
+use nextclade::make_error;
+use nextclade::o;
+use nextclade::run::nextclade_cache::{
+  cache_context_dirs, cache_context_files, is_cache_temp_file, read_cache_context, read_cache_entry,
+  AnalysisCacheContext, AnalysisCacheFile, CACHE_ENTRY_EXT,
+};
+use nextclade::utils::datetime::timestamp_format;
+use nextclade::utils::info::this_package_version_str;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Temporary files younger than this might belong to a running process and are never removed
+const CACHE_TEMP_FILE_MIN_AGE: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CacheContextInfo {
+  hash: String,
+  context: Option<AnalysisCacheContext>,
+  entries: usize,
+  size: u64,
+  last_used: Option<String>,
+}
+
+pub fn nextclade_cache_info(NextcladeCacheInfoArgs { cache_dir, json }: &NextcladeCacheInfoArgs) -> Result<(), Report> {
+  let infos = cache_context_dirs(cache_dir)?
+    .into_iter()
+    .map(|context_dir| {
+      let files = cache_context_files(&context_dir)?;
+      let entries = files
+        .iter()
+        .filter(|file| !is_cache_temp_file(&file.path))
+        .collect::<Vec<_>>();
+      Ok(CacheContextInfo {
+        hash: filename_maybe(&context_dir).unwrap_or_default(),
+        context: read_cache_context(&context_dir).ok(),
+        entries: entries.len(),
+        size: entries.iter().map(|file| file.size).sum(),
+        last_used: entries
+          .iter()
+          .map(|file| file.modified)
+          .max()
+          .map(|modified| timestamp_format(system_time_to_timestamp(modified))),
+      })
+    })
+    .collect::<Result<Vec<_>, Report>>()?;
+
+  if *json {
+    println!("{}", json_stringify(&infos, JsonPretty(true))?);
+    return Ok(());
+  }
+
+  if infos.is_empty() {
+    println!("Analysis cache in {cache_dir:#?} is empty");
+    return Ok(());
+  }
+
+  let mut table = Table::new();
+  table
+    .load_preset(UTF8_FULL)
+    .apply_modifier(UTF8_ROUND_CORNERS)
+    .apply_modifier(UTF8_SOLID_INNER_BORDERS)
+    .set_content_arrangement(ContentArrangement::Dynamic);
+
+  table.set_header([
+    o!("context"),
+    o!("dataset"),
+    o!("dataset version"),
+    o!("nextclade version"),
+    o!("entries"),
+    o!("size"),
+    o!("last used"),
+  ]);
+
+  for info in &infos {
+    let (dataset, tag, version) = info.context.as_ref().map_or_else(
+      || (o!("(unreadable)"), o!(""), o!("")),
+      |context| {
+        (
+          context.dataset_name.clone(),
+          context.dataset_tag.clone().unwrap_or_default(),
+          context.nextclade_version.clone(),
+        )
+      },
+    );
+    table.add_row([
+      info.hash.chars().take(12).collect(),
+      dataset,
+      tag,
+      version,
+      info.entries.to_string(),
+      format_size(info.size),
+      info.last_used.clone().unwrap_or_default(),
+    ]);
+  }
+
+  println!("{table}");
+  Ok(())
+}
+
+pub fn nextclade_cache_prune(
+  NextcladeCachePruneArgs {
+    cache_dir,
+    older_than,
+    other_versions,
+    all,
+    dry_run,
+  }: &NextcladeCachePruneArgs,
+) -> Result<(), Report> {
+  let now = SystemTime::now();
+  let min_modified = older_than.map(|days| now - Duration::from_secs(days * 24 * 60 * 60));
+
+  let mut n_removed = 0_usize;
+  let mut size_removed = 0_u64;
+
+  for context_dir in cache_context_dirs(cache_dir)? {
+    let files = cache_context_files(&context_dir)?;
+
+    let is_other_version =
+      read_cache_context(&context_dir).map_or(true, |context| context.nextclade_version != this_package_version_str());
+
+    if *all || (*other_versions && is_other_version) {
+      n_removed += files.iter().filter(|file| !is_cache_temp_file(&file.path)).count();
+      size_removed += files.iter().map(|file| file.size).sum::<u64>();
+      if !dry_run {
+        fs::remove_dir_all(&context_dir).wrap_err_with(|| format!("When removing {context_dir:#?}"))?;
+      }
+      continue;
+    }
+
+    for file in &files {
+      let AnalysisCacheFile { path, size, modified } = file;
+      let is_stale_temp_file =
+        is_cache_temp_file(path) && now.duration_since(*modified).unwrap_or_default() > CACHE_TEMP_FILE_MIN_AGE;
+      let is_old = min_modified.map_or(false, |min_modified| *modified < min_modified);
+      if is_stale_temp_file || is_old {
+        if !is_cache_temp_file(path) {
+          n_removed += 1;
+        }
+        size_removed += size;
+        if !dry_run {
+          remove_file(path)?;
+        }
+      }
+    }
+  }
+
+  let size = format_size(size_removed);
+  if *dry_run {
+    println!("Would remove {n_removed} entries ({size}) from analysis cache in {cache_dir:#?}");
+  } else {
+    println!("Removed {n_removed} entries ({size}) from analysis cache in {cache_dir:#?}");
+  }
+  Ok(())
+}
+
+pub fn nextclade_cache_verify(
+  NextcladeCacheVerifyArgs {
+    cache_dir,
+    remove_invalid,
+  }: &NextcladeCacheVerifyArgs,
+) -> Result<(), Report> {
+  let mut n_checked = 0_usize;
+  let mut n_invalid = 0_usize;
+
+  for context_dir in cache_context_dirs(cache_dir)? {
+    if let Err(report) = read_cache_context(&context_dir) {
+      warn!("Invalid analysis cache context {context_dir:#?}: {report:#}");
+      n_invalid += 1;
+      if *remove_invalid {
+        fs::remove_dir_all(&context_dir).wrap_err_with(|| format!("When removing {context_dir:#?}"))?;
+      }
+      continue;
+    }
+
+    for file in cache_context_files(&context_dir)? {
+      if is_cache_temp_file(&file.path) {
+        continue;
+      }
+      n_checked += 1;
+      if let Err(report) = verify_entry(&file.path) {
+        warn!("Invalid analysis cache entry {:#?}: {report:#}", file.path);
+        n_invalid += 1;
+        if *remove_invalid {
+          remove_file(&file.path)?;
+        }
+      } else {
+        info!("Analysis cache entry {:#?} is valid", file.path);
+      }
+    }
+  }
+
+  if n_invalid > 0 && !remove_invalid {
+    return make_error!(
+      "Found {n_invalid} invalid entries in analysis cache in {cache_dir:#?}. Use `--remove-invalid` to remove them."
+    );
+  }
+
+  println!("Verified {n_checked} entries in analysis cache in {cache_dir:#?}: {n_invalid} invalid entries removed");
+  Ok(())
+}
+
+fn verify_entry(filepath: &Path) -> Result<(), Report> {
+  let filename = filename_maybe(filepath).unwrap_or_default();
+  let Some(seq_hash) = filename.strip_suffix(&format!(".{CACHE_ENTRY_EXT}")) else {
+    return make_error!("Unexpected file name");
+  };
+
+  let entry = read_cache_entry(filepath)?;
+  if entry.seq_hash != seq_hash {
+    return make_error!(
+      "Sequence hash '{}' does not match the file name, expected '{seq_hash}'",
+      entry.seq_hash
+    );
+  }
+
+  Ok(())
+}
+
+fn remove_file(filepath: &Path) -> Result<(), Report> {
+  fs::remove_file(filepath).wrap_err_with(|| format!("When removing {filepath:#?}"))?;
+  // Remove prefix directory if it became empty. This fails if the directory is not empty, which is fine.
+  if let Some(dir) = filepath.parent() {
+    fs::remove_dir(dir).ok();
+  }
+  Ok(())
+}
+
+fn system_time_to_timestamp(time: SystemTime) -> i64 {
+  time.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as i64
+}
+
+fn format_size(size: u64) -> String {
+  const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+  let mut size = size as f64;
+  let mut unit = 0;
+  while size >= 1024.0 && unit < UNITS.len() - 1 {
+    size /= 1024.0;
+    unit += 1;
+  }
+  if unit == 0 {
+    format!("{size} {}", UNITS[unit])
+  } else {
+    format!("{size:.1} {}", UNITS[unit])
+  }
+}
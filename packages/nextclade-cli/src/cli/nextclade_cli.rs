@@ -1,8 +1,10 @@
+use crate::cli::nextclade_cache::{nextclade_cache_info, nextclade_cache_prune, nextclade_cache_verify};
 use crate::cli::nextclade_dataset_get::nextclade_dataset_get;
 use crate::cli::nextclade_dataset_list::nextclade_dataset_list;
 use crate::cli::nextclade_loop::nextclade_run;
 use crate::cli::nextclade_read_annotation::nextclade_read_annotation;
 use crate::cli::nextclade_seq_sort::nextclade_seq_sort;
+use crate::cli::nextclade_seq_sort_build_index::nextclade_seq_sort_build_index;
 use crate::cli::print_help_markdown::print_help_markdown;
 use crate::cli::verbosity::Verbosity;
 use crate::io::http_client::ProxyConfig;
@@ -16,6 +18,7 @@ use lazy_static::lazy_static;
 use nextclade::io::console::CliColorMode;
 use nextclade::io::fs::add_extension;
 use nextclade::run::params::NextcladeInputParamsOptional;
+use nextclade::sort::minimizer_index::{MINIMIZER_INDEX_DEFAULT_CUTOFF, MINIMIZER_INDEX_DEFAULT_K};
 use nextclade::sort::params::NextcladeSeqSortParams;
 use nextclade::utils::global_init::{global_init, GlobalInitConfig};
 use nextclade::{getenv, make_error};
@@ -96,6 +99,11 @@ pub enum NextcladeCommands {
   /// For short help type: `nextclade -h`, for extended help type: `nextclade --help`. Each subcommand has its own help, for example: `nextclade sort --help`.
   Sort(Box<NextcladeSortArgs>),
 
+  /// Inspect and maintain the analysis cache (see `nextclade run --cache-dir`)
+  ///
+  /// For short help type: `nextclade -h`, for extended help type: `nextclade --help`. Each subcommand has its own help, for example: `nextclade cache --help`.
+  Cache(Box<NextcladeCacheArgs>),
+
   /// Read genome annotation and present it in Nextclade's internal formats. This is mostly only useful for Nextclade maintainers and the most curious users. Note that these internal formats have no stability guarantees and can be changed at any time without notice.
   ///
   /// For short help type: `nextclade -h`, for extended help type: `nextclade --help`. Each subcommand has its own help, for example: `nextclade sort --help`.
@@ -273,6 +281,88 @@ pub struct NextcladeDatasetGetArgs {
   pub attribute: Vec<String>,
 }
 
+#[derive(Parser, Debug)]
+pub struct NextcladeCacheArgs {
+  #[clap(subcommand)]
+  pub command: NextcladeCacheCommands,
+}
+
+#[derive(Subcommand, Debug)]
+#[clap(verbatim_doc_comment)]
+pub enum NextcladeCacheCommands {
+  /// Show summary of the analysis cache: datasets, Nextclade versions, number of entries and disk usage
+  ///
+  /// For short help type: `nextclade -h`, for extended help type: `nextclade --help`. Each subcommand has its own help, for example: `nextclade cache info --help`.
+  Info(NextcladeCacheInfoArgs),
+
+  /// Remove entries from the analysis cache
+  ///
+  /// For short help type: `nextclade -h`, for extended help type: `nextclade --help`. Each subcommand has its own help, for example: `nextclade cache prune --help`.
+  Prune(NextcladeCachePruneArgs),
+
+  /// Check that the entries of the analysis cache are readable and consistent
+  ///
+  /// For short help type: `nextclade -h`, for extended help type: `nextclade --help`. Each subcommand has its own help, for example: `nextclade cache verify --help`.
+  Verify(NextcladeCacheVerifyArgs),
+}
+
+#[derive(Parser, Debug)]
+#[clap(verbatim_doc_comment)]
+pub struct NextcladeCacheInfoArgs {
+  /// Path to the analysis cache directory
+  #[clap(long)]
+  #[clap(value_hint = ValueHint::DirPath)]
+  pub cache_dir: PathBuf,
+
+  /// Print output in JSON format.
+  ///
+  /// This is useful for automated processing. However, at this time, we cannot guarantee stability of the format. Use at own risk.
+  #[clap(long)]
+  pub json: bool,
+}
+
+#[derive(Parser, Debug)]
+#[clap(verbatim_doc_comment)]
+#[clap(group(ArgGroup::new("criteria").required(true).multiple(true)))]
+pub struct NextcladeCachePruneArgs {
+  /// Path to the analysis cache directory
+  #[clap(long)]
+  #[clap(value_hint = ValueHint::DirPath)]
+  pub cache_dir: PathBuf,
+
+  /// Remove entries which have not been used for this number of days
+  #[clap(long, value_name = "DAYS")]
+  #[clap(group = "criteria")]
+  pub older_than: Option<u64>,
+
+  /// Remove entries computed by Nextclade versions other than this one
+  #[clap(long)]
+  #[clap(group = "criteria")]
+  pub other_versions: bool,
+
+  /// Remove all entries
+  #[clap(long)]
+  #[clap(group = "criteria")]
+  pub all: bool,
+
+  /// Only report what would be removed, without removing anything
+  #[clap(long)]
+  pub dry_run: bool,
+}
+
+#[derive(Parser, Debug)]
+#[clap(verbatim_doc_comment)]
+pub struct NextcladeCacheVerifyArgs {
+  /// Path to the analysis cache directory
+  #[clap(long)]
+  #[clap(value_hint = ValueHint::DirPath)]
+  pub cache_dir: PathBuf,
+
+  /// Remove the entries which failed verification. Without this flag, the command fails if invalid entries are found.
+  #[clap(long)]
+  pub remove_invalid: bool,
+}
+
 #[derive(Copy, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, EnumIter)]
 pub enum NextcladeOutputSelection {
   All,
@@ -281,8 +371,13 @@ pub enum NextcladeOutputSelection {
   Ndjson,
   Csv,
   Tsv,
+  Parquet,
+  Vcf,
+  Sam,
+  MutationalSpectrum,
   Tree,
   TreeNwk,
+  Jplace,
   Translations,
   Gff,
   Tbl,
@@ -335,6 +430,39 @@ pub struct NextcladeRunInputArgs {
   #[clap(long, short = 'd')]
   pub dataset_name: Option<String>,
 
+  /// Detect the most suitable dataset for each of the input sequences and analyze each sequence using this dataset
+  ///
+  /// This is a convenience shortcut for inputs containing sequences of different pathogens (or of different lineages requiring different datasets). It is equivalent to running `nextclade sort` to split the input sequences by dataset, followed by a separate `nextclade run` for each of the detected datasets. Datasets are detected using the minimizer index (see `--input-minimizer-index`), the same way as in `nextclade sort`, taking the top matching dataset for each sequence. Only the detected datasets are loaded. They are downloaded from the dataset server (see `--server`), unless the index refers to local dataset directories or zip files (e.g. an index built with `nextclade sort build-index`).
+  ///
+  /// Requires `--output-all`. Outputs for each dataset are written into a subdirectory of the output directory, named after the dataset, and can be restricted with `--output-selection`. Additionally, the summary table `<basename>.summary.tsv` is written into the output directory. It lists all input sequences, along with the dataset used for each of them and the main analysis results. Sequences for which no suitable dataset is found are not analyzed, but are listed in the summary table.
+  ///
+  /// Input FASTA files are read multiple times in this mode, so reading from standard input is not supported.
+  ///
+  /// This flag is mutually exclusive with `--input-dataset`, `--dataset-name` and the flags overriding individual dataset files.
+  #[clap(long)]
+  #[clap(conflicts_with_all = [
+    "input_dataset",
+    "dataset_name",
+    "input_ref",
+    "input_tree",
+    "input_pathogen_json",
+    "input_annotation",
+    "cds_selection",
+    "input_pcr_primers",
+    "resume",
+  ])]
+  pub auto_dataset: bool,
+
+  /// Path to minimizer index file used for dataset detection with `--auto-dataset`
+  ///
+  /// By default, the latest reference minimizer index is fetched from the dataset server (see `--server`). The index can be either in JSON or in binary format. See `nextclade sort --help` and `nextclade sort build-index --help` for more details.
+  ///
+  /// Supports the following compression formats: "gz", "bz2", "xz", "zst".
+  #[clap(long)]
+  #[clap(requires = "auto_dataset")]
+  #[clap(value_hint = ValueHint::FilePath)]
+  pub input_minimizer_index: Option<PathBuf>,
+
   /// Path to a FASTA file containing reference sequence. This file should contain exactly 1 sequence.
   ///
   /// Overrides path to `reference.fasta` in the dataset (`--input-dataset`).
@@ -462,7 +590,7 @@ pub struct NextcladeRunOutputArgs {
   ///
   /// If both the `--output-all` and individual `--output-*` flags are provided, each individual flag overrides the corresponding default output path.
   ///
-  /// At least one of the output flags is required: `--output-all`, `--output-fasta`, `--output-ndjson`, `--output-json`, `--output-csv`, `--output-tsv`, `--output-tree`, `--output-translations`.
+  /// At least one of the output flags is required: `--output-all`, `--output-fasta`, `--output-ndjson`, `--output-json`, `--output-csv`, `--output-tsv`, `--output-parquet`, `--output-vcf`, `--output-sam`, `--output-mutational-spectrum`, `--output-tree`, `--output-jplace`, `--output-translations`.
   ///
   /// If the required directory tree does not exist, it will be created.
   #[clap(long, short = 'O')]
@@ -580,13 +708,75 @@ pub struct NextcladeRunOutputArgs {
   #[clap(value_hint = ValueHint::AnyPath)]
   pub output_tsv: Option<PathBuf>,
 
-  /// Restricts columns written into tabular output files (CSV and TSV).
+  /// Path to output Apache Parquet results file
+  ///
+  /// This file format is most suitable for further machine processing of the results, for example with DuckDB, Polars, pandas, R (arrow) or Spark.
+  ///
+  /// Contains the same columns as CSV and TSV outputs, but the values are typed: counts and positions are integers, scores are floating point numbers, and lists of mutations, ranges and QC details are stored as lists of structs rather than as delimited strings. Positions and ranges are 1-based, ranges are closed. Values which are not applicable are stored as nulls.
+  ///
+  /// Rows are written in row groups as the analysis progresses, so memory consumption does not grow with the number of sequences. The file is only valid after Nextclade finished successfully.
+  ///
+  /// Takes precedence over paths configured with `--output-all`, `--output-basename` and `--output-selection`.
+  ///
+  /// Use "-" to write to standard output (stdout). The file is always compressed internally and external compression extensions are not supported.
+  ///
+  /// If the required directory tree does not exist, it will be created.
+  #[clap(long)]
+  #[clap(value_hint = ValueHint::AnyPath)]
+  pub output_parquet: Option<PathBuf>,
+
+  /// Path to output multi-sample VCF file with nucleotide substitutions, deletions and insertions of every sequence relative to the reference sequence.
+  ///
+  /// This file format is suitable for downstream processing with variant analysis tools, such as bcftools or GATK. The reference sequence name (first word of its FASTA header) is used as CHROM. Every sequence is a haploid sample. Deletions and insertions are left-anchored. Genotypes of sites overlapping missing or ambiguous nucleotides, deletions or unaligned regions of a sequence are written as no-calls (`.`). Sequences which failed to be analyzed are included with no-calls only. FORMAT fields contain genotype (GT), overall QC status (QC) and, if the dataset contains a reference tree, clade (CLADE).
+  ///
+  /// Due to format limitations, all samples need to be known before the first variant is written, so the data for the VCF file is accumulated in memory and the file is written after all sequences are processed.
+  ///
+  /// Takes precedence over paths configured with `--output-all`, `--output-basename` and `--output-selection`.
+  ///
+  /// If the provided file path ends with one of the supported extensions: "gz", "bz2", "xz", "zst", then the file will be written compressed. Use "-" to write the uncompressed to standard output (stdout).
+  ///
+  /// If the required directory tree does not exist, it will be created.
+  #[clap(long)]
+  #[clap(value_hint = ValueHint::AnyPath)]
+  pub output_vcf: Option<PathBuf>,
+
+  /// Path to output SAM file with pairwise alignments of every sequence to the reference sequence.
+  ///
+  /// This file format is suitable for viewing alignments in genome browsers, such as IGV, and for processing with tools such as samtools. The reference sequence name (first word of its FASTA header) is used as RNAME. Alignments are described with CIGAR strings, where insertions relative to the reference are `I` operations and parts of a sequence extending beyond the aligned region are soft-clipped (`S`). SEQ is in the orientation of the reference sequence and the reverse complement flag (16) is set for sequences which have been reverse complemented. Records contain alignment score (`AS` tag) and, if the dataset contains a reference tree, clade (`XC` tag). Sequences which failed to be analyzed are written as unmapped records.
+  ///
+  /// Use `samtools sort` or `samtools view -b` to convert the file to BAM format.
+  ///
+  /// Takes precedence over paths configured with `--output-all`, `--output-basename` and `--output-selection`.
+  ///
+  /// If the provided file path ends with one of the supported extensions: "gz", "bz2", "xz", "zst", then the file will be written compressed. Use "-" to write the uncompressed to standard output (stdout).
+  ///
+  /// If the required directory tree does not exist, it will be created.
+  #[clap(long)]
+  #[clap(value_hint = ValueHint::AnyPath)]
+  pub output_sam: Option<PathBuf>,
+
+  /// Path to output TSV file with mutational spectra of every sequence.
   ///
-  /// Should contain a comma-separated list of individual column names and/or column category names to include into both CSV and TSV outputs.
+  /// Every nucleotide substitution is classified by its trinucleotide context in the reference sequence: the substituted nucleotide together with its 5' and 3' neighbours. Each sequence has 2 rows: for all substitutions relative to the reference sequence (`all`) and for private substitutions relative to the nearest node of the reference tree (`private`). Columns contain counts of substitutions in each of the channels of the 96-channel spectrum (prefixed with `sbs96.`), where substitutions are expressed on the strand with pyrimidine reference nucleotide (e.g. `A[C>T]G`), followed by the channels of the 192-channel strand-aware spectrum (prefixed with `sbs192.`). Substitutions involving ambiguous nucleotides and substitutions at the ends of the genome are not counted. Sequences which failed to be analyzed are not included.
   ///
-  /// If this flag is omitted, or if category 'all' is present in the list, then all other entries are ignored and all columns are written.
+  /// The contexts of individual substitutions and the spectra are also available in JSON and NDJSON outputs, in the `mutationalSpectra` property.
   ///
-  /// Only valid together with one or multiple of flags: `--output-csv`, `--output-tsv`, `--output-all`.
+  /// Takes precedence over paths configured with `--output-all`, `--output-basename` and `--output-selection`.
+  ///
+  /// If the provided file path ends with one of the supported extensions: "gz", "bz2", "xz", "zst", then the file will be written compressed. Use "-" to write the uncompressed to standard output (stdout).
+  ///
+  /// If the required directory tree does not exist, it will be created.
+  #[clap(long)]
+  #[clap(value_hint = ValueHint::AnyPath)]
+  pub output_mutational_spectrum: Option<PathBuf>,
+
+  /// Restricts columns written into tabular output files (CSV, TSV and Parquet).
+  ///
+  /// Should contain a comma-separated list of individual column names and/or column category names to include into CSV, TSV and Parquet outputs.
+  ///
+  /// If this flag is omitted, or if category 'all' is present in the list, then all other entries are ignored and all columns are written. The exception is optional category 'alignment-diagnostics', which is only written when listed explicitly.
+  ///
+  /// Only valid together with one or multiple of flags: `--output-csv`, `--output-tsv`, `--output-parquet`, `--output-all`.
   #[clap(
     long,
     short = 'C',
@@ -640,6 +830,21 @@ pub struct NextcladeRunOutputArgs {
   #[clap(value_hint = ValueHint::AnyPath)]
   pub output_tree_nwk: Option<PathBuf>,
 
+  /// Path to output phylogenetic placements of input sequences onto the reference tree, in jplace format
+  ///
+  /// Unlike `--output-tree` and `--output-tree-nwk`, the reference tree is not modified. It is written with numbered edges, and each sequence is described by the edge it is placed onto, with the distance from the lower end of that edge (distal length) and the length of the new branch (pendant length). Sequences with ambiguous placement receive multiple placements weighted by their support (like weight ratio). This format can be consumed by gappa, guppy, iTOL and other tools for placement analysis.
+  ///
+  /// For file format description see: https://doi.org/10.1371/journal.pone.0031009
+  ///
+  /// Takes precedence over paths configured with `--output-all`, `--output-basename` and `--output-selection`.
+  ///
+  /// If the provided file path ends with one of the supported extensions: "gz", "bz2", "xz", "zst", then the file will be written compressed. Use "-" to write the uncompressed to standard output (stdout).
+  ///
+  /// If the required directory tree does not exist, it will be created.
+  #[clap(long)]
+  #[clap(value_hint = ValueHint::AnyPath)]
+  pub output_jplace: Option<PathBuf>,
+
   /// Path to output annotation for query sequences in GFF3 format (EXPERIMENTAL)
   ///
   /// This output contains annotation of genetic features (genes and CDSes) for each query sequence.
@@ -674,6 +879,34 @@ pub struct NextcladeRunOutputArgs {
   #[clap(value_hint = ValueHint::AnyPath)]
   pub output_annotation_tbl: Option<PathBuf>,
 
+  /// Make the run resumable, and resume the previous run if it has been interrupted.
+  ///
+  /// With this flag, Nextclade keeps a journal of the sequences which have been written to the output files. If the run is interrupted (e.g. killed, or the machine is restarted), then running the same command again continues from the last checkpoint recorded in the journal: the sequences which are already completed are skipped and the results of the remaining sequences are appended to the existing output files.
+  ///
+  /// Nextclade refuses to resume if the dataset or parameters have changed since the interrupted run. The input sequences must also be the same.
+  ///
+  /// Only NDJSON, CSV, TSV, FASTA (including translations), SAM and mutational spectrum outputs can be resumed. Requesting other outputs in this mode is an error. When used with `--output-all` without `--output-selection`, only the outputs which can be resumed are written. Output files cannot be compressed and cannot be written to standard output.
+  #[clap(long)]
+  pub resume: bool,
+
+  /// Path to the journal file of the resumable run. Only used with `--resume`.
+  ///
+  /// By default, the journal is written next to the output files, using the path of the first of the NDJSON, CSV, TSV or FASTA outputs with added `.journal` extension (e.g. `nextclade.ndjson.journal`).
+  #[clap(long, requires = "resume")]
+  #[clap(value_hint = ValueHint::FilePath)]
+  pub journal: Option<PathBuf>,
+
+  /// Path to the directory of the analysis cache.
+  ///
+  /// When provided, analysis results are stored in this directory, keyed by the hash of the sequence. When the same sequence is encountered again (in this or in one of the later runs), the stored results are reused instead of repeating the analysis. This can greatly speed up repeated analysis of growing collections of sequences, where most of the sequences have been seen before.
+  ///
+  /// Results are reused only if they were computed by the same version of Nextclade, with the same dataset and the same parameters. The cache directory can be shared between multiple runs, including runs with different datasets.
+  ///
+  /// Use `nextclade cache` command to inspect and prune the cache.
+  #[clap(long)]
+  #[clap(value_hint = ValueHint::DirPath)]
+  pub cache_dir: Option<PathBuf>,
+
   /// REMOVED. The argument `--output-insertions` have been removed in favor of `--output-csv` and `--output-tsv`.
   #[clap(long, short = 'I')]
   #[clap(value_hint = ValueHint::AnyPath)]
@@ -712,7 +945,11 @@ pub struct NextcladeRunArgs {
 #[allow(clippy::struct_excessive_bools)]
 #[derive(Parser, Debug)]
 #[clap(verbatim_doc_comment)]
+#[clap(args_conflicts_with_subcommands = true)]
 pub struct NextcladeSortArgs {
+  #[clap(subcommand)]
+  pub command: Option<NextcladeSortCommands>,
+
   /// Path to one or multiple FASTA files with input sequences
   ///
   /// Supports the following compression formats: "gz", "bz2", "xz", "zst". If no files provided, the plain fasta input is read from standard input (stdin).
@@ -721,9 +958,11 @@ pub struct NextcladeSortArgs {
   #[clap(value_hint = ValueHint::FilePath)]
   pub input_fastas: Vec<PathBuf>,
 
-  /// Path to input minimizer index JSON file.
+  /// Path to input minimizer index file.
+  ///
+  /// By default, the latest reference minimizer index is fetched from the dataset server (default or customized with `--server` argument). If this argument is provided, the algorithm skips fetching the default index and uses the index provided in the file.
   ///
-  /// By default, the latest reference minimizer index is fetched from the dataset server (default or customized with `--server` argument). If this argument is provided, the algorithm skips fetching the default index and uses the index provided in the JSON file.
+  /// The index can be either in JSON format or in compact binary format (see `nextclade sort build-index --binary`). Uncompressed binary index is memory-mapped rather than read, which makes startup faster for large indices.
   ///
   /// Supports the following compression formats: "gz", "bz2", "xz", "zst". Use "-" to read uncompressed data from standard input (stdin).
   #[clap(long, short = 'm')]
@@ -785,6 +1024,71 @@ pub struct NextcladeSortArgs {
   pub proxy_config: ProxyConfig,
 }
 
+#[derive(Subcommand, Debug)]
+#[clap(verbatim_doc_comment)]
+pub enum NextcladeSortCommands {
+  /// Build reference minimizer index from a set of datasets or reference sequences
+  ///
+  /// The resulting index can be passed to `nextclade sort --input-minimizer-index-json`, to sort sequences according to custom datasets, for example the ones which are not published on the dataset server.
+  ///
+  /// For short help type: `nextclade -h`, for extended help type: `nextclade --help`. Each subcommand has its own help, for example: `nextclade sort build-index --help`.
+  BuildIndex(NextcladeSortBuildIndexArgs),
+}
+
+#[derive(Parser, Debug)]
+#[clap(verbatim_doc_comment)]
+pub struct NextcladeSortBuildIndexArgs {
+  /// Paths to dataset directories or to FASTA files with reference sequences
+  ///
+  /// For a dataset directory, the reference sequence is taken from the file declared in `pathogen.json`, and the dataset name is the path to the directory, as provided. For a FASTA file, the file should contain exactly one sequence, and the dataset name is the ID of this sequence (first word of its header). The name can be set explicitly by prepending it to the path, separated with `=`. The names are reported in the results of `nextclade sort` and are used as names of the output subdirectories.
+  ///
+  /// Supports the following compression formats for FASTA files: "gz", "bz2", "xz", "zst".
+  ///
+  /// Example:
+  ///
+  ///   nextclade sort build-index -o index.json my-org/flu-h5 my-org/rsv-a=references/rsv_a.fasta
+  #[clap(required_unless_present = "input_minimizer_index")]
+  #[clap(conflicts_with = "input_minimizer_index")]
+  #[clap(value_hint = ValueHint::AnyPath)]
+  pub inputs: Vec<String>,
+
+  /// Path to an existing minimizer index file, in JSON or binary format, to convert instead of building a new index
+  ///
+  /// This is useful to convert the official JSON index into the compact binary format, with `--binary`, or back.
+  ///
+  /// Supports the following compression formats: "gz", "bz2", "xz", "zst". Use "-" to read uncompressed data from standard input (stdin).
+  #[clap(long, short = 'm')]
+  #[clap(value_hint = ValueHint::FilePath)]
+  pub input_minimizer_index: Option<PathBuf>,
+
+  /// Path to output minimizer index file
+  ///
+  /// If the provided file path ends with one of the supported extensions: "gz", "bz2", "xz", "zst", then the file will be written compressed. Use "-" to write uncompressed to standard output (stdout). If the required directory tree does not exist, it will be created.
+  #[clap(long, short = 'o')]
+  #[clap(value_hint = ValueHint::FilePath)]
+  pub output: PathBuf,
+
+  /// Write the index in compact binary format instead of JSON
+  ///
+  /// Uncompressed binary index is memory-mapped by `nextclade sort` rather than read and parsed, which makes startup faster for large indices. Note that compressed binary index has to be decompressed into memory.
+  #[clap(long)]
+  pub binary: bool,
+
+  /// Length of k-mers used for computing minimizers
+  ///
+  /// The default value is the same as in the official index. Sequences are only compared to the references using the same k-mer length, so changing this value requires rebuilding the index.
+  #[clap(long, short = 'k')]
+  #[clap(default_value_t = MINIMIZER_INDEX_DEFAULT_K)]
+  pub kmer_size: i64,
+
+  /// Maximum value of minimizer hashes retained in the index
+  ///
+  /// Lower values produce smaller index and faster search, at the cost of sensitivity. The default value is the same as in the official index.
+  #[clap(long)]
+  #[clap(default_value_t = MINIMIZER_INDEX_DEFAULT_CUTOFF)]
+  pub cutoff: i64,
+}
+
 #[allow(clippy::struct_excessive_bools)]
 #[derive(Parser, Debug)]
 #[clap(verbatim_doc_comment)]
@@ -846,10 +1150,16 @@ pub fn nextclade_get_output_filenames(run_args: &mut NextcladeRunArgs) -> Result
         output_json,
         output_csv,
         output_tsv,
+        output_parquet,
+        output_vcf,
+        output_sam,
+        output_mutational_spectrum,
         output_tree,
         output_tree_nwk,
+        output_jplace,
         output_annotation_gff,
         output_annotation_tbl,
+        resume,
         ..
       },
     ..
@@ -863,6 +1173,19 @@ pub fn nextclade_get_output_filenames(run_args: &mut NextcladeRunArgs) -> Result
 
     let default_output_file_path = output_all.join(&output_basename);
 
+    // If `--resume` is used and `--output-selection` is empty, then only select the outputs which can be resumed
+    if *resume && output_selection.is_empty() {
+      *output_selection = vec![
+        NextcladeOutputSelection::Fasta,
+        NextcladeOutputSelection::Translations,
+        NextcladeOutputSelection::Ndjson,
+        NextcladeOutputSelection::Csv,
+        NextcladeOutputSelection::Tsv,
+        NextcladeOutputSelection::Sam,
+        NextcladeOutputSelection::MutationalSpectrum,
+      ];
+    }
+
     // If `--output-selection` is empty or contains `all`, then fill it with all possible variants
     if output_selection.is_empty() || output_selection.contains(&NextcladeOutputSelection::All) {
       *output_selection = NextcladeOutputSelection::iter().collect_vec();
@@ -904,6 +1227,22 @@ pub fn nextclade_get_output_filenames(run_args: &mut NextcladeRunArgs) -> Result
       output_tsv.get_or_insert(add_extension(&default_output_file_path, "tsv"));
     }
 
+    if output_selection.contains(&NextcladeOutputSelection::Parquet) {
+      output_parquet.get_or_insert(add_extension(&default_output_file_path, "parquet"));
+    }
+
+    if output_selection.contains(&NextcladeOutputSelection::Vcf) {
+      output_vcf.get_or_insert(add_extension(&default_output_file_path, "vcf"));
+    }
+
+    if output_selection.contains(&NextcladeOutputSelection::Sam) {
+      output_sam.get_or_insert(add_extension(&default_output_file_path, "sam"));
+    }
+
+    if output_selection.contains(&NextcladeOutputSelection::MutationalSpectrum) {
+      output_mutational_spectrum.get_or_insert(add_extension(&default_output_file_path, "mutational_spectrum.tsv"));
+    }
+
     if output_selection.contains(&NextcladeOutputSelection::Tree) {
       output_tree.get_or_insert(add_extension(&default_output_file_path, "auspice.json"));
     }
@@ -912,6 +1251,10 @@ pub fn nextclade_get_output_filenames(run_args: &mut NextcladeRunArgs) -> Result
       output_tree_nwk.get_or_insert(add_extension(&default_output_file_path, "nwk"));
     }
 
+    if output_selection.contains(&NextcladeOutputSelection::Jplace) {
+      output_jplace.get_or_insert(add_extension(&default_output_file_path, "jplace"));
+    }
+
     if output_selection.contains(&NextcladeOutputSelection::Gff) {
       output_annotation_gff.get_or_insert(add_extension(&default_output_file_path, "gff"));
     }
@@ -946,7 +1289,12 @@ Example for bash shell:
     output_json,
     output_csv,
     output_tsv,
+    output_parquet,
+    output_vcf,
+    output_sam,
+    output_mutational_spectrum,
     output_tree,
+    output_jplace,
   ]
   .iter()
   .all(|o| o.is_none())
@@ -963,7 +1311,12 @@ At least one of the following flags is required:
   --output-json
   --output-csv
   --output-tsv
+  --output-parquet
+  --output-vcf
+  --output-sam
+  --output-mutational-spectrum
   --output-tree
+  --output-jplace
   --output-translations"#
     );
   }
@@ -1139,12 +1492,17 @@ pub fn nextclade_check_column_config_args(run_args: &NextcladeRunArgs) -> Result
     output_all,
     output_csv,
     output_tsv,
+    output_parquet,
     output_columns_selection,
     ..
   } = &run_args.outputs;
 
-  if !output_columns_selection.is_empty() && [output_all, output_csv, output_tsv].iter().all(|arg| arg.is_none()) {
-    return make_error!("The `--output-columns-selection` argument configures column-based output formats and can only be used when one or more of the column-based file outputs is requested, i.e. together with one or multiple of `--output-all`, `--output-csv`, `--output-tsv`.");
+  if !output_columns_selection.is_empty()
+    && [output_all, output_csv, output_tsv, output_parquet]
+      .iter()
+      .all(|arg| arg.is_none())
+  {
+    return make_error!("The `--output-columns-selection` argument configures column-based output formats and can only be used when one or more of the column-based file outputs is requested, i.e. together with one or multiple of `--output-all`, `--output-csv`, `--output-tsv`, `--output-parquet`.");
   }
 
   Ok(())
@@ -1166,7 +1524,10 @@ pub fn nextclade_parse_cli_args() -> Result<(), Report> {
     NextcladeCommands::Run(mut run_args) => {
       nextclade_check_removed_args(&run_args)?;
       nextclade_check_column_config_args(&run_args)?;
-      nextclade_get_output_filenames(&mut run_args).wrap_err("When deducing output filenames")?;
+      // In auto-dataset mode, output filenames are deduced separately for each of the detected datasets
+      if !run_args.inputs.auto_dataset {
+        nextclade_get_output_filenames(&mut run_args).wrap_err("When deducing output filenames")?;
+      }
       nextclade_run(*run_args)
     }
     NextcladeCommands::Dataset(dataset_command) => match dataset_command.command {
@@ -1179,7 +1540,15 @@ pub fn nextclade_parse_cli_args() -> Result<(), Report> {
         nextclade_dataset_get(&dataset_get_args)
       }
     },
-    NextcladeCommands::Sort(seq_sort_args) => nextclade_seq_sort(&seq_sort_args),
+    NextcladeCommands::Sort(seq_sort_args) => match &seq_sort_args.command {
+      Some(NextcladeSortCommands::BuildIndex(build_index_args)) => nextclade_seq_sort_build_index(build_index_args),
+      None => nextclade_seq_sort(&seq_sort_args),
+    },
+    NextcladeCommands::Cache(cache_command) => match cache_command.command {
+      NextcladeCacheCommands::Info(cache_info_args) => nextclade_cache_info(&cache_info_args),
+      NextcladeCacheCommands::Prune(cache_prune_args) => nextclade_cache_prune(&cache_prune_args),
+      NextcladeCacheCommands::Verify(cache_verify_args) => nextclade_cache_verify(&cache_verify_args),
+    },
     NextcladeCommands::ReadAnnotation(read_annotation_args) => nextclade_read_annotation(&read_annotation_args),
   }
 }
@@ -0,0 +1,417 @@
+use crate::cli::nextclade_cli::NextcladeRunArgs;
+use eyre::{Report, WrapErr};
+use itertools::Itertools;
+use log::{info, warn};
+use nextclade::analyze::pcr_primers::PcrPrimer;
+use nextclade::io::compression::{guess_compression_from_filepath, CompressionType};
+use nextclade::io::file::is_path_stdout;
+use nextclade::io::fs::ensure_dir;
+use nextclade::make_error;
+use nextclade::run::nextclade_wasm::NextcladeParams;
+use nextclade::utils::hash::hash_json_sha256;
+use nextclade::utils::info::this_package_version_str;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{File, OpenOptions};
+use std::hash::{Hash, Hasher};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+const JOURNAL_VERSION: u32 = 1;
+
+/// Number of written records after which output files are flushed and a checkpoint is added to the journal
+pub const JOURNAL_CHECKPOINT_INTERVAL: usize = 1000;
+
+/// First line of the journal file
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JournalHeader {
+  version: u32,
+  hash: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JournalRecord {
+  index: usize,
+  seq_name: String,
+}
+
+/// Subsequent lines of the journal file. Each checkpoint lists records written to the output files since the previous
+/// checkpoint, and the sizes of the output files after these records have been flushed to disk.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JournalCheckpoint {
+  records: Vec<JournalRecord>,
+  output_sizes: BTreeMap<PathBuf, u64>,
+}
+
+/// Records which have been written to the output files by a previous, interrupted run
+#[derive(Debug, Default)]
+pub struct ResumeState {
+  /// Hashes of names of the completed records, by record index. Only the hashes are kept, to save memory.
+  completed: HashMap<usize, u64>,
+}
+
+impl ResumeState {
+  pub fn is_completed(&self, index: usize) -> bool {
+    self.completed.contains_key(&index)
+  }
+
+  /// Checks whether the record has been completed by the previous run. Fails if the record with the same index had
+  /// a different name, which means that the input sequences are not the same as in the previous run.
+  pub fn check_completed(&self, index: usize, seq_name: &str) -> Result<bool, Report> {
+    match self.completed.get(&index) {
+      None => Ok(false),
+      Some(name_hash) if *name_hash == hash_name(seq_name) => Ok(true),
+      Some(_) => make_error!(
+        "Unable to resume: sequence #{index} '{seq_name}' has a different name in the journal of the previous run. Input sequences should be the same as in the interrupted run."
+      ),
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.completed.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.completed.is_empty()
+  }
+}
+
+/// Journal of a resumable run (`--resume`).
+///
+/// The journal is a newline-delimited JSON file. The first line contains a hash of the dataset and of the parameters
+/// of the run. Each following line is a checkpoint: the records written since the previous checkpoint and the sizes of
+/// the output files at that moment. When resuming, output files are truncated to the sizes at the last checkpoint,
+/// which discards any records written after it, and new records are appended.
+pub struct CheckpointJournal {
+  filepath: PathBuf,
+  file: File,
+  pending: Vec<JournalRecord>,
+}
+
+impl CheckpointJournal {
+  /// Opens the journal. If the journal exists, verifies that the hash matches, truncates output files to the last
+  /// checkpoint and returns the records completed by the previous run. Otherwise, starts a new journal.
+  pub fn open(filepath: impl AsRef<Path>, hash: &str) -> Result<(Self, ResumeState), Report> {
+    let filepath = filepath.as_ref();
+
+    if filepath.is_file() {
+      if let Some((journal, state)) = Self::resume(filepath, hash)? {
+        return Ok((journal, state));
+      }
+    } else {
+      info!("Journal file {filepath:#?} is not found. Starting a new run.");
+    }
+
+    let journal = Self::create(filepath, hash)?;
+    Ok((journal, ResumeState::default()))
+  }
+
+  fn create(filepath: &Path, hash: &str) -> Result<Self, Report> {
+    ensure_dir(filepath)?;
+    let mut file = File::create(filepath).wrap_err_with(|| format!("When creating journal file {filepath:#?}"))?;
+    let header = JournalHeader {
+      version: JOURNAL_VERSION,
+      hash: hash.to_owned(),
+    };
+    write_line(&mut file, &header).wrap_err_with(|| format!("When writing journal file {filepath:#?}"))?;
+    Ok(Self {
+      filepath: filepath.to_owned(),
+      file,
+      pending: vec![],
+    })
+  }
+
+  fn resume(filepath: &Path, hash: &str) -> Result<Option<(Self, ResumeState)>, Report> {
+    let file = File::open(filepath).wrap_err_with(|| format!("When opening journal file {filepath:#?}"))?;
+    let mut lines = BufReader::new(file).lines();
+
+    let Some(header_line) = lines.next().transpose()? else {
+      warn!("Journal file {filepath:#?} is empty. Starting a new run.");
+      return Ok(None);
+    };
+    let header: JournalHeader =
+      serde_json::from_str(&header_line).wrap_err_with(|| format!("When parsing journal file {filepath:#?}"))?;
+
+    if header.version != JOURNAL_VERSION {
+      return make_error!(
+        "Unable to resume: journal file {filepath:#?} has version {}, but this version of Nextclade expects version {JOURNAL_VERSION}. Remove the journal and the output files to start a new run.",
+        header.version
+      );
+    }
+
+    if header.hash != hash {
+      return make_error!(
+        "Unable to resume: dataset or parameters have changed since the interrupted run (the hash in journal file {filepath:#?} does not match). Remove the journal and the output files to start a new run, or restore the dataset and parameters of the interrupted run."
+      );
+    }
+
+    // Length of the journal up to and including the last valid checkpoint
+    let mut valid_len = header_line.len() as u64 + 1;
+    let mut state = ResumeState::default();
+    let mut output_sizes = None;
+
+    let mut lines = lines.enumerate().peekable();
+    while let Some((i, line)) = lines.next() {
+      let line = line?;
+      let is_last_line = lines.peek().is_none();
+      match serde_json::from_str::<JournalCheckpoint>(&line) {
+        Ok(checkpoint) => {
+          for JournalRecord { index, seq_name } in &checkpoint.records {
+            state.completed.insert(*index, hash_name(seq_name));
+          }
+          output_sizes = Some(checkpoint.output_sizes);
+          valid_len += line.len() as u64 + 1;
+        }
+        // The last line can be incomplete if the run has been interrupted while writing it. It is discarded.
+        Err(_) if is_last_line => {
+          warn!("Journal file {filepath:#?}: discarding incomplete last checkpoint");
+        }
+        Err(report) => {
+          return Err(report).wrap_err_with(|| format!("When parsing line {} of journal file {filepath:#?}", i + 2));
+        }
+      }
+    }
+
+    let Some(output_sizes) = output_sizes else {
+      info!("Journal file {filepath:#?} contains no checkpoints. Starting a new run.");
+      return Ok(None);
+    };
+
+    for (output_filepath, size) in &output_sizes {
+      truncate_file(output_filepath, *size).wrap_err("When restoring output files to the last checkpoint")?;
+    }
+
+    let file = OpenOptions::new().write(true).open(filepath)?;
+    file.set_len(valid_len)?;
+    let file = OpenOptions::new()
+      .append(true)
+      .open(filepath)
+      .wrap_err_with(|| format!("When opening journal file {filepath:#?} for appending"))?;
+
+    info!(
+      "Resuming interrupted run: {} sequences are already completed and will be skipped",
+      state.len()
+    );
+
+    let journal = Self {
+      filepath: filepath.to_owned(),
+      file,
+      pending: vec![],
+    };
+
+    Ok(Some((journal, state)))
+  }
+
+  /// Adds a record to the next checkpoint. The record should already be written to the output files.
+  pub fn add(&mut self, index: usize, seq_name: &str) {
+    self.pending.push(JournalRecord {
+      index,
+      seq_name: seq_name.to_owned(),
+    });
+  }
+
+  /// Number of records added since the last checkpoint
+  pub fn pending(&self) -> usize {
+    self.pending.len()
+  }
+
+  /// Writes a checkpoint. Output files should be flushed before calling this.
+  pub fn checkpoint(&mut self, output_filepaths: &[PathBuf]) -> Result<(), Report> {
+    let output_sizes = output_filepaths
+      .iter()
+      .map(|filepath| {
+        let size = std::fs::metadata(filepath)
+          .wrap_err_with(|| format!("When reading size of output file {filepath:#?}"))?
+          .len();
+        Ok((filepath.clone(), size))
+      })
+      .collect::<Result<BTreeMap<_, _>, Report>>()?;
+
+    let checkpoint = JournalCheckpoint {
+      records: std::mem::take(&mut self.pending),
+      output_sizes,
+    };
+
+    write_line(&mut self.file, &checkpoint)
+      .and_then(|()| Ok(self.file.sync_data()?))
+      .wrap_err_with(|| format!("When writing checkpoint to journal file {:#?}", self.filepath))
+  }
+}
+
+/// Journal and restored state of a resumable run
+pub struct NextcladeResume {
+  pub journal: CheckpointJournal,
+  pub state: Arc<ResumeState>,
+}
+
+impl NextcladeResume {
+  /// Whether some of the records have been completed by an interrupted run, so that the run is continued
+  pub fn is_resumed(&self) -> bool {
+    !self.state.is_empty()
+  }
+}
+
+/// Prepares a resumable run (`--resume`): checks that all of the requested outputs can be resumed and appended to, and
+/// opens the journal.
+pub fn nextclade_resume_prepare(
+  run_args: &NextcladeRunArgs,
+  inputs: &NextcladeParams,
+  primers: &[PcrPrimer],
+) -> Result<NextcladeResume, Report> {
+  let outputs = &run_args.outputs;
+
+  let unsupported = [
+    ("--output-json", outputs.output_json.is_some()),
+    ("--output-parquet", outputs.output_parquet.is_some()),
+    ("--output-vcf", outputs.output_vcf.is_some()),
+    ("--output-tree", outputs.output_tree.is_some()),
+    ("--output-tree-nwk", outputs.output_tree_nwk.is_some()),
+    ("--output-jplace", outputs.output_jplace.is_some()),
+    ("--output-graph", outputs.output_graph.is_some()),
+    ("--output-annotation-gff", outputs.output_annotation_gff.is_some()),
+    ("--output-annotation-tbl", outputs.output_annotation_tbl.is_some()),
+  ]
+  .into_iter()
+  .filter_map(|(arg, is_requested)| is_requested.then_some(arg))
+  .collect_vec();
+
+  if !unsupported.is_empty() {
+    return make_error!(
+      "Unable to use `--resume`: the following outputs cannot be resumed: {}. Please remove them, or run without `--resume`",
+      unsupported.join(", ")
+    );
+  }
+
+  let output_filepaths = [
+    &outputs.output_ndjson,
+    &outputs.output_csv,
+    &outputs.output_tsv,
+    &outputs.output_fasta,
+    &outputs.output_sam,
+    &outputs.output_mutational_spectrum,
+  ]
+  .into_iter()
+  .flatten()
+  .cloned()
+  .collect_vec();
+
+  let output_translations = outputs.output_translations.iter().map(PathBuf::from);
+  for filepath in output_filepaths.iter().cloned().chain(output_translations) {
+    if is_path_stdout(&filepath) {
+      return make_error!(
+        "Unable to use `--resume`: output {filepath:#?} is standard output, which cannot be appended to"
+      );
+    }
+    let (compression_type, _) = guess_compression_from_filepath(&filepath);
+    if !matches!(compression_type, CompressionType::None) {
+      return make_error!(
+        "Unable to use `--resume`: output file {filepath:#?} is compressed. Compressed output files cannot be resumed."
+      );
+    }
+  }
+
+  let journal_path = run_args
+    .outputs
+    .journal
+    .clone()
+    .or_else(|| default_journal_path(&output_filepaths));
+
+  let Some(journal_path) = journal_path else {
+    return make_error!(
+      "Unable to use `--resume`: at least one of the `--output-ndjson`, `--output-csv`, `--output-tsv` or `--output-fasta` outputs is required, or the journal path should be provided explicitly with `--journal`"
+    );
+  };
+
+  let hash = run_params_hash(inputs, primers, run_args)?;
+  let (journal, state) = CheckpointJournal::open(journal_path, &hash)?;
+
+  Ok(NextcladeResume {
+    journal,
+    state: Arc::new(state),
+  })
+}
+
+/// Default path of the journal file: next to the first of the resumable output files
+pub fn default_journal_path(output_filepaths: &[PathBuf]) -> Option<PathBuf> {
+  output_filepaths.first().map(|filepath| {
+    let mut filename = filepath.file_name().unwrap_or_default().to_owned();
+    filename.push(".journal");
+    filepath.with_file_name(filename)
+  })
+}
+
+/// Calculates hash of the dataset and of the parameters which affect the content of output files
+pub fn run_params_hash(
+  inputs: &NextcladeParams,
+  primers: &[PcrPrimer],
+  run_args: &NextcladeRunArgs,
+) -> Result<String, Report> {
+  let NextcladeParams {
+    ref_record,
+    gene_map,
+    tree,
+    virus_properties,
+    ..
+  } = inputs;
+
+  let outputs = &run_args.outputs;
+
+  let value = json!({
+    "refSeq": ref_record.seq,
+    "geneMap": gene_map,
+    "tree": tree,
+    "virusProperties": virus_properties,
+    "primers": primers,
+    "params": run_args.params,
+    "outputs": {
+      "fasta": outputs.output_fasta,
+      "translations": outputs.output_translations,
+      "ndjson": outputs.output_ndjson,
+      "csv": outputs.output_csv,
+      "tsv": outputs.output_tsv,
+      "sam": outputs.output_sam,
+      "mutationalSpectrum": outputs.output_mutational_spectrum,
+      "columnsSelection": outputs.output_columns_selection,
+    },
+    "version": this_package_version_str(),
+  });
+
+  hash_json_sha256(&value).wrap_err("When calculating hash of run parameters")
+}
+
+fn hash_name(seq_name: &str) -> u64 {
+  let mut hasher = DefaultHasher::new();
+  seq_name.hash(&mut hasher);
+  hasher.finish()
+}
+
+fn write_line<T: Serialize>(file: &mut File, entry: &T) -> Result<(), Report> {
+  let mut line = serde_json::to_vec(entry)?;
+  line.push(b'\n');
+  file.write_all(&line)?;
+  Ok(())
+}
+
+fn truncate_file(filepath: &Path, size: u64) -> Result<(), Report> {
+  let file = OpenOptions::new()
+    .write(true)
+    .open(filepath)
+    .wrap_err_with(|| format!("When opening output file {filepath:#?}"))?;
+
+  let actual_size = file.metadata()?.len();
+  if actual_size < size {
+    return make_error!(
+      "Unable to resume: output file {filepath:#?} is smaller ({actual_size} bytes) than recorded in the journal ({size} bytes). It might have been modified after the interrupted run."
+    );
+  }
+
+  file
+    .set_len(size)
+    .wrap_err_with(|| format!("When truncating output file {filepath:#?}"))
+}
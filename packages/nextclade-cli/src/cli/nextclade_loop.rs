@@ -1,19 +1,26 @@
+use crate::cli::nextclade_auto_dataset::nextclade_run_auto_dataset;
 use crate::cli::nextclade_cli::{NextcladeOutputSelection, NextcladeRunArgs};
+use crate::cli::nextclade_journal::nextclade_resume_prepare;
 use crate::cli::nextclade_ordered_writer::NextcladeOrderedWriter;
-use crate::dataset::dataset_download::nextclade_get_inputs;
+use crate::cli::nextclade_segmented_loop::nextclade_run_segmented;
+use crate::dataset::dataset_download::{nextclade_get_inputs, nextclade_get_segment_inputs};
 use eyre::{ContextCompat, Report, WrapErr};
+use itertools::Itertools;
 use log::info;
 use nextclade::analyze::pcr_primers::PcrPrimer;
 use nextclade::gene::gene_map_display::gene_map_to_table_string;
 use nextclade::graph::graph::Graph;
 use nextclade::io::fasta::{FastaReader, FastaRecord};
+use nextclade::io::jplace::{jplace_from_results, jplace_write_to_file};
 use nextclade::io::json::{json_write, JsonPretty};
 use nextclade::io::nextclade_csv_column_config::CsvColumnConfig;
 use nextclade::io::nwk_writer::nwk_write_to_file;
-use nextclade::run::nextclade_wasm::{AnalysisInitialData, AnalysisOutput, Nextclade};
+use nextclade::run::nextclade_cache::{AnalysisCache, AnalysisCacheContext};
+use nextclade::run::nextclade_wasm::{AnalysisInitialData, AnalysisOutput, Nextclade, NextcladeParams};
 use nextclade::tree::tree_builder::graph_attach_new_nodes_in_place;
 use nextclade::types::outputs::NextcladeOutputs;
 use nextclade::utils::option::OptionMapRefFallible;
+use std::collections::BTreeSet;
 use std::sync::{Arc, Mutex};
 
 pub struct NextcladeRecord {
@@ -22,11 +29,32 @@ pub struct NextcladeRecord {
   pub outputs_or_err: Result<AnalysisOutput, Report>,
 }
 
-pub fn nextclade_run(mut run_args: NextcladeRunArgs) -> Result<(), Report> {
+pub fn nextclade_run(run_args: NextcladeRunArgs) -> Result<(), Report> {
   info!("Command-line arguments:\n{run_args:#?}");
 
+  if run_args.inputs.auto_dataset {
+    return nextclade_run_auto_dataset(&run_args);
+  }
+
+  if let Some(segments) = nextclade_get_segment_inputs(&run_args, &run_args.inputs.cds_selection)? {
+    return nextclade_run_segmented(&run_args, segments);
+  }
+
   let inputs = nextclade_get_inputs(&run_args, &run_args.inputs.cds_selection)?;
 
+  nextclade_run_dataset(run_args, inputs, None, |_| {})
+}
+
+/// Runs analysis of input sequences using a given dataset.
+///
+/// If `selection` is provided, only the input records with these indices are analyzed and written to the outputs. All
+/// analyzed records are passed to `on_record` before they are written.
+pub fn nextclade_run_dataset(
+  mut run_args: NextcladeRunArgs,
+  inputs: NextcladeParams,
+  selection: Option<&Arc<BTreeSet<usize>>>,
+  mut on_record: impl FnMut(&NextcladeRecord) + Send,
+) -> Result<(), Report> {
   if inputs.gene_map.is_empty() {
     // If there is no genome annotation, then we cannot emit these output files
     let to_remove = [
@@ -49,10 +77,25 @@ pub fn nextclade_run(mut run_args: NextcladeRunArgs) -> Result<(), Report> {
     .wrap_err("When parsing PCR primers input CSV")
     .unwrap_or_default();
 
-  let nextclade = Nextclade::new(inputs, primers, &run_args.params)?;
+  let resume = run_args
+    .outputs
+    .resume
+    .then(|| nextclade_resume_prepare(&run_args, &inputs, &primers))
+    .transpose()?;
+  let resume_state = resume.as_ref().map(|resume| Arc::clone(&resume.state));
+
+  let mut nextclade = Nextclade::new(inputs, primers, &run_args.params)?;
+
+  if let Some(cache_dir) = &run_args.outputs.cache_dir {
+    let cache = AnalysisCache::open(cache_dir, &AnalysisCacheContext::from_nextclade(&nextclade)?)
+      .wrap_err_with(|| format!("When opening analysis cache in {cache_dir:#?}"))?;
+    info!("Using analysis cache in {:#?}", cache.dir());
+    nextclade.cache = Some(cache);
+  }
 
   let should_write_tree = run_args.outputs.output_tree.is_some()
     || run_args.outputs.output_tree_nwk.is_some()
+    || run_args.outputs.output_jplace.is_some()
     || run_args.outputs.output_graph.is_some();
   let mut outputs = Vec::<NextcladeOutputs>::new();
 
@@ -71,6 +114,7 @@ pub fn nextclade_run(mut run_args: NextcladeRunArgs) -> Result<(), Report> {
     let nextclade = &nextclade;
     let outputs = &mut outputs;
     let run_args = &run_args;
+    let resume_state = &resume_state;
 
     let thread_errors_cloned = Arc::clone(&thread_errors);
     s.spawn(move || {
@@ -82,6 +126,17 @@ pub fn nextclade_run(mut run_args: NextcladeRunArgs) -> Result<(), Report> {
           if record.is_empty() {
             break;
           }
+          if let Some(resume_state) = resume_state {
+            if resume_state.check_completed(record.index, &record.seq_name)? {
+              continue;
+            }
+          }
+          if selection
+            .as_ref()
+            .is_some_and(|selection| !selection.contains(&record.index))
+          {
+            continue;
+          }
           fasta_sender.send(record).wrap_err("When sending a FastaRecord")?;
         }
         Ok::<_, Report>(())
@@ -138,29 +193,44 @@ pub fn nextclade_run(mut run_args: NextcladeRunArgs) -> Result<(), Report> {
           clade_node_attr_key_descs,
           phenotype_attr_descs,
           aa_motif_keys,
+          nuc_motif_keys,
+          qc_custom_rule_keys,
           ref_nodes,
           ..
         } = nextclade.get_initial_data();
 
         let mut output_writer = NextcladeOrderedWriter::new(
+          &nextclade.ref_record,
+          &nextclade.ref_seq,
+          nextclade.graph.is_some(),
           &nextclade.gene_map,
           &clade_node_attr_key_descs,
           &phenotype_attr_descs,
           &ref_nodes,
           &aa_motif_keys,
+          &nuc_motif_keys,
+          &qc_custom_rule_keys,
           &csv_column_config,
           &run_args.outputs,
           &nextclade.params,
+          resume,
         )
         .wrap_err("When creating output writer")?;
 
+        if let Some(selection) = selection {
+          output_writer.set_selection(Arc::clone(selection));
+        }
+
         if nextclade.params.general.include_reference {
           output_writer
             .write_ref(&nextclade.ref_record, &nextclade.ref_translation)
             .wrap_err("When writing output record for ref sequence")?;
         }
 
+        output_writer.checkpoint()?;
+
         for record in result_receiver {
+          on_record(&record);
           if should_write_tree {
             if let Ok(AnalysisOutput { analysis_result, .. }) = &record.outputs_or_err {
               outputs.push(analysis_result.clone());
@@ -190,6 +260,13 @@ pub fn nextclade_run(mut run_args: NextcladeRunArgs) -> Result<(), Report> {
       ref_seq, params, graph, ..
     } = nextclade;
     if let Some(mut graph) = graph {
+      // Placements are reported against the reference tree, so they need to be written before the tree is modified
+      if let Some(output_jplace) = run_args.outputs.output_jplace {
+        let invocation = std::env::args().join(" ");
+        let jplace = jplace_from_results(&graph, &outputs, ref_seq.len(), &params.tree_builder, invocation)?;
+        jplace_write_to_file(output_jplace, &jplace)?;
+      }
+
       graph_attach_new_nodes_in_place(&mut graph, outputs, ref_seq.len(), &params.tree_builder)?;
 
       if let Some(output_tree) = run_args.outputs.output_tree {
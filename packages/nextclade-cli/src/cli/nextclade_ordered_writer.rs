@@ -1,18 +1,24 @@
 use crate::cli::nextclade_cli::NextcladeRunOutputArgs;
+use crate::cli::nextclade_journal::{NextcladeResume, JOURNAL_CHECKPOINT_INTERVAL};
 use crate::cli::nextclade_loop::NextcladeRecord;
 use eyre::{Report, WrapErr};
 use itertools::Itertools;
 use log::{info, warn};
-use nextclade::alphabet::nuc::from_nuc_seq;
+use nextclade::alphabet::nuc::{from_nuc_seq, Nuc};
 use nextclade::analyze::virus_properties::PhenotypeAttrDesc;
 use nextclade::gene::gene_map::GeneMap;
 use nextclade::io::fasta::{FastaPeptideWriter, FastaRecord, FastaWriter};
+use nextclade::io::file::OutputFileMode;
 use nextclade::io::genbank_tbl::GenbankTblFileWriter;
 use nextclade::io::gff3_writer::Gff3FileWriter;
+use nextclade::io::mutational_spectrum_tsv::MutationalSpectrumTsvFileWriter;
 use nextclade::io::ndjson::NdjsonFileWriter;
-use nextclade::io::nextclade_csv::NextcladeResultsCsvFileWriter;
+use nextclade::io::nextclade_csv::{prepare_headers, NextcladeResultsCsvFileWriter};
 use nextclade::io::nextclade_csv_column_config::CsvColumnConfig;
+use nextclade::io::nextclade_parquet::NextcladeResultsParquetFileWriter;
 use nextclade::io::results_json::ResultsJsonWriter;
+use nextclade::io::sam_writer::SamWriter;
+use nextclade::io::vcf_writer::VcfFileWriter;
 use nextclade::run::nextclade_wasm::AnalysisOutput;
 use nextclade::run::params::NextcladeInputParams;
 use nextclade::translate::translate_genes::Translation;
@@ -20,7 +26,9 @@ use nextclade::tree::tree::{AuspiceRefNodesDesc, CladeNodeAttrKeyDesc};
 use nextclade::types::outputs::NextcladeOutputs;
 use nextclade::utils::error::report_to_string;
 use nextclade::utils::option::OptionMapRefFallible;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
+use std::path::PathBuf;
+use std::sync::Arc;
 
 /// Writes output files, potentially preserving the initial order of records (same as in the inputs)
 pub struct NextcladeOrderedWriter {
@@ -30,35 +38,59 @@ pub struct NextcladeOrderedWriter {
   output_ndjson_writer: Option<NdjsonFileWriter>,
   output_csv_writer: Option<NextcladeResultsCsvFileWriter>,
   output_tsv_writer: Option<NextcladeResultsCsvFileWriter>,
+  output_parquet_writer: Option<NextcladeResultsParquetFileWriter>,
+  output_vcf_writer: Option<VcfFileWriter>,
+  output_sam_writer: Option<SamWriter>,
+  output_mutational_spectrum_writer: Option<MutationalSpectrumTsvFileWriter>,
   output_gff_writer: Option<Gff3FileWriter>,
   output_tbl_writer: Option<GenbankTblFileWriter>,
   expected_index: usize,
   queue: HashMap<usize, NextcladeRecord>,
   in_order: bool,
+  resume: Option<NextcladeResume>,
+  selection: Option<Arc<BTreeSet<usize>>>,
+  output_filepaths: Vec<PathBuf>,
 }
 
 impl NextcladeOrderedWriter {
   pub fn new(
+    ref_record: &FastaRecord,
+    ref_seq: &[Nuc],
+    has_tree: bool,
     gene_map: &GeneMap,
     clade_node_attr_descs: &[CladeNodeAttrKeyDesc],
     phenotype_attr_key_desc: &[PhenotypeAttrDesc],
     ref_nodes: &AuspiceRefNodesDesc,
     aa_motifs_keys: &[String],
+    nuc_motifs_keys: &[String],
+    qc_custom_rule_keys: &[String],
     csv_column_config: &CsvColumnConfig,
     output_params: &NextcladeRunOutputArgs,
     params: &NextcladeInputParams,
+    resume: Option<NextcladeResume>,
   ) -> Result<Self, Report> {
-    let fasta_writer = output_params.output_fasta.map_ref_fallible(FastaWriter::from_path)?;
+    // When resuming an interrupted run, results are appended to the existing output files
+    let mode = if resume.as_ref().is_some_and(NextcladeResume::is_resumed) {
+      OutputFileMode::Append
+    } else {
+      OutputFileMode::Create
+    };
+
+    let fasta_writer = output_params
+      .output_fasta
+      .map_ref_fallible(|output_fasta| FastaWriter::from_path_with_mode(output_fasta, mode))?;
 
     let fasta_peptide_writer = output_params
       .output_translations
-      .map_ref_fallible(|output_translations| FastaPeptideWriter::new(gene_map, output_translations))?;
+      .map_ref_fallible(|output_translations| FastaPeptideWriter::with_mode(gene_map, output_translations, mode))?;
 
     let output_json_writer = output_params.output_json.map_ref_fallible(|output_json| {
       ResultsJsonWriter::new(output_json, clade_node_attr_descs, phenotype_attr_key_desc, ref_nodes)
     })?;
 
-    let output_ndjson_writer = output_params.output_ndjson.map_ref_fallible(NdjsonFileWriter::new)?;
+    let output_ndjson_writer = output_params
+      .output_ndjson
+      .map_ref_fallible(|output_ndjson| NdjsonFileWriter::with_mode(output_ndjson, mode))?;
 
     let phenotype_attr_keys = phenotype_attr_key_desc
       .iter()
@@ -66,29 +98,69 @@ impl NextcladeOrderedWriter {
       .collect_vec();
 
     let output_csv_writer = output_params.output_csv.map_ref_fallible(|output_csv| {
-      NextcladeResultsCsvFileWriter::new(
+      NextcladeResultsCsvFileWriter::with_mode(
         output_csv,
         b';',
         clade_node_attr_descs,
         &phenotype_attr_keys,
         ref_nodes,
         aa_motifs_keys,
+        nuc_motifs_keys,
+        qc_custom_rule_keys,
         csv_column_config,
+        mode,
       )
     })?;
 
     let output_tsv_writer = output_params.output_tsv.map_ref_fallible(|output_tsv| {
-      NextcladeResultsCsvFileWriter::new(
+      NextcladeResultsCsvFileWriter::with_mode(
         output_tsv,
         b'\t',
         clade_node_attr_descs,
         &phenotype_attr_keys,
         ref_nodes,
         aa_motifs_keys,
+        nuc_motifs_keys,
+        qc_custom_rule_keys,
+        csv_column_config,
+        mode,
+      )
+    })?;
+
+    let output_parquet_writer = output_params.output_parquet.map_ref_fallible(|output_parquet| {
+      let headers = prepare_headers(
+        clade_node_attr_descs,
+        &phenotype_attr_keys,
+        ref_nodes,
+        aa_motifs_keys,
+        nuc_motifs_keys,
+        qc_custom_rule_keys,
         csv_column_config,
+      );
+      NextcladeResultsParquetFileWriter::new(
+        output_parquet,
+        &headers,
+        &phenotype_attr_keys,
+        aa_motifs_keys,
+        nuc_motifs_keys,
       )
     })?;
 
+    let output_vcf_writer = output_params
+      .output_vcf
+      .map_ref_fallible(|output_vcf| VcfFileWriter::new(output_vcf, &ref_record.seq_name, ref_seq, has_tree))?;
+
+    let output_sam_writer = output_params
+      .output_sam
+      .map_ref_fallible(|output_sam| SamWriter::with_mode(output_sam, &ref_record.seq_name, ref_seq.len(), mode))?;
+
+    let output_mutational_spectrum_writer =
+      output_params
+        .output_mutational_spectrum
+        .map_ref_fallible(|output_mutational_spectrum| {
+          MutationalSpectrumTsvFileWriter::with_mode(output_mutational_spectrum, mode)
+        })?;
+
     let output_gff_writer = output_params
       .output_annotation_gff
       .map_ref_fallible(Gff3FileWriter::new)?;
@@ -97,22 +169,55 @@ impl NextcladeOrderedWriter {
       .output_annotation_tbl
       .map_ref_fallible(GenbankTblFileWriter::new)?;
 
-    Ok(Self {
+    // Files which are resumable and which sizes are recorded in the journal checkpoints
+    let output_filepaths = [
+      &output_params.output_fasta,
+      &output_params.output_ndjson,
+      &output_params.output_csv,
+      &output_params.output_tsv,
+      &output_params.output_sam,
+      &output_params.output_mutational_spectrum,
+    ]
+    .into_iter()
+    .flatten()
+    .cloned()
+    .chain(
+      fasta_peptide_writer
+        .iter()
+        .flat_map(|writer| writer.filepaths().iter().cloned()),
+    )
+    .collect_vec();
+
+    let mut this = Self {
       fasta_writer,
       fasta_peptide_writer,
       output_json_writer,
       output_ndjson_writer,
       output_csv_writer,
       output_tsv_writer,
+      output_parquet_writer,
+      output_vcf_writer,
+      output_sam_writer,
+      output_mutational_spectrum_writer,
       output_tbl_writer,
       output_gff_writer,
       expected_index: 0,
       queue: HashMap::<usize, NextcladeRecord>::new(),
       in_order: params.general.in_order,
-    })
+      resume,
+      selection: None,
+      output_filepaths,
+    };
+    this.skip_absent_records();
+    Ok(this)
   }
 
   pub fn write_ref(&mut self, ref_record: &FastaRecord, ref_translation: &Translation) -> Result<(), Report> {
+    if self.resume.as_ref().is_some_and(NextcladeResume::is_resumed) {
+      // Reference is already written into the output files by the interrupted run
+      return Ok(());
+    }
+
     let FastaRecord { seq_name, seq, .. } = &ref_record;
 
     if let Some(fasta_writer) = &mut self.fasta_writer {
@@ -171,6 +276,22 @@ impl NextcladeOrderedWriter {
           output_tsv_writer.write(&analysis_result)?;
         }
 
+        if let Some(output_parquet_writer) = &mut self.output_parquet_writer {
+          output_parquet_writer.write(&analysis_result)?;
+        }
+
+        if let Some(output_vcf_writer) = &mut self.output_vcf_writer {
+          output_vcf_writer.write(&analysis_result);
+        }
+
+        if let Some(output_sam_writer) = &mut self.output_sam_writer {
+          output_sam_writer.write(&query, &analysis_result)?;
+        }
+
+        if let Some(output_mutational_spectrum_writer) = &mut self.output_mutational_spectrum_writer {
+          output_mutational_spectrum_writer.write(&analysis_result)?;
+        }
+
         if let Some(output_ndjson_writer) = &mut self.output_ndjson_writer {
           output_ndjson_writer.write(&analysis_result)?;
         }
@@ -203,6 +324,15 @@ impl NextcladeOrderedWriter {
         if let Some(output_tsv_writer) = &mut self.output_tsv_writer {
           output_tsv_writer.write_nuc_error(index, &seq_name, &cause)?;
         }
+        if let Some(output_parquet_writer) = &mut self.output_parquet_writer {
+          output_parquet_writer.write_nuc_error(index, &seq_name, &[cause.clone()])?;
+        }
+        if let Some(output_vcf_writer) = &mut self.output_vcf_writer {
+          output_vcf_writer.write_nuc_error(index, &seq_name);
+        }
+        if let Some(output_sam_writer) = &mut self.output_sam_writer {
+          output_sam_writer.write_nuc_error(&seq_name)?;
+        }
         if let Some(output_ndjson_writer) = &mut self.output_ndjson_writer {
           output_ndjson_writer.write_nuc_error(index, &seq_name, &[cause.clone()])?;
         }
@@ -212,15 +342,76 @@ impl NextcladeOrderedWriter {
       }
     }
 
+    if let Some(resume) = &mut self.resume {
+      resume.journal.add(index, &seq_name);
+      if resume.journal.pending() >= JOURNAL_CHECKPOINT_INTERVAL {
+        self.checkpoint()?;
+      }
+    }
+
+    Ok(())
+  }
+
+  /// In resumable mode, flushes output files and records a checkpoint in the journal. Does nothing otherwise.
+  pub fn checkpoint(&mut self) -> Result<(), Report> {
+    if self.resume.is_none() {
+      return Ok(());
+    }
+
+    if let Some(fasta_writer) = &mut self.fasta_writer {
+      fasta_writer.flush()?;
+    }
+    if let Some(fasta_peptide_writer) = &mut self.fasta_peptide_writer {
+      fasta_peptide_writer.flush()?;
+    }
+    if let Some(output_ndjson_writer) = &mut self.output_ndjson_writer {
+      output_ndjson_writer.flush()?;
+    }
+    if let Some(output_csv_writer) = &mut self.output_csv_writer {
+      output_csv_writer.flush()?;
+    }
+    if let Some(output_tsv_writer) = &mut self.output_tsv_writer {
+      output_tsv_writer.flush()?;
+    }
+    if let Some(output_sam_writer) = &mut self.output_sam_writer {
+      output_sam_writer.flush()?;
+    }
+    if let Some(output_mutational_spectrum_writer) = &mut self.output_mutational_spectrum_writer {
+      output_mutational_spectrum_writer.flush()?;
+    }
+
+    if let Some(resume) = &mut self.resume {
+      resume.journal.checkpoint(&self.output_filepaths)?;
+    }
+
     Ok(())
   }
 
+  /// Restricts output to a subset of input records, given by their indices. Records outside of this subset are not
+  /// expected to arrive (e.g. because they are analyzed with a different dataset).
+  pub fn set_selection(&mut self, selection: Arc<BTreeSet<usize>>) {
+    self.selection = Some(selection);
+    self.skip_absent_records();
+  }
+
+  /// In in-order mode, advances the next expected index past the records which are never going to arrive: the ones
+  /// which have been completed by the interrupted run, and the ones which are not selected
+  fn skip_absent_records(&mut self) {
+    let state = self.resume.as_ref().map(|resume| Arc::clone(&resume.state));
+    self.expected_index = next_present_index(
+      self.expected_index,
+      |index| state.as_ref().is_some_and(|state| state.is_completed(index)),
+      self.selection.as_deref(),
+    );
+  }
+
   /// In in-order mode, writes all queued records with indices subsequent to the next expected index.
   /// On out-of-order mode, does nothing - the queue is always empty.
   fn write_queued_records(&mut self) -> Result<(), Report> {
     while let Some(record) = self.queue.remove(&self.expected_index) {
       self.write_impl(record)?;
       self.expected_index += 1;
+      self.skip_absent_records();
     }
     Ok(())
   }
@@ -243,6 +434,7 @@ impl NextcladeOrderedWriter {
         // If the record has next expected index, write it immediately
         self.write_impl(record)?;
         self.expected_index += 1;
+        self.skip_absent_records();
       } else {
         // If the record has an unexpected index, queue it to write later
         self.queue.insert(record.index, record);
@@ -260,12 +452,87 @@ impl NextcladeOrderedWriter {
     if let Some(output_json_writer) = &mut self.output_json_writer {
       output_json_writer.finish()?;
     }
+    if let Some(output_parquet_writer) = self.output_parquet_writer.take() {
+      output_parquet_writer.finish()?;
+    }
+    if let Some(mut output_vcf_writer) = self.output_vcf_writer.take() {
+      output_vcf_writer.finish()?;
+    }
+    if let Some(output_sam_writer) = &mut self.output_sam_writer {
+      output_sam_writer.flush()?;
+    }
+    self.checkpoint()?;
     Ok(())
   }
 }
 
+/// Finds the first index, starting from the given one, of a record which is going to arrive: the one which is not
+/// completed and, if there is a selection, which is selected. Skipping over one kind of absent records can land on the
+/// other kind, so both are skipped repeatedly, until the index no longer changes.
+fn next_present_index(
+  mut index: usize,
+  is_completed: impl Fn(usize) -> bool,
+  selection: Option<&BTreeSet<usize>>,
+) -> usize {
+  loop {
+    let start = index;
+    while is_completed(index) {
+      index += 1;
+    }
+    if let Some(selection) = selection {
+      if let Some(&next_index) = selection.range(index..).next() {
+        index = next_index;
+      }
+    }
+    if index == start {
+      return index;
+    }
+  }
+}
+
 impl Drop for NextcladeOrderedWriter {
   fn drop(&mut self) {
     self.finish().wrap_err("When finalizing output writer").unwrap();
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use pretty_assertions::assert_eq;
+
+  #[test]
+  fn skips_completed_records() {
+    let completed = BTreeSet::from([0, 1, 3]);
+    let is_completed = |index| completed.contains(&index);
+    assert_eq!(next_present_index(0, is_completed, None), 2);
+    assert_eq!(next_present_index(3, is_completed, None), 4);
+  }
+
+  #[test]
+  fn skips_unselected_records() {
+    let selection = BTreeSet::from([2, 5, 6]);
+    assert_eq!(next_present_index(0, |_| false, Some(&selection)), 2);
+    assert_eq!(next_present_index(3, |_| false, Some(&selection)), 5);
+  }
+
+  #[test]
+  fn skips_completed_records_within_selection() {
+    // Resumed run, where selected records 2 and 5 are completed, and the next selected record after each of them
+    // is completed as well
+    let completed = BTreeSet::from([0, 1, 2, 5, 6]);
+    let is_completed = |index| completed.contains(&index);
+    let selection = BTreeSet::from([2, 5, 6, 8, 9]);
+    assert_eq!(next_present_index(0, is_completed, Some(&selection)), 8);
+    assert_eq!(next_present_index(3, is_completed, Some(&selection)), 8);
+    assert_eq!(next_present_index(9, is_completed, Some(&selection)), 9);
+  }
+
+  #[test]
+  fn stops_after_last_selected_record() {
+    let completed = BTreeSet::from([4]);
+    let is_completed = |index| completed.contains(&index);
+    let selection = BTreeSet::from([1, 4]);
+    assert_eq!(next_present_index(2, is_completed, Some(&selection)), 5);
+  }
+}
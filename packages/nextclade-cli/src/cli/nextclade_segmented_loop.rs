@@ -0,0 +1,339 @@
+use crate::cli::nextclade_cli::NextcladeRunArgs;
+use eyre::{Report, WrapErr};
+use itertools::Itertools;
+use log::{info, warn};
+use nextclade::io::fasta::{FastaReader, FastaRecord};
+use nextclade::io::ndjson::NdjsonFileWriter;
+use nextclade::io::nextclade_csv::prepare_headers;
+use nextclade::io::nextclade_csv_column_config::CsvColumnConfig;
+use nextclade::io::nextclade_csv_isolates::{
+  NextcladeIsolatesCsvFileWriter, NextcladeIsolatesCsvRows, SegmentCsvHeaders,
+};
+use nextclade::run::nextclade_segmented::{NextcladeSegmentParams, NextcladeSegmented, SegmentedAnalysisOutput};
+use nextclade::run::nextclade_wasm::AnalysisInitialData;
+use nextclade::types::outputs::{NextcladeOutputOrError, NextcladeOutputs};
+use nextclade::utils::error::report_to_string;
+use nextclade::utils::option::OptionMapRefFallible;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+pub struct NextcladeSegmentedRecord {
+  pub index: usize,
+  pub seq_name: String,
+  pub outputs_or_err: Result<SegmentedAnalysisOutput, Report>,
+}
+
+/// Entry of the ndjson output in multi-segment mode: regular analysis results, annotated with segment and isolate
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct NdjsonSegmentedEntry<'a> {
+  segment: &'a str,
+  isolate: &'a str,
+  #[serde(flatten)]
+  outputs: &'a NextcladeOutputs,
+}
+
+/// Runs analysis of a multi-segment dataset.
+///
+/// Unlike the regular run, CSV and TSV results are grouped per isolate, so their rows are collected in memory and
+/// written after all sequences are processed. Other outputs are written as results arrive.
+pub fn nextclade_run_segmented(
+  run_args: &NextcladeRunArgs,
+  segments: Vec<NextcladeSegmentParams>,
+) -> Result<(), Report> {
+  let nextclade = NextcladeSegmented::new(segments, &run_args.params)?;
+
+  info!(
+    "Multi-segment dataset with segments: {}",
+    nextclade.segment_names().join(", ")
+  );
+
+  warn_unsupported_outputs(run_args);
+
+  let csv_column_config = CsvColumnConfig::new(&run_args.outputs.output_columns_selection)?;
+
+  let thread_errors: Arc<Mutex<Vec<Report>>> = Arc::new(Mutex::new(Vec::new()));
+
+  std::thread::scope(|s| {
+    const CHANNEL_SIZE: usize = 128;
+    let (fasta_sender, fasta_receiver) = crossbeam_channel::bounded::<FastaRecord>(CHANNEL_SIZE);
+    let (result_sender, result_receiver) = crossbeam_channel::bounded::<NextcladeSegmentedRecord>(CHANNEL_SIZE);
+
+    let nextclade = &nextclade;
+    let csv_column_config = &csv_column_config;
+
+    let thread_errors_cloned = Arc::clone(&thread_errors);
+    s.spawn(move || {
+      let result = (|| {
+        let mut reader = FastaReader::from_paths(&run_args.inputs.input_fastas)?;
+        loop {
+          let mut record = FastaRecord::default();
+          reader.read(&mut record)?;
+          if record.is_empty() {
+            break;
+          }
+          fasta_sender.send(record).wrap_err("When sending a FastaRecord")?;
+        }
+        Ok::<_, Report>(())
+      })();
+      if let Err(e) = result {
+        thread_errors_cloned.lock().unwrap().push(e);
+      }
+      drop(fasta_sender);
+    });
+
+    for _ in 0..run_args.other_params.jobs {
+      let fasta_receiver = fasta_receiver.clone();
+      let result_sender = result_sender.clone();
+      let thread_errors = Arc::clone(&thread_errors);
+
+      s.spawn(move || {
+        let result = (|| {
+          for fasta_record in &fasta_receiver {
+            info!("Processing sequence '{}'", fasta_record.seq_name);
+
+            let outputs_or_err = nextclade.run(&fasta_record).wrap_err_with(|| {
+              format!(
+                "When processing sequence #{} '{}'",
+                fasta_record.index, fasta_record.seq_name
+              )
+            });
+
+            result_sender
+              .send(NextcladeSegmentedRecord {
+                index: fasta_record.index,
+                seq_name: fasta_record.seq_name,
+                outputs_or_err,
+              })
+              .wrap_err("When sending NextcladeSegmentedRecord")?;
+          }
+          Ok::<_, Report>(())
+        })();
+
+        if let Err(e) = result {
+          thread_errors.lock().unwrap().push(e);
+        }
+        drop(result_sender);
+      });
+    }
+
+    drop(result_sender);
+
+    let thread_errors_cloned = Arc::clone(&thread_errors);
+    s.spawn(move || {
+      let result = (|| {
+        let mut output_writer = NextcladeSegmentedWriter::new(run_args, nextclade, csv_column_config)
+          .wrap_err("When creating output writer")?;
+
+        for record in result_receiver {
+          output_writer
+            .write_record(record)
+            .wrap_err("When writing output record")?;
+        }
+
+        output_writer.finish()
+      })();
+
+      if let Err(e) = result {
+        thread_errors_cloned.lock().unwrap().push(e);
+      }
+    });
+  });
+
+  let mut errors = Arc::try_unwrap(thread_errors).unwrap_or_default().into_inner()?;
+  if !errors.is_empty() {
+    return Err(errors.remove(0));
+  }
+
+  Ok(())
+}
+
+/// Writes outputs of a multi-segment run.
+///
+/// NDJSON entries are written as records arrive (in the order of the input, in in-order mode). Rows of the per-isolate
+/// CSV and TSV files can only be written after all sequences of the isolate are processed, so only their formatted
+/// cells are retained until the end of the run, and analysis results are dropped as soon as they are written.
+struct NextcladeSegmentedWriter<'a> {
+  nextclade: &'a NextcladeSegmented,
+  ndjson_writer: Option<NdjsonFileWriter>,
+  csv_writers: Vec<NextcladeIsolatesCsvFileWriter>,
+  csv_rows: Option<NextcladeIsolatesCsvRows>,
+  expected_index: usize,
+  queue: HashMap<usize, NextcladeSegmentedRecord>,
+  in_order: bool,
+}
+
+impl<'a> NextcladeSegmentedWriter<'a> {
+  fn new(
+    run_args: &NextcladeRunArgs,
+    nextclade: &'a NextcladeSegmented,
+    csv_column_config: &CsvColumnConfig,
+  ) -> Result<Self, Report> {
+    let ndjson_writer = run_args.outputs.output_ndjson.map_ref_fallible(NdjsonFileWriter::new)?;
+
+    let segment_headers = nextclade
+      .segments()
+      .iter()
+      .map(|segment| {
+        let AnalysisInitialData {
+          clade_node_attr_key_descs,
+          phenotype_attr_descs,
+          aa_motif_keys,
+          nuc_motif_keys,
+          qc_custom_rule_keys,
+          ref_nodes,
+          ..
+        } = segment.nextclade.get_initial_data();
+        let phenotype_attr_keys = phenotype_attr_descs.iter().map(|desc| desc.name.clone()).collect_vec();
+        let headers = prepare_headers(
+          &clade_node_attr_key_descs,
+          &phenotype_attr_keys,
+          &ref_nodes,
+          &aa_motif_keys,
+          &nuc_motif_keys,
+          &qc_custom_rule_keys,
+          csv_column_config,
+        );
+        SegmentCsvHeaders {
+          name: segment.name.clone(),
+          headers,
+        }
+      })
+      .collect_vec();
+
+    let csv_writers = [
+      (&run_args.outputs.output_csv, b';'),
+      (&run_args.outputs.output_tsv, b'\t'),
+    ]
+    .into_iter()
+    .filter_map(|(filepath, delimiter)| filepath.as_ref().map(|filepath| (filepath, delimiter)))
+    .map(|(filepath, delimiter)| NextcladeIsolatesCsvFileWriter::new(filepath, delimiter, &segment_headers))
+    .collect::<Result<Vec<_>, Report>>()?;
+
+    // Rows are only collected if there are CSV or TSV outputs to write them to
+    let csv_rows = if csv_writers.is_empty() {
+      None
+    } else {
+      Some(NextcladeIsolatesCsvRows::new(&segment_headers)?)
+    };
+
+    Ok(Self {
+      nextclade,
+      ndjson_writer,
+      csv_writers,
+      csv_rows,
+      expected_index: 0,
+      queue: HashMap::new(),
+      in_order: run_args.params.general.in_order,
+    })
+  }
+
+  /// Writes a record. In in-order mode, records which arrive before some of the preceding records are queued and
+  /// written once the preceding records are written.
+  fn write_record(&mut self, record: NextcladeSegmentedRecord) -> Result<(), Report> {
+    if !self.in_order {
+      return self.write_impl(record);
+    }
+
+    self.queue.insert(record.index, record);
+    while let Some(record) = self.queue.remove(&self.expected_index) {
+      self.write_impl(record)?;
+      self.expected_index += 1;
+    }
+    Ok(())
+  }
+
+  fn write_impl(&mut self, record: NextcladeSegmentedRecord) -> Result<(), Report> {
+    let NextcladeSegmentedRecord {
+      index,
+      seq_name,
+      outputs_or_err,
+    } = record;
+
+    let isolate_name = self.nextclade.isolate_name(&seq_name);
+    match outputs_or_err {
+      Ok(SegmentedAnalysisOutput { segment_index, output }) => {
+        if let Some(ndjson_writer) = &mut self.ndjson_writer {
+          ndjson_writer.write(&NdjsonSegmentedEntry {
+            segment: &self.nextclade.segments()[segment_index].name,
+            isolate: isolate_name,
+            outputs: &output.analysis_result,
+          })?;
+        }
+        if let Some(csv_rows) = &mut self.csv_rows {
+          let output = NextcladeOutputOrError::Outputs(Box::new(output.analysis_result));
+          csv_rows.add(isolate_name, index, Some((segment_index, &output)))?;
+        }
+      }
+      Err(report) => {
+        let cause = report_to_string(&report);
+        warn!(
+          "In sequence #{index} '{seq_name}': {cause}. Note that this sequence will not be included in the results."
+        );
+        if let Some(ndjson_writer) = &mut self.ndjson_writer {
+          ndjson_writer.write_nuc_error(index, &seq_name, &[cause])?;
+        }
+        if let Some(csv_rows) = &mut self.csv_rows {
+          csv_rows.add(isolate_name, index, None)?;
+        }
+      }
+    }
+
+    Ok(())
+  }
+
+  /// Writes the remaining queued records and the per-isolate CSV and TSV files
+  fn finish(mut self) -> Result<(), Report> {
+    let queued = self.queue.drain().sorted_by_key(|(index, _)| *index).collect_vec();
+    for (_, record) in queued {
+      self.write_impl(record)?;
+    }
+
+    if let Some(csv_rows) = &self.csv_rows {
+      for csv_writer in &mut self.csv_writers {
+        csv_writer
+          .write(csv_rows)
+          .wrap_err("When writing per-isolate results")?;
+      }
+    }
+
+    Ok(())
+  }
+}
+
+fn warn_unsupported_outputs(run_args: &NextcladeRunArgs) {
+  let outputs = &run_args.outputs;
+  let unsupported = [
+    ("--output-fasta", outputs.output_fasta.is_some()),
+    ("--output-translations", outputs.output_translations.is_some()),
+    ("--output-json", outputs.output_json.is_some()),
+    ("--output-parquet", outputs.output_parquet.is_some()),
+    ("--output-vcf", outputs.output_vcf.is_some()),
+    ("--output-sam", outputs.output_sam.is_some()),
+    (
+      "--output-mutational-spectrum",
+      outputs.output_mutational_spectrum.is_some(),
+    ),
+    ("--output-tree", outputs.output_tree.is_some()),
+    ("--output-tree-nwk", outputs.output_tree_nwk.is_some()),
+    ("--output-jplace", outputs.output_jplace.is_some()),
+    ("--output-graph", outputs.output_graph.is_some()),
+    ("--output-annotation-gff", outputs.output_annotation_gff.is_some()),
+    ("--output-annotation-tbl", outputs.output_annotation_tbl.is_some()),
+  ]
+  .into_iter()
+  .filter_map(|(arg, is_requested)| is_requested.then_some(arg))
+  .collect_vec();
+
+  if !unsupported.is_empty() {
+    warn!(
+      "Multi-segment dataset is used. The following outputs are not yet supported in this mode and will not be written: {}. Only CSV, TSV and NDJSON outputs are supported.",
+      unsupported.join(", ")
+    );
+  }
+
+  if outputs.cache_dir.is_some() {
+    warn!("Multi-segment dataset is used. Analysis cache is not yet supported in this mode. The `--cache-dir` argument will be ignored.");
+  }
+}
@@ -1,6 +1,6 @@
 use crate::cli::nextclade_cli::{NextcladeRunOtherParams, NextcladeSortArgs};
 use crate::dataset::dataset_download::download_datasets_index_json;
-use crate::io::http_client::HttpClient;
+use crate::io::http_client::{HttpClient, ProxyConfig};
 use console::style;
 use eyre::{Report, WrapErr};
 use itertools::Itertools;
@@ -10,7 +10,8 @@ use nextclade::io::csv::CsvStructFileWriter;
 use nextclade::io::fasta::{FastaReader, FastaRecord, FastaWriter};
 use nextclade::io::fs::path_to_string;
 use nextclade::make_error;
-use nextclade::sort::minimizer_index::{MinimizerIndexJson, MINIMIZER_INDEX_ALGO_VERSION};
+use nextclade::sort::minimizer_index::MINIMIZER_INDEX_ALGO_VERSION;
+use nextclade::sort::minimizer_index_binary::MinimizerIndexBinary;
 use nextclade::sort::minimizer_search::{
   find_best_datasets, find_best_suggestion_for_seq, run_minimizer_search, MinimizerSearchDatasetResult,
   MinimizerSearchRecord,
@@ -25,6 +26,7 @@ use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use tinytemplate::TinyTemplate;
+use url::Url;
 
 pub fn nextclade_seq_sort(args: &NextcladeSortArgs) -> Result<(), Report> {
   check_args(args)?;
@@ -38,10 +40,28 @@ pub fn nextclade_seq_sort(args: &NextcladeSortArgs) -> Result<(), Report> {
 
   let verbose = log::max_level() >= LevelFilter::Info;
 
-  let (minimizer_index, ref_names) = if let Some(input_minimizer_index_json) = &input_minimizer_index_json {
+  let (minimizer_index, ref_names) =
+    minimizer_index_load(input_minimizer_index_json.as_deref(), server, proxy_config, verbose)?;
+
+  run(args, &ref_names, &minimizer_index, verbose)
+}
+
+/// Loads minimizer index from a file, if provided, or otherwise from the dataset server. Returns the index and the
+/// names of the datasets, in the order of preference.
+pub fn minimizer_index_load(
+  input_minimizer_index_json: Option<&Path>,
+  server: &Url,
+  proxy_config: &ProxyConfig,
+  verbose: bool,
+) -> Result<(MinimizerIndexBinary, Vec<String>), Report> {
+  if let Some(input_minimizer_index_json) = input_minimizer_index_json {
     // If a file is provided, use data from it
-    let minimizer_index = MinimizerIndexJson::from_path(input_minimizer_index_json)?;
-    let ref_names = minimizer_index.references.iter().map(|r| r.name.clone()).collect_vec();
+    let minimizer_index = MinimizerIndexBinary::from_path(input_minimizer_index_json)?;
+    let ref_names = minimizer_index
+      .references()
+      .iter()
+      .map(|r| r.name.clone())
+      .collect_vec();
     Ok((minimizer_index, ref_names))
   } else {
     // Otherwise fetch from dataset server
@@ -54,8 +74,8 @@ pub fn nextclade_seq_sort(args: &NextcladeSortArgs) -> Result<(), Report> {
       .map(|minimizer_index| &minimizer_index.path);
 
     if let Some(minimizer_index_path) = minimizer_index_path {
-      let minimizer_index_str = http.get(minimizer_index_path)?;
-      let minimizer_index = MinimizerIndexJson::from_str(String::from_utf8(minimizer_index_str)?)?;
+      let minimizer_index_bytes = http.get(minimizer_index_path)?;
+      let minimizer_index = MinimizerIndexBinary::from_bytes(minimizer_index_bytes)?;
       let ref_names = index
         .collections
         .iter()
@@ -76,15 +96,13 @@ pub fn nextclade_seq_sort(args: &NextcladeSortArgs) -> Result<(), Report> {
 
       make_error!("No compatible reference minimizer index data is found for this dataset sever. Cannot proceed. \n\nThis version of Nextclade supports index versions up to '{}', but the server has {}.\n\nTry to to upgrade Nextclade to the latest version and/or contact dataset server maintainers.", MINIMIZER_INDEX_ALGO_VERSION, server_versions)
     }
-  }?;
-
-  run(args, &ref_names, &minimizer_index, verbose)
+  }
 }
 
 pub fn run(
   args: &NextcladeSortArgs,
   ref_names: &[String],
-  minimizer_index: &MinimizerIndexJson,
+  minimizer_index: &MinimizerIndexBinary,
   verbose: bool,
 ) -> Result<(), Report> {
   let NextcladeSortArgs {
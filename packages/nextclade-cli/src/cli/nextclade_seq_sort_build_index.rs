@@ -0,0 +1,89 @@
+use crate::cli::nextclade_cli::NextcladeSortBuildIndexArgs;
+use eyre::{Report, WrapErr};
+use itertools::Itertools;
+use log::info;
+use nextclade::analyze::virus_properties::VirusProperties;
+use nextclade::io::fasta::{parse_fasta_header, read_one_fasta_from_file, FastaRecord};
+use nextclade::io::json::{json_write, JsonPretty};
+use nextclade::make_error;
+use nextclade::sort::minimizer_index::{MinimizerIndexJson, MinimizerIndexParams};
+use nextclade::sort::minimizer_index_binary::MinimizerIndexBinary;
+use std::path::Path;
+
+pub fn nextclade_seq_sort_build_index(args: &NextcladeSortBuildIndexArgs) -> Result<(), Report> {
+  let NextcladeSortBuildIndexArgs {
+    inputs,
+    input_minimizer_index,
+    output,
+    binary,
+    kmer_size,
+    cutoff,
+  } = args;
+
+  let index = if let Some(input_minimizer_index) = input_minimizer_index {
+    MinimizerIndexBinary::from_path(input_minimizer_index)?.to_json()
+  } else {
+    build_index(inputs, *kmer_size, *cutoff)?
+  };
+
+  if *binary {
+    MinimizerIndexBinary::from_json(&index)?.write(output)
+  } else {
+    json_write(output, &index, JsonPretty(false))
+      .wrap_err_with(|| format!("When writing minimizer index to {output:#?}"))
+  }
+}
+
+fn build_index(inputs: &[String], kmer_size: i64, cutoff: i64) -> Result<MinimizerIndexJson, Report> {
+  let references = inputs
+    .iter()
+    .map(|input| read_index_input(input).wrap_err_with(|| format!("When reading minimizer index input '{input}'")))
+    .collect::<Result<Vec<(String, FastaRecord)>, Report>>()?;
+
+  if let Some(name) = references.iter().map(|(name, _)| name).duplicates().next() {
+    return make_error!("Dataset names in minimizer index are required to be unique, but found duplicate name '{name}'. Use `NAME=PATH` syntax to set the names explicitly.");
+  }
+
+  let params = MinimizerIndexParams {
+    k: kmer_size,
+    cutoff,
+    ..MinimizerIndexParams::default()
+  };
+
+  let index = MinimizerIndexJson::build(
+    references.iter().map(|(name, reference)| (name.as_str(), reference)),
+    params,
+  )?;
+
+  for reference in &index.references {
+    info!(
+      "Added '{}' to minimizer index: length {}, {} minimizers",
+      reference.name, reference.length, reference.n_minimizers
+    );
+  }
+
+  Ok(index)
+}
+
+/// Reads reference sequence and its dataset name from an input, which is either a dataset directory or a FASTA file,
+/// optionally prefixed with the name: `NAME=PATH`.
+fn read_index_input(input: &str) -> Result<(String, FastaRecord), Report> {
+  let (name, path) = match input.split_once('=') {
+    Some((name, path)) if !Path::new(input).exists() => (Some(name.to_owned()), Path::new(path)),
+    _ => (None, Path::new(input)),
+  };
+
+  if path.is_dir() {
+    let virus_properties = VirusProperties::from_path(path.join("pathogen.json"))?;
+    let Some(reference) = &virus_properties.files.reference else {
+      return make_error!("The dataset's pathogen.json does not declare a reference sequence in its `.files` section");
+    };
+    let reference = read_one_fasta_from_file(path.join(reference))?;
+    let name = name.unwrap_or_else(|| path.to_string_lossy().trim_end_matches('/').to_owned());
+    Ok((name, reference))
+  } else {
+    let reference = read_one_fasta_from_file(path)?;
+    let name = name.unwrap_or_else(|| parse_fasta_header(&reference.seq_name).0);
+    Ok((name, reference))
+  }
+}
@@ -11,6 +11,7 @@ use nextclade::io::dataset::{Dataset, DatasetsIndexJson};
 use nextclade::io::fasta::{read_one_fasta_from_file, read_one_fasta_from_str};
 use nextclade::io::file::create_file_or_stdout;
 use nextclade::io::fs::{ensure_dir, has_extension, read_file_to_string};
+use nextclade::run::nextclade_segmented::NextcladeSegmentParams;
 use nextclade::run::nextclade_wasm::{NextcladeParams, NextcladeParamsOptional};
 use nextclade::tree::tree::{check_ref_seq_mismatch, AuspiceTree};
 use nextclade::utils::fs::list_files_recursive;
@@ -52,6 +53,108 @@ pub fn nextclade_get_inputs(
   }
 }
 
+/// Loads inputs for each segment of a multi-segment dataset. Returns `None` if the dataset is not segmented (its
+/// pathogen.json does not declare any `segments`), or if the dataset is not provided as a local directory or zip file.
+pub fn nextclade_get_segment_inputs(
+  run_args: &NextcladeRunArgs,
+  cdses: &Option<Vec<String>>,
+) -> Result<Option<Vec<NextcladeSegmentParams>>, Report> {
+  let Some(input_dataset) = run_args.inputs.input_dataset.as_ref() else {
+    return Ok(None);
+  };
+
+  if input_dataset.is_file() && has_extension(input_dataset, "zip") {
+    let file = File::open(input_dataset)?;
+    let mut zip = ZipArchive::new(BufReader::new(file))?;
+    dataset_segments_load(run_args, input_dataset, cdses, |filename| {
+      zip_read_str(&mut zip, filename)
+    })
+    .wrap_err_with(|| format!("When loading multi-segment dataset from {input_dataset:#?}"))
+  } else if input_dataset.is_dir() {
+    dataset_segments_load(run_args, input_dataset, cdses, |filename| {
+      read_file_to_string(input_dataset.join(filename))
+    })
+    .wrap_err_with(|| format!("When loading multi-segment dataset from {input_dataset:#?}"))
+  } else {
+    Ok(None)
+  }
+}
+
+fn dataset_segments_load(
+  run_args: &NextcladeRunArgs,
+  dataset_path: &Path,
+  cdses: &Option<Vec<String>>,
+  mut read_file: impl FnMut(&str) -> Result<String, Report>,
+) -> Result<Option<Vec<NextcladeSegmentParams>>, Report> {
+  let virus_properties = match &run_args.inputs.input_pathogen_json {
+    Some(input_pathogen_json) => VirusProperties::from_path(input_pathogen_json)?,
+    None => {
+      VirusProperties::from_str(&read_file("pathogen.json")?).wrap_err("When reading pathogen JSON from dataset")?
+    }
+  };
+
+  if virus_properties.segments.is_empty() {
+    return Ok(None);
+  }
+
+  if run_args.inputs.input_ref.is_some()
+    || run_args.inputs.input_annotation.is_some()
+    || run_args.inputs.input_tree.is_some()
+  {
+    warn!(
+      "Multi-segment dataset is used. Arguments `--input-ref`, `--input-annotation` and `--input-tree` are ignored, \
+      because each segment has its own reference sequence, genome annotation and reference tree"
+    );
+  }
+
+  let segments = virus_properties
+    .segments
+    .iter()
+    .map(|segment| {
+      let name = &segment.name;
+
+      let reference = segment.files.reference.as_ref().wrap_err_with(|| {
+        format!("Reference sequence is required, but it is not declared in the `files` section of segment '{name}'")
+      })?;
+      let ref_record = read_one_fasta_from_str(read_file(reference)?)
+        .wrap_err_with(|| format!("When reading reference sequence of segment '{name}'"))?;
+
+      let gene_map = segment
+        .files
+        .genome_annotation
+        .map_ref_fallible(|genome_annotation| GeneMap::from_str(read_file(genome_annotation)?))
+        .wrap_err_with(|| format!("When reading genome annotation of segment '{name}'"))?
+        .map(|gene_map| filter_gene_map(gene_map, cdses))
+        .unwrap_or_default();
+
+      let tree = segment
+        .files
+        .tree_json
+        .map_ref_fallible(|tree_json| AuspiceTree::from_str(read_file(tree_json)?))
+        .wrap_err_with(|| format!("When reading reference tree JSON of segment '{name}'"))?;
+
+      if let Some(tree) = &tree {
+        if let Some(tree_ref) = tree.root_sequence() {
+          check_ref_seq_mismatch(&ref_record.seq, tree_ref).wrap_err_with(|| format!("In segment '{name}'"))?;
+        }
+      }
+
+      Ok(NextcladeSegmentParams {
+        name: name.clone(),
+        params: NextcladeParams {
+          dataset_name: format!("{}/{name}", dataset_path.to_string_lossy()),
+          ref_record,
+          gene_map,
+          tree,
+          virus_properties: virus_properties.clone(),
+        },
+      })
+    })
+    .collect::<Result<Vec<_>, Report>>()?;
+
+  Ok(Some(segments))
+}
+
 #[inline]
 pub fn download_datasets_index_json(http: &HttpClient) -> Result<DatasetsIndexJson, Report> {
   let data_bytes = http.get("/index.json")?;
@@ -87,9 +190,9 @@ pub fn zip_read_str<R: Read + Seek>(zip: &mut ZipArchive<R>, name: impl AsRef<st
   Ok(s)
 }
 
-pub fn read_from_path_or_zip(
+pub fn read_from_path_or_zip<R: Read + Seek>(
   filepath: &Option<impl AsRef<Path>>,
-  zip: &mut ZipArchive<BufReader<File>>,
+  zip: &mut ZipArchive<R>,
   zip_filename: &Option<impl AsRef<str>>,
 ) -> Result<Option<String>, Report> {
   if let Some(filepath) = filepath {
@@ -116,20 +219,39 @@ pub fn dataset_zip_load(
   let file = File::open(dataset_zip)?;
   let buf_file = BufReader::new(file);
   let mut zip = ZipArchive::new(buf_file)?;
+  dataset_zip_archive_load(run_args, &mut zip, dataset_zip.to_str().unwrap(), cdses)
+}
+
+/// Loads dataset from contents of a dataset zip archive (e.g. fetched from dataset server with `dataset_zip_fetch()`)
+pub fn dataset_zip_bytes_load(
+  run_args: &NextcladeRunArgs,
+  dataset_zip: Vec<u8>,
+  dataset_name: &str,
+  cdses: &Option<Vec<String>>,
+) -> Result<NextcladeParams, Report> {
+  let mut zip = ZipArchive::new(Cursor::new(dataset_zip))?;
+  dataset_zip_archive_load(run_args, &mut zip, dataset_name, cdses)
+}
 
-  let virus_properties = read_from_path_or_zip(&run_args.inputs.input_pathogen_json, &mut zip, &Some("pathogen.json"))?
+fn dataset_zip_archive_load<R: Read + Seek>(
+  run_args: &NextcladeRunArgs,
+  zip: &mut ZipArchive<R>,
+  dataset_name: &str,
+  cdses: &Option<Vec<String>>,
+) -> Result<NextcladeParams, Report> {
+  let virus_properties = read_from_path_or_zip(&run_args.inputs.input_pathogen_json, zip, &Some("pathogen.json"))?
     .map_ref_fallible(VirusProperties::from_str)
     .wrap_err("When reading pathogen JSON from dataset")?
     .ok_or_else(|| eyre!("Pathogen JSON must always be present in the dataset but not found."))?;
 
-  let ref_record = read_from_path_or_zip(&run_args.inputs.input_ref, &mut zip, &virus_properties.files.reference)?
+  let ref_record = read_from_path_or_zip(&run_args.inputs.input_ref, zip, &virus_properties.files.reference)?
     .map_ref_fallible(read_one_fasta_from_str)
     .wrap_err("When reading reference sequence from dataset")?
     .ok_or_else(|| eyre!("Reference sequence must always be present in the dataset but not found."))?;
 
   let gene_map = read_from_path_or_zip(
     &run_args.inputs.input_annotation,
-    &mut zip,
+    zip,
     &virus_properties.files.genome_annotation,
   )?
   .map_ref_fallible(GeneMap::from_str)
@@ -137,7 +259,7 @@ pub fn dataset_zip_load(
   .map(|gene_map| filter_gene_map(gene_map, cdses))
   .unwrap_or_default();
 
-  let tree = read_from_path_or_zip(&run_args.inputs.input_tree, &mut zip, &virus_properties.files.tree_json)?
+  let tree = read_from_path_or_zip(&run_args.inputs.input_tree, zip, &virus_properties.files.tree_json)?
     .map_ref_fallible(AuspiceTree::from_str)
     .wrap_err("When reading reference tree JSON from dataset")?;
 
@@ -150,7 +272,7 @@ pub fn dataset_zip_load(
   }
 
   Ok(NextcladeParams {
-    dataset_name: dataset_zip.to_str().unwrap().to_owned(),
+    dataset_name: dataset_name.to_owned(),
     ref_record,
     gene_map,
     tree,
@@ -346,6 +346,7 @@ export function CsvColumnConfigCategory({ category, columns }: CsvColumnConfigCa
           'priv-muts': t('Mutations relative to nearest node (private mutations)'),
           'qc': t('Quality control'),
           'primers': t('PCR primers'),
+          'alignment-diagnostics': t('Alignment diagnostics'),
           'errs-warns': t('Errors & warnings'),
         },
         category,
@@ -8,6 +8,7 @@ const statusColors = {
   good: '#68b844',
   mediocre: '#e4902f',
   bad: '#da4e3c',
+  fail: '#8b1a1a',
 }
 
 export const CircleBase = styled.div<{ color: string }>`
@@ -1,4 +1,5 @@
 import React, { useCallback, useState } from 'react'
+import { round } from 'lodash'
 
 import type { AnalysisResult } from 'src/types'
 import { getSafeId } from 'src/helpers/getSafeId'
@@ -13,18 +14,33 @@ export function ColumnClade({ analysisResult }: ColumnCladeProps) {
   const { t } = useTranslation()
   const [showTooltip, setShowTooltip] = useState(false)
 
-  const { clade, seqName, index } = analysisResult
+  const { clade, seqName, index, placementUncertainty } = analysisResult
   const id = getSafeId('col-clade', { index, seqName })
   const cladeText = clade ?? ''
+  const isAmbiguous = placementUncertainty?.isAmbiguous ?? false
 
   const onMouseEnter = useCallback(() => setShowTooltip(true), [])
   const onMouseLeave = useCallback(() => setShowTooltip(false), [])
 
   return (
     <div id={id} className="w-100" onMouseEnter={onMouseEnter} onMouseLeave={onMouseLeave}>
-      {cladeText}
+      {isAmbiguous ? `${cladeText}?` : cladeText}
       <Tooltip id={id} isOpen={showTooltip} target={id}>
         <div>{t('Clade: {{cladeText}}', { cladeText })}</div>
+        {placementUncertainty && (
+          <>
+            <div>
+              {t('Clade assignment confidence: {{confidence}}%', {
+                confidence: round(placementUncertainty.cladeConfidence * 100, 1),
+              })}
+            </div>
+            {placementUncertainty.alternativeClades.map(({ clade: altClade, support }) => (
+              <div key={altClade}>
+                {t('Alternative clade: {{altClade}} ({{support}}%)', { altClade, support: round(support * 100, 1) })}
+              </div>
+            ))}
+          </>
+        )}
       </Tooltip>
     </div>
   )
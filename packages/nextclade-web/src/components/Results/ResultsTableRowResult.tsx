@@ -50,7 +50,7 @@ export function getQcRowColor(qcStatus: QcStatus) {
   if (qcStatus === 'mediocre') {
     return '#ffeeaa'
   }
-  if (qcStatus === 'bad') {
+  if (qcStatus === 'bad' || qcStatus === 'fail') {
     return '#eeaaaa'
   }
   return 'transparent'
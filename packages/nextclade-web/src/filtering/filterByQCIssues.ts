@@ -7,10 +7,13 @@ export function filterByQCIssues({ showGood, showMediocre, showBad, showErrors }
     const isError = !isNil(error)
     const isPending = !isError && !result
 
+    const overallStatus = result?.analysisResult?.qc?.overallStatus
+
     // The sequences which are still being processed are presumed to be 'good' until QC results come and prove otherwise
-    const isGood = isPending || result?.analysisResult?.qc?.overallStatus === 'good'
-    const isMediocre = result?.analysisResult?.qc?.overallStatus === 'mediocre'
-    const isBad = result?.analysisResult?.qc?.overallStatus === 'bad'
+    const isGood = isPending || overallStatus === 'good'
+    const isMediocre = overallStatus === 'mediocre'
+    // The optional 'fail' status is shown together with 'bad'
+    const isBad = overallStatus === 'bad' || overallStatus === 'fail'
 
     const good = showGood && isGood
     const mediocre = showMediocre && isMediocre
@@ -0,0 +1,21 @@
+import type { QcResultBandBoundary } from 'src/types'
+import type { TFunctionInterface } from 'src/helpers/TFunctionInterface'
+
+export function formatQCBandBoundary<TFunction extends TFunctionInterface>(
+  t: TFunction,
+  qcBandBoundary?: QcResultBandBoundary,
+) {
+  if (!qcBandBoundary || qcBandBoundary.status === 'good') {
+    return undefined
+  }
+
+  const { score, totalAttempts } = qcBandBoundary
+
+  return t(
+    'Alignment hit the band boundary after {{totalAttempts}} attempt(s). The alignment might be suboptimal. QC score: {{score}}',
+    {
+      totalAttempts,
+      score,
+    },
+  )
+}
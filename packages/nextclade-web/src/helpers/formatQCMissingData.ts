@@ -16,7 +16,7 @@ export function formatQCMissingData<TFunction extends TFunctionInterface>(
   const { score, totalMissing, missingDataThreshold, status } = missingData
 
   let message = t('Missing data found')
-  if (status === 'bad') {
+  if (status === 'bad' || status === 'fail') {
     message = t('Too much missing data found')
   }
 
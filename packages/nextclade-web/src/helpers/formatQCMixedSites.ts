@@ -16,7 +16,7 @@ export function formatQCMixedSites<TFunction extends TFunctionInterface>(
   const { score, totalMixedSites, mixedSitesThreshold, status } = mixedSites
 
   let message = t('Mixed sites found')
-  if (status === 'bad') {
+  if (status === 'bad' || status === 'fail') {
     message = t('Too many mixed sites found')
   }
 
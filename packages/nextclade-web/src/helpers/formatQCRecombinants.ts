@@ -0,0 +1,26 @@
+import type { QcResultRecombinants } from 'src/types'
+import type { TFunctionInterface } from 'src/helpers/TFunctionInterface'
+
+export function formatQCRecombinants<TFunction extends TFunctionInterface>(
+  t: TFunction,
+  qcRecombinants?: QcResultRecombinants,
+) {
+  if (!qcRecombinants || qcRecombinants.status === 'good') {
+    return undefined
+  }
+
+  const { score, support, parents, breakpoints } = qcRecombinants
+
+  const parentList = parents.join(', ')
+  const breakpointList = breakpoints.map(({ begin, end }) => `${begin + 1}-${end}`).join(', ')
+
+  return t(
+    'Possible recombinant of {{parentList}}, with breakpoint(s) at {{breakpointList}}. Sites explained by recombination: {{support}}. QC score: {{score}}',
+    {
+      parentList,
+      breakpointList,
+      support,
+      score,
+    },
+  )
+}
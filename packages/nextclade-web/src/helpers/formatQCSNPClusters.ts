@@ -16,7 +16,7 @@ export function formatQCSNPClusters<TFunction extends TFunctionInterface>(
   const { score, clusteredSNPs, totalSNPs, status } = snpClusters
 
   let message = t('Mutation clusters found')
-  if (status === 'bad') {
+  if (status === 'bad' || status === 'fail') {
     message = t('Too many mutation clusters found')
   }
 
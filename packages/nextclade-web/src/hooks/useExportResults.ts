@@ -15,6 +15,7 @@ import {
   cdsesAtom,
   cladeNodeAttrDescsAtom,
   csvColumnConfigAtom,
+  initialDataAtom,
   phenotypeAttrDescsAtom,
   refNodesAtom,
   treeAtom,
@@ -164,6 +165,7 @@ async function prepareResultsCsv(snapshot: Snapshot, datasetName: string, worker
   const phenotypeAttrDescs = await snapshot.getPromise(phenotypeAttrDescsAtom({ datasetName }))
   const refNodes = await snapshot.getPromise(refNodesAtom({ datasetName }))
   const aaMotifsDescs = await snapshot.getPromise(aaMotifsDescsAtom({ datasetName }))
+  const initialData = await snapshot.getPromise(initialDataAtom(datasetName))
   const csvColumnConfig = await snapshot.getPromise(csvColumnConfigAtom)
   if (!csvColumnConfig) {
     throw new ErrorInternal('CSV column config is not initialized, but it should be')
@@ -176,6 +178,8 @@ async function prepareResultsCsv(snapshot: Snapshot, datasetName: string, worker
     phenotypeAttrDescs ?? [],
     refNodes ?? {},
     aaMotifsDescs ?? [],
+    initialData?.nucMotifKeys ?? [],
+    initialData?.qcCustomRuleKeys ?? [],
     delimiter,
     csvColumnConfig,
   )
@@ -229,6 +229,8 @@ impl NextcladeWasm {
     phenotype_attrs_json_str: &str,
     ref_nodes_json_str: &str,
     aa_motifs_keys_json_str: &str,
+    nuc_motifs_keys_json_str: &str,
+    qc_custom_rule_keys_json_str: &str,
     delimiter: char,
     csv_colum_config_json_str: &str,
   ) -> Result<String, JsError> {
@@ -260,6 +262,16 @@ impl NextcladeWasm {
         .wrap_err("When serializing results into CSV: When parsing AA motifs keys JSON internally"),
     )?;
 
+    let nuc_motifs_keys: Vec<String> = jserr(
+      json_parse(nuc_motifs_keys_json_str)
+        .wrap_err("When serializing results into CSV: When parsing nucleotide motifs keys JSON internally"),
+    )?;
+
+    let qc_custom_rule_keys: Vec<String> = jserr(
+      json_parse(qc_custom_rule_keys_json_str)
+        .wrap_err("When serializing results into CSV: When parsing custom QC rule keys JSON internally"),
+    )?;
+
     let phenotype_attr_keys = phenotype_attrs.into_iter().map(|attr| attr.name).collect_vec();
     let aa_motifs_keys = aa_motifs_descs.into_iter().map(|desc| desc.name).collect_vec();
 
@@ -275,6 +287,8 @@ impl NextcladeWasm {
       &phenotype_attr_keys,
       &ref_nodes,
       &aa_motifs_keys,
+      &nuc_motifs_keys,
+      &qc_custom_rule_keys,
       delimiter as u8,
       &csv_colum_config,
     ))
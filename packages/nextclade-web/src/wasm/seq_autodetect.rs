@@ -7,6 +7,7 @@ use nextclade::io::dataset::DatasetsIndexJson;
 use nextclade::io::fasta::{FastaReader, FastaRecord};
 use nextclade::io::json::{json_parse, json_stringify, JsonPretty};
 use nextclade::sort::minimizer_index::MinimizerIndexJson;
+use nextclade::sort::minimizer_index_binary::MinimizerIndexBinary;
 use nextclade::sort::minimizer_search::{
   find_best_datasets, run_minimizer_search, MinimizerSearchRecord, MinimizerSearchResult,
 };
@@ -37,7 +38,7 @@ impl Default for NextcladeSeqAutodetectWasmParams {
 #[wasm_bindgen]
 pub struct NextcladeSeqAutodetectWasm {
   index: DatasetsIndexJson,
-  minimizer_index: MinimizerIndexJson,
+  minimizer_index: MinimizerIndexBinary,
   run_params: NextcladeSeqAutodetectWasmParams,
   search_params: NextcladeSeqSortParams,
   results: BTreeMap<usize, MinimizerSearchResult>,
@@ -52,7 +53,9 @@ impl NextcladeSeqAutodetectWasm {
   ) -> Result<NextcladeSeqAutodetectWasm, JsError> {
     let index = jserr(DatasetsIndexJson::from_str(index_json_str))?;
 
-    let minimizer_index = jserr(MinimizerIndexJson::from_str(minimizer_index_json_str))?;
+    let minimizer_index = jserr(
+      MinimizerIndexJson::from_str(minimizer_index_json_str).and_then(|index| MinimizerIndexBinary::from_json(&index)),
+    )?;
 
     let search_params = NextcladeSeqSortParams::default();
 
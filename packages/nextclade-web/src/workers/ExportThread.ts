@@ -58,6 +58,8 @@ export class ExportWorker {
     phenotypeAttrs: PhenotypeAttrDesc[],
     refNodes: AuspiceRefNodesDesc,
     aaMotifsDescs: AaMotifsDesc[],
+    nucMotifKeys: string[],
+    qcCustomRuleKeys: string[],
     delimiter: string,
     csvColumnConfig: CsvColumnConfig,
   ) {
@@ -68,6 +70,8 @@ export class ExportWorker {
       phenotypeAttrs,
       refNodes,
       aaMotifsDescs,
+      nucMotifKeys,
+      qcCustomRuleKeys,
       delimiter,
       csvColumnConfig,
     )
@@ -151,6 +151,8 @@ export async function serializeResultsCsv(
   phenotypeAttrsJson: PhenotypeAttrDesc[],
   refNodesJson: AuspiceRefNodesDesc,
   aaMotifsDescs: AaMotifsDesc[],
+  nucMotifKeys: string[],
+  qcCustomRuleKeys: string[],
   delimiter: string,
   csvColumnConfig: CsvColumnConfig,
 ) {
@@ -161,6 +163,8 @@ export async function serializeResultsCsv(
     JSON.stringify(phenotypeAttrsJson),
     JSON.stringify(refNodesJson),
     JSON.stringify(aaMotifsDescs),
+    JSON.stringify(nucMotifKeys),
+    JSON.stringify(qcCustomRuleKeys),
     delimiter,
     JSON.stringify(csvColumnConfig),
   )
@@ -0,0 +1,56 @@
+use std::path::PathBuf;
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use nextclade::align::band_2d::simple_stripes;
+use nextclade::align::gap_open::get_gap_open_close_scores_flat;
+use nextclade::align::params::AlignPairwiseParams;
+use nextclade::align::score_matrix::{score_matrix_into_with, ScoreMatrixScratch};
+use nextclade::align::score_matrix_simd::ScoreMatrixSimd;
+use nextclade::alphabet::nuc::{to_nuc_seq, Nuc};
+use nextclade::io::fasta::read_many_fasta;
+
+pub fn bench_score_matrix(c: &mut Criterion) {
+  let params = AlignPairwiseParams::default();
+
+  let data_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../../data/sars-cov-2");
+  let ref_seq = first_sequence_from_path(data_dir.join("reference.fasta"));
+  let qry_seq = first_sequence_from_path(data_dir.join("sequences.fasta"));
+  let gap_open_close = get_gap_open_close_scores_flat(&ref_seq, &params);
+
+  let simds = [ScoreMatrixSimd::Sse2, ScoreMatrixSimd::Avx2, ScoreMatrixSimd::Neon]
+    .into_iter()
+    .filter(|simd| simd.is_supported())
+    .map(Some);
+
+  let mut group = c.benchmark_group("score_matrix");
+  group.sample_size(10);
+  for band_width in [32, 256] {
+    let stripes = simple_stripes(0, band_width, ref_seq.len(), qry_seq.len());
+    for simd in std::iter::once(None).chain(simds.clone()) {
+      let mut scratch = ScoreMatrixScratch::default();
+      let name = simd.map_or_else(|| "Scalar".to_owned(), |simd| format!("{simd:?}"));
+      group.bench_function(BenchmarkId::new(name, band_width), |b| {
+        b.iter(|| {
+          score_matrix_into_with(
+            simd,
+            &qry_seq,
+            &ref_seq,
+            &gap_open_close,
+            &stripes,
+            &params,
+            &mut scratch,
+          );
+        });
+      });
+    }
+  }
+  group.finish();
+}
+
+fn first_sequence_from_path(path: PathBuf) -> Vec<Nuc> {
+  let records = read_many_fasta(&[path]).unwrap();
+  black_box(to_nuc_seq(&records[0].seq).unwrap())
+}
+
+criterion_group!(benches, bench_score_matrix);
+criterion_main!(benches);
@@ -1,7 +1,8 @@
-use crate::align::backtrace::{backtrace, AlignmentOutput};
+use crate::align::align_chained::align_nuc_chained;
+use crate::align::backtrace::{backtrace, AlignmentDiagnostics, AlignmentMode, AlignmentOutput};
 use crate::align::band_2d::Stripe;
 use crate::align::band_2d::{full_matrix, simple_stripes};
-use crate::align::score_matrix::{score_matrix, ScoreMatrixResult};
+use crate::align::score_matrix::{score_matrix_into, ScoreMatrixScratch};
 use crate::align::seed_alignment::create_alignment_band;
 use crate::align::params::AlignPairwiseParams;
 use crate::align::seed_match::{get_seed_matches_maybe_reverse_complement, CodonSpacedIndex, SeedMatchesResult};
@@ -9,22 +10,25 @@ use crate::alphabet::aa::Aa;
 use crate::alphabet::letter::Letter;
 use crate::alphabet::nuc::Nuc;
 use crate::make_error;
+use crate::utils::error::report_to_string;
 use eyre::{Report, WrapErr};
-use log::{info, trace};
+use log::{info, trace, warn};
+use ordered_float::OrderedFloat;
 use std::cmp::max;
 
-fn align_pairwise<T: Letter<T>>(
+pub fn align_pairwise<T: Letter<T>>(
   qry_seq: &[T],
   ref_seq: &[T],
   gap_open_close: &[i32],
   params: &AlignPairwiseParams,
   stripes: &[Stripe],
+  scratch: &mut ScoreMatrixScratch,
 ) -> AlignmentOutput<T> {
   trace!("Align pairwise: started. Params: {params:?}");
 
-  let ScoreMatrixResult { scores, paths } = score_matrix(qry_seq, ref_seq, gap_open_close, stripes, params);
+  score_matrix_into(qry_seq, ref_seq, gap_open_close, stripes, params, scratch);
 
-  backtrace(qry_seq, ref_seq, &scores, &paths)
+  backtrace(qry_seq, ref_seq, scratch.scores(), scratch.paths())
 }
 
 /// align nucleotide sequences via seed alignment and banded smith watermann without penalizing terminal gaps
@@ -36,6 +40,30 @@ pub fn align_nuc(
   seed_index: &CodonSpacedIndex,
   gap_open_close: &[i32],
   params: &AlignPairwiseParams,
+) -> Result<AlignmentOutput<Nuc>, Report> {
+  let mut scratch = ScoreMatrixScratch::default();
+  align_nuc_with_scratch(
+    index,
+    seq_name,
+    qry_seq,
+    ref_seq,
+    seed_index,
+    gap_open_close,
+    params,
+    &mut scratch,
+  )
+}
+
+/// Same as `align_nuc()`, but reuses the memory of the alignment matrices from previous calls
+pub fn align_nuc_with_scratch(
+  index: usize,
+  seq_name: &str,
+  qry_seq: &[Nuc],
+  ref_seq: &[Nuc],
+  seed_index: &CodonSpacedIndex,
+  gap_open_close: &[i32],
+  params: &AlignPairwiseParams,
+  scratch: &mut ScoreMatrixScratch,
 ) -> Result<AlignmentOutput<Nuc>, Report> {
   let qry_len = qry_seq.len();
   let ref_len = ref_seq.len();
@@ -50,7 +78,14 @@ pub fn align_nuc(
     // for very short sequences, use full square
     let stripes = full_matrix(ref_len, qry_len);
     trace!("When processing sequence #{index} '{seq_name}': In nucleotide alignment: Band construction: short sequences, using full matrix");
-    return Ok(align_pairwise(qry_seq, ref_seq, gap_open_close, params, &stripes));
+    let mut alignment = align_pairwise(qry_seq, ref_seq, gap_open_close, params, &stripes, scratch);
+    alignment.alignment_mode = AlignmentMode::Full;
+    alignment.diagnostics = AlignmentDiagnostics {
+      band_areas: vec![(ref_len + 1) * (qry_len + 1)],
+      hit_boundary: alignment.hit_boundary,
+      ..AlignmentDiagnostics::default()
+    };
+    return Ok(alignment);
   }
 
   // otherwise, determine seed matches roughly regularly spaced along the query sequence
@@ -58,6 +93,7 @@ pub fn align_nuc(
     qry_seq,
     seed_matches,
     is_reverse_complement,
+    circular_rotation,
   } = get_seed_matches_maybe_reverse_complement(qry_seq, ref_seq, seed_index, params)
     .wrap_err("When calculating seed matches")?;
 
@@ -75,11 +111,32 @@ pub fn align_nuc(
     excess_bandwidth,
     minimal_bandwidth,
   );
+
+  let seed_length: usize = seed_matches.iter().map(|sm| sm.length).sum();
+  let mut diagnostics = AlignmentDiagnostics {
+    seed_count: seed_matches.len(),
+    seed_coverage: OrderedFloat(seed_length as f64 / qry_len.min(ref_len) as f64),
+    band_areas: vec![band_area],
+    terminal_bandwidth: terminal_bandwidth as usize,
+    excess_bandwidth: excess_bandwidth as usize,
+    minimal_bandwidth: minimal_bandwidth as usize,
+    hit_boundary: false,
+  };
+
   if band_area > max_band_area {
-    return make_error!("Alignment matrix size {band_area} exceeds maximum value {max_band_area}. The threshold can be adjusted using CLI flag '--max-band-area' or using 'maxBandArea' field in the dataset's pathogen.json");
+    if params.chained_alignment_fallback {
+      info!("When processing sequence #{index} '{seq_name}': In nucleotide alignment: Alignment matrix size {band_area} exceeds maximum value {max_band_area}. Falling back to chained alignment");
+      let mut alignment = align_nuc_chained(&qry_seq, ref_seq, gap_open_close, &seed_matches, params, scratch)?;
+      alignment.is_reverse_complement = is_reverse_complement;
+      alignment.circular_rotation = circular_rotation;
+      diagnostics.hit_boundary = alignment.hit_boundary;
+      alignment.diagnostics = diagnostics;
+      return Ok(alignment);
+    }
+    return make_error!("Alignment matrix size {band_area} exceeds maximum value {max_band_area}. The threshold can be adjusted using CLI flag '--max-band-area' or using 'maxBandArea' field in the dataset's pathogen.json. Alternatively, the CLI flag '--chained-alignment-fallback' or the 'chainedAlignmentFallback' field in the dataset's pathogen.json allows to align such sequences piece by piece");
   }
 
-  let mut alignment = align_pairwise(&qry_seq, ref_seq, gap_open_close, params, &stripes);
+  let mut alignment = align_pairwise(&qry_seq, ref_seq, gap_open_close, params, &stripes, scratch);
 
   while alignment.hit_boundary && attempt < params.max_alignment_attempts {
     info!("When processing sequence #{index} '{seq_name}': In nucleotide alignment: Band boundary is hit on attempt {}. Retrying with relaxed parameters. Alignment score was: {}", attempt+1, alignment.alignment_score);
@@ -101,8 +158,12 @@ pub fn align_nuc(
     if band_area > max_band_area {
       break;
     }
+    diagnostics.band_areas.push(band_area);
     // realign
-    alignment = align_pairwise(&qry_seq, ref_seq, gap_open_close, params, &stripes);
+    alignment = align_pairwise(&qry_seq, ref_seq, gap_open_close, params, &stripes, scratch);
+    diagnostics.terminal_bandwidth = terminal_bandwidth as usize;
+    diagnostics.excess_bandwidth = excess_bandwidth as usize;
+    diagnostics.minimal_bandwidth = minimal_bandwidth as usize;
   }
   // report success/failure of broadening of band width
   if alignment.hit_boundary {
@@ -111,14 +172,42 @@ pub fn align_nuc(
       info!(
         "When processing sequence #{index} '{seq_name}': final band area {band_area} exceeded the cutoff {max_band_area}"
       );
+      if params.chained_alignment_fallback {
+        info!(
+          "When processing sequence #{index} '{seq_name}': In nucleotide alignment: Falling back to chained alignment"
+        );
+        let chained = align_nuc_chained(&qry_seq, ref_seq, gap_open_close, &seed_matches, params, scratch);
+        alignment = chained_or_banded_alignment(index, seq_name, chained, alignment);
+      }
     }
   } else if attempt > 0 {
     info!("When processing sequence #{index} '{seq_name}': In nucleotide alignment: Succeeded without hitting band boundary on attempt {}. Alignment score was: {}", attempt+1, alignment.alignment_score);
   }
   alignment.is_reverse_complement = is_reverse_complement;
+  alignment.circular_rotation = circular_rotation;
+  diagnostics.hit_boundary = alignment.hit_boundary;
+  alignment.diagnostics = diagnostics;
   Ok(alignment)
 }
 
+/// Chained alignment is only a fallback for the banded alignment which keeps hitting the band boundary. If it fails,
+/// the banded alignment is still a usable result, so it is returned instead of failing the whole sequence.
+fn chained_or_banded_alignment(
+  index: usize,
+  seq_name: &str,
+  chained: Result<AlignmentOutput<Nuc>, Report>,
+  banded: AlignmentOutput<Nuc>,
+) -> AlignmentOutput<Nuc> {
+  chained.unwrap_or_else(|report| {
+    warn!(
+      "When processing sequence #{index} '{seq_name}': In nucleotide alignment: Chained alignment failed, returning the last banded alignment with score {}: {}",
+      banded.alignment_score,
+      report_to_string(&report)
+    );
+    banded
+  })
+}
+
 /// align amino acids using a fixed bandwidth banded alignment while penalizing terminal indels
 pub fn align_aa(
   qry_seq: &[Aa],
@@ -130,7 +219,14 @@ pub fn align_aa(
 ) -> AlignmentOutput<Aa> {
   let stripes = simple_stripes(mean_shift, band_width, ref_seq.len(), qry_seq.len());
 
-  align_pairwise(qry_seq, ref_seq, gap_open_close, params, &stripes)
+  align_pairwise(
+    qry_seq,
+    ref_seq,
+    gap_open_close,
+    params,
+    &stripes,
+    &mut ScoreMatrixScratch::default(),
+  )
 }
 
 #[cfg(test)]
@@ -141,10 +237,11 @@ mod tests {
 
   // rstest fixtures are passed by value
   use super::*;
-  use crate::align::gap_open::{get_gap_open_close_scores_codon_aware, GapScoreMap};
+  use crate::align::gap_open::{get_gap_open_close_scores_codon_aware, get_gap_open_close_scores_flat, GapScoreMap};
   use crate::align::params::GapAlignmentSide;
   use crate::alphabet::nuc::{from_nuc_seq, to_nuc_seq};
   use crate::gene::gene_map::GeneMap;
+  use crate::test_utils::Xorshift64;
   use eyre::Report;
   use pretty_assertions::assert_eq;
   use rstest::{fixture, rstest};
@@ -599,6 +696,62 @@ mod tests {
     Ok(())
   }
 
+  #[rstest]
+  fn rotates_query_of_circular_genome() -> Result<(), Report> {
+    // Random reference, long enough for seed matching. The query is the same genome, but starting at a different origin.
+    let mut rng = Xorshift64::new(0x2545_f491_4f6c_dd1d);
+    let ref_seq: String = (0..3000).map(|_| char::from(b"ACGT"[rng.next_below(4)])).collect();
+    let ref_seq = to_nuc_seq(&ref_seq)?;
+    let mut qry_seq = ref_seq.clone();
+    qry_seq.rotate_left(1000);
+
+    let seed_index = CodonSpacedIndex::from_sequence(&ref_seq);
+    let linear_params = AlignPairwiseParams::default();
+    let gap_open_close = get_gap_open_close_scores_flat(&ref_seq, &linear_params);
+
+    let linear = align_nuc(0, "", &qry_seq, &ref_seq, &seed_index, &gap_open_close, &linear_params)?;
+    assert_eq!(0, linear.circular_rotation);
+
+    let circular_params = AlignPairwiseParams {
+      circular: true,
+      ..linear_params
+    };
+    let circular = align_nuc(
+      0,
+      "",
+      &qry_seq,
+      &ref_seq,
+      &seed_index,
+      &gap_open_close,
+      &circular_params,
+    )?;
+    assert_eq!(2000, circular.circular_rotation);
+    assert_eq!(from_nuc_seq(&ref_seq), from_nuc_seq(&circular.ref_seq));
+    assert_eq!(from_nuc_seq(&ref_seq), from_nuc_seq(&circular.qry_seq));
+    Ok(())
+  }
+
+  #[rstest]
+  fn keeps_banded_alignment_if_chained_alignment_fails() -> Result<(), Report> {
+    let banded = AlignmentOutput {
+      qry_seq: to_nuc_seq("ACGT")?,
+      ref_seq: to_nuc_seq("ACGT")?,
+      alignment_score: 12,
+      is_reverse_complement: false,
+      hit_boundary: true,
+      alignment_mode: AlignmentMode::default(),
+      diagnostics: AlignmentDiagnostics::default(),
+      circular_rotation: 0,
+    };
+    let chained = make_error!("Unable to align");
+
+    let result = chained_or_banded_alignment(0, "", chained, banded);
+    assert_eq!(12, result.alignment_score);
+    assert!(result.hit_boundary);
+    assert_eq!("ACGT", from_nuc_seq(&result.qry_seq));
+    Ok(())
+  }
+
   #[rstest]
   #[rustfmt::skip]
   fn general_case(ctx: Context) -> Result<(), Report> {
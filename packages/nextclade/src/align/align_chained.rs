@@ -0,0 +1,418 @@
+use crate::align::align::align_pairwise;
+use crate::align::backtrace::{AlignmentDiagnostics, AlignmentMode, AlignmentOutput};
+use crate::align::params::AlignPairwiseParams;
+use crate::align::score_matrix::ScoreMatrixScratch;
+use crate::align::seed_alignment::create_alignment_band;
+use crate::align::seed_match::SeedMatch2;
+use crate::alphabet::nuc::Nuc;
+use crate::make_error;
+use eyre::Report;
+use log::trace;
+use std::cmp::max;
+use std::ops::Range;
+
+/// Point on the chain of seed matches, through which the alignment is forced to pass
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Anchor {
+  qry_pos: usize,
+  ref_pos: usize,
+}
+
+/// Rectangular part of the alignment matrix, spanning from one anchor to the next (or to the ends of the sequences)
+#[derive(Clone, Debug)]
+struct Segment {
+  qry: Range<usize>,
+  reff: Range<usize>,
+  is_first: bool,
+  is_last: bool,
+}
+
+struct ChainedAlignmentContext<'a> {
+  qry_seq: &'a [Nuc],
+  ref_seq: &'a [Nuc],
+  gap_open_close: &'a [i32],
+  seed_matches: &'a [SeedMatch2],
+  params: &'a AlignPairwiseParams,
+}
+
+/// Align nucleotide sequences piece by piece, such that the band of every piece fits into `max_band_area`.
+///
+/// The alignment matrix is split into segments at anchor points, placed evenly along the chained seed matches,
+/// and each segment is aligned separately using the regular banded alignment. Segments are split in halves
+/// recursively, until the band of each of them fits into the maximum band area, or until the boundary of the band is
+/// no longer hit. This way only the band of a single segment is held in memory at a time.
+///
+/// Terminal gaps are only free at the ends of the full sequences, not at the ends of the individual segments. Because
+/// the alignment is forced through the anchors, it can be slightly suboptimal compared to the unconstrained alignment.
+pub fn align_nuc_chained(
+  qry_seq: &[Nuc],
+  ref_seq: &[Nuc],
+  gap_open_close: &[i32],
+  seed_matches: &[SeedMatch2],
+  params: &AlignPairwiseParams,
+  scratch: &mut ScoreMatrixScratch,
+) -> Result<AlignmentOutput<Nuc>, Report> {
+  let ctx = ChainedAlignmentContext {
+    qry_seq,
+    ref_seq,
+    gap_open_close,
+    seed_matches,
+    params,
+  };
+
+  let segment = Segment {
+    qry: 0..qry_seq.len(),
+    reff: 0..ref_seq.len(),
+    is_first: true,
+    is_last: true,
+  };
+
+  let anchors = find_anchors(seed_matches, params.min_match_length);
+
+  let capacity = qry_seq.len() + ref_seq.len();
+  let mut alignment = AlignmentOutput {
+    qry_seq: Vec::with_capacity(capacity),
+    ref_seq: Vec::with_capacity(capacity),
+    alignment_score: 0,
+    is_reverse_complement: false,
+    hit_boundary: false,
+    alignment_mode: AlignmentMode::Chained,
+    diagnostics: AlignmentDiagnostics::default(),
+    circular_rotation: 0,
+  };
+
+  align_segment(&ctx, &segment, &anchors, scratch, &mut alignment)?;
+
+  Ok(alignment)
+}
+
+/// Place anchors evenly along every seed match, roughly one per `step` nucleotides, keeping only anchors which strictly
+/// increase in both sequences. Short seeds receive a single anchor in the middle.
+fn find_anchors(seed_matches: &[SeedMatch2], step: usize) -> Vec<Anchor> {
+  let mut anchors: Vec<Anchor> = Vec::with_capacity(seed_matches.len());
+  for seed in seed_matches {
+    let n_anchors = max(1, seed.length / max(1, step));
+    for i in 0..n_anchors {
+      let shift = (2 * i + 1) * seed.length / (2 * n_anchors);
+      let anchor = Anchor {
+        qry_pos: seed.qry_pos + shift,
+        ref_pos: seed.ref_pos + shift,
+      };
+      let is_increasing = anchors.last().map_or(true, |prev| {
+        prev.qry_pos < anchor.qry_pos && prev.ref_pos < anchor.ref_pos
+      });
+      if is_increasing {
+        anchors.push(anchor);
+      }
+    }
+  }
+  anchors
+}
+
+/// Align a segment and append the result to the output alignment. The anchors are the ones strictly inside of the
+/// segment.
+fn align_segment(
+  ctx: &ChainedAlignmentContext,
+  segment: &Segment,
+  anchors: &[Anchor],
+  scratch: &mut ScoreMatrixScratch,
+  alignment: &mut AlignmentOutput<Nuc>,
+) -> Result<(), Report> {
+  let segment_alignment = align_segment_banded(ctx, segment, scratch);
+
+  if anchors.is_empty() {
+    return match segment_alignment {
+      Some(segment_alignment) => {
+        append_alignment(alignment, segment_alignment);
+        Ok(())
+      }
+      None => make_error!(
+        "Unable to align: segment of the query sequence from {} to {}, aligned to the reference from {} to {}, requires an alignment band larger than the maximum band area {} and cannot be split further. The threshold can be adjusted using CLI flag '--max-band-area' or using 'maxBandArea' field in the dataset's pathogen.json",
+        segment.qry.start,
+        segment.qry.end,
+        segment.reff.start,
+        segment.reff.end,
+        ctx.params.max_band_area
+      ),
+    };
+  }
+
+  if let Some(segment_alignment) = segment_alignment {
+    if !segment_alignment.hit_boundary {
+      append_alignment(alignment, segment_alignment);
+      return Ok(());
+    }
+  }
+
+  // The band is too large or too narrow: split the segment at the middle anchor and align each half separately
+  let mid = anchors.len() / 2;
+  let anchor = &anchors[mid];
+  trace!(
+    "In chained nucleotide alignment: splitting segment qry={:?} ref={:?} at qry={}, ref={}",
+    segment.qry,
+    segment.reff,
+    anchor.qry_pos,
+    anchor.ref_pos
+  );
+
+  let left = Segment {
+    qry: segment.qry.start..anchor.qry_pos,
+    reff: segment.reff.start..anchor.ref_pos,
+    is_first: segment.is_first,
+    is_last: false,
+  };
+  align_segment(ctx, &left, &anchors[..mid], scratch, alignment)?;
+
+  let right = Segment {
+    qry: anchor.qry_pos..segment.qry.end,
+    reff: anchor.ref_pos..segment.reff.end,
+    is_first: false,
+    is_last: segment.is_last,
+  };
+  align_segment(ctx, &right, &anchors[mid + 1..], scratch, alignment)
+}
+
+/// Align a segment using banded alignment, relaxing the band on boundary hits in the same way as `align_nuc()`.
+/// Returns `None` if even the initial band does not fit into the maximum band area.
+fn align_segment_banded(
+  ctx: &ChainedAlignmentContext,
+  segment: &Segment,
+  scratch: &mut ScoreMatrixScratch,
+) -> Option<AlignmentOutput<Nuc>> {
+  let seeds = clip_seed_matches(ctx.seed_matches, segment);
+  if seeds.is_empty() {
+    return None;
+  }
+
+  let qry_seq = &ctx.qry_seq[segment.qry.clone()];
+  let ref_seq = &ctx.ref_seq[segment.reff.clone()];
+  let gap_open_close = &ctx.gap_open_close[segment.reff.start..=segment.reff.end];
+  let params = AlignPairwiseParams {
+    left_terminal_gaps_free: ctx.params.left_terminal_gaps_free && segment.is_first,
+    right_terminal_gaps_free: ctx.params.right_terminal_gaps_free && segment.is_last,
+    ..ctx.params.clone()
+  };
+
+  let mut terminal_bandwidth = params.terminal_bandwidth as isize;
+  let mut excess_bandwidth = params.excess_bandwidth as isize;
+  let mut minimal_bandwidth = max(1, params.allowed_mismatches as isize);
+
+  let mut alignment: Option<AlignmentOutput<Nuc>> = None;
+  for attempt in 0..=params.max_alignment_attempts {
+    if attempt > 0 {
+      terminal_bandwidth = max(2 * terminal_bandwidth, 1);
+      excess_bandwidth = max(2 * excess_bandwidth, 1);
+      minimal_bandwidth = max(2 * minimal_bandwidth, 1);
+    }
+
+    let (stripes, band_area) = create_alignment_band(
+      &seeds,
+      qry_seq.len() as isize,
+      ref_seq.len() as isize,
+      terminal_bandwidth,
+      excess_bandwidth,
+      minimal_bandwidth,
+    );
+    if band_area > params.max_band_area {
+      break;
+    }
+
+    let current = align_pairwise(qry_seq, ref_seq, gap_open_close, &params, &stripes, scratch);
+    let hit_boundary = current.hit_boundary;
+    alignment = Some(current);
+    if !hit_boundary {
+      break;
+    }
+  }
+  alignment
+}
+
+/// Crop seed matches to the segment and convert them to the coordinates relative to the segment start
+fn clip_seed_matches(seed_matches: &[SeedMatch2], segment: &Segment) -> Vec<SeedMatch2> {
+  let qry_begin = segment.qry.start as isize;
+  let qry_end = segment.qry.end as isize;
+  let ref_begin = segment.reff.start as isize;
+  let ref_end = segment.reff.end as isize;
+
+  seed_matches
+    .iter()
+    .filter_map(|seed| {
+      // Seed matches are diagonal, so the distance between query and reference positions is the same along the seed
+      let shift = seed.ref_pos as isize - seed.qry_pos as isize;
+      let begin = (seed.qry_pos as isize).max(qry_begin).max(ref_begin - shift);
+      let end = ((seed.qry_pos + seed.length) as isize)
+        .min(qry_end)
+        .min(ref_end - shift);
+      (begin < end).then(|| {
+        let qry_pos = begin - qry_begin;
+        let ref_pos = begin + shift - ref_begin;
+        SeedMatch2 {
+          qry_pos: qry_pos as usize,
+          ref_pos: ref_pos as usize,
+          length: (end - begin) as usize,
+          offset: qry_pos - ref_pos,
+        }
+      })
+    })
+    .collect()
+}
+
+fn append_alignment(alignment: &mut AlignmentOutput<Nuc>, segment_alignment: AlignmentOutput<Nuc>) {
+  alignment.qry_seq.extend(segment_alignment.qry_seq);
+  alignment.ref_seq.extend(segment_alignment.ref_seq);
+  alignment.alignment_score += segment_alignment.alignment_score;
+  alignment.hit_boundary |= segment_alignment.hit_boundary;
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::align::align::align_nuc;
+  use crate::align::gap_open::get_gap_open_close_scores_flat;
+  use crate::align::seed_match::CodonSpacedIndex;
+  use crate::alphabet::nuc::{from_nuc_seq, to_nuc_seq};
+  use crate::test_utils::Xorshift64;
+  use assert2::assert;
+  use eyre::Report;
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+
+  /// Random reference and a query which differs from it by sparse substitutions, a deletion and an insertion
+  fn make_seqs(len: usize) -> Result<(Vec<Nuc>, Vec<Nuc>), Report> {
+    let mut rng = Xorshift64::new(0x2545_f491_4f6c_dd1d);
+    let ref_seq = (0..len).map(|_| b"ACGT"[rng.next_below(4)]).collect::<Vec<u8>>();
+    let mut qry_seq = vec![];
+    for (pos, &letter) in ref_seq.iter().enumerate() {
+      if pos == len / 3 {
+        qry_seq.extend(b"TTAGCA");
+      }
+      if (2 * len / 3..2 * len / 3 + 9).contains(&pos) {
+        continue;
+      }
+      if pos % 97 == 50 {
+        qry_seq.push(if letter == b'A' { b'C' } else { b'A' });
+      } else {
+        qry_seq.push(letter);
+      }
+    }
+
+    Ok((
+      to_nuc_seq(&String::from_utf8(ref_seq)?)?,
+      to_nuc_seq(&String::from_utf8(qry_seq)?)?,
+    ))
+  }
+
+  #[rustfmt::skip]
+  #[rstest]
+  fn finds_increasing_anchors() {
+    let seed_matches = vec![
+      SeedMatch2 { qry_pos: 0,   ref_pos: 10,  length: 40, offset: -10 },
+      SeedMatch2 { qry_pos: 50,  ref_pos: 25,  length: 10, offset: 25  },
+      SeedMatch2 { qry_pos: 100, ref_pos: 110, length: 41, offset: -10 },
+    ];
+    let expected = vec![
+      Anchor { qry_pos: 10,  ref_pos: 20  },
+      Anchor { qry_pos: 30,  ref_pos: 40  },
+      Anchor { qry_pos: 110, ref_pos: 120 },
+      Anchor { qry_pos: 130, ref_pos: 140 },
+    ];
+    assert_eq!(expected, find_anchors(&seed_matches, 20));
+  }
+
+  #[rustfmt::skip]
+  #[rstest]
+  fn clips_seed_matches_to_segment() {
+    let seed_matches = vec![
+      SeedMatch2 { qry_pos: 0,   ref_pos: 10,  length: 40, offset: -10 },
+      SeedMatch2 { qry_pos: 60,  ref_pos: 65,  length: 40, offset: -5  },
+      SeedMatch2 { qry_pos: 150, ref_pos: 150, length: 40, offset: 0   },
+    ];
+    let segment = Segment { qry: 20..80, reff: 30..85, is_first: false, is_last: false };
+    let expected = vec![
+      SeedMatch2 { qry_pos: 0,  ref_pos: 0,  length: 20, offset: 0 },
+      SeedMatch2 { qry_pos: 40, ref_pos: 35, length: 20, offset: 5 },
+    ];
+    assert_eq!(expected, clip_seed_matches(&seed_matches, &segment));
+  }
+
+  #[rstest]
+  fn chained_alignment_is_same_as_banded() -> Result<(), Report> {
+    let (ref_seq, qry_seq) = make_seqs(3000)?;
+    let params = AlignPairwiseParams::default();
+    let seed_index = CodonSpacedIndex::from_sequence(&ref_seq);
+    let gap_open_close = get_gap_open_close_scores_flat(&ref_seq, &params);
+
+    let banded = align_nuc(0, "", &qry_seq, &ref_seq, &seed_index, &gap_open_close, &params)?;
+    assert_eq!(AlignmentMode::Banded, banded.alignment_mode);
+
+    let chained_params = AlignPairwiseParams {
+      max_band_area: 20_000,
+      chained_alignment_fallback: true,
+      ..params
+    };
+    let chained = align_nuc(0, "", &qry_seq, &ref_seq, &seed_index, &gap_open_close, &chained_params)?;
+
+    assert_eq!(AlignmentMode::Chained, chained.alignment_mode);
+    assert_eq!(from_nuc_seq(&banded.ref_seq), from_nuc_seq(&chained.ref_seq));
+    assert_eq!(from_nuc_seq(&banded.qry_seq), from_nuc_seq(&chained.qry_seq));
+    assert_eq!(banded.alignment_score, chained.alignment_score);
+    Ok(())
+  }
+
+  #[rstest]
+  fn reports_alignment_diagnostics() -> Result<(), Report> {
+    let (ref_seq, qry_seq) = make_seqs(3000)?;
+    let params = AlignPairwiseParams::default();
+    let seed_index = CodonSpacedIndex::from_sequence(&ref_seq);
+    let gap_open_close = get_gap_open_close_scores_flat(&ref_seq, &params);
+
+    let banded = align_nuc(0, "", &qry_seq, &ref_seq, &seed_index, &gap_open_close, &params)?;
+    let diagnostics = &banded.diagnostics;
+    assert!(diagnostics.seed_count > 0);
+    assert!(*diagnostics.seed_coverage > 0.5);
+    assert!(!diagnostics.band_areas.is_empty());
+    assert!(diagnostics.terminal_bandwidth >= params.terminal_bandwidth as usize);
+    assert_eq!(banded.hit_boundary, diagnostics.hit_boundary);
+
+    let chained_params = AlignPairwiseParams {
+      max_band_area: 20_000,
+      chained_alignment_fallback: true,
+      ..params
+    };
+    let chained = align_nuc(0, "", &qry_seq, &ref_seq, &seed_index, &gap_open_close, &chained_params)?;
+    assert_eq!(diagnostics.band_areas[0], chained.diagnostics.band_areas[0]);
+    assert!(chained.diagnostics.band_areas[0] > 20_000);
+    Ok(())
+  }
+
+  #[rstest]
+  fn fails_when_band_area_is_exceeded_without_fallback() -> Result<(), Report> {
+    let (ref_seq, qry_seq) = make_seqs(3000)?;
+    let params = AlignPairwiseParams {
+      max_band_area: 20_000,
+      ..AlignPairwiseParams::default()
+    };
+    let seed_index = CodonSpacedIndex::from_sequence(&ref_seq);
+    let gap_open_close = get_gap_open_close_scores_flat(&ref_seq, &params);
+
+    let result = align_nuc(0, "", &qry_seq, &ref_seq, &seed_index, &gap_open_close, &params);
+    assert!(result.is_err());
+    Ok(())
+  }
+
+  #[rstest]
+  fn fails_when_segment_cannot_be_split_further() -> Result<(), Report> {
+    let (ref_seq, qry_seq) = make_seqs(3000)?;
+    let params = AlignPairwiseParams {
+      max_band_area: 100,
+      chained_alignment_fallback: true,
+      ..AlignPairwiseParams::default()
+    };
+    let seed_index = CodonSpacedIndex::from_sequence(&ref_seq);
+    let gap_open_close = get_gap_open_close_scores_flat(&ref_seq, &params);
+
+    let result = align_nuc(0, "", &qry_seq, &ref_seq, &seed_index, &gap_open_close, &params);
+    assert!(result.is_err());
+    Ok(())
+  }
+}
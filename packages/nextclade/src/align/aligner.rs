@@ -0,0 +1,173 @@
+use crate::align::align::align_nuc_with_scratch;
+use crate::align::backtrace::AlignmentOutput;
+use crate::align::gap_open::{get_gap_open_close_scores_codon_aware, get_gap_open_close_scores_flat, GapScoreMap};
+use crate::align::params::AlignPairwiseParams;
+use crate::align::score_matrix::ScoreMatrixScratch;
+use crate::align::seed_match::CodonSpacedIndex;
+use crate::alphabet::nuc::Nuc;
+use crate::gene::gene_map::GeneMap;
+use crate::make_error;
+use crate::run::validate_ref_seq::validate_ref_seq;
+use eyre::Report;
+
+/// Nucleotide aligner for a single reference sequence.
+///
+/// Holds everything which depends only on the reference: the seed index and the gap open/close penalties. These are
+/// computed once, in the constructor, and are then reused for every query sequence. The aligner is immutable and can
+/// be shared between threads.
+///
+/// The memory for alignment matrices is not part of the aligner. Instead, each thread should own a
+/// `ScoreMatrixScratch` and pass it to every call of `align()`, so that the matrices are not reallocated for every
+/// query sequence.
+///
+/// Usage:
+///
+/// ```rust,ignore
+/// let aligner = Aligner::new(ref_seq, &gene_map, params)?;
+/// let mut scratch = ScoreMatrixScratch::default();
+/// for qry_seq in qry_seqs {
+///   let alignment = aligner.align(&qry_seq, &mut scratch)?;
+/// }
+/// ```
+pub struct Aligner {
+  ref_seq: Vec<Nuc>,
+  seed_index: CodonSpacedIndex,
+  gap_open_close: GapScoreMap,
+  params: AlignPairwiseParams,
+}
+
+impl Aligner {
+  /// Prepares the aligner for a given reference. If genome annotation is not empty, gap open penalties are
+  /// codon-aware, i.e. the same as in the full Nextclade analysis.
+  pub fn new(ref_seq: Vec<Nuc>, gene_map: &GeneMap, params: AlignPairwiseParams) -> Result<Self, Report> {
+    let gap_open_close = if gene_map.is_empty() {
+      get_gap_open_close_scores_flat(&ref_seq, &params)
+    } else {
+      get_gap_open_close_scores_codon_aware(&ref_seq, gene_map, &params)
+    };
+    Self::with_gap_open_close(ref_seq, gap_open_close, params)
+  }
+
+  /// Prepares the aligner for a given reference with custom gap open/close penalties. The penalty map should contain
+  /// an entry for each reference position, plus 2.
+  pub fn with_gap_open_close(
+    ref_seq: Vec<Nuc>,
+    gap_open_close: GapScoreMap,
+    params: AlignPairwiseParams,
+  ) -> Result<Self, Report> {
+    validate_ref_seq("reference", &ref_seq)?;
+
+    let expected_len = ref_seq.len() + 2;
+    if gap_open_close.len() != expected_len {
+      return make_error!(
+        "Gap open/close penalty map has length {}, but expected {expected_len} (reference length + 2)",
+        gap_open_close.len()
+      );
+    }
+
+    let seed_index = CodonSpacedIndex::from_sequence(&ref_seq);
+
+    Ok(Self {
+      ref_seq,
+      seed_index,
+      gap_open_close,
+      params,
+    })
+  }
+
+  /// Aligns a query sequence against the reference
+  pub fn align(&self, qry_seq: &[Nuc], scratch: &mut ScoreMatrixScratch) -> Result<AlignmentOutput<Nuc>, Report> {
+    self.align_named(0, "", qry_seq, scratch)
+  }
+
+  /// Aligns a query sequence against the reference. Index and name of the sequence are only used in messages.
+  pub fn align_named(
+    &self,
+    index: usize,
+    seq_name: &str,
+    qry_seq: &[Nuc],
+    scratch: &mut ScoreMatrixScratch,
+  ) -> Result<AlignmentOutput<Nuc>, Report> {
+    align_nuc_with_scratch(
+      index,
+      seq_name,
+      qry_seq,
+      &self.ref_seq,
+      &self.seed_index,
+      &self.gap_open_close,
+      &self.params,
+      scratch,
+    )
+  }
+
+  pub fn ref_seq(&self) -> &[Nuc] {
+    &self.ref_seq
+  }
+
+  pub const fn seed_index(&self) -> &CodonSpacedIndex {
+    &self.seed_index
+  }
+
+  pub fn gap_open_close(&self) -> &[i32] {
+    &self.gap_open_close
+  }
+
+  pub const fn params(&self) -> &AlignPairwiseParams {
+    &self.params
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::align::align::align_nuc;
+  use crate::alphabet::nuc::{from_nuc_seq, to_nuc_seq};
+  use crate::test_utils::random_seq;
+  use eyre::Report;
+  use pretty_assertions::assert_eq;
+
+  #[test]
+  fn aligns_many_queries_with_reused_scratch_same_as_align_nuc() -> Result<(), Report> {
+    // Pseudo-random reference, so that seed matching has unique hits
+    let ref_seq = to_nuc_seq(&random_seq(42, 3000))?;
+
+    let params = AlignPairwiseParams::default();
+    let gene_map = GeneMap::new();
+    let aligner = Aligner::new(ref_seq.clone(), &gene_map, params.clone())?;
+
+    // Queries of different lengths, so that the scratch buffers have to shrink and grow
+    let qry_seqs = [
+      ref_seq[100..2900].to_vec(),
+      [&ref_seq[0..300], &ref_seq[309..1200]].concat(),
+      ref_seq[2000..2600].to_vec(),
+      ref_seq.clone(),
+    ];
+
+    let mut scratch = ScoreMatrixScratch::default();
+    for qry_seq in &qry_seqs {
+      let actual = aligner.align(qry_seq, &mut scratch)?;
+      let expected = align_nuc(
+        0,
+        "",
+        qry_seq,
+        &ref_seq,
+        aligner.seed_index(),
+        aligner.gap_open_close(),
+        &params,
+      )?;
+      assert_eq!(from_nuc_seq(&expected.qry_seq), from_nuc_seq(&actual.qry_seq));
+      assert_eq!(from_nuc_seq(&expected.ref_seq), from_nuc_seq(&actual.ref_seq));
+      assert_eq!(expected.alignment_score, actual.alignment_score);
+    }
+
+    Ok(())
+  }
+
+  #[test]
+  fn rejects_gap_open_close_map_of_wrong_length() -> Result<(), Report> {
+    let ref_seq = to_nuc_seq("ACGCTCGCT")?;
+    let result = Aligner::with_gap_open_close(ref_seq, vec![6; 5], AlignPairwiseParams::default());
+    assert2::assert!(result.is_err());
+    Ok(())
+  }
+}
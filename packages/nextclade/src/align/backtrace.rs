@@ -1,8 +1,51 @@
 use crate::align::band_2d::Band2d;
 use crate::align::score_matrix::{BOUNDARY, MATCH, QRY_GAP_EXTEND, QRY_GAP_MATRIX, REF_GAP_EXTEND, REF_GAP_MATRIX};
 use crate::alphabet::letter::Letter;
+use ordered_float::OrderedFloat;
 use serde::{Deserialize, Serialize};
 
+/// Describes how the alignment of a sequence was computed
+#[derive(
+  Copy, Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize, schemars::JsonSchema, strum_macros::Display,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum AlignmentMode {
+  /// Full alignment matrix. Used for short sequences.
+  Full,
+
+  /// Banded alignment matrix, with the band constructed from seed matches
+  #[default]
+  Banded,
+
+  /// Sequence of banded alignments of consecutive segments, anchored on seed matches. Used as a fallback when the band
+  /// of the full sequence does not fit into the maximum band area.
+  Chained,
+}
+
+/// Details of how the nucleotide alignment of a sequence was computed. Helps to find out why a sequence aligned badly.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AlignmentDiagnostics {
+  /// Number of chained seed matches between query and reference
+  pub seed_count: usize,
+
+  /// Fraction of the shorter of the two sequences which is covered by the seed matches
+  pub seed_coverage: OrderedFloat<f64>,
+
+  /// Area of the alignment band on each of the attempts, in order. Band is widened on every attempt after the first.
+  /// Widened bands which exceed the maximum band area are not attempted and are not included.
+  pub band_areas: Vec<usize>,
+
+  /// Bandwidths used to construct the band of the last attempt
+  pub terminal_bandwidth: usize,
+  pub excess_bandwidth: usize,
+  pub minimal_bandwidth: usize,
+
+  /// Whether the final alignment still touches the boundary of the band
+  pub hit_boundary: bool,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema, PartialEq, Eq)]
 pub struct AlignmentOutput<T> {
   pub qry_seq: Vec<T>,
@@ -10,6 +53,12 @@ pub struct AlignmentOutput<T> {
   pub alignment_score: i32,
   pub is_reverse_complement: bool,
   pub hit_boundary: bool,
+  #[serde(default)]
+  pub alignment_mode: AlignmentMode,
+  #[serde(default)]
+  pub diagnostics: AlignmentDiagnostics,
+  #[serde(default)]
+  pub circular_rotation: usize,
 }
 
 pub fn backtrace<T: Letter<T>>(
@@ -89,6 +138,9 @@ pub fn backtrace<T: Letter<T>>(
     alignment_score: scores[(num_rows - 1, num_cols - 1)],
     is_reverse_complement: false,
     hit_boundary,
+    alignment_mode: AlignmentMode::Banded,
+    diagnostics: AlignmentDiagnostics::default(),
+    circular_rotation: 0,
   }
 }
 
@@ -135,6 +187,9 @@ mod tests {
       alignment_score: 18,
       is_reverse_complement: false,
       hit_boundary: false,
+      alignment_mode: AlignmentMode::Banded,
+      diagnostics: AlignmentDiagnostics::default(),
+      circular_rotation: 0,
     };
 
     let output = backtrace(&qry_seq, &ref_seq, &scores, &paths);
@@ -101,6 +101,20 @@ where
     }
   }
 
+  /// Re-initializes the band with the new stripes and default values.
+  ///
+  /// Reuses the memory which is already allocated, so that the same band can serve many alignments without
+  /// reallocation, as long as the bands are of similar size.
+  pub fn reset(&mut self, stripes: &[Stripe]) {
+    let (n_rows, n_cols) = calculate_dimensions_into(stripes, &mut self.row_start_points);
+    self.data.clear();
+    self.data.resize(self.row_start_points[n_rows], T::default());
+    self.stripes.clear();
+    self.stripes.extend_from_slice(stripes);
+    self.n_rows = n_rows;
+    self.n_cols = n_cols;
+  }
+
   #[inline]
   pub const fn num_rows(&self) -> usize {
     self.n_rows
@@ -116,6 +130,29 @@ where
     self.data.len()
   }
 
+  /// Cells of a given row, from `stripe.begin` to `stripe.end`
+  #[inline]
+  pub fn row(&self, row: usize) -> &[T] {
+    &self.data[self.row_start_points[row]..self.row_start_points[row + 1]]
+  }
+
+  /// Mutable cells of a given row, from `stripe.begin` to `stripe.end`
+  #[inline]
+  pub fn row_mut(&mut self, row: usize) -> &mut [T] {
+    &mut self.data[self.row_start_points[row]..self.row_start_points[row + 1]]
+  }
+
+  /// Cells of the previous row together with the mutable cells of a given row. Allows to compute a row from the
+  /// previous one without copying.
+  #[inline]
+  pub fn row_with_previous_mut(&mut self, row: usize) -> (&[T], &mut [T]) {
+    assert!(row > 0, "Row 0 has no previous row");
+    let (before, after) = self.data.split_at_mut(self.row_start_points[row]);
+    let prev = &before[self.row_start_points[row - 1]..];
+    let curr = &mut after[..self.row_start_points[row + 1] - self.row_start_points[row]];
+    (prev, curr)
+  }
+
   #[inline]
   fn get_index<I: NumCast + Copy, J: NumCast + Copy>(&self, index2d: (I, J)) -> usize {
     let row = index2d.0.to_usize().unwrap();
@@ -133,15 +170,30 @@ where
 }
 
 fn calculate_dimensions(stripes: &[Stripe]) -> (usize, usize, Vec<usize>) {
+  let mut row_start_points = Vec::with_capacity(stripes.len() + 1);
+  let (n_rows, n_cols) = calculate_dimensions_into(stripes, &mut row_start_points);
+  (n_rows, n_cols, row_start_points)
+}
+
+fn calculate_dimensions_into(stripes: &[Stripe], row_start_points: &mut Vec<usize>) -> (usize, usize) {
   let n_rows = stripes.len();
-  let mut row_start_points = vec![0_usize; n_rows + 1];
   let mut n_cols = 0_usize;
-  row_start_points[0] = 0;
-  for (i, stripe) in stripes.iter().enumerate() {
-    row_start_points[i + 1] = row_start_points[i] + stripe.len();
+  row_start_points.clear();
+  row_start_points.push(0);
+  for stripe in stripes {
+    row_start_points.push(row_start_points[row_start_points.len() - 1] + stripe.len());
     n_cols = n_cols.max(stripe.end);
   }
-  (n_rows, n_cols, row_start_points)
+  (n_rows, n_cols)
+}
+
+impl<T> Default for Band2d<T>
+where
+  T: Default + Clone,
+{
+  fn default() -> Self {
+    Self::new(&[])
+  }
 }
 
 /// Allows 2-dimensional indexing using a tuple
@@ -237,6 +289,48 @@ mod tests {
     Ok(())
   }
 
+  #[rstest]
+  fn test_band_2d_reset() -> Result<(), Report> {
+    let mut band = Band2d::<i32>::new(&[Stripe { begin: 0, end: 3 }, Stripe { begin: 1, end: 4 }]);
+    band[(0, 0)] = 11;
+    band[(1, 3)] = 24;
+
+    let stripes = vec![
+      Stripe { begin: 0, end: 2 },
+      Stripe { begin: 0, end: 3 },
+      Stripe { begin: 2, end: 5 },
+    ];
+    band.reset(&stripes);
+
+    assert_eq!(band, Band2d::<i32>::new(&stripes));
+    assert_eq!(&band.row_start_points, &[0, 2, 5, 8]);
+    assert_eq!(band.num_rows(), 3);
+    assert_eq!(band.num_cols(), 5);
+
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_band_2d_rows() -> Result<(), Report> {
+    let stripes = vec![
+      Stripe { begin: 0, end: 2 },
+      Stripe { begin: 1, end: 4 },
+      Stripe { begin: 2, end: 4 },
+    ];
+    let mut band = Band2d::<i32>::with_data(&stripes, &[1, 2, 3, 4, 5, 6, 7]);
+
+    assert_eq!(band.row(1), &[3, 4, 5]);
+
+    let (prev, curr) = band.row_with_previous_mut(2);
+    assert_eq!(prev, &[3, 4, 5]);
+    curr[1] = prev[2] + 10;
+    band.row_mut(0)[0] = 0;
+
+    assert_eq!(band, Band2d::<i32>::with_data(&stripes, &[0, 2, 3, 4, 5, 6, 15]));
+
+    Ok(())
+  }
+
   #[rstest]
   fn test_simple_stripes() -> Result<(), Report> {
     let expected_stripes = vec![
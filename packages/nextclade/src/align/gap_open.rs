@@ -109,6 +109,7 @@ mod tests {
           attributes: indexmap! {},
           compat_is_gene: false,
           color: None,
+          genetic_code: None,
         })
       })
       .collect::<Result<Vec<Gene>, Report>>()?;
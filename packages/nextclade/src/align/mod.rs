@@ -1,4 +1,6 @@
 pub mod align;
+pub mod align_chained;
+pub mod aligner;
 pub mod backtrace;
 pub mod band_2d;
 pub mod gap_open;
@@ -8,5 +10,6 @@ pub mod remove_gaps;
 pub mod score_matrix;
 pub mod score_matrix_aa;
 pub mod score_matrix_nuc;
+pub mod score_matrix_simd;
 pub mod seed_alignment;
 pub mod seed_match;
@@ -1,3 +1,4 @@
+use crate::translate::genetic_code::GeneticCode;
 use crate::utils::any::AnyType;
 use crate::{make_error, o};
 use clap::{Parser, ValueEnum};
@@ -39,6 +40,54 @@ impl Default for AlignmentPreset {
     Self::Default
   }
 }
+
+#[derive(ValueEnum, Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize, schemars::JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum NucScoringMatrix {
+  #[clap(
+    help = "Matching nucleotides receive `--score-match`, all mismatches receive `--penalty-mismatch` (this is the default)"
+  )]
+  MatchMismatch,
+
+  #[clap(help = "As `match-mismatch`, but transitions (A<->G and C<->T) receive `--penalty-transition`")]
+  TransitionTransversion,
+}
+
+impl Default for NucScoringMatrix {
+  fn default() -> Self {
+    Self::MatchMismatch
+  }
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize, schemars::JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum AaScoringMatrix {
+  #[clap(
+    help = "Matching amino acids receive `--score-match`, all mismatches receive `--penalty-mismatch` (this is the default)"
+  )]
+  MatchMismatch,
+
+  #[clap(help = "BLOSUM62 substitution matrix")]
+  Blosum62,
+
+  #[clap(help = "PAM40 substitution matrix")]
+  Pam40,
+
+  #[clap(help = "PAM120 substitution matrix")]
+  Pam120,
+
+  #[clap(help = "PAM200 substitution matrix")]
+  Pam200,
+
+  #[clap(help = "PAM250 substitution matrix")]
+  Pam250,
+}
+
+impl Default for AaScoringMatrix {
+  fn default() -> Self {
+    Self::MatchMismatch
+  }
+}
 // NOTE: The `optfield` attribute creates a struct that have the same fields, but which are wrapped into `Option`,
 // as well as adds a method `.merge_opt(&opt)` to the original struct, which merges values from the optional counterpart
 // into self (mutably).
@@ -82,6 +131,20 @@ pub struct AlignPairwiseParams {
   #[clap(long)]
   pub score_match: i32,
 
+  /// Scoring of aligned nucleotides.
+  #[clap(long, value_enum)]
+  pub nuc_scoring_matrix: NucScoringMatrix,
+
+  /// Penalty for transitions (A<->G and C<->T) in nucleotide alignments. Required if `--nuc-scoring-matrix=transition-transversion` is used, and ignored otherwise. All other mismatches (transversions) are penalized with `--penalty-mismatch`. Should not be greater than `--penalty-mismatch`, and is usually less than it: equal values score alignments the same as `--nuc-scoring-matrix=match-mismatch`.
+  #[clap(long)]
+  pub penalty_transition: Option<i32>,
+
+  /// Scoring of aligned amino acids.
+  ///
+  /// Substitution matrices score every pair of amino acids individually, which can improve alignment of divergent CDSes. Note that the scores in these matrices are on a different scale than `--score-match` and `--penalty-mismatch`, so gap penalties might need to be adjusted accordingly.
+  #[clap(long, value_enum)]
+  pub aa_scoring_matrix: AaScoringMatrix,
+
   /// Maximum area of the band in the alignment matrix. Alignments with large bands are slow to compute and require substantial memory. Alignment of sequences requiring bands with area larger than this value, will not be attempted and a warning will be emitted.
   #[clap(long)]
   pub max_band_area: usize,
@@ -96,6 +159,12 @@ pub struct AlignPairwiseParams {
   #[clap(num_args=0..=1, default_missing_value = "true")]
   pub no_translate_past_stop: bool,
 
+  /// Genetic code (NCBI translation table number) used to translate CDSes which do not specify one.
+  ///
+  /// A CDS in genome annotation can specify its own genetic code using `transl_table` attribute, which takes precedence. All NCBI translation tables are supported, see https://www.ncbi.nlm.nih.gov/Taxonomy/Utils/wprintgc.cgi. Default: 1 (standard code).
+  #[clap(long)]
+  pub genetic_code: GeneticCode,
+
   // Internal alignment parameter
   #[clap(skip)]
   pub left_terminal_gaps_free: bool,
@@ -146,6 +215,20 @@ pub struct AlignPairwiseParams {
   #[clap(long)]
   pub max_alignment_attempts: usize,
 
+  /// Instead of failing, align sequences which require a band larger than `--max-band-area` piece by piece.
+  ///
+  /// The sequence is split at seed matches into segments, each of which is aligned separately with a band no larger than the maximum band area. This is slower than the regular alignment and the result can be slightly suboptimal around the split points, but the memory usage stays bounded. The alignment mode used for each sequence is reported in the `alignmentMode` output column.
+  #[clap(long)]
+  #[clap(num_args=0..=1, default_missing_value = "true")]
+  pub chained_alignment_fallback: bool,
+
+  /// Treat the reference genome as circular.
+  ///
+  /// Query sequences of circular genomes, such as HBV, HPV or plasmids, can start at a different position than the reference. In this mode, such queries are rotated to start at the origin of the reference before the alignment, instead of being aligned with large terminal indels. The number of nucleotides moved from the beginning to the end of the query is reported in the `circularRotation` output column. All output coordinates are relative to the reference.
+  #[clap(long)]
+  #[clap(num_args=0..=1, default_missing_value = "true")]
+  pub circular: bool,
+
   // The following args are deprecated and are kept for backwards compatibility (to emit errors if they are set)
   /// REMOVED
   #[clap(long, hide_long_help = true, hide_short_help = true)]
@@ -184,9 +267,13 @@ impl Default for AlignPairwiseParams {
       penalty_gap_open_out_of_frame: 8,
       penalty_mismatch: 1,
       score_match: 3,
+      nuc_scoring_matrix: NucScoringMatrix::default(),
+      penalty_transition: None,
+      aa_scoring_matrix: AaScoringMatrix::default(),
       max_band_area: 500_000_000, // requires around 500Mb for paths, 2GB for the scores
       retry_reverse_complement: false,
       no_translate_past_stop: false,
+      genetic_code: GeneticCode::default(),
       left_terminal_gaps_free: true,
       right_terminal_gaps_free: true,
       gap_alignment_side: GapAlignmentSide::default(),
@@ -199,6 +286,8 @@ impl Default for AlignPairwiseParams {
       allowed_mismatches: 8, // Ns count as mismatches
       window_size: 30,
       max_alignment_attempts: 3,
+      chained_alignment_fallback: false,
+      circular: false,
 
       // The following args are deprecated and are kept for backwards compatibility (to emit errors if they are set)
       max_indel: None,
@@ -271,6 +360,80 @@ Refer to user documentation for more details."#
       );
     }
 
+    if self.penalty_mismatch < 0 {
+      return make_error!(
+        "Alignment parameter '--penalty-mismatch' (penaltyMismatch) should be non-negative, but found: {}",
+        self.penalty_mismatch
+      );
+    }
+
+    if let Some(penalty_transition) = self.penalty_transition {
+      if penalty_transition < 0 {
+        return make_error!(
+          "Alignment parameter '--penalty-transition' (penaltyTransition) should be non-negative, but found: {penalty_transition}"
+        );
+      }
+    }
+
+    if self.nuc_scoring_matrix == NucScoringMatrix::TransitionTransversion {
+      let Some(penalty_transition) = self.penalty_transition else {
+        return make_error!(
+          "Alignment parameter '--penalty-transition' (penaltyTransition) is required when '--nuc-scoring-matrix' (nucScoringMatrix) is set to 'transition-transversion'"
+        );
+      };
+
+      if penalty_transition > self.penalty_mismatch {
+        return make_error!(
+          "Alignment parameter '--penalty-transition' (penaltyTransition) should not be greater than '--penalty-mismatch' (penaltyMismatch), which is used for transversions, but found: {} > {}",
+          penalty_transition,
+          self.penalty_mismatch
+        );
+      }
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use assert2::assert;
+  use rstest::rstest;
+
+  #[rstest]
+  #[case(NucScoringMatrix::MatchMismatch, 1, None, true)]
+  #[case(NucScoringMatrix::TransitionTransversion, 2, Some(1), true)]
+  #[case(NucScoringMatrix::TransitionTransversion, 2, Some(2), true)]
+  #[case(NucScoringMatrix::TransitionTransversion, 2, None, false)]
+  #[case(NucScoringMatrix::TransitionTransversion, 1, Some(2), false)]
+  #[case(NucScoringMatrix::MatchMismatch, 1, Some(-1), false)]
+  #[case(NucScoringMatrix::MatchMismatch, -1, None, false)]
+  fn validates_scoring_params(
+    #[case] nuc_scoring_matrix: NucScoringMatrix,
+    #[case] penalty_mismatch: i32,
+    #[case] penalty_transition: Option<i32>,
+    #[case] is_valid: bool,
+  ) {
+    let params = AlignPairwiseParams {
+      penalty_mismatch,
+      nuc_scoring_matrix,
+      penalty_transition,
+      ..AlignPairwiseParams::default()
+    };
+    assert!(params.validate().is_ok() == is_valid);
+  }
+
+  #[rstest]
+  fn parses_scoring_params_from_json() -> Result<(), Report> {
+    let params: AlignPairwiseParamsOptional = serde_json::from_str(
+      r#"{ "nucScoringMatrix": "transition-transversion", "penaltyTransition": 1, "aaScoringMatrix": "blosum62" }"#,
+    )?;
+    let mut merged = AlignPairwiseParams::default();
+    merged.merge_opt(params);
+    assert!(merged.nuc_scoring_matrix == NucScoringMatrix::TransitionTransversion);
+    assert!(merged.penalty_transition == Some(1));
+    assert!(merged.aa_scoring_matrix == AaScoringMatrix::Blosum62);
     Ok(())
   }
 }
@@ -1,7 +1,9 @@
 use crate::align::band_2d::{Band2d, Stripe};
 use crate::align::params::{AlignPairwiseParams, GapAlignmentSide};
+use crate::align::score_matrix_simd::{score_row_vertical, ScoreMatrixSimd, ScoreRowVertical};
 use crate::alphabet::letter::Letter;
 use log::trace;
+use std::collections::BTreeMap;
 
 // store direction info for backtrace as bits in paths matrix
 // these indicate the currently optimal move
@@ -20,6 +22,34 @@ pub struct ScoreMatrixResult {
   pub paths: Band2d<i8>,
 }
 
+/// Working memory of the score matrix computation.
+///
+/// Allocating the score and path matrices is a considerable part of the cost of aligning a sequence. When aligning
+/// many sequences, keep one instance per thread and pass it to `score_matrix_into()` repeatedly: the memory is reused
+/// and only grows when a larger band is needed.
+#[derive(Clone, Default)]
+pub struct ScoreMatrixScratch {
+  scores: Band2d<i32>,
+  paths: Band2d<i8>,
+  qry_gaps: Vec<i32>,
+  profile: Vec<i32>,
+  row_delta: Vec<i32>,
+  row_diag: Vec<i32>,
+  row_flags: Vec<i32>,
+}
+
+impl ScoreMatrixScratch {
+  /// Scores computed by the last call to `score_matrix_into()`
+  pub const fn scores(&self) -> &Band2d<i32> {
+    &self.scores
+  }
+
+  /// Paths computed by the last call to `score_matrix_into()`
+  pub const fn paths(&self) -> &Band2d<i8> {
+    &self.paths
+  }
+}
+
 pub fn score_matrix<T: Letter<T>>(
   qry_seq: &[T],
   ref_seq: &[T],
@@ -27,6 +57,61 @@ pub fn score_matrix<T: Letter<T>>(
   stripes: &[Stripe],
   params: &AlignPairwiseParams,
 ) -> ScoreMatrixResult {
+  let mut scratch = ScoreMatrixScratch::default();
+  score_matrix_into(qry_seq, ref_seq, gap_open_close, stripes, params, &mut scratch);
+  let ScoreMatrixScratch { scores, paths, .. } = scratch;
+  ScoreMatrixResult { scores, paths }
+}
+
+/// Same as `score_matrix()`, but writes the resulting matrices into `scratch`, reusing memory allocated by previous
+/// calls.
+///
+/// Uses SIMD instructions if the CPU supports them (see `ScoreMatrixSimd::detect()`).
+pub fn score_matrix_into<T: Letter<T>>(
+  qry_seq: &[T],
+  ref_seq: &[T],
+  gap_open_close: &[i32],
+  stripes: &[Stripe],
+  params: &AlignPairwiseParams,
+  scratch: &mut ScoreMatrixScratch,
+) {
+  score_matrix_into_with(
+    ScoreMatrixSimd::detect(),
+    qry_seq,
+    ref_seq,
+    gap_open_close,
+    stripes,
+    params,
+    scratch,
+  );
+}
+
+/// Same as `score_matrix_into()`, but with explicitly chosen SIMD instruction set. Falls back to the scalar
+/// implementation if `simd` is `None` or if the instruction set is not supported by the CPU. The resulting scores and
+/// paths are the same in all cases.
+pub fn score_matrix_into_with<T: Letter<T>>(
+  simd: Option<ScoreMatrixSimd>,
+  qry_seq: &[T],
+  ref_seq: &[T],
+  gap_open_close: &[i32],
+  stripes: &[Stripe],
+  params: &AlignPairwiseParams,
+  scratch: &mut ScoreMatrixScratch,
+) {
+  match simd.filter(|simd| simd.is_supported()) {
+    Some(simd) => score_matrix_into_vectorized(simd, qry_seq, ref_seq, gap_open_close, stripes, params, scratch),
+    None => score_matrix_into_scalar(qry_seq, ref_seq, gap_open_close, stripes, params, scratch),
+  }
+}
+
+fn score_matrix_into_scalar<T: Letter<T>>(
+  qry_seq: &[T],
+  ref_seq: &[T],
+  gap_open_close: &[i32],
+  stripes: &[Stripe],
+  params: &AlignPairwiseParams,
+  scratch: &mut ScoreMatrixScratch,
+) {
   assert!(gap_open_close.len() > 0);
   assert!(stripes.len() > 0);
 
@@ -37,8 +122,15 @@ pub fn score_matrix<T: Letter<T>>(
 
   trace!("Score matrix: started: query_size={query_size}, ref_len={ref_len}, n_rows={n_rows}, n_cols={n_cols}");
 
-  let mut paths = Band2d::<i8>::new(stripes);
-  let mut scores = Band2d::<i32>::new(stripes);
+  let ScoreMatrixScratch {
+    scores,
+    paths,
+    qry_gaps,
+    ..
+  } = scratch;
+
+  paths.reset(stripes);
+  scores.reset(stripes);
   let band_size = paths.data_len();
 
   trace!("Score matrix: allocated alignment band of size={band_size}");
@@ -61,26 +153,9 @@ pub fn score_matrix<T: Letter<T>>(
   // 2) if X is a base and Y is '-', rPos advances the same and the shift increases
   //    -> diagonal step in the matrix from (ri,si-1) to (ri+1,si)
 
-  paths[(0, 0)] = 0;
-  scores[(0, 0)] = 0;
-
-  // Initialize first row (start at + 1 since [(0,0)] is already set)
-  for qpos in (stripes[0].begin + 1)..stripes[0].end {
-    paths[(0, qpos)] = REF_GAP_EXTEND + REF_GAP_MATRIX;
-    if params.left_terminal_gaps_free {
-      // Left terminal qry insertion  is free
-      scores[(0, qpos)] = 0;
-    } else {
-      // Left terminal qry insertion is not free
-      // TODO: Consider whether qry insertion should ever be free, not only qry deletion!
-      if qpos == 1 {
-        scores[(0, 1)] = -gap_open_close[0];
-      } else {
-        scores[(0, qpos)] = scores[(0, qpos - 1)] - params.penalty_gap_extend;
-      }
-    }
-  }
-  let mut qry_gaps = vec![NO_ALIGN; n_cols];
+  score_first_row(scores, paths, gap_open_close, stripes, params);
+  qry_gaps.clear();
+  qry_gaps.resize(n_cols, NO_ALIGN);
 
   // Iterate over rows
   for ri in 1..=ref_len {
@@ -101,32 +176,14 @@ pub fn score_matrix<T: Letter<T>>(
         // precedes query sequence -- no score, origin is query gap
         tmp_path = QRY_GAP_EXTEND;
         origin = QRY_GAP_MATRIX;
-        if params.left_terminal_gaps_free {
-          // Left terminal qry gap is free
-          score = 0;
-        } else {
-          // Left terminal qry gap is not free
-          if ri == 1 {
-            score = -gap_open_close[0];
-          } else {
-            score = scores[(ri - 1, 0)] - params.penalty_gap_extend;
-          }
-        }
+        score = score_first_column(ri, scores[(ri - 1, 0)], gap_open_close, params);
       } else {
         // if the position is within the query sequence
         // no gap -- match case
 
         // ^ If stripes allow to move up diagonally to upper left
         if qpos > stripes[ri - 1].begin && qpos - 1 < stripes[ri - 1].end {
-          score = if qry_seq[qpos - 1].is_unknown() || ref_seq[ri - 1].is_unknown() {
-            // no need to look-up match score since unknown matches with everything.
-            // reduce match score by 1 to de-prioritize matches with unknown states.
-            scores[(ri - 1, qpos - 1)] + params.score_match - 1
-          } else if T::lookup_match_score(qry_seq[qpos - 1], ref_seq[ri - 1]) > 0 {
-            scores[(ri - 1, qpos - 1)] + params.score_match
-          } else {
-            scores[(ri - 1, qpos - 1)] - params.penalty_mismatch
-          };
+          score = scores[(ri - 1, qpos - 1)] + match_score(qry_seq[qpos - 1], ref_seq[ri - 1], params);
           origin = MATCH;
         } else {
           tmp_path = tmp_path | BOUNDARY; // mark boundary when possible moves are restricted. here: can't move up or left-up
@@ -199,18 +256,283 @@ pub fn score_matrix<T: Letter<T>>(
       scores[(ri, qpos)] = score;
     }
   }
-  ScoreMatrixResult { scores, paths }
+}
+
+/// Vectorized version of `score_matrix_into_scalar()`.
+///
+/// Rows are processed one by one. In the interior of the band, where all moves are allowed, match and query gap scores
+/// only depend on the previous row, and they are computed using SIMD instructions. Then, the reference gap scores,
+/// which depend on the cell to the left, and the final scores and paths are computed sequentially. Cells at the edges
+/// of the band are computed the same way as in the scalar code.
+fn score_matrix_into_vectorized<T: Letter<T>>(
+  simd: ScoreMatrixSimd,
+  qry_seq: &[T],
+  ref_seq: &[T],
+  gap_open_close: &[i32],
+  stripes: &[Stripe],
+  params: &AlignPairwiseParams,
+  scratch: &mut ScoreMatrixScratch,
+) {
+  assert!(!gap_open_close.is_empty());
+  assert!(!stripes.is_empty());
+
+  let query_size = qry_seq.len();
+  let ref_len = ref_seq.len();
+  let n_rows = ref_len + 1;
+  let n_cols = query_size + 1;
+
+  trace!(
+    "Score matrix (vectorized, {simd:?}): started: query_size={query_size}, ref_len={ref_len}, n_rows={n_rows}, n_cols={n_cols}"
+  );
+
+  let ScoreMatrixScratch {
+    scores,
+    paths,
+    qry_gaps,
+    profile,
+    row_delta,
+    row_diag,
+    row_flags,
+  } = scratch;
+
+  paths.reset(stripes);
+  scores.reset(stripes);
+  let band_size = paths.data_len();
+
+  let left_align = match params.gap_alignment_side {
+    GapAlignmentSide::Left => 1,
+    GapAlignmentSide::Right => 0,
+  };
+
+  score_first_row(scores, paths, gap_open_close, stripes, params);
+  qry_gaps.clear();
+  qry_gaps.resize(n_cols, NO_ALIGN);
+
+  // Match scores of each query letter against a given reference letter (query profile). These are computed once per
+  // distinct reference letter, as long as the profile is not larger than the band.
+  profile.clear();
+  let mut profile_rows = BTreeMap::<T, usize>::new();
+
+  for ri in 1..=ref_len {
+    let stripe = &stripes[ri];
+    let stripe_prev = &stripes[ri - 1];
+    let ref_letter = ref_seq[ri - 1];
+
+    // Interior of the band: cells where match and query gap moves are allowed, excluding the last column, where
+    // query gaps can be free
+    let lo = stripe.begin.max(stripe_prev.begin + 1).max(1);
+    let hi = stripe.end.min(stripe_prev.end).min(query_size);
+
+    let (scores_prev, scores_curr) = scores.row_with_previous_mut(ri);
+
+    if lo < hi {
+      let n_profile_rows = profile_rows.len();
+      let profile_row = match profile_rows.get(&ref_letter) {
+        Some(&profile_row) => Some(profile_row),
+        None if profile.len() + query_size <= band_size => {
+          profile.extend(
+            qry_seq
+              .iter()
+              .map(|&qry_letter| match_score(qry_letter, ref_letter, params)),
+          );
+          profile_rows.insert(ref_letter, n_profile_rows);
+          Some(n_profile_rows)
+        }
+        None => None,
+      };
+
+      let delta = if let Some(profile_row) = profile_row {
+        let offset = profile_row * query_size;
+        &profile[offset + lo - 1..offset + hi - 1]
+      } else {
+        row_delta.clear();
+        row_delta.extend(
+          qry_seq[lo - 1..hi - 1]
+            .iter()
+            .map(|&qry_letter| match_score(qry_letter, ref_letter, params)),
+        );
+        row_delta.as_slice()
+      };
+
+      row_diag.clear();
+      row_diag.resize(hi - lo, 0);
+      row_flags.clear();
+      row_flags.resize(hi - lo, 0);
+
+      let prev2_end = if ri >= 2 { stripes[ri - 2].end } else { 0 };
+
+      score_row_vertical(
+        Some(simd),
+        &mut ScoreRowVertical {
+          prev: &scores_prev[lo - 1 - stripe_prev.begin..hi - stripe_prev.begin],
+          delta,
+          qry_gaps: &mut qry_gaps[lo..hi],
+          diag: row_diag,
+          flags: row_flags,
+          ext_allowed: prev2_end.saturating_sub(lo),
+          penalty_gap_extend: params.penalty_gap_extend,
+          gap_open: gap_open_close[ri - 1],
+        },
+      );
+    }
+
+    let paths_curr = paths.row_mut(ri);
+    let mut ref_gaps = NO_ALIGN;
+    let mut score_left = NO_ALIGN;
+
+    for qpos in stripe.begin..stripe.end {
+      let mut tmp_path = 0;
+      let mut score = NO_ALIGN;
+      let mut origin = 0;
+
+      if qpos == 0 {
+        tmp_path = QRY_GAP_EXTEND;
+        origin = QRY_GAP_MATRIX;
+        score = score_first_column(ri, scores_prev[0], gap_open_close, params);
+      } else {
+        let is_interior = lo <= qpos && qpos < hi;
+
+        // match
+        if qpos > stripe_prev.begin && qpos - 1 < stripe_prev.end {
+          score = if is_interior {
+            row_diag[qpos - lo]
+          } else {
+            scores_prev[qpos - 1 - stripe_prev.begin] + match_score(qry_seq[qpos - 1], ref_letter, params)
+          };
+          origin = MATCH;
+        } else {
+          tmp_path |= BOUNDARY;
+        }
+
+        // reference gap
+        if qpos > stripe.begin {
+          let (r_gap_extend, r_gap_open) = if ri != ref_len || !params.right_terminal_gaps_free {
+            (ref_gaps - params.penalty_gap_extend, score_left - gap_open_close[ri])
+          } else {
+            (ref_gaps, score_left)
+          };
+          if r_gap_extend >= r_gap_open && qpos > stripe.begin + 1 {
+            ref_gaps = r_gap_extend;
+            tmp_path += REF_GAP_EXTEND;
+          } else {
+            ref_gaps = r_gap_open;
+          }
+          if score - left_align < ref_gaps {
+            score = ref_gaps;
+            origin = REF_GAP_MATRIX;
+          }
+        } else if ri < n_rows - 1 {
+          tmp_path |= BOUNDARY;
+        }
+
+        // query gap
+        if qpos < stripe_prev.end {
+          let q_gap = if is_interior {
+            tmp_path += row_flags[qpos - lo] as i8;
+            qry_gaps[qpos]
+          } else {
+            let score_above = scores_prev[qpos - stripe_prev.begin];
+            let (q_gap_extend, q_gap_open) = if qpos != query_size || !params.right_terminal_gaps_free {
+              (
+                qry_gaps[qpos] - params.penalty_gap_extend,
+                score_above - gap_open_close[ri - 1],
+              )
+            } else {
+              (qry_gaps[qpos], score_above)
+            };
+            if q_gap_extend >= q_gap_open && qpos < stripes[ri - 2].end {
+              tmp_path += QRY_GAP_EXTEND;
+              q_gap_extend
+            } else {
+              q_gap_open
+            }
+          };
+          qry_gaps[qpos] = q_gap;
+          if score - left_align < q_gap {
+            score = q_gap;
+            origin = QRY_GAP_MATRIX;
+          }
+        } else if qpos < n_cols - 1 {
+          qry_gaps[qpos] = NO_ALIGN;
+          tmp_path |= BOUNDARY;
+        }
+      }
+
+      paths_curr[qpos - stripe.begin] = tmp_path + origin;
+      scores_curr[qpos - stripe.begin] = score;
+      score_left = score;
+    }
+  }
+}
+
+/// Initializes the first row of the matrices: leading insertions in the query
+fn score_first_row(
+  scores: &mut Band2d<i32>,
+  paths: &mut Band2d<i8>,
+  gap_open_close: &[i32],
+  stripes: &[Stripe],
+  params: &AlignPairwiseParams,
+) {
+  let first_stripe = &stripes[0];
+
+  paths[(0, 0)] = 0;
+  scores[(0, 0)] = 0;
+
+  // Initialize first row (start at + 1 since [(0,0)] is already set)
+  for qpos in (first_stripe.begin + 1)..first_stripe.end {
+    paths[(0, qpos)] = REF_GAP_EXTEND + REF_GAP_MATRIX;
+    if params.left_terminal_gaps_free {
+      // Left terminal qry insertion  is free
+      scores[(0, qpos)] = 0;
+    } else {
+      // Left terminal qry insertion is not free
+      // TODO: Consider whether qry insertion should ever be free, not only qry deletion!
+      if qpos == 1 {
+        scores[(0, 1)] = -gap_open_close[0];
+      } else {
+        scores[(0, qpos)] = scores[(0, qpos - 1)] - params.penalty_gap_extend;
+      }
+    }
+  }
+}
+
+/// Score of the first column in a given row: leading deletions in the query
+const fn score_first_column(ri: usize, score_above: i32, gap_open_close: &[i32], params: &AlignPairwiseParams) -> i32 {
+  if params.left_terminal_gaps_free {
+    // Left terminal qry gap is free
+    0
+  } else if ri == 1 {
+    // Left terminal qry gap is not free
+    -gap_open_close[0]
+  } else {
+    score_above - params.penalty_gap_extend
+  }
+}
+
+/// Score of aligning a query letter against a reference letter
+#[inline]
+fn match_score<T: Letter<T>>(qry_letter: T, ref_letter: T, params: &AlignPairwiseParams) -> i32 {
+  if qry_letter.is_unknown() || ref_letter.is_unknown() {
+    // no need to look-up match score since unknown matches with everything.
+    // reduce match score by 1 to de-prioritize matches with unknown states.
+    params.score_match - 1
+  } else {
+    T::lookup_substitution_score(qry_letter, ref_letter, params)
+  }
 }
 
 #[cfg(test)]
 mod tests {
   #![allow(clippy::needless_pass_by_value)] // rstest fixtures are passed by value
   use super::*;
-  use crate::align::band_2d::simple_stripes;
+  use crate::align::band_2d::{full_matrix, simple_stripes};
   use crate::align::gap_open::{get_gap_open_close_scores_codon_aware, GapScoreMap};
+  use crate::align::params::{AaScoringMatrix, NucScoringMatrix};
 
+  use crate::alphabet::aa::to_aa_seq;
   use crate::alphabet::nuc::{to_nuc_seq, Nuc};
   use crate::gene::gene_map::GeneMap;
+  use crate::test_utils::Xorshift64;
   use eyre::Report;
   use pretty_assertions::assert_eq;
   use rstest::{fixture, rstest};
@@ -346,4 +668,130 @@ mod tests {
 
     Ok(())
   }
+
+  /// Deterministic pseudo-random sequence of letters from the given alphabet, with mutations applied to a copy of it
+  fn random_seqs(alphabet: &[u8], len: usize, seed: u64) -> (String, String) {
+    let mut rng = Xorshift64::new(seed);
+
+    let ref_seq = (0..len)
+      .map(|_| alphabet[rng.next_below(alphabet.len())])
+      .collect::<Vec<u8>>();
+    let mut qry_seq = vec![];
+    for &letter in &ref_seq {
+      match rng.next_below(40) {
+        0 => {}                                                                  // deletion
+        1 => qry_seq.extend([letter, alphabet[rng.next_below(alphabet.len())]]), // insertion
+        2 | 3 => qry_seq.push(alphabet[rng.next_below(alphabet.len())]),         // substitution
+        _ => qry_seq.push(letter),
+      }
+    }
+
+    (String::from_utf8(ref_seq).unwrap(), String::from_utf8(qry_seq).unwrap())
+  }
+
+  fn assert_same_as_scalar<T: Letter<T>>(
+    qry_seq: &[T],
+    ref_seq: &[T],
+    gap_open_close: &[i32],
+    stripes: &[Stripe],
+    params: &AlignPairwiseParams,
+  ) {
+    let mut expected = ScoreMatrixScratch::default();
+    score_matrix_into_with(None, qry_seq, ref_seq, gap_open_close, stripes, params, &mut expected);
+
+    for simd in [ScoreMatrixSimd::Sse2, ScoreMatrixSimd::Avx2, ScoreMatrixSimd::Neon] {
+      if !simd.is_supported() {
+        continue;
+      }
+      let mut actual = ScoreMatrixScratch::default();
+      score_matrix_into_with(
+        Some(simd),
+        qry_seq,
+        ref_seq,
+        gap_open_close,
+        stripes,
+        params,
+        &mut actual,
+      );
+      assert_eq!(expected.scores(), actual.scores(), "Scores differ for {simd:?}");
+      assert_eq!(expected.paths(), actual.paths(), "Paths differ for {simd:?}");
+    }
+  }
+
+  #[rstest]
+  #[case(false, false, GapAlignmentSide::Left, NucScoringMatrix::MatchMismatch)]
+  #[case(true, false, GapAlignmentSide::Left, NucScoringMatrix::MatchMismatch)]
+  #[case(false, true, GapAlignmentSide::Right, NucScoringMatrix::MatchMismatch)]
+  #[case(true, true, GapAlignmentSide::Right, NucScoringMatrix::MatchMismatch)]
+  #[case(true, true, GapAlignmentSide::Left, NucScoringMatrix::TransitionTransversion)]
+  fn vectorized_nuc_score_matrix_is_identical_to_scalar(
+    #[case] left_terminal_gaps_free: bool,
+    #[case] right_terminal_gaps_free: bool,
+    #[case] gap_alignment_side: GapAlignmentSide,
+    #[case] nuc_scoring_matrix: NucScoringMatrix,
+  ) -> Result<(), Report> {
+    let params = AlignPairwiseParams {
+      left_terminal_gaps_free,
+      right_terminal_gaps_free,
+      gap_alignment_side,
+      nuc_scoring_matrix,
+      penalty_mismatch: 2,
+      penalty_transition: Some(1),
+      ..AlignPairwiseParams::default()
+    };
+
+    for seed in 1..=10 {
+      let (ref_seq, qry_seq) = random_seqs(b"ACGTACGTACGTNRY-", 200 + 17 * seed as usize, seed);
+      let ref_seq = to_nuc_seq(&ref_seq)?;
+      let qry_seq = to_nuc_seq(&qry_seq)?;
+      let gap_open_close = get_gap_open_close_scores_codon_aware(&ref_seq, &GeneMap::new(), &params);
+
+      let mean_shift = ref_seq.len() as i32 - qry_seq.len() as i32;
+      for band_width in [1, 3, 8, 21] {
+        let mut stripes = simple_stripes(mean_shift, band_width, ref_seq.len(), qry_seq.len());
+        assert_same_as_scalar(&qry_seq, &ref_seq, &gap_open_close, &stripes, &params);
+
+        // Irregular band
+        for (i, stripe) in stripes.iter_mut().enumerate().skip(1) {
+          if i % 7 == 0 && stripe.end > stripe.begin + 1 {
+            stripe.end -= 1;
+          }
+        }
+        assert_same_as_scalar(&qry_seq, &ref_seq, &gap_open_close, &stripes, &params);
+      }
+
+      let stripes = full_matrix(ref_seq.len(), qry_seq.len());
+      assert_same_as_scalar(&qry_seq, &ref_seq, &gap_open_close, &stripes, &params);
+    }
+
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(AaScoringMatrix::MatchMismatch)]
+  #[case(AaScoringMatrix::Blosum62)]
+  #[case(AaScoringMatrix::Pam250)]
+  fn vectorized_aa_score_matrix_is_identical_to_scalar(
+    #[case] aa_scoring_matrix: AaScoringMatrix,
+  ) -> Result<(), Report> {
+    let params = AlignPairwiseParams {
+      aa_scoring_matrix,
+      ..AlignPairwiseParams::default()
+    };
+
+    for seed in 1..=10 {
+      let (ref_seq, qry_seq) = random_seqs(b"ACDEFGHIKLMNPQRSTVWYX*", 150 + 13 * seed as usize, seed);
+      let ref_seq = to_aa_seq(&ref_seq)?;
+      let qry_seq = to_aa_seq(&qry_seq)?;
+      let gap_open_close = vec![params.penalty_gap_open_in_frame; ref_seq.len() + 2];
+
+      let mean_shift = ref_seq.len() as i32 - qry_seq.len() as i32;
+      for band_width in [2, 9, 30] {
+        let stripes = simple_stripes(mean_shift, band_width, ref_seq.len(), qry_seq.len());
+        assert_same_as_scalar(&qry_seq, &ref_seq, &gap_open_close, &stripes, &params);
+      }
+    }
+
+    Ok(())
+  }
 }
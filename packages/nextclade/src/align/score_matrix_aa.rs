@@ -1,4 +1,6 @@
-use crate::alphabet::aa::Aa;
+use crate::align::params::{AaScoringMatrix, AlignPairwiseParams};
+use crate::alphabet::aa::{from_aa, Aa};
+use crate::alphabet::letter::Letter;
 
 const NUM_COLS: usize = 28;
 const SCORING_MATRIX_AA_SIZE: usize = NUM_COLS * NUM_COLS;
@@ -40,3 +42,65 @@ static SCORING_MATRIX_AA: &[i32; SCORING_MATRIX_AA_SIZE] = &[
 pub fn lookup_aa_scoring_matrix(x: Aa, y: Aa) -> i32 {
   SCORING_MATRIX_AA[x as usize * NUM_COLS + y as usize]
 }
+
+/// Score of aligning two amino acids, none of which is unknown, according to the selected scoring matrix
+pub fn lookup_aa_substitution_score(x: Aa, y: Aa, params: &AlignPairwiseParams) -> i32 {
+  let substitution_matrix: fn(u8, u8) -> i32 = match params.aa_scoring_matrix {
+    AaScoringMatrix::MatchMismatch => return lookup_aa_match_mismatch_score(x, y, params),
+    AaScoringMatrix::Blosum62 => bio::scores::blosum62,
+    AaScoringMatrix::Pam40 => bio::scores::pam40,
+    AaScoringMatrix::Pam120 => bio::scores::pam120,
+    AaScoringMatrix::Pam200 => bio::scores::pam200,
+    AaScoringMatrix::Pam250 => bio::scores::pam250,
+  };
+
+  // Substitution matrices have no entries for gaps
+  if x.is_gap() || y.is_gap() {
+    return lookup_aa_match_mismatch_score(x, y, params);
+  }
+
+  substitution_matrix(from_aa(x) as u8, from_aa(y) as u8)
+}
+
+fn lookup_aa_match_mismatch_score(x: Aa, y: Aa, params: &AlignPairwiseParams) -> i32 {
+  if lookup_aa_scoring_matrix(x, y) > 0 {
+    params.score_match
+  } else {
+    -params.penalty_mismatch
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::alphabet::aa::to_aa;
+  use eyre::Report;
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+
+  #[rstest]
+  #[case(AaScoringMatrix::MatchMismatch, 'W', 'W', 3)]
+  #[case(AaScoringMatrix::MatchMismatch, 'I', 'L', -1)]
+  #[case(AaScoringMatrix::MatchMismatch, 'B', 'D', 3)]
+  #[case(AaScoringMatrix::Blosum62, 'W', 'W', 11)]
+  #[case(AaScoringMatrix::Blosum62, 'I', 'L', 2)]
+  #[case(AaScoringMatrix::Blosum62, 'W', 'G', -2)]
+  #[case(AaScoringMatrix::Blosum62, '*', '*', 1)]
+  #[case(AaScoringMatrix::Blosum62, 'A', '-', -1)]
+  #[case(AaScoringMatrix::Blosum62, '-', '-', 3)]
+  #[case(AaScoringMatrix::Pam250, 'W', 'W', 17)]
+  #[case(AaScoringMatrix::Pam250, 'C', 'W', -8)]
+  fn scores_amino_acid_substitutions(
+    #[case] aa_scoring_matrix: AaScoringMatrix,
+    #[case] x: char,
+    #[case] y: char,
+    #[case] expected: i32,
+  ) -> Result<(), Report> {
+    let params = AlignPairwiseParams {
+      aa_scoring_matrix,
+      ..AlignPairwiseParams::default()
+    };
+    assert_eq!(expected, lookup_aa_substitution_score(to_aa(x)?, to_aa(y)?, &params));
+    Ok(())
+  }
+}
@@ -1,3 +1,4 @@
+use crate::align::params::{AlignPairwiseParams, NucScoringMatrix};
 use crate::alphabet::nuc::Nuc;
 
 const NUM_COLS: usize = 16;
@@ -28,3 +29,66 @@ static SCORING_MATRIX_NUC: &[i32; SCORING_MATRIX_NUC_SIZE] = &[
 pub fn lookup_nuc_scoring_matrix(x: Nuc, y: Nuc) -> i32 {
   SCORING_MATRIX_NUC[x as usize * NUM_COLS + y as usize]
 }
+
+/// Score of aligning two nucleotides, none of which is unknown, according to the selected scoring matrix
+pub fn lookup_nuc_substitution_score(x: Nuc, y: Nuc, params: &AlignPairwiseParams) -> i32 {
+  if lookup_nuc_scoring_matrix(x, y) > 0 {
+    params.score_match
+  } else if params.nuc_scoring_matrix == NucScoringMatrix::TransitionTransversion && is_transition(x, y) {
+    -params.penalty_transition.unwrap_or(params.penalty_mismatch)
+  } else {
+    -params.penalty_mismatch
+  }
+}
+
+/// Checks whether a substitution between two canonical nucleotides is a transition, i.e. a substitution within purines
+/// (A<->G) or within pyrimidines (C<->T)
+const fn is_transition(x: Nuc, y: Nuc) -> bool {
+  matches!(
+    (x, y),
+    (Nuc::A, Nuc::G) | (Nuc::G, Nuc::A) | (Nuc::C, Nuc::T) | (Nuc::T, Nuc::C)
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::alphabet::nuc::to_nuc;
+  use eyre::Report;
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+
+  #[rstest]
+  #[case('A', 'A', 3, 3)]
+  #[case('A', 'R', 3, 3)]
+  #[case('A', 'G', -4, -1)]
+  #[case('G', 'A', -4, -1)]
+  #[case('C', 'T', -4, -1)]
+  #[case('T', 'C', -4, -1)]
+  #[case('A', 'C', -4, -4)]
+  #[case('G', 'T', -4, -4)]
+  #[case('A', 'Y', -4, -4)]
+  fn scores_nucleotide_substitutions(
+    #[case] x: char,
+    #[case] y: char,
+    #[case] match_mismatch: i32,
+    #[case] transition_transversion: i32,
+  ) -> Result<(), Report> {
+    let params = AlignPairwiseParams {
+      score_match: 3,
+      penalty_mismatch: 4,
+      penalty_transition: Some(1),
+      ..AlignPairwiseParams::default()
+    };
+    let (x, y) = (to_nuc(x)?, to_nuc(y)?);
+
+    assert_eq!(match_mismatch, lookup_nuc_substitution_score(x, y, &params));
+
+    let params = AlignPairwiseParams {
+      nuc_scoring_matrix: NucScoringMatrix::TransitionTransversion,
+      ..params
+    };
+    assert_eq!(transition_transversion, lookup_nuc_substitution_score(x, y, &params));
+    Ok(())
+  }
+}
@@ -0,0 +1,296 @@
+//! Vectorized parts of the score matrix computation.
+//!
+//! Within a row of the score matrix, the match score and the query gap score of a cell only depend on the previous row,
+//! so they are computed for many cells at once, using SIMD instructions. The reference gap score depends on the cell to
+//! the left, in the same row, and it is computed sequentially, by the caller. The vectorized code performs exactly the
+//! same integer operations as the scalar code, so the resulting scores and paths are identical.
+
+use crate::align::score_matrix::QRY_GAP_EXTEND;
+
+/// Set of SIMD instructions used to compute the score matrix
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ScoreMatrixSimd {
+  /// 4 lanes, x86_64
+  Sse2,
+  /// 8 lanes, x86_64
+  Avx2,
+  /// 4 lanes, aarch64
+  Neon,
+}
+
+impl ScoreMatrixSimd {
+  /// Finds the widest instruction set supported by the current CPU, if any
+  pub fn detect() -> Option<Self> {
+    [Self::Avx2, Self::Sse2, Self::Neon]
+      .into_iter()
+      .find(|simd| simd.is_supported())
+  }
+
+  /// Checks whether the current CPU supports this instruction set
+  pub fn is_supported(self) -> bool {
+    match self {
+      #[cfg(target_arch = "x86_64")]
+      Self::Sse2 => is_x86_feature_detected!("sse2"),
+      #[cfg(target_arch = "x86_64")]
+      Self::Avx2 => is_x86_feature_detected!("avx2"),
+      #[cfg(target_arch = "aarch64")]
+      Self::Neon => std::arch::is_aarch64_feature_detected!("neon"),
+      #[allow(unreachable_patterns)]
+      _ => false,
+    }
+  }
+}
+
+/// Inputs and outputs of the vectorized part of the score matrix computation, for a contiguous range of `n` cells of a
+/// row. For a cell `k`:
+///
+///  - `prev[k]` is the score of the upper left cell and `prev[k + 1]` is the score of the cell above (length `n + 1`)
+///  - `delta[k]` is the match or mismatch score of the letters of the cell
+///  - `qry_gaps[k]` is the score of the query gap ending in the cell above. It is updated with the score of the query
+///    gap ending in this cell.
+///  - `diag[k]` receives the score of the match move
+///  - `flags[k]` receives `QRY_GAP_EXTEND` if the query gap is extended rather than opened, and 0 otherwise
+///
+/// Query gaps can only be extended in the first `ext_allowed` cells.
+pub struct ScoreRowVertical<'a> {
+  pub prev: &'a [i32],
+  pub delta: &'a [i32],
+  pub qry_gaps: &'a mut [i32],
+  pub diag: &'a mut [i32],
+  pub flags: &'a mut [i32],
+  pub ext_allowed: usize,
+  pub penalty_gap_extend: i32,
+  pub gap_open: i32,
+}
+
+/// Computes match and query gap scores for a range of cells of a row, using the given instruction set, if supported,
+/// and scalar code otherwise
+pub fn score_row_vertical(simd: Option<ScoreMatrixSimd>, row: &mut ScoreRowVertical) {
+  let n = row.diag.len();
+  assert_eq!(row.prev.len(), n + 1);
+  assert_eq!(row.delta.len(), n);
+  assert_eq!(row.qry_gaps.len(), n);
+  assert_eq!(row.flags.len(), n);
+
+  let simd = simd.filter(|simd| simd.is_supported());
+  let k = match simd {
+    #[cfg(target_arch = "x86_64")]
+    #[allow(unsafe_code)]
+    // SAFETY: the instruction set is supported by the CPU, and the lengths of the slices are checked above
+    Some(ScoreMatrixSimd::Sse2) => unsafe { x86::score_row_vertical_sse2(row) },
+    #[cfg(target_arch = "x86_64")]
+    #[allow(unsafe_code)]
+    // SAFETY: the instruction set is supported by the CPU, and the lengths of the slices are checked above
+    Some(ScoreMatrixSimd::Avx2) => unsafe { x86::score_row_vertical_avx2(row) },
+    #[cfg(target_arch = "aarch64")]
+    #[allow(unsafe_code)]
+    // SAFETY: the instruction set is supported by the CPU, and the lengths of the slices are checked above
+    Some(ScoreMatrixSimd::Neon) => unsafe { aarch64::score_row_vertical_neon(row) },
+    #[allow(unreachable_patterns)]
+    _ => 0,
+  };
+
+  // Remaining cells, which do not fill a whole vector
+  score_row_vertical_scalar(row, k);
+}
+
+fn score_row_vertical_scalar(row: &mut ScoreRowVertical, begin: usize) {
+  for k in begin..row.diag.len() {
+    row.diag[k] = row.prev[k] + row.delta[k];
+    let q_gap_extend = row.qry_gaps[k] - row.penalty_gap_extend;
+    let q_gap_open = row.prev[k + 1] - row.gap_open;
+    if q_gap_extend >= q_gap_open && k < row.ext_allowed {
+      row.qry_gaps[k] = q_gap_extend;
+      row.flags[k] = i32::from(QRY_GAP_EXTEND);
+    } else {
+      row.qry_gaps[k] = q_gap_open;
+      row.flags[k] = 0;
+    }
+  }
+}
+
+// Intrinsics are unsafe functions, so the kernels consist almost entirely of unsafe operations
+#[cfg(target_arch = "x86_64")]
+#[allow(unsafe_code, clippy::multiple_unsafe_ops_per_block)]
+#[allow(clippy::cast_ptr_alignment)] // unaligned loads and stores are used
+mod x86 {
+  use super::ScoreRowVertical;
+  use crate::align::score_matrix::QRY_GAP_EXTEND;
+  use std::arch::x86_64::{
+    __m128i, __m256i, _mm256_add_epi32, _mm256_and_si256, _mm256_andnot_si256, _mm256_cmpgt_epi32, _mm256_loadu_si256,
+    _mm256_or_si256, _mm256_set1_epi32, _mm256_setr_epi32, _mm256_storeu_si256, _mm256_sub_epi32, _mm_add_epi32,
+    _mm_and_si128, _mm_andnot_si128, _mm_cmpgt_epi32, _mm_loadu_si128, _mm_or_si128, _mm_set1_epi32, _mm_setr_epi32,
+    _mm_storeu_si128, _mm_sub_epi32,
+  };
+
+  /// Returns the number of cells processed
+  ///
+  /// # Safety
+  ///
+  /// The CPU must support SSE2. Slices must have lengths as described in `ScoreRowVertical`.
+  #[target_feature(enable = "sse2")]
+  pub unsafe fn score_row_vertical_sse2(row: &mut ScoreRowVertical) -> usize {
+    // SAFETY: the caller guarantees that the CPU supports the instruction set and that the slices have the
+    // expected lengths
+    unsafe {
+      const LANES: usize = 4;
+      let n = row.diag.len();
+      let penalty_gap_extend = _mm_set1_epi32(row.penalty_gap_extend);
+      let gap_open = _mm_set1_epi32(row.gap_open);
+      let flag = _mm_set1_epi32(i32::from(QRY_GAP_EXTEND));
+      let ext_allowed = _mm_set1_epi32(row.ext_allowed.min(n) as i32);
+      let step = _mm_set1_epi32(LANES as i32);
+      let mut index = _mm_setr_epi32(0, 1, 2, 3);
+
+      let mut k = 0;
+      while k + LANES <= n {
+        // Loads and stores are in bounds: k + LANES <= n, and `prev` has n + 1 elements
+        let upper_left = _mm_loadu_si128(row.prev.as_ptr().add(k).cast::<__m128i>());
+        let upper = _mm_loadu_si128(row.prev.as_ptr().add(k + 1).cast::<__m128i>());
+        let delta = _mm_loadu_si128(row.delta.as_ptr().add(k).cast::<__m128i>());
+        let qry_gaps = _mm_loadu_si128(row.qry_gaps.as_ptr().add(k).cast::<__m128i>());
+
+        let diag = _mm_add_epi32(upper_left, delta);
+        let q_gap_extend = _mm_sub_epi32(qry_gaps, penalty_gap_extend);
+        let q_gap_open = _mm_sub_epi32(upper, gap_open);
+
+        // q_gap_extend >= q_gap_open && k < ext_allowed
+        let is_extend = _mm_andnot_si128(
+          _mm_cmpgt_epi32(q_gap_open, q_gap_extend),
+          _mm_cmpgt_epi32(ext_allowed, index),
+        );
+        let qry_gaps = _mm_or_si128(
+          _mm_and_si128(is_extend, q_gap_extend),
+          _mm_andnot_si128(is_extend, q_gap_open),
+        );
+
+        _mm_storeu_si128(row.diag.as_mut_ptr().add(k).cast::<__m128i>(), diag);
+        _mm_storeu_si128(row.qry_gaps.as_mut_ptr().add(k).cast::<__m128i>(), qry_gaps);
+        _mm_storeu_si128(
+          row.flags.as_mut_ptr().add(k).cast::<__m128i>(),
+          _mm_and_si128(is_extend, flag),
+        );
+
+        index = _mm_add_epi32(index, step);
+        k += LANES;
+      }
+      k
+    }
+  }
+
+  /// Returns the number of cells processed
+  ///
+  /// # Safety
+  ///
+  /// The CPU must support AVX2. Slices must have lengths as described in `ScoreRowVertical`.
+  #[target_feature(enable = "avx2")]
+  pub unsafe fn score_row_vertical_avx2(row: &mut ScoreRowVertical) -> usize {
+    // SAFETY: the caller guarantees that the CPU supports the instruction set and that the slices have the
+    // expected lengths
+    unsafe {
+      const LANES: usize = 8;
+      let n = row.diag.len();
+      let penalty_gap_extend = _mm256_set1_epi32(row.penalty_gap_extend);
+      let gap_open = _mm256_set1_epi32(row.gap_open);
+      let flag = _mm256_set1_epi32(i32::from(QRY_GAP_EXTEND));
+      let ext_allowed = _mm256_set1_epi32(row.ext_allowed.min(n) as i32);
+      let step = _mm256_set1_epi32(LANES as i32);
+      let mut index = _mm256_setr_epi32(0, 1, 2, 3, 4, 5, 6, 7);
+
+      let mut k = 0;
+      while k + LANES <= n {
+        // Loads and stores are in bounds: k + LANES <= n, and `prev` has n + 1 elements
+        let upper_left = _mm256_loadu_si256(row.prev.as_ptr().add(k).cast::<__m256i>());
+        let upper = _mm256_loadu_si256(row.prev.as_ptr().add(k + 1).cast::<__m256i>());
+        let delta = _mm256_loadu_si256(row.delta.as_ptr().add(k).cast::<__m256i>());
+        let qry_gaps = _mm256_loadu_si256(row.qry_gaps.as_ptr().add(k).cast::<__m256i>());
+
+        let diag = _mm256_add_epi32(upper_left, delta);
+        let q_gap_extend = _mm256_sub_epi32(qry_gaps, penalty_gap_extend);
+        let q_gap_open = _mm256_sub_epi32(upper, gap_open);
+
+        // q_gap_extend >= q_gap_open && k < ext_allowed
+        let is_extend = _mm256_andnot_si256(
+          _mm256_cmpgt_epi32(q_gap_open, q_gap_extend),
+          _mm256_cmpgt_epi32(ext_allowed, index),
+        );
+        let qry_gaps = _mm256_or_si256(
+          _mm256_and_si256(is_extend, q_gap_extend),
+          _mm256_andnot_si256(is_extend, q_gap_open),
+        );
+
+        _mm256_storeu_si256(row.diag.as_mut_ptr().add(k).cast::<__m256i>(), diag);
+        _mm256_storeu_si256(row.qry_gaps.as_mut_ptr().add(k).cast::<__m256i>(), qry_gaps);
+        _mm256_storeu_si256(
+          row.flags.as_mut_ptr().add(k).cast::<__m256i>(),
+          _mm256_and_si256(is_extend, flag),
+        );
+
+        index = _mm256_add_epi32(index, step);
+        k += LANES;
+      }
+      k
+    }
+  }
+}
+
+// Intrinsics are unsafe functions, so the kernels consist almost entirely of unsafe operations
+#[cfg(target_arch = "aarch64")]
+#[allow(unsafe_code, clippy::multiple_unsafe_ops_per_block)]
+mod aarch64 {
+  use super::ScoreRowVertical;
+  use crate::align::score_matrix::QRY_GAP_EXTEND;
+  use std::arch::aarch64::{
+    vaddq_s32, vandq_s32, vandq_u32, vbslq_s32, vcgeq_s32, vcltq_s32, vdupq_n_s32, vld1q_s32, vreinterpretq_s32_u32,
+    vst1q_s32, vsubq_s32,
+  };
+
+  /// Returns the number of cells processed
+  ///
+  /// # Safety
+  ///
+  /// The CPU must support NEON. Slices must have lengths as described in `ScoreRowVertical`.
+  #[target_feature(enable = "neon")]
+  pub unsafe fn score_row_vertical_neon(row: &mut ScoreRowVertical) -> usize {
+    // SAFETY: the caller guarantees that the CPU supports the instruction set and that the slices have the
+    // expected lengths
+    unsafe {
+      const LANES: usize = 4;
+      let n = row.diag.len();
+      let penalty_gap_extend = vdupq_n_s32(row.penalty_gap_extend);
+      let gap_open = vdupq_n_s32(row.gap_open);
+      let flag = vdupq_n_s32(i32::from(QRY_GAP_EXTEND));
+      let ext_allowed = vdupq_n_s32(row.ext_allowed.min(n) as i32);
+      let step = vdupq_n_s32(LANES as i32);
+      let mut index = vld1q_s32([0, 1, 2, 3].as_ptr());
+
+      let mut k = 0;
+      while k + LANES <= n {
+        // Loads and stores are in bounds: k + LANES <= n, and `prev` has n + 1 elements
+        let upper_left = vld1q_s32(row.prev.as_ptr().add(k));
+        let upper = vld1q_s32(row.prev.as_ptr().add(k + 1));
+        let delta = vld1q_s32(row.delta.as_ptr().add(k));
+        let qry_gaps = vld1q_s32(row.qry_gaps.as_ptr().add(k));
+
+        let diag = vaddq_s32(upper_left, delta);
+        let q_gap_extend = vsubq_s32(qry_gaps, penalty_gap_extend);
+        let q_gap_open = vsubq_s32(upper, gap_open);
+
+        // q_gap_extend >= q_gap_open && k < ext_allowed
+        let is_extend = vandq_u32(vcgeq_s32(q_gap_extend, q_gap_open), vcltq_s32(index, ext_allowed));
+        let qry_gaps = vbslq_s32(is_extend, q_gap_extend, q_gap_open);
+
+        vst1q_s32(row.diag.as_mut_ptr().add(k), diag);
+        vst1q_s32(row.qry_gaps.as_mut_ptr().add(k), qry_gaps);
+        vst1q_s32(
+          row.flags.as_mut_ptr().add(k),
+          vandq_s32(vreinterpretq_s32_u32(is_extend), flag),
+        );
+
+        index = vaddq_s32(index, step);
+        k += LANES;
+      }
+      k
+    }
+  }
+}
@@ -498,10 +498,40 @@ pub fn get_seed_matches2(
   Ok(seed_matches)
 }
 
+/// Total length of chained seed matches of the query against the reference, taking the best of the forward and, if
+/// enabled, reverse complement strands.
+///
+/// This is a cheap measure of how well a query matches a reference, which does not require running a full alignment.
+/// It is used for example to assign query sequences to segments of multi-segment datasets.
+pub fn get_seed_cover(
+  qry_seq: &[Nuc],
+  ref_seq: &[Nuc],
+  seed_index: &CodonSpacedIndex,
+  params: &AlignPairwiseParams,
+) -> usize {
+  let seed_cover = |qry_seq: &[Nuc]| -> usize {
+    let matches = seed_index.extended_matches(qry_seq, ref_seq, params);
+    if matches.is_empty() {
+      return 0;
+    }
+    chain_seeds(&matches).iter().map(|sm| sm.length).sum()
+  };
+
+  let forward = seed_cover(qry_seq);
+  if params.retry_reverse_complement {
+    let mut rev_complement = qry_seq.to_owned();
+    reverse_complement_in_place(&mut rev_complement);
+    max(forward, seed_cover(&rev_complement))
+  } else {
+    forward
+  }
+}
+
 pub struct SeedMatchesResult<'a> {
   pub qry_seq: Cow<'a, [Nuc]>,
   pub seed_matches: Vec<SeedMatch2>,
   pub is_reverse_complement: bool,
+  pub circular_rotation: usize,
 }
 
 #[allow(clippy::map_err_ignore)]
@@ -511,26 +541,79 @@ pub fn get_seed_matches_maybe_reverse_complement<'a>(
   seed_index: &CodonSpacedIndex,
   params: &AlignPairwiseParams,
 ) -> Result<SeedMatchesResult<'a>, Report> {
-  match get_seed_matches2(qry_seq, ref_seq, seed_index, params) {
-    Ok(seed_matches) => Ok(SeedMatchesResult {
+  let result = match get_seed_matches2(qry_seq, ref_seq, seed_index, params) {
+    Ok(seed_matches) => SeedMatchesResult {
       qry_seq: Cow::Borrowed(qry_seq),
       seed_matches,
       is_reverse_complement: false,
-    }),
+      circular_rotation: 0,
+    },
     Err(report) => {
       if params.retry_reverse_complement {
         let mut rev_complement = qry_seq.to_owned();
         reverse_complement_in_place(&mut rev_complement);
         let seed_matches = get_seed_matches2(&rev_complement, ref_seq, seed_index, params).map_err(|_| report)?;
-        Ok(SeedMatchesResult {
+        SeedMatchesResult {
           qry_seq: Cow::Owned(rev_complement),
           seed_matches,
           is_reverse_complement: true,
-        })
+          circular_rotation: 0,
+        }
       } else {
-        Err(report)
+        return Err(report);
       }
     }
+  };
+
+  if params.circular {
+    Ok(rotate_circular_maybe(result, ref_seq, seed_index, params))
+  } else {
+    Ok(result)
+  }
+}
+
+/// Number of nucleotides to move from the beginning to the end of the query, such that the query starts at the origin
+/// of a circular reference. Returns `None` if the query does not wrap around the origin.
+///
+/// Seed matches are chained along a single diagonal, so for a query which starts at a different origin they only cover
+/// one of the two parts of the query on either side of the reference origin. The diagonal of the longest seed tells
+/// where the reference origin is located in the query.
+pub fn find_circular_rotation(seed_matches: &[SeedMatch2], qry_len: usize, ref_len: usize) -> Option<usize> {
+  let seed = seed_matches.iter().max_by_key(|seed| seed.length)?;
+  let rotation = (seed.qry_pos as isize - seed.ref_pos as isize).rem_euclid(ref_len as isize) as usize;
+  (rotation > 0 && rotation < qry_len).then_some(rotation)
+}
+
+/// Rotate the query of a circular genome such that it starts at the reference origin. The rotation is only kept if it
+/// increases the total length of seed matches, so that queries with insertions or extra sequence near their ends are
+/// not rotated.
+fn rotate_circular_maybe<'a>(
+  result: SeedMatchesResult<'a>,
+  ref_seq: &[Nuc],
+  seed_index: &CodonSpacedIndex,
+  params: &AlignPairwiseParams,
+) -> SeedMatchesResult<'a> {
+  let Some(rotation) = find_circular_rotation(&result.seed_matches, result.qry_seq.len(), ref_seq.len()) else {
+    return result;
+  };
+
+  let mut rotated = result.qry_seq.to_vec();
+  rotated.rotate_left(rotation);
+
+  let Ok(seed_matches) = get_seed_matches2(&rotated, ref_seq, seed_index, params) else {
+    return result;
+  };
+
+  let seed_cover = |seed_matches: &[SeedMatch2]| -> usize { seed_matches.iter().map(|sm| sm.length).sum() };
+  if seed_cover(&seed_matches) <= seed_cover(&result.seed_matches) {
+    return result;
+  }
+
+  SeedMatchesResult {
+    qry_seq: Cow::Owned(rotated),
+    seed_matches,
+    is_reverse_complement: result.is_reverse_complement,
+    circular_rotation: rotation,
   }
 }
 
@@ -561,4 +644,25 @@ mod tests {
     assert_eq!(expected, actual);
     Ok(())
   }
+
+  #[rustfmt::skip]
+  #[rstest]
+  #[case::starts_after_origin(0,   300, 700, 1000, Some(300))]
+  #[case::ends_before_origin( 400, 0,   600, 1000, Some(600))]
+  #[case::not_wrapping(       100, 0,   800, 800,  None)]
+  #[case::aligned_to_origin(  0,   0,   900, 1000, None)]
+  fn finds_circular_rotation(
+    #[case] ref_pos: usize,
+    #[case] qry_pos: usize,
+    #[case] length: usize,
+    #[case] qry_len: usize,
+    #[case] expected: Option<usize>,
+  ) {
+    let offset = qry_pos as isize - ref_pos as isize;
+    let seed_matches = vec![
+      SeedMatch2 { ref_pos: 10, qry_pos: 20, length: 40, offset: 10 },
+      SeedMatch2 { ref_pos, qry_pos, length, offset },
+    ];
+    assert_eq!(expected, find_circular_rotation(&seed_matches, qry_len, 1000));
+  }
 }
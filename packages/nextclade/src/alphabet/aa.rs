@@ -1,4 +1,5 @@
-use crate::align::score_matrix_aa::lookup_aa_scoring_matrix;
+use crate::align::params::AlignPairwiseParams;
+use crate::align::score_matrix_aa::{lookup_aa_scoring_matrix, lookup_aa_substitution_score};
 use crate::alphabet::letter::{Letter, ScoreMatrixLookup};
 use crate::make_error;
 use eyre::{eyre, Report, WrapErr};
@@ -56,6 +57,10 @@ impl ScoreMatrixLookup<Aa> for Aa {
   fn lookup_match_score(x: Aa, y: Aa) -> i32 {
     lookup_aa_scoring_matrix(x, y)
   }
+
+  fn lookup_substitution_score(x: Aa, y: Aa, params: &AlignPairwiseParams) -> i32 {
+    lookup_aa_substitution_score(x, y, params)
+  }
 }
 
 impl Display for Aa {
@@ -1,3 +1,4 @@
+use crate::align::params::AlignPairwiseParams;
 use color_eyre::{Section, SectionExt};
 use eyre::{Report, WrapErr};
 use serde::{Deserialize, Deserializer, Serializer};
@@ -6,6 +7,10 @@ use std::fmt::Display;
 /// Allows to lookup scores for nucleotides and amino acids in a generic way
 pub trait ScoreMatrixLookup<T> {
   fn lookup_match_score(x: T, y: T) -> i32;
+
+  /// Score of aligning two letters, none of which is unknown, according to the scoring matrix selected in the
+  /// alignment parameters
+  fn lookup_substitution_score(x: T, y: T, params: &AlignPairwiseParams) -> i32;
 }
 
 /// Generic representation of a character defining nucleotide or amino acid
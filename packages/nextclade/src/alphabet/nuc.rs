@@ -1,4 +1,5 @@
-use crate::align::score_matrix_nuc::lookup_nuc_scoring_matrix;
+use crate::align::params::AlignPairwiseParams;
+use crate::align::score_matrix_nuc::{lookup_nuc_scoring_matrix, lookup_nuc_substitution_score};
 use crate::alphabet::letter::{Letter, ScoreMatrixLookup};
 use crate::make_error;
 use eyre::{eyre, Report, WrapErr};
@@ -41,12 +42,34 @@ impl Nuc {
   pub const fn is_acgtn(self) -> bool {
     matches!(self, Nuc::A | Nuc::C | Nuc::G | Nuc::T | Nuc::N)
   }
+
+  /// Whether the nucleotide is an ambiguous IUPAC code, other than `N`
+  #[inline]
+  pub const fn is_ambiguous(self) -> bool {
+    !self.is_acgtn() && !matches!(self, Nuc::Gap)
+  }
+
+  /// Number of canonical nucleotides (A, C, G, T) which the IUPAC code represents
+  #[inline]
+  pub const fn num_possible_nucs(self) -> usize {
+    match self {
+      Nuc::A | Nuc::C | Nuc::G | Nuc::T => 1,
+      Nuc::W | Nuc::Y | Nuc::M | Nuc::K | Nuc::R | Nuc::S => 2,
+      Nuc::H | Nuc::D | Nuc::B | Nuc::V => 3,
+      Nuc::N => 4,
+      Nuc::Gap => 0,
+    }
+  }
 }
 
 impl ScoreMatrixLookup<Nuc> for Nuc {
   fn lookup_match_score(x: Nuc, y: Nuc) -> i32 {
     lookup_nuc_scoring_matrix(x, y)
   }
+
+  fn lookup_substitution_score(x: Nuc, y: Nuc, params: &AlignPairwiseParams) -> i32 {
+    lookup_nuc_substitution_score(x, y, params)
+  }
 }
 
 impl Display for Nuc {
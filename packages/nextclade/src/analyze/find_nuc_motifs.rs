@@ -0,0 +1,231 @@
+use crate::alphabet::nuc::{from_nuc_seq, to_nuc_seq, Nuc};
+use crate::analyze::find_nuc_motifs_changes::NucMotifsMap;
+use crate::analyze::virus_properties::NucMotifsDesc;
+use crate::coord::position::NucRefGlobalPosition;
+use crate::coord::range::{intersect_or_none, NucRefGlobalRange};
+use crate::make_error;
+use eyre::{Report, WrapErr};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::hash::{Hash, Hasher};
+
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema, Ord, PartialOrd, Eq, PartialEq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub struct NucMotif {
+  pub name: String,
+  pub position: NucRefGlobalPosition,
+  pub seq: String,
+}
+
+impl From<NucMotifWithoutSeq> for NucMotif {
+  fn from(nuc_motif: NucMotifWithoutSeq) -> Self {
+    nuc_motif.0
+  }
+}
+
+/// Find motifs in a nucleotide sequence (aligned to reference, with insertions stripped), given a list of IUPAC
+/// patterns (with optional restriction by ranges in reference coordinates). This is useful for example to find
+/// transcription regulatory sequences, CpG or APOBEC contexts, or restriction sites.
+///
+/// Only the part of the sequence within `alignment_range` is searched. Overlapping matches are all reported.
+pub fn find_nuc_motifs(
+  nuc_motifs_desc: &[NucMotifsDesc],
+  seq: &[Nuc],
+  alignment_range: &NucRefGlobalRange,
+) -> Result<NucMotifsMap, Report> {
+  nuc_motifs_desc
+    .iter()
+    .map(|desc| {
+      let motifs = process_one_nuc_motifs_desc(desc, seq, alignment_range)
+        .wrap_err_with(|| format!("When searching nucleotide motifs '{}'", desc.name))?;
+      Ok((desc.name.clone(), motifs))
+    })
+    .filter_ok(|(_, motifs)| !motifs.is_empty())
+    .collect()
+}
+
+fn process_one_nuc_motifs_desc(
+  nuc_motifs_desc: &NucMotifsDesc,
+  seq: &[Nuc],
+  alignment_range: &NucRefGlobalRange,
+) -> Result<Vec<NucMotif>, Report> {
+  let NucMotifsDesc {
+    name, motifs, ranges, ..
+  } = nuc_motifs_desc;
+
+  let patterns = motifs
+    .iter()
+    .map(|motif| parse_nuc_motif_pattern(motif))
+    .collect::<Result<Vec<Vec<Nuc>>, Report>>()?;
+
+  // If no ranges specified, search the whole sequence
+  let ranges = if ranges.is_empty() {
+    Cow::Owned(vec![NucRefGlobalRange::from_usize(0, seq.len())])
+  } else {
+    Cow::Borrowed(ranges)
+  };
+
+  // NOTE: motifs outside of alignment range (e.g. in partial sequences) are ignored, same as for AA motifs
+  let found = ranges
+    .iter()
+    .filter_map(|range| intersect_or_none(alignment_range, range))
+    .flat_map(|range| {
+      patterns
+        .iter()
+        .flat_map(move |pattern| find_pattern_in_range(name, seq, &range, pattern))
+    })
+    .sorted()
+    .dedup()
+    .collect_vec();
+
+  Ok(found)
+}
+
+/// Converts a motif pattern to a sequence of IUPAC nucleotide codes
+fn parse_nuc_motif_pattern(motif: &str) -> Result<Vec<Nuc>, Report> {
+  let pattern =
+    to_nuc_seq(&motif.to_uppercase()).wrap_err_with(|| format!("When parsing nucleotide motif '{motif}'"))?;
+  if pattern.is_empty() || pattern.contains(&Nuc::Gap) {
+    return make_error!(
+      "Nucleotide motif is expected to be a non-empty sequence of IUPAC nucleotide codes, but found: '{motif}'"
+    );
+  }
+  Ok(pattern)
+}
+
+fn find_pattern_in_range(name: &str, seq: &[Nuc], range: &NucRefGlobalRange, pattern: &[Nuc]) -> Vec<NucMotif> {
+  let range = range.to_std();
+  let end = range.end.min(seq.len());
+  if range.start >= end {
+    return vec![];
+  }
+
+  seq[range.start..end]
+    .windows(pattern.len())
+    .enumerate()
+    .filter(|(_, window)| {
+      window
+        .iter()
+        .zip(pattern)
+        .all(|(nuc, code)| iupac_includes(*code, *nuc))
+    })
+    .map(|(offset, window)| NucMotif {
+      name: name.to_owned(),
+      position: NucRefGlobalPosition::from(range.start + offset),
+      seq: from_nuc_seq(window),
+    })
+    .collect_vec()
+}
+
+/// Whether a canonical nucleotide is among the nucleotides represented by an IUPAC code. Ambiguous nucleotides
+/// in the sequence never match, so that a motif is only reported when it is unambiguously present.
+const fn iupac_includes(code: Nuc, nuc: Nuc) -> bool {
+  match nuc {
+    Nuc::A => matches!(
+      code,
+      Nuc::A | Nuc::W | Nuc::M | Nuc::R | Nuc::H | Nuc::D | Nuc::V | Nuc::N
+    ),
+    Nuc::C => matches!(
+      code,
+      Nuc::C | Nuc::Y | Nuc::M | Nuc::S | Nuc::H | Nuc::B | Nuc::V | Nuc::N
+    ),
+    Nuc::G => matches!(
+      code,
+      Nuc::G | Nuc::K | Nuc::R | Nuc::S | Nuc::D | Nuc::B | Nuc::V | Nuc::N
+    ),
+    Nuc::T => matches!(
+      code,
+      Nuc::T | Nuc::W | Nuc::Y | Nuc::K | Nuc::H | Nuc::D | Nuc::B | Nuc::N
+    ),
+    _ => false,
+  }
+}
+
+// Wrapper for `struct NucMotif` which disregards `.seq` during comparison.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema, PartialOrd)]
+pub struct NucMotifWithoutSeq(pub NucMotif);
+
+impl From<NucMotif> for NucMotifWithoutSeq {
+  fn from(nuc_motif: NucMotif) -> Self {
+    Self(nuc_motif)
+  }
+}
+
+impl Hash for NucMotifWithoutSeq {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    self.0.name.hash(state);
+    self.0.position.hash(state);
+    // NOTE: `.seq` is disregarded
+  }
+}
+
+impl Eq for NucMotifWithoutSeq {}
+
+impl PartialEq<Self> for NucMotifWithoutSeq {
+  fn eq(&self, other: &Self) -> bool {
+    (&self.0.name, &self.0.position).eq(&(&other.0.name, &other.0.position))
+    // NOTE: `.seq` is disregarded
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::coord::position::PositionLike;
+  use crate::o;
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+
+  fn desc(motifs: &[&str], ranges: Vec<NucRefGlobalRange>) -> NucMotifsDesc {
+    NucMotifsDesc {
+      name: o!("motif"),
+      motifs: motifs.iter().map(|motif| (*motif).to_owned()).collect(),
+      ranges,
+      ..NucMotifsDesc::default()
+    }
+  }
+
+  fn find(desc: &NucMotifsDesc, seq: &str, (begin, end): (usize, usize)) -> Result<Vec<(usize, String)>, Report> {
+    let seq = to_nuc_seq(seq)?;
+    let motifs = find_nuc_motifs(&[desc.clone()], &seq, &NucRefGlobalRange::from_usize(begin, end))?;
+    Ok(
+      motifs
+        .into_values()
+        .flatten()
+        .map(|motif| (motif.position.as_usize(), motif.seq))
+        .collect(),
+    )
+  }
+
+  #[rstest]
+  fn finds_iupac_motifs_including_overlapping() -> Result<(), Report> {
+    let actual = find(&desc(&["TCW"], vec![]), "TCATCTTCCTCAA", (0, 13))?;
+    assert_eq!(actual, vec![(0, o!("TCA")), (3, o!("TCT")), (9, o!("TCA"))]);
+
+    let actual = find(&desc(&["ARA"], vec![]), "AAAGA", (0, 5))?;
+    assert_eq!(actual, vec![(0, o!("AAA")), (2, o!("AGA"))]);
+    Ok(())
+  }
+
+  #[rstest]
+  fn ignores_ambiguous_nucleotides_and_gaps() -> Result<(), Report> {
+    let actual = find(&desc(&["CG"], vec![]), "CGNCGC-GCRCG", (0, 12))?;
+    assert_eq!(actual, vec![(0, o!("CG")), (3, o!("CG")), (10, o!("CG"))]);
+    Ok(())
+  }
+
+  #[rstest]
+  fn restricts_search_to_ranges_and_alignment_range() -> Result<(), Report> {
+    let desc = desc(&["CG"], vec![NucRefGlobalRange::from_usize(2, 9)]);
+    let actual = find(&desc, "CGCGCGCGCGCG", (0, 8))?;
+    assert_eq!(actual, vec![(2, o!("CG")), (4, o!("CG")), (6, o!("CG"))]);
+    Ok(())
+  }
+
+  #[rstest]
+  fn rejects_invalid_patterns() {
+    assert2::assert!(find(&desc(&["CXG"], vec![]), "ACGT", (0, 4)).is_err());
+    assert2::assert!(find(&desc(&["C-G"], vec![]), "ACGT", (0, 4)).is_err());
+  }
+}
@@ -0,0 +1,185 @@
+use crate::alphabet::nuc::{from_nuc_seq, Nuc};
+use crate::analyze::find_nuc_motifs::{NucMotif, NucMotifWithoutSeq};
+use crate::coord::position::{NucRefGlobalPosition, PositionLike};
+use crate::coord::range::{intersect, NucRefGlobalRange, Range};
+use crate::utils::collections::cloned_into;
+use crate::utils::zip_map::zip_by_key;
+use itertools::{Either, Itertools, Zip};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::ops::Deref;
+
+pub type NucMotifsMap = BTreeMap<String, Vec<NucMotif>>;
+pub type NucMotifsChangesMap = BTreeMap<String, NucMotifChanges>;
+
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NucMotifChanges {
+  pub preserved: Vec<NucMotifMutation>,
+  pub gained: Vec<NucMotifMutation>,
+  pub lost: Vec<NucMotifMutation>,
+  pub ambiguous: Vec<NucMotifMutation>,
+  pub total: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema, Ord, PartialOrd, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct NucMotifMutation {
+  pub name: String,
+  pub position: NucRefGlobalPosition,
+  pub ref_seq: String,
+  pub qry_seq: String,
+}
+
+/// Find changes between nucleotide motifs in reference and in query sequence
+pub fn find_nuc_motifs_changes(
+  nuc_motifs_ref: &NucMotifsMap,
+  nuc_motifs_qry: &NucMotifsMap,
+  ref_seq: &[Nuc],
+  qry_seq: &[Nuc],
+  alignment_range: &NucRefGlobalRange,
+) -> NucMotifsChangesMap {
+  zip_by_key(nuc_motifs_ref, nuc_motifs_qry)
+    .map(|(name, motifs_ref, motifs_qry)| {
+      let motifs_ref = motifs_ref.map(Deref::deref).unwrap_or_default();
+      let motifs_qry = motifs_qry.map(Deref::deref).unwrap_or_default();
+      let changes = find_nuc_motifs_changes_one(motifs_ref, motifs_qry, ref_seq, qry_seq, alignment_range);
+      (name.clone(), changes)
+    })
+    .collect()
+}
+
+fn find_nuc_motifs_changes_one(
+  motifs_ref: &[NucMotif],
+  motifs_qry: &[NucMotif],
+  ref_seq: &[Nuc],
+  qry_seq: &[Nuc],
+  alignment_range: &NucRefGlobalRange,
+) -> NucMotifChanges {
+  // We want to find added and removed positions (`.position`).
+  // So we use a wrapper for `struct NucMotif` which disregards `.seq` during comparison.
+  let motifs_ref: HashSet<NucMotifWithoutSeq> = motifs_ref.iter().cloned().map(NucMotifWithoutSeq::from).collect();
+  let motifs_qry: HashSet<NucMotifWithoutSeq> = motifs_qry.iter().cloned().map(NucMotifWithoutSeq::from).collect();
+
+  // Gained motifs: not present in ref, present in qry
+  let gained = motifs_qry
+    .difference(&motifs_ref)
+    .map(|motif| add_ref_seq(&motif.0, ref_seq))
+    .sorted()
+    .collect_vec();
+
+  // Lost motifs: present in ref, not present in query.
+  // Ambiguous motifs: present in ref, contain ambiguous nucleotides in query.
+  let (lost, ambiguous): (Vec<NucMotifMutation>, Vec<NucMotifMutation>) = motifs_ref
+    .difference(&motifs_qry)
+    .filter_map(|motif| add_qry_seq(&motif.0, qry_seq, alignment_range))
+    .sorted()
+    .partition_map(|motif_change| {
+      if motif_change
+        .qry_seq
+        .chars()
+        .any(|c| !matches!(c, 'A' | 'C' | 'G' | 'T' | '-'))
+      {
+        Either::Right(motif_change)
+      } else {
+        Either::Left(motif_change)
+      }
+    });
+
+  // Preserved motifs: present in ref and qry
+  let preserved = {
+    // Zip ref and qry motifs, so that we can compare them pairwise
+    let kept_ref: Vec<NucMotif> = cloned_into(motifs_ref.intersection(&motifs_qry)).sorted().collect_vec();
+    let kept_qry: Vec<NucMotif> = cloned_into(motifs_qry.intersection(&motifs_ref)).sorted().collect_vec();
+    Zip::from((kept_ref, kept_qry))
+      .map(|(motif_ref, motif_qry)| NucMotifMutation {
+        name: motif_ref.name,
+        position: motif_ref.position,
+        ref_seq: motif_ref.seq,
+        qry_seq: motif_qry.seq,
+      })
+      .sorted()
+      .collect_vec()
+  };
+
+  let total = gained.len() + preserved.len();
+
+  NucMotifChanges {
+    preserved,
+    gained,
+    lost,
+    ambiguous,
+    total,
+  }
+}
+
+// Add ref sequence fragment to motif
+fn add_ref_seq(motif: &NucMotif, ref_seq: &[Nuc]) -> NucMotifMutation {
+  let begin = motif.position.as_usize();
+  let end = (begin + motif.seq.len()).min(ref_seq.len());
+
+  NucMotifMutation {
+    name: motif.name.clone(),
+    position: motif.position,
+    ref_seq: from_nuc_seq(&ref_seq[begin..end]),
+    qry_seq: motif.seq.clone(),
+  }
+}
+
+// Add query sequence fragment to motif, if the motif is fully within the sequenced region of the query
+fn add_qry_seq(motif: &NucMotif, qry_seq: &[Nuc], alignment_range: &NucRefGlobalRange) -> Option<NucMotifMutation> {
+  let begin = motif.position;
+  let end = begin + motif.seq.len() as isize;
+  let sequenced_motif_range = intersect(alignment_range, &Range::new(begin, end));
+  (sequenced_motif_range.len() >= motif.seq.len()).then(|| NucMotifMutation {
+    name: motif.name.clone(),
+    position: motif.position,
+    ref_seq: motif.seq.clone(),
+    qry_seq: from_nuc_seq(&qry_seq[sequenced_motif_range.to_std()]),
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::alphabet::nuc::to_nuc_seq;
+  use crate::analyze::find_nuc_motifs::find_nuc_motifs;
+  use crate::analyze::virus_properties::NucMotifsDesc;
+  use crate::o;
+  use eyre::Report;
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+
+  fn positions(mutations: &[NucMotifMutation]) -> Vec<(usize, &str, &str)> {
+    mutations
+      .iter()
+      .map(|m| (m.position.as_usize(), m.ref_seq.as_str(), m.qry_seq.as_str()))
+      .collect()
+  }
+
+  #[rstest]
+  fn finds_nuc_motifs_changes() -> Result<(), Report> {
+    let descs = vec![NucMotifsDesc {
+      name: o!("CpG"),
+      motifs: vec![o!("CG")],
+      ..NucMotifsDesc::default()
+    }];
+
+    let ref_seq = to_nuc_seq("ACGTTCGAACGTACGTCG")?;
+    let qry_seq = to_nuc_seq("ACGTTCAAACNTACGCGN")?;
+    let ref_range = NucRefGlobalRange::from_usize(0, ref_seq.len());
+    let qry_range = NucRefGlobalRange::from_usize(0, 17);
+
+    let motifs_ref = find_nuc_motifs(&descs, &ref_seq, &ref_range)?;
+    let motifs_qry = find_nuc_motifs(&descs, &qry_seq, &qry_range)?;
+    let changes = find_nuc_motifs_changes(&motifs_ref, &motifs_qry, &ref_seq, &qry_seq, &qry_range);
+    let changes = &changes["CpG"];
+
+    assert_eq!(positions(&changes.preserved), vec![(1, "CG", "CG"), (13, "CG", "CG")]);
+    assert_eq!(positions(&changes.gained), vec![(15, "TC", "CG")]);
+    assert_eq!(positions(&changes.lost), vec![(5, "CG", "CA")]);
+    assert_eq!(positions(&changes.ambiguous), vec![(9, "CG", "CN")]);
+    assert_eq!(changes.total, 3);
+    Ok(())
+  }
+}
@@ -7,11 +7,13 @@ pub mod aa_del;
 pub mod aa_sub;
 pub mod aa_sub_min;
 pub mod abstract_mutation;
-pub mod find_clade_founder;
 pub mod count_gaps;
 pub mod divergence;
 pub mod find_aa_motifs;
 pub mod find_aa_motifs_changes;
+pub mod find_clade_founder;
+pub mod find_nuc_motifs;
+pub mod find_nuc_motifs_changes;
 pub mod find_private_aa_mutations;
 pub mod find_private_nuc_mutations;
 pub mod find_relative_aa_mutations;
@@ -20,6 +22,7 @@ pub mod group_adjacent_deletions;
 pub mod is_sequenced;
 pub mod letter_composition;
 pub mod letter_ranges;
+pub mod mutational_spectrum;
 pub mod nuc_alignment;
 pub mod nuc_changes;
 pub mod nuc_del;
@@ -27,4 +30,5 @@ pub mod nuc_sub;
 pub mod pcr_primer_changes;
 pub mod pcr_primers;
 pub mod phenotype;
+pub mod protein_changes;
 pub mod virus_properties;
@@ -0,0 +1,227 @@
+use crate::alphabet::nuc::{from_nuc, Nuc};
+use crate::analyze::nuc_sub::NucSub;
+use crate::coord::position::{NucRefGlobalPosition, PositionLike};
+use crate::translate::complement::complement;
+use itertools::{iproduct, Itertools};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+const NUCS: [Nuc; 4] = [Nuc::A, Nuc::C, Nuc::G, Nuc::T];
+
+/// Substitution types of the 96-channel spectrum, with reference nucleotide normalized to a pyrimidine
+const PYRIMIDINE_SUBSTITUTIONS: [(Nuc, Nuc); 6] = [
+  (Nuc::C, Nuc::A),
+  (Nuc::C, Nuc::G),
+  (Nuc::C, Nuc::T),
+  (Nuc::T, Nuc::A),
+  (Nuc::T, Nuc::C),
+  (Nuc::T, Nuc::G),
+];
+
+/// Nucleotide substitution together with its trinucleotide context in the reference sequence
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NucSubContext {
+  pub pos: NucRefGlobalPosition,
+  pub ref_nuc: Nuc,
+  pub qry_nuc: Nuc,
+
+  /// Reference nucleotides at positions `pos - 1`, `pos` and `pos + 1`
+  pub context: String,
+
+  /// Channel of the 96-channel spectrum, where the substitution is expressed on the strand with pyrimidine reference
+  /// nucleotide, e.g. `A[C>T]G`
+  pub channel: String,
+
+  /// Channel of the 192-channel (strand-aware) spectrum, where the substitution is expressed as is, e.g. `C[G>A]T`
+  pub channel_stranded: String,
+}
+
+/// Counts of substitutions in each of the channels of the mutational spectrum. Only non-zero counts are stored.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MutationalSpectrum {
+  /// Number of substitutions counted in the spectrum
+  pub total: usize,
+
+  pub sbs96: BTreeMap<String, usize>,
+
+  pub sbs192: BTreeMap<String, usize>,
+}
+
+impl MutationalSpectrum {
+  pub fn from_contexts(contexts: &[NucSubContext]) -> Self {
+    let mut spectrum = Self::default();
+    for context in contexts {
+      spectrum.total += 1;
+      *spectrum.sbs96.entry(context.channel.clone()).or_default() += 1;
+      *spectrum.sbs192.entry(context.channel_stranded.clone()).or_default() += 1;
+    }
+    spectrum
+  }
+}
+
+/// Trinucleotide contexts of substitutions and the resulting mutational spectra of a sequence
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MutationalSpectra {
+  /// Contexts of substitutions relative to the reference sequence
+  pub substitution_contexts: Vec<NucSubContext>,
+
+  /// Spectrum of all substitutions relative to the reference sequence
+  pub all: MutationalSpectrum,
+
+  /// Spectrum of private substitutions, i.e. relative to the nearest node of the reference tree. Contexts are taken
+  /// from the reference sequence.
+  pub private: MutationalSpectrum,
+}
+
+/// Annotates substitutions with their trinucleotide context in the reference sequence and counts them into
+/// 96-channel and 192-channel mutational spectra.
+///
+/// Substitutions to or from ambiguous nucleotides, as well as substitutions with ambiguous or missing neighbours in the
+/// reference sequence (at the ends of the genome), are not counted.
+pub fn find_mutational_spectra(
+  ref_seq: &[Nuc],
+  substitutions: &[NucSub],
+  private_substitutions: &[NucSub],
+) -> MutationalSpectra {
+  let substitution_contexts = substitutions
+    .iter()
+    .filter_map(|sub| nuc_sub_context(ref_seq, sub))
+    .collect_vec();
+
+  let private_contexts = private_substitutions
+    .iter()
+    .filter_map(|sub| nuc_sub_context(ref_seq, sub))
+    .collect_vec();
+
+  MutationalSpectra {
+    all: MutationalSpectrum::from_contexts(&substitution_contexts),
+    private: MutationalSpectrum::from_contexts(&private_contexts),
+    substitution_contexts,
+  }
+}
+
+/// Finds trinucleotide context of a substitution in the reference sequence
+pub fn nuc_sub_context(ref_seq: &[Nuc], sub: &NucSub) -> Option<NucSubContext> {
+  let pos = sub.pos.as_usize();
+  if pos == 0 || !sub.ref_nuc.is_acgt() || !sub.qry_nuc.is_acgt() || sub.ref_nuc == sub.qry_nuc {
+    return None;
+  }
+
+  let left = *ref_seq.get(pos - 1)?;
+  let right = *ref_seq.get(pos + 1)?;
+  if !left.is_acgt() || !right.is_acgt() {
+    return None;
+  }
+
+  let channel_stranded = channel_label(left, sub.ref_nuc, sub.qry_nuc, right);
+
+  // Express substitutions of purines on the opposite strand
+  let channel = if matches!(sub.ref_nuc, Nuc::A | Nuc::G) {
+    channel_label(
+      complement(right),
+      complement(sub.ref_nuc),
+      complement(sub.qry_nuc),
+      complement(left),
+    )
+  } else {
+    channel_stranded.clone()
+  };
+
+  Some(NucSubContext {
+    pos: sub.pos,
+    ref_nuc: sub.ref_nuc,
+    qry_nuc: sub.qry_nuc,
+    context: [left, sub.ref_nuc, right].iter().map(|nuc| from_nuc(*nuc)).collect(),
+    channel,
+    channel_stranded,
+  })
+}
+
+fn channel_label(left: Nuc, ref_nuc: Nuc, qry_nuc: Nuc, right: Nuc) -> String {
+  format!(
+    "{}[{}>{}]{}",
+    from_nuc(left),
+    from_nuc(ref_nuc),
+    from_nuc(qry_nuc),
+    from_nuc(right)
+  )
+}
+
+/// All channels of the 96-channel spectrum, in the conventional order: by substitution type, then by 5' and 3'
+/// neighbouring nucleotides
+pub fn sbs96_channels() -> Vec<String> {
+  iproduct!(PYRIMIDINE_SUBSTITUTIONS, NUCS, NUCS)
+    .map(|((ref_nuc, qry_nuc), left, right)| channel_label(left, ref_nuc, qry_nuc, right))
+    .collect()
+}
+
+/// All channels of the 192-channel (strand-aware) spectrum: by substitution type, then by 5' and 3' neighbouring
+/// nucleotides
+pub fn sbs192_channels() -> Vec<String> {
+  iproduct!(NUCS, NUCS, NUCS, NUCS)
+    .filter(|(ref_nuc, qry_nuc, _, _)| ref_nuc != qry_nuc)
+    .map(|(ref_nuc, qry_nuc, left, right)| channel_label(left, ref_nuc, qry_nuc, right))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::alphabet::nuc::to_nuc_seq;
+  use eyre::Report;
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+  use std::str::FromStr;
+
+  #[rstest]
+  fn lists_all_channels() {
+    let sbs96 = sbs96_channels();
+    let sbs192 = sbs192_channels();
+    assert_eq!(sbs96.len(), 96);
+    assert_eq!(sbs192.len(), 192);
+    assert_eq!(sbs96.iter().unique().count(), 96);
+    assert_eq!(sbs192.iter().unique().count(), 192);
+    assert_eq!(&sbs96[..2], &["A[C>A]A", "A[C>A]C"]);
+    assert_eq!(&sbs192[..2], &["A[A>C]A", "A[A>C]C"]);
+  }
+
+  #[rstest]
+  fn finds_contexts_and_counts_spectra() -> Result<(), Report> {
+    //                        0123456789
+    let ref_seq = to_nuc_seq("ACGTTCAGAT")?;
+    let substitutions = vec![
+      NucSub::from_str("C2T")?,  // A[C>T]G
+      NucSub::from_str("G8A")?,  // A[G>A]A, normalized: T[C>T]T
+      NucSub::from_str("C6T")?,  // T[C>T]A
+      NucSub::from_str("A1G")?,  // no 5' neighbour
+      NucSub::from_str("T5N")?,  // ambiguous
+      NucSub::from_str("T10C")?, // no 3' neighbour
+    ];
+    let private_substitutions = vec![NucSub::from_str("G8A")?];
+
+    let spectra = find_mutational_spectra(&ref_seq, &substitutions, &private_substitutions);
+
+    assert_eq!(
+      spectra
+        .substitution_contexts
+        .iter()
+        .map(|c| (c.context.as_str(), c.channel.as_str(), c.channel_stranded.as_str()))
+        .collect_vec(),
+      vec![
+        ("ACG", "A[C>T]G", "A[C>T]G"),
+        ("AGA", "T[C>T]T", "A[G>A]A"),
+        ("TCA", "T[C>T]A", "T[C>T]A"),
+      ]
+    );
+
+    assert_eq!(spectra.all.total, 3);
+    assert_eq!(spectra.all.sbs96.get("T[C>T]T"), Some(&1));
+    assert_eq!(spectra.all.sbs192.get("A[G>A]A"), Some(&1));
+    assert_eq!(spectra.private.total, 1);
+    assert_eq!(spectra.private.sbs96.keys().collect_vec(), vec!["T[C>T]T"]);
+    Ok(())
+  }
+}
@@ -7,12 +7,16 @@ use crate::coord::range::NucRefGlobalRange;
 pub struct FindNucChangesOutput {
   pub substitutions: Vec<NucSub>,
   pub deletions: Vec<NucDelRange>,
+  pub ambiguous_sites: Vec<NucSub>,
   pub alignment_range: NucRefGlobalRange,
 }
 
 /// Finds nucleotide changes (nucleotide substitutions and deletions) as well
 /// as the beginning and end of the alignment range.
 ///
+/// Ambiguous nucleotides (IUPAC codes other than `N`) are not considered substitutions. Instead, they are reported
+/// separately as ambiguous sites.
+///
 /// @pre Precondition: sequences are expected to be aligned and stripped from insertions.
 pub fn find_nuc_changes(qry_aln: &[Nuc], ref_aln: &[Nuc]) -> FindNucChangesOutput {
   assert_eq!(ref_aln.len(), qry_aln.len());
@@ -23,6 +27,7 @@ pub fn find_nuc_changes(qry_aln: &[Nuc], ref_aln: &[Nuc]) -> FindNucChangesOutpu
 
   let mut substitutions = Vec::<NucSub>::new();
   let mut deletions = Vec::<NucDelRange>::new();
+  let mut ambiguous_sites = Vec::<NucSub>::new();
   let mut alignment_start: i64 = -1;
   let mut alignment_end: i64 = -1;
 
@@ -47,6 +52,12 @@ pub fn find_nuc_changes(qry_aln: &[Nuc], ref_aln: &[Nuc]) -> FindNucChangesOutpu
         pos: i.into(),
         qry_nuc: d,
       });
+    } else if d.is_ambiguous() {
+      ambiguous_sites.push(NucSub {
+        ref_nuc,
+        pos: i.into(),
+        qry_nuc: d,
+      });
     } else if d.is_gap() && !before_alignment {
       if n_del == 0 {
         del_pos = i as i64;
@@ -57,10 +68,12 @@ pub fn find_nuc_changes(qry_aln: &[Nuc], ref_aln: &[Nuc]) -> FindNucChangesOutpu
 
   substitutions.sort();
   deletions.sort();
+  ambiguous_sites.sort();
 
   FindNucChangesOutput {
     substitutions,
     deletions,
+    ambiguous_sites,
     alignment_range: NucRefGlobalRange::from_usize(alignment_start as usize, alignment_end as usize),
   }
 }
@@ -0,0 +1,220 @@
+use crate::align::insertions_strip::AaIns;
+use crate::analyze::aa_del::AaDel;
+use crate::analyze::aa_sub::AaSub;
+use crate::coord::coord_map::local_to_codon_range_exclusive;
+use crate::coord::coord_map_cds_to_global::global_ref_pos_to_local;
+use crate::coord::position::{AaRefPosition, PositionLike};
+use crate::coord::range::{AaRefRange, NucRefLocalRange};
+use crate::gene::cds::Cds;
+use crate::gene::gene_map::GeneMap;
+use crate::gene::protein::Protein;
+use itertools::Itertools;
+
+/// Aminoacid changes expressed in coordinates of proteins (mature peptides and signal peptides) rather than of CDSes.
+///
+/// These reuse the CDS-level mutation types, but the `cds_name` (or `cds` for insertions) contains name of the protein,
+/// and positions are counted from the beginning of the protein, e.g. `nsp12:P323L` instead of `ORF1b:P314L`.
+#[derive(Clone, Debug, Default)]
+pub struct FindProteinChangesOutput {
+  pub protein_aa_substitutions: Vec<AaSub>,
+  pub protein_aa_deletions: Vec<AaDel>,
+  pub protein_aa_insertions: Vec<AaIns>,
+}
+
+/// Finds the range of codons of a CDS which is occupied by a given protein.
+///
+/// Returns `None` if the protein does not overlap the CDS, and for the implicit proteins which are created from gene
+/// records when genome annotation contains no CDS records: these span the entire CDS and carry no additional information.
+pub fn protein_codon_range(cds: &Cds, protein: &Protein) -> Option<AaRefRange> {
+  if protein.segments.iter().all(|segment| segment.compat_is_gene) {
+    return None;
+  }
+
+  // Protein segments are in global coordinates. Boundaries of every segment are converted to positions within CDS
+  // (there may be multiple for a nucleotide shared by overlapping CDS segments, e.g. at ribosomal slippage sites).
+  let local_positions = protein
+    .segments
+    .iter()
+    .flat_map(|segment| [segment.range.begin, segment.range.end - 1])
+    .flat_map(|pos| global_ref_pos_to_local(cds, pos))
+    .collect_vec();
+
+  let begin = *local_positions.iter().min()?;
+  let end = *local_positions.iter().max()? + 1;
+  let range = local_to_codon_range_exclusive(&NucRefLocalRange::new(begin, end));
+  (!range.is_empty()).then_some(range)
+}
+
+/// Converts aminoacid substitutions, deletions and insertions found in CDSes into coordinates of proteins
+/// contained in these CDSes.
+///
+/// The same protein can be contained in multiple CDSes (e.g. nsp1-nsp10 of SARS-CoV-2 are in both ORF1a and ORF1ab),
+/// so the duplicate changes are removed.
+pub fn find_protein_changes(
+  gene_map: &GeneMap,
+  aa_substitutions: &[AaSub],
+  aa_deletions: &[AaDel],
+  aa_insertions: &[AaIns],
+) -> FindProteinChangesOutput {
+  let mut protein_aa_substitutions = vec![];
+  let mut protein_aa_deletions = vec![];
+  let mut protein_aa_insertions = vec![];
+
+  for cds in gene_map.iter_cdses() {
+    for protein in &cds.proteins {
+      let Some(range) = protein_codon_range(cds, protein) else {
+        continue;
+      };
+
+      let to_protein_pos = |pos: AaRefPosition| AaRefPosition::new(pos.as_isize() - range.begin.as_isize());
+
+      protein_aa_substitutions.extend(
+        aa_substitutions
+          .iter()
+          .filter(|sub| sub.cds_name == cds.name && range.contains(sub.pos))
+          .map(|sub| AaSub {
+            cds_name: protein.name.clone(),
+            pos: to_protein_pos(sub.pos),
+            ref_aa: sub.ref_aa,
+            qry_aa: sub.qry_aa,
+          }),
+      );
+
+      protein_aa_deletions.extend(
+        aa_deletions
+          .iter()
+          .filter(|del| del.cds_name == cds.name && range.contains(del.pos))
+          .map(|del| AaDel {
+            cds_name: protein.name.clone(),
+            pos: to_protein_pos(del.pos),
+            ref_aa: del.ref_aa,
+          }),
+      );
+
+      // Insertion is attributed to the protein which contains the aminoacid preceding the insertion
+      protein_aa_insertions.extend(
+        aa_insertions
+          .iter()
+          .filter(|ins| ins.cds == cds.name && range.contains(AaRefPosition::from(ins.pos)))
+          .map(|ins| AaIns {
+            cds: protein.name.clone(),
+            pos: ins.pos - range.begin.as_isize() as i32,
+            ins: ins.ins.clone(),
+          }),
+      );
+    }
+  }
+
+  FindProteinChangesOutput {
+    protein_aa_substitutions: protein_aa_substitutions.into_iter().sorted().dedup().collect(),
+    protein_aa_deletions: protein_aa_deletions.into_iter().sorted().dedup().collect(),
+    protein_aa_insertions: protein_aa_insertions.into_iter().sorted().dedup().collect(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::alphabet::aa::Aa;
+  use crate::coord::range::Range;
+  use crate::o;
+  use eyre::Report;
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+  use std::str::FromStr;
+
+  const GFF: &str = "##gff-version 3
+seq\t.\tgene\t11\t70\t.\t+\t.\tID=gene1;Name=ORF1
+seq\t.\tCDS\t11\t70\t.\t+\t0\tID=cds1;Parent=gene1;Name=ORF1
+seq\t.\tmature_protein_region_of_CDS\t11\t25\t.\t+\t.\tID=p1;Parent=cds1;product=nsp1
+seq\t.\tmature_protein_region_of_CDS\t26\t70\t.\t+\t.\tID=p2;Parent=cds1;product=nsp2
+seq\t.\tgene\t81\t110\t.\t-\t.\tID=gene2;Name=ORF2
+seq\t.\tCDS\t81\t110\t.\t-\t0\tID=cds2;Parent=gene2;Name=ORF2
+seq\t.\tsignal_peptide_region_of_CDS\t102\t110\t.\t-\t.\tID=p3;Parent=cds2;product=sig
+seq\t.\tmature_protein_region_of_CDS\t81\t101\t.\t-\t.\tID=p4;Parent=cds2;product=mat
+";
+
+  fn protein_ranges(gene_map: &GeneMap) -> Vec<(String, Option<AaRefRange>)> {
+    gene_map
+      .iter_cdses()
+      .flat_map(|cds| {
+        cds
+          .proteins
+          .iter()
+          .map(|protein| (protein.name.clone(), protein_codon_range(cds, protein)))
+      })
+      .collect()
+  }
+
+  #[rstest]
+  fn finds_protein_codon_ranges() -> Result<(), Report> {
+    let gene_map = GeneMap::from_str(GFF)?;
+    assert_eq!(
+      protein_ranges(&gene_map),
+      vec![
+        (o!("nsp1"), Some(Range::from_usize(0, 5))),
+        (o!("nsp2"), Some(Range::from_usize(5, 20))),
+        (o!("sig"), Some(Range::from_usize(0, 3))),
+        (o!("mat"), Some(Range::from_usize(3, 10))),
+      ]
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  fn ignores_implicit_proteins_of_genes() -> Result<(), Report> {
+    let gene_map = GeneMap::from_str("##gff-version 3\nseq\t.\tgene\t11\t70\t.\t+\t.\tID=gene1;Name=ORF1\n")?;
+    assert_eq!(protein_ranges(&gene_map), vec![(o!("ORF1"), None)]);
+    Ok(())
+  }
+
+  #[rstest]
+  fn converts_changes_to_protein_coordinates() -> Result<(), Report> {
+    let gene_map = GeneMap::from_str(GFF)?;
+
+    let aa_substitutions = vec![
+      AaSub::from_str("ORF1:P3L")?,
+      AaSub::from_str("ORF1:A7T")?,
+      AaSub::from_str("ORF2:K2R")?,
+      AaSub::from_str("ORF2:S10F")?,
+    ];
+    let aa_deletions = vec![AaDel {
+      cds_name: o!("ORF1"),
+      pos: AaRefPosition::new(19),
+      ref_aa: Aa::G,
+    }];
+    let aa_insertions = vec![AaIns {
+      cds: o!("ORF1"),
+      pos: 4,
+      ins: vec![Aa::E, Aa::P],
+    }];
+
+    let actual = find_protein_changes(&gene_map, &aa_substitutions, &aa_deletions, &aa_insertions);
+
+    assert_eq!(
+      actual
+        .protein_aa_substitutions
+        .iter()
+        .map(ToString::to_string)
+        .collect_vec(),
+      vec!["mat:S7F", "nsp1:P3L", "nsp2:A2T", "sig:K2R"]
+    );
+    assert_eq!(
+      actual.protein_aa_deletions,
+      vec![AaDel {
+        cds_name: o!("nsp2"),
+        pos: AaRefPosition::new(14),
+        ref_aa: Aa::G,
+      }]
+    );
+    assert_eq!(
+      actual.protein_aa_insertions,
+      vec![AaIns {
+        cds: o!("nsp1"),
+        pos: 4,
+        ins: vec![Aa::E, Aa::P],
+      }]
+    );
+    Ok(())
+  }
+}
@@ -3,9 +3,9 @@ use crate::alphabet::aa::Aa;
 use crate::alphabet::nuc::Nuc;
 use crate::analyze::aa_changes_find_for_cds::AaChangesParamsOptional;
 use crate::coord::position::AaRefPosition;
-use crate::coord::range::AaRefRange;
+use crate::coord::range::{AaRefRange, NucRefGlobalRange};
 use crate::gene::genotype::Genotype;
-use crate::io::dataset::{DatasetCompatibility, DatasetFiles, DatasetMeta, DatasetVersion};
+use crate::io::dataset::{DatasetCompatibility, DatasetFiles, DatasetMeta, DatasetSegment, DatasetVersion};
 use crate::io::fs::read_file_to_string;
 use crate::io::json::json_parse;
 use crate::io::schema_version::{SchemaVersion, SchemaVersionParams};
@@ -42,6 +42,11 @@ pub struct VirusProperties {
   #[serde(default, skip_serializing_if = "DatasetFiles::is_default")]
   pub files: DatasetFiles,
 
+  /// If not empty, the dataset is a multi-segment dataset and `files` of each segment are used instead of the
+  /// top-level `files.reference`, `files.genomeAnnotation` and `files.treeJson`
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub segments: Vec<DatasetSegment>,
+
   #[serde(default, skip_serializing_if = "Option::is_none")]
   pub default_cds: Option<String>,
 
@@ -71,6 +76,9 @@ pub struct VirusProperties {
   #[serde(default, skip_serializing_if = "Vec::is_empty")]
   pub aa_motifs: Vec<AaMotifsDesc>,
 
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub nuc_motifs: Vec<NucMotifsDesc>,
+
   #[serde(default, skip_serializing_if = "Vec::is_empty")]
   pub versions: Vec<DatasetVersion>,
 
@@ -185,6 +193,22 @@ pub struct CountAaMotifsCdsDesc {
   pub ranges: Vec<AaRefRange>,
 }
 
+#[derive(Clone, Default, Debug, Eq, PartialEq, Serialize, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NucMotifsDesc {
+  pub name: String,
+  pub name_short: String,
+  pub name_friendly: String,
+  pub description: String,
+
+  /// Patterns of IUPAC nucleotide codes, e.g. `TCW`
+  pub motifs: Vec<String>,
+
+  /// Ranges of the reference sequence to search in. If empty, the entire sequence is searched.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub ranges: Vec<NucRefGlobalRange>,
+}
+
 impl VirusProperties {
   pub fn from_path(filepath: impl AsRef<Path>) -> Result<Self, Report> {
     let filepath = filepath.as_ref();
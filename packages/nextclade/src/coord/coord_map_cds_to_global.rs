@@ -129,6 +129,7 @@ mod coord_map_tests {
       attributes: indexmap! {},
       compat_is_gene: false,
       color: None,
+      genetic_code: None,
     }
   }
 
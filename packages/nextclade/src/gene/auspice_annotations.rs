@@ -43,6 +43,7 @@ pub fn convert_auspice_annotations_to_genes(anns: &AuspiceGenomeAnnotations) ->
         attributes: IndexMap::default(),
         compat_is_gene: true,
         color: ann.color.clone(),
+        genetic_code: None,
       };
 
       let gff_seqid = single_unique_value(&cds.segments, |s| &s.gff_seqid)?.clone();
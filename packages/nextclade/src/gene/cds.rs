@@ -7,6 +7,7 @@ use crate::gene::frame::Frame;
 use crate::gene::gene::GeneStrand;
 use crate::gene::phase::Phase;
 use crate::gene::protein::{Protein, ProteinSegment};
+use crate::translate::genetic_code::GeneticCode;
 use crate::utils::iter::single_unique_value;
 use crate::{make_error, make_internal_error};
 use eyre::{eyre, Report, WrapErr};
@@ -28,6 +29,9 @@ pub struct Cds {
   pub attributes: IndexMap<String, Vec<String>>,
   pub compat_is_gene: bool,
   pub color: Option<String>,
+  /// Genetic code from `transl_table` attribute. If not specified, the dataset-wide genetic code is used.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub genetic_code: Option<GeneticCode>,
 }
 
 impl Cds {
@@ -109,6 +113,9 @@ impl Cds {
       .unique()
       .collect_vec();
 
+    let genetic_code = genetic_code_from_attributes(&attributes)
+      .wrap_err_with(|| format!("When reading genetic code of CDS '{}'", feature_group.name))?;
+
     Ok(Self {
       id: feature_group.id.clone(),
       name: feature_group.name.clone(),
@@ -119,6 +126,7 @@ impl Cds {
       attributes,
       compat_is_gene: false,
       color: None,
+      genetic_code,
     })
   }
 
@@ -178,6 +186,9 @@ impl Cds {
     let segments = vec![cds_segment];
     let segments = split_circular_cds_segments(&segments)?;
 
+    let genetic_code = genetic_code_from_attributes(&feature.attributes)
+      .wrap_err_with(|| format!("When reading genetic code of gene '{}'", feature.name))?;
+
     Ok(Self {
       id: format!("cds-from-gene-{}", feature.id),
       name: feature.name.clone(),
@@ -188,6 +199,7 @@ impl Cds {
       attributes: feature.attributes.clone(),
       compat_is_gene: true,
       color: None,
+      genetic_code,
     })
   }
 
@@ -336,8 +348,23 @@ fn validate_segment_bounds(segment: &CdsSegment, allow_overflow: bool) -> Result
   Ok(())
 }
 
+/// Reads genetic code from `transl_table` attribute, if present
+fn genetic_code_from_attributes(attributes: &IndexMap<String, Vec<String>>) -> Result<Option<GeneticCode>, Report> {
+  let Some(values) = attributes.get("transl_table") else {
+    return Ok(None);
+  };
+  match values.iter().unique().collect_vec().as_slice() {
+    [] => Ok(None),
+    [value] => Ok(Some(value.parse()?)),
+    values => make_error!(
+      "Attribute 'transl_table' has conflicting values: {}",
+      values.iter().join(", ")
+    ),
+  }
+}
+
 fn find_proteins_recursive(feature_group: &FeatureGroup, proteins: &mut Vec<Protein>) -> Result<(), Report> {
-  if feature_group.feature_type == "mature_protein_region_of_CDS" {
+  if ["mature_protein_region_of_CDS", "signal_peptide_region_of_CDS"].contains(&feature_group.feature_type.as_str()) {
     let protein = Protein::from_feature_group(feature_group)
       .wrap_err_with(|| eyre!("When processing protein, '{}'", feature_group.name))?;
     proteins.push(protein);
@@ -60,6 +60,17 @@ impl Protein {
   pub fn name_and_type(&self) -> String {
     format!("Protein '{}'", self.name)
   }
+
+  #[inline]
+  pub fn len(&self) -> usize {
+    // sum of lengths of all segments
+    self.segments.iter().map(ProteinSegment::len).sum()
+  }
+
+  #[inline]
+  pub fn is_empty(&self) -> bool {
+    self.segments.is_empty() || self.len() == 0
+  }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
@@ -84,4 +95,14 @@ impl ProteinSegment {
   pub fn name_and_type(&self) -> String {
     format!("Protein segment '{}'", self.name)
   }
+
+  #[inline]
+  pub fn len(&self) -> usize {
+    self.range.len()
+  }
+
+  #[inline]
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
 }
@@ -1,4 +1,4 @@
-use crate::io::file::create_file_or_stdout;
+use crate::io::file::{create_file_or_stdout, open_file_or_stdout, OutputFileMode};
 use crate::io::fs::read_file_to_string;
 use crate::utils::error::to_eyre_error;
 use csv::{ReaderBuilder as CsvReaderBuilder, Writer as CsvWriterImpl, WriterBuilder as CsvWriterBuilder};
@@ -49,6 +49,8 @@ impl CsvStructFileWriter {
 
 pub trait VecWriter {
   fn write<I: IntoIterator<Item = T>, T: AsRef<[u8]>>(&mut self, values: I) -> Result<(), Report>;
+
+  fn flush(&mut self) -> Result<(), Report>;
 }
 
 /// Writes CSV. Each row is a vec of strings.
@@ -59,12 +61,18 @@ pub struct CsvVecWriter<W: Write + Send> {
 
 impl<W: Write + Send> CsvVecWriter<W> {
   pub fn new(writer: W, delimiter: u8, headers: &[String]) -> Result<Self, Report> {
-    let mut writer = CsvWriterBuilder::new().delimiter(delimiter).from_writer(writer);
-    writer.write_record(headers)?;
-    Ok(Self {
+    let mut this = Self::without_header_row(writer, delimiter, headers);
+    this.writer.write_record(headers)?;
+    Ok(this)
+  }
+
+  /// Creates a writer which does not write the header row. Useful when appending to an existing CSV file.
+  pub fn without_header_row(writer: W, delimiter: u8, headers: &[String]) -> Self {
+    let writer = CsvWriterBuilder::new().delimiter(delimiter).from_writer(writer);
+    Self {
       headers: headers.to_owned(),
       writer,
-    })
+    }
   }
 }
 
@@ -73,6 +81,11 @@ impl<W: Write + Send> VecWriter for CsvVecWriter<W> {
     self.writer.write_record(values)?;
     Ok(())
   }
+
+  fn flush(&mut self) -> Result<(), Report> {
+    self.writer.flush()?;
+    Ok(())
+  }
 }
 
 /// Writes CSV files. Each row is a vec of strings.
@@ -84,9 +97,22 @@ pub struct CsvVecFileWriter {
 
 impl CsvVecFileWriter {
   pub fn new(filepath: impl AsRef<Path>, delimiter: u8, headers: &[String]) -> Result<Self, Report> {
+    Self::with_mode(filepath, delimiter, headers, OutputFileMode::Create)
+  }
+
+  /// Opens CSV file in a given mode. In append mode, the header row is not written.
+  pub fn with_mode(
+    filepath: impl AsRef<Path>,
+    delimiter: u8,
+    headers: &[String],
+    mode: OutputFileMode,
+  ) -> Result<Self, Report> {
     let filepath = filepath.as_ref();
-    let file = create_file_or_stdout(filepath)?;
-    let writer = CsvVecWriter::new(file, delimiter, headers)?;
+    let file = open_file_or_stdout(filepath, mode)?;
+    let writer = match mode {
+      OutputFileMode::Create => CsvVecWriter::new(file, delimiter, headers)?,
+      OutputFileMode::Append => CsvVecWriter::without_header_row(file, delimiter, headers),
+    };
     Ok(Self {
       filepath: filepath.to_owned(),
       headers: headers.to_owned(),
@@ -100,6 +126,10 @@ impl VecWriter for CsvVecFileWriter {
     self.writer.write(values)?;
     Ok(())
   }
+
+  fn flush(&mut self) -> Result<(), Report> {
+    self.writer.flush()
+  }
 }
 
 /// Parses CSV data from string.
@@ -379,6 +379,19 @@ impl DatasetFiles {
   }
 }
 
+/// Describes one segment of a multi-segment dataset (e.g. for influenza). Each segment has its own reference sequence
+/// and, optionally, genome annotation and reference tree. Paths are relative to the dataset root.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DatasetSegment {
+  pub name: String,
+
+  pub files: DatasetFiles,
+
+  #[serde(flatten)]
+  pub other: serde_json::Value,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct DatasetCollectionUrl {
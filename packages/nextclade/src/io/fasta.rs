@@ -3,7 +3,7 @@ use crate::constants::REVERSE_COMPLEMENT_SUFFIX;
 use crate::gene::gene_map::GeneMap;
 use crate::io::compression::Decompressor;
 use crate::io::concat::Concat;
-use crate::io::file::{create_file_or_stdout, open_file_or_stdin, open_stdin};
+use crate::io::file::{create_file_or_stdout, open_file_or_stdin, open_file_or_stdout, open_stdin, OutputFileMode};
 use crate::translate::translate_genes::CdsTranslation;
 use crate::utils::string::truncate_right;
 use crate::{make_error, make_internal_error};
@@ -203,6 +203,10 @@ impl FastaWriter {
     Ok(Self::new(create_file_or_stdout(filepath)?))
   }
 
+  pub fn from_path_with_mode(filepath: impl AsRef<Path>, mode: OutputFileMode) -> Result<Self, Report> {
+    Ok(Self::new(open_file_or_stdout(filepath, mode)?))
+  }
+
   pub fn write(&mut self, seq_name: &str, seq: &str, is_reverse_complement: bool) -> Result<(), Report> {
     let seq_name = if is_reverse_complement {
       format!("{seq_name}{REVERSE_COMPLEMENT_SUFFIX}")
@@ -230,16 +234,26 @@ pub type FastaPeptideWritersMap = BTreeMap<String, FastaWriter>;
 /// Writes peptides, each into a separate fasta file
 pub struct FastaPeptideWriter {
   writers: FastaPeptideWritersMap,
+  filepaths: Vec<PathBuf>,
 }
 
 impl FastaPeptideWriter {
   pub fn new(gene_map: &GeneMap, output_translations: impl AsRef<str>) -> Result<Self, Report> {
+    Self::with_mode(gene_map, output_translations, OutputFileMode::Create)
+  }
+
+  pub fn with_mode(
+    gene_map: &GeneMap,
+    output_translations: impl AsRef<str>,
+    mode: OutputFileMode,
+  ) -> Result<Self, Report> {
     let output_translations = output_translations.as_ref();
 
     let mut tt = TinyTemplate::new();
     tt.add_template("output_translations", output_translations)
       .wrap_err_with(|| format!("When parsing template: {output_translations}"))?;
 
+    let mut filepaths = vec![];
     let writers = gene_map
       .iter_cdses()
       .map(|cds| -> Result<_, Report> {
@@ -249,12 +263,18 @@ impl FastaPeptideWriter {
           .wrap_err_with(|| format!("When rendering output translations path template: '{output_translations}', using context: {template_context:?}"))?;
         let out_gene_fasta_path = PathBuf::from_str(&rendered_path).wrap_err_with(|| format!("Invalid output translations path: '{rendered_path}'"))?;
         trace!("Creating fasta writer to file {out_gene_fasta_path:#?}");
-        let writer = FastaWriter::from_path(&out_gene_fasta_path)?;
+        let writer = FastaWriter::from_path_with_mode(&out_gene_fasta_path, mode)?;
+        filepaths.push(out_gene_fasta_path);
         Ok((cds.name.clone(), writer))
       })
       .collect::<Result<FastaPeptideWritersMap, Report>>()?;
 
-    Ok(Self { writers })
+    Ok(Self { writers, filepaths })
+  }
+
+  /// Paths of all output files, one per CDS
+  pub fn filepaths(&self) -> &[PathBuf] {
+    &self.filepaths
   }
 
   pub fn write(&mut self, seq_name: &str, translation: &CdsTranslation) -> Result<(), Report> {
@@ -263,6 +283,10 @@ impl FastaPeptideWriter {
       Some(writer) => writer.write(seq_name, &from_aa_seq(&translation.seq), false),
     }
   }
+
+  pub fn flush(&mut self) -> Result<(), Report> {
+    self.writers.values_mut().try_for_each(FastaWriter::flush)
+  }
 }
 
 pub fn parse_fasta_header(header: &str) -> (String, String) {
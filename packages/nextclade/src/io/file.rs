@@ -1,8 +1,9 @@
 use crate::io::compression::{Compressor, Decompressor};
 use crate::io::fs::ensure_dir;
+use crate::make_error;
 use eyre::{Report, WrapErr};
 use log::info;
-use std::fs::File;
+use std::fs::{File, OpenOptions};
 use std::io::{stdin, stdout, BufRead, BufReader, BufWriter, Write};
 use std::path::{Path, PathBuf};
 
@@ -37,16 +38,45 @@ pub fn open_file_or_stdin<P: AsRef<Path>>(filepath: &Option<P>) -> Result<Box<dy
   }
 }
 
+/// Whether an output file is created anew, discarding existing contents, or existing contents are preserved and new
+/// data is appended to the end of the file
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFileMode {
+  #[default]
+  Create,
+  Append,
+}
+
 /// Open file for writing. If the path does not exist it will be created recursively.
 pub fn create_file_or_stdout(filepath: impl AsRef<Path>) -> Result<Box<dyn Write + Send>, Report> {
+  open_file_or_stdout(filepath, OutputFileMode::Create)
+}
+
+/// Open file for writing in a given mode. If the path does not exist it will be created recursively. Standard output
+/// cannot be appended to.
+pub fn open_file_or_stdout(filepath: impl AsRef<Path>, mode: OutputFileMode) -> Result<Box<dyn Write + Send>, Report> {
   let filepath = filepath.as_ref();
 
   let file: Box<dyn Write + Sync + Send> = if is_path_stdout(filepath) {
+    if mode == OutputFileMode::Append {
+      return make_error!("Unable to append to standard output");
+    }
     info!("File path is {filepath:?}. Writing to standard output.");
     Box::new(BufWriter::with_capacity(DEFAULT_FILE_BUF_SIZE, stdout()))
   } else {
     ensure_dir(filepath)?;
-    Box::new(File::create(filepath).wrap_err_with(|| format!("When creating file: '{filepath:?}'"))?)
+    match mode {
+      OutputFileMode::Create => {
+        Box::new(File::create(filepath).wrap_err_with(|| format!("When creating file: '{filepath:?}'"))?)
+      }
+      OutputFileMode::Append => Box::new(
+        OpenOptions::new()
+          .create(true)
+          .append(true)
+          .open(filepath)
+          .wrap_err_with(|| format!("When opening file for appending: '{filepath:?}'"))?,
+      ),
+    }
   };
 
   let buf_file = BufWriter::with_capacity(DEFAULT_FILE_BUF_SIZE, file);
@@ -108,7 +108,7 @@ impl GffCommonInfo {
 
     let name_attrs = match record.feature_type().to_lowercase().as_str() {
       "cds" => NAME_ATTRS_CDS,
-      "mature_protein_region_of_cds" => NAME_ATTRS_PROTEIN,
+      "mature_protein_region_of_cds" | "signal_peptide_region_of_cds" => NAME_ATTRS_PROTEIN,
       _ => NAME_ATTRS_GENE,
     };
     let name = get_one_of_attributes_optional(&attributes, name_attrs);
@@ -1,7 +1,8 @@
 use crate::coord::position::PositionLike;
 use crate::gene::cds_segment::CdsSegment;
-use crate::gene::gene::Gene;
+use crate::gene::gene::{Gene, GeneStrand};
 use crate::gene::gene_map::GeneMap;
+use crate::gene::protein::ProteinSegment;
 use crate::io::file::create_file_or_stdout;
 use crate::io::gff3_encoding::{gff_encode_attribute, gff_encode_non_attribute};
 use crate::o;
@@ -60,8 +61,16 @@ impl<W: Write> Gff3Writer<W> {
           self.write_record(&record)?;
         }
 
-        // TODO: once we support proteins, output proteins as well
-        // for protein in &cds.proteins {}
+        // Implicit proteins, created from genes when there are no CDS records, duplicate their CDS, so skip them
+        if !cds.compat_is_gene {
+          for protein in &cds.proteins {
+            for (i, segment) in protein.segments.iter().enumerate() {
+              let record = protein_to_bio_gff_record(segment, cds.strand()?)
+                .wrap_err_with(|| format!("When converting segment {} of protein {}", i, protein.name))?;
+              self.write_record(&record)?;
+            }
+          }
+        }
       }
     }
 
@@ -108,6 +117,23 @@ fn cds_to_bio_gff_record(seg: &CdsSegment) -> Result<BioGffRecord, Report> {
   gff_encode_record(&record)
 }
 
+fn protein_to_bio_gff_record(seg: &ProteinSegment, strand: GeneStrand) -> Result<BioGffRecord, Report> {
+  let mut record = BioGffRecord::new();
+  *record.seqname_mut() = seg.gff_seqid.clone().unwrap_or_else(|| o!("."));
+  *record.source_mut() = o!("nextclade");
+  *record.feature_type_mut() = seg
+    .gff_feature_type
+    .clone()
+    .unwrap_or_else(|| o!("mature_protein_region_of_CDS"));
+  *record.start_mut() = (seg.range.begin.as_usize() + 1) as u64;
+  *record.end_mut() = seg.range.end.as_usize() as u64;
+  *record.score_mut() = o!(".");
+  *record.strand_mut() = strand.to_string();
+  *record.frame_mut() = o!(".");
+  *record.attributes_mut() = gff_write_convert_all_attributes(&seg.attributes)?;
+  gff_encode_record(&record)
+}
+
 fn create_bio_gff_region_record(seq_index: usize, seqid: &str, seq_len: usize) -> Result<BioGffRecord, Report> {
   let mut record = BioGffRecord::new();
   *record.seqname_mut() = seqid.to_owned();
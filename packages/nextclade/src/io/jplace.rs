@@ -0,0 +1,364 @@
+use crate::analyze::divergence::calculate_branch_length;
+use crate::analyze::find_private_nuc_mutations::BranchMutations;
+use crate::graph::node::GraphNodeKey;
+use crate::graph::traits::HasDivergence;
+use crate::io::json::{json_write, JsonPretty};
+use crate::io::nwk_writer::convert_graph_to_nwk_string_with_edge_labels;
+use crate::make_internal_report;
+use crate::tree::params::TreeBuilderParams;
+use crate::tree::split_muts::{split_muts, SplitMutsResult};
+use crate::tree::tree::{AuspiceGraph, DivergenceUnits};
+use crate::tree::tree_builder::{finetune_nearest_node, query_private_mutations};
+use crate::tree::tree_find_nearest_node::PlacementCandidate;
+use crate::types::outputs::NextcladeOutputs;
+use eyre::{Report, WrapErr};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+pub const JPLACE_VERSION: usize = 3;
+
+pub const JPLACE_FIELDS: &[&str] = &["edge_num", "like_weight_ratio", "distal_length", "pendant_length"];
+
+/// Phylogenetic placements in jplace format
+///
+/// See: Matsen FA, Hoffman NG, Gallagher A, Stamatakis A (2012) A Format for Phylogenetic Placements.
+/// https://doi.org/10.1371/journal.pone.0031009
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Jplace {
+  /// Reference tree in Newick format, with edge numbers in curly braces
+  pub tree: String,
+  pub placements: Vec<JplacePlacements>,
+  pub metadata: JplaceMetadata,
+  pub version: usize,
+  pub fields: Vec<String>,
+}
+
+/// Placements of one query sequence
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct JplacePlacements {
+  pub p: Vec<JplacePlacement>,
+  pub n: Vec<String>,
+}
+
+/// One placement, with values in the order of `JPLACE_FIELDS`: edge number, like weight ratio, distal length and
+/// pendant length
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct JplacePlacement(pub usize, pub f64, pub f64, pub f64);
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct JplaceMetadata {
+  pub invocation: String,
+}
+
+pub fn jplace_write_to_file(filepath: impl AsRef<Path>, jplace: &Jplace) -> Result<(), Report> {
+  let filepath = filepath.as_ref();
+  json_write(filepath, jplace, JsonPretty(true)).wrap_err_with(|| format!("When writing jplace file: {filepath:#?}"))
+}
+
+/// Converts placements of query sequences onto the reference tree into jplace format.
+///
+/// The graph must be the reference tree, before any of the new nodes are attached to it.
+pub fn jplace_from_results(
+  graph: &AuspiceGraph,
+  results: &[NextcladeOutputs],
+  ref_seq_len: usize,
+  params: &TreeBuilderParams,
+  invocation: String,
+) -> Result<Jplace, Report> {
+  let mut edge_nums = BTreeMap::new();
+  let tree = convert_graph_to_jplace_tree(graph, &mut edge_nums)?;
+
+  let node_keys_by_name = graph
+    .iter_nodes()
+    .map(|node| (node.payload().name.as_str(), node.key()))
+    .collect();
+
+  let context = JplaceContext {
+    graph,
+    edge_nums: &edge_nums,
+    node_keys_by_name: &node_keys_by_name,
+    ref_seq_len,
+    params,
+  };
+
+  let placements = results
+    .iter()
+    .sorted_by_key(|result| result.index)
+    .map(|result| {
+      let candidates = result
+        .placement_uncertainty
+        .as_ref()
+        .map(|uncertainty| uncertainty.candidates.as_slice())
+        .unwrap_or_default();
+
+      let p = find_jplace_placements(
+        &context,
+        result.nearest_node_id,
+        &query_private_mutations(result),
+        candidates,
+      )
+      .wrap_err_with(|| format!("When placing query sequence '{}' for jplace output", result.seq_name))?;
+
+      Ok(JplacePlacements {
+        p,
+        n: vec![result.seq_name.clone()],
+      })
+    })
+    .collect::<Result<Vec<_>, Report>>()?;
+
+  Ok(Jplace {
+    tree,
+    placements,
+    metadata: JplaceMetadata { invocation },
+    version: JPLACE_VERSION,
+    fields: JPLACE_FIELDS.iter().map(ToString::to_string).collect_vec(),
+  })
+}
+
+/// Writes the tree in Newick format with edge numbers. Edges are numbered in postorder, that is children before
+/// parents, so that the root receives the largest number.
+pub fn convert_graph_to_jplace_tree(
+  graph: &AuspiceGraph,
+  edge_nums: &mut BTreeMap<GraphNodeKey, usize>,
+) -> Result<String, Report> {
+  let tree = convert_graph_to_nwk_string_with_edge_labels(graph, |node_key| {
+    let edge_num = edge_nums.len();
+    edge_nums.insert(node_key, edge_num);
+    format!("{{{edge_num}}}")
+  })
+  .wrap_err("When converting graph to jplace tree")?;
+  Ok(format!("{tree};"))
+}
+
+struct JplaceContext<'a> {
+  graph: &'a AuspiceGraph,
+  edge_nums: &'a BTreeMap<GraphNodeKey, usize>,
+  node_keys_by_name: &'a BTreeMap<&'a str, GraphNodeKey>,
+  ref_seq_len: usize,
+  params: &'a TreeBuilderParams,
+}
+
+/// Finds placements of one query sequence, sorted by decreasing like weight ratio.
+///
+/// The main placement is found the same way as the tree builder attaches the query to the tree: starting from the
+/// nearest node, the position is fine-tuned, and the branch above the chosen node is split according to the mutations
+/// shared with the query. Other placement candidates with non-negligible support are attached directly to their nodes.
+fn find_jplace_placements(
+  context: &JplaceContext,
+  nearest_node_key: GraphNodeKey,
+  query_mutations: &BranchMutations,
+  candidates: &[PlacementCandidate],
+) -> Result<Vec<JplacePlacement>, Report> {
+  let JplaceContext {
+    graph,
+    edge_nums,
+    node_keys_by_name,
+    ref_seq_len,
+    params,
+  } = context;
+
+  let get_edge_num = |node_key: GraphNodeKey| {
+    edge_nums
+      .get(&node_key)
+      .copied()
+      .ok_or_else(|| make_internal_report!("Node with key {node_key} is not numbered in jplace tree"))
+  };
+
+  let (node_key, private_mutations) = if params.without_greedy_tree_builder {
+    (nearest_node_key, query_mutations.clone())
+  } else {
+    finetune_nearest_node(graph, nearest_node_key, query_mutations)?
+  };
+  let (distal_length, pendant_length) =
+    placement_branch_lengths(graph, node_key, &private_mutations, *ref_seq_len, params)?;
+
+  let nearest_node_name = &graph.get_node(nearest_node_key)?.payload().name;
+  let like_weight_ratio = candidates
+    .iter()
+    .find(|candidate| &candidate.node_name == nearest_node_name)
+    .map_or(1.0, |candidate| candidate.support);
+
+  let mut placements = vec![JplacePlacement(
+    get_edge_num(node_key)?,
+    like_weight_ratio,
+    distal_length,
+    pendant_length,
+  )];
+
+  let divergence_units = graph.data.tmp.divergence_units;
+  for candidate in candidates {
+    if &candidate.node_name == nearest_node_name {
+      continue;
+    }
+
+    let candidate_key = node_keys_by_name.get(candidate.node_name.as_str()).ok_or_else(|| {
+      make_internal_report!(
+        "Placement candidate node '{}' is not found in the reference tree",
+        candidate.node_name
+      )
+    })?;
+    let edge_num = get_edge_num(*candidate_key)?;
+
+    // Fine-tuning could have moved the main placement onto the edge of one of the candidates
+    if let Some(placement) = placements.iter_mut().find(|placement| placement.0 == edge_num) {
+      placement.1 += candidate.support;
+    } else {
+      let pendant_length = distance_to_branch_length(candidate.distance, divergence_units, *ref_seq_len);
+      placements.push(JplacePlacement(edge_num, candidate.support, 0.0, pendant_length));
+    }
+  }
+
+  // Candidates with negligible support are not reported, so the remaining ratios need to be rescaled
+  let total_ratio: f64 = placements.iter().map(|placement| placement.1).sum();
+  for placement in &mut placements {
+    placement.1 /= total_ratio;
+  }
+  placements.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+  Ok(placements)
+}
+
+/// Calculates distal and pendant lengths of a query attached to the branch leading to a given node. This mirrors
+/// how the tree builder splits the branch, so that the lengths agree with the tree in Auspice JSON and Newick outputs.
+fn placement_branch_lengths(
+  graph: &AuspiceGraph,
+  node_key: GraphNodeKey,
+  private_mutations: &BranchMutations,
+  ref_seq_len: usize,
+  params: &TreeBuilderParams,
+) -> Result<(f64, f64), Report> {
+  let masked_ranges = graph.data.meta.placement_mask_ranges();
+  let divergence_units = graph.data.tmp.divergence_units;
+  let branch_length =
+    |muts: &BranchMutations| calculate_branch_length(&muts.nuc_muts, masked_ranges, divergence_units, ref_seq_len);
+
+  let node = graph.get_node(node_key)?;
+  let parent = graph.parent_of(node);
+  let (Some(parent), false) = (parent, params.without_greedy_tree_builder) else {
+    // Branch is not split: the query is attached directly to the node
+    return Ok((0.0, branch_length(private_mutations)));
+  };
+
+  let SplitMutsResult {
+    left: muts_common_branch,
+    right: muts_new_node,
+    ..
+  } = split_muts(&node.payload().tmp.private_mutations.invert(), private_mutations).wrap_err_with(|| {
+    format!(
+      "When splitting mutations between query sequence and the node '{}'",
+      node.payload().name
+    )
+  })?;
+
+  let edge_length = node.payload().divergence() - parent.payload().divergence();
+  let distal_length = (edge_length - branch_length(&muts_common_branch)).max(0.0);
+
+  Ok((distal_length, branch_length(&muts_new_node)))
+}
+
+/// Converts placement distance, which is in units of mutations, into units of tree divergence
+fn distance_to_branch_length(distance: f64, divergence_units: DivergenceUnits, ref_seq_len: usize) -> f64 {
+  if divergence_units == DivergenceUnits::NumSubstitutionsPerYearPerSite {
+    distance / ref_seq_len as f64
+  } else {
+    distance
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::analyze::nuc_sub::NucSub;
+  use crate::tree::tree::{AuspiceGraphEdgePayload, AuspiceGraphMeta, AuspiceGraphNodePayload};
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+  use std::str::FromStr;
+
+  fn muts(subs: &[&str]) -> Result<BranchMutations, Report> {
+    Ok(BranchMutations {
+      nuc_muts: subs.iter().map(|sub| NucSub::from_str(sub)).collect::<Result<_, _>>()?,
+      aa_muts: BTreeMap::new(),
+    })
+  }
+
+  fn node(name: &str, div: f64, subs: &[&str]) -> Result<AuspiceGraphNodePayload, Report> {
+    let mut node = AuspiceGraphNodePayload::new(name);
+    node.node_attrs.div = Some(div);
+    node.tmp.private_mutations = muts(subs)?;
+    Ok(node)
+  }
+
+  //       root
+  //      /    \
+  //     A      B
+  fn graph() -> Result<(AuspiceGraph, [GraphNodeKey; 3]), Report> {
+    let mut graph = AuspiceGraph::new(AuspiceGraphMeta::default());
+    let root = graph.add_node(node("root", 0.0, &[])?);
+    let a = graph.add_node(node("A", 3.0, &["C100T", "G200A", "A300G"])?);
+    let b = graph.add_node(node("B", 1.0, &["T400C"])?);
+    graph.add_edge(root, a, AuspiceGraphEdgePayload::new())?;
+    graph.add_edge(root, b, AuspiceGraphEdgePayload::new())?;
+    Ok((graph.build()?, [root, a, b]))
+  }
+
+  #[rstest]
+  fn writes_tree_with_edges_numbered_in_postorder() -> Result<(), Report> {
+    let (graph, [root, a, b]) = graph()?;
+    let mut edge_nums = BTreeMap::new();
+
+    let tree = convert_graph_to_jplace_tree(&graph, &mut edge_nums)?;
+
+    assert_eq!(tree, "(A:3{0},B:1{1}):0{2};");
+    assert_eq!(edge_nums, BTreeMap::from([(a, 0), (b, 1), (root, 2)]));
+    Ok(())
+  }
+
+  #[rstest]
+  fn splits_branch_into_distal_and_pendant_lengths() -> Result<(), Report> {
+    let (graph, [_, a, _]) = graph()?;
+
+    // Query shares 2 of the 3 mutations of A (reverts the third one) and has 1 mutation of its own
+    let private_mutations = muts(&["G300A", "C500T"])?;
+
+    let actual = placement_branch_lengths(&graph, a, &private_mutations, 1000, &TreeBuilderParams::default())?;
+
+    assert_eq!(actual, (1.0, 1.0));
+    Ok(())
+  }
+
+  #[rstest]
+  fn adds_placement_candidates_with_rescaled_like_weight_ratios() -> Result<(), Report> {
+    let (graph, [_, a, _]) = graph()?;
+    let mut edge_nums = BTreeMap::new();
+    convert_graph_to_jplace_tree(&graph, &mut edge_nums)?;
+    let node_keys_by_name = graph
+      .iter_nodes()
+      .map(|node| (node.payload().name.as_str(), node.key()))
+      .collect();
+    let params = TreeBuilderParams::default();
+    let context = JplaceContext {
+      graph: &graph,
+      edge_nums: &edge_nums,
+      node_keys_by_name: &node_keys_by_name,
+      ref_seq_len: 1000,
+      params: &params,
+    };
+
+    let candidates = [("A", 1.0, 0.375), ("B", 2.0, 0.125)].map(|(node_name, distance, support)| PlacementCandidate {
+      node_name: node_name.to_owned(),
+      clade: None,
+      distance,
+      support,
+    });
+
+    let actual = find_jplace_placements(&context, a, &muts(&["C500T"])?, &candidates)?;
+
+    assert_eq!(
+      actual,
+      vec![JplacePlacement(0, 0.75, 0.0, 1.0), JplacePlacement(1, 0.25, 0.0, 2.0),]
+    );
+    Ok(())
+  }
+}
@@ -10,14 +10,21 @@ pub mod genbank_tbl;
 pub mod gff3_encoding;
 pub mod gff3_reader;
 pub mod gff3_writer;
+pub mod jplace;
 pub mod json;
+pub mod mutational_spectrum_tsv;
 pub mod ndjson;
 pub mod nextclade_csv;
 pub mod nextclade_csv_column_config;
+pub mod nextclade_csv_isolates;
 pub mod nextclade_csv_row;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod nextclade_parquet;
 pub mod nwk_writer;
 pub mod parse_pos;
 pub mod results_json;
+pub mod sam_writer;
 pub mod schema_version;
+pub mod vcf_writer;
 pub mod xlsx;
 pub mod yaml;
@@ -0,0 +1,90 @@
+//! Writes mutational spectra of query sequences in TSV format.
+//!
+//! Each sequence produces two rows: one for all substitutions relative to the reference sequence and one for private
+//! substitutions (relative to the nearest node of the reference tree). Columns contain counts in each of the channels of
+//! the 96-channel spectrum (prefixed with `sbs96.`), followed by the 192-channel strand-aware spectrum (prefixed with
+//! `sbs192.`).
+use crate::analyze::mutational_spectrum::{sbs192_channels, sbs96_channels, MutationalSpectrum};
+use crate::io::csv::{CsvVecFileWriter, VecWriter};
+use crate::io::file::OutputFileMode;
+use crate::o;
+use crate::types::outputs::NextcladeOutputs;
+use eyre::Report;
+use itertools::{chain, Itertools};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+pub struct MutationalSpectrumTsvFileWriter {
+  writer: CsvVecFileWriter,
+  sbs96: Vec<String>,
+  sbs192: Vec<String>,
+}
+
+impl MutationalSpectrumTsvFileWriter {
+  pub fn new(filepath: impl AsRef<Path>) -> Result<Self, Report> {
+    Self::with_mode(filepath, OutputFileMode::Create)
+  }
+
+  pub fn with_mode(filepath: impl AsRef<Path>, mode: OutputFileMode) -> Result<Self, Report> {
+    let sbs96 = sbs96_channels();
+    let sbs192 = sbs192_channels();
+
+    let headers = chain!(
+      [o!("index"), o!("seqName"), o!("mutations"), o!("total")],
+      sbs96.iter().map(|channel| format!("sbs96.{channel}")),
+      sbs192.iter().map(|channel| format!("sbs192.{channel}")),
+    )
+    .collect_vec();
+
+    let writer = CsvVecFileWriter::with_mode(filepath, b'\t', &headers, mode)?;
+    Ok(Self { writer, sbs96, sbs192 })
+  }
+
+  pub fn write(&mut self, nextclade_outputs: &NextcladeOutputs) -> Result<(), Report> {
+    let NextcladeOutputs {
+      index,
+      seq_name,
+      mutational_spectra,
+      ..
+    } = nextclade_outputs;
+
+    self.write_row(*index, seq_name, "all", &mutational_spectra.all)?;
+    self.write_row(*index, seq_name, "private", &mutational_spectra.private)
+  }
+
+  fn write_row(
+    &mut self,
+    index: usize,
+    seq_name: &str,
+    mutations: &str,
+    spectrum: &MutationalSpectrum,
+  ) -> Result<(), Report> {
+    let row = chain!(
+      [
+        index.to_string(),
+        seq_name.to_owned(),
+        mutations.to_owned(),
+        spectrum.total.to_string()
+      ],
+      channel_counts(&self.sbs96, &spectrum.sbs96),
+      channel_counts(&self.sbs192, &spectrum.sbs192),
+    )
+    .collect_vec();
+
+    self.writer.write(row)
+  }
+
+  pub fn flush(&mut self) -> Result<(), Report> {
+    self.writer.flush()
+  }
+}
+
+/// Counts in each of the channels, in the order of the channels. Channels which are not in the spectrum have zero count.
+fn channel_counts<'a>(
+  channels: &'a [String],
+  counts: &'a BTreeMap<String, usize>,
+) -> impl Iterator<Item = String> + 'a {
+  channels
+    .iter()
+    .map(|channel| counts.get(channel).copied().unwrap_or_default().to_string())
+}
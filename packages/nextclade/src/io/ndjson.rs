@@ -1,4 +1,4 @@
-use crate::io::file::create_file_or_stdout;
+use crate::io::file::{open_file_or_stdout, OutputFileMode};
 use crate::types::outputs::NextcladeErrorOutputs;
 use eyre::{Report, WrapErr};
 
@@ -28,6 +28,11 @@ impl<W: Write + Send> NdjsonWriter<W> {
       errors: errors.to_vec(),
     })
   }
+
+  pub fn flush(&mut self) -> Result<(), Report> {
+    self.line_writer.flush()?;
+    Ok(())
+  }
 }
 
 pub struct NdjsonFileWriter {
@@ -37,8 +42,12 @@ pub struct NdjsonFileWriter {
 
 impl NdjsonFileWriter {
   pub fn new(filepath: impl AsRef<Path>) -> Result<Self, Report> {
+    Self::with_mode(filepath, OutputFileMode::Create)
+  }
+
+  pub fn with_mode(filepath: impl AsRef<Path>, mode: OutputFileMode) -> Result<Self, Report> {
     let filepath = filepath.as_ref();
-    let file = create_file_or_stdout(filepath)?;
+    let file = open_file_or_stdout(filepath, mode)?;
     let line_writer = NdjsonWriter::new(file)?;
     Ok(Self {
       filepath: filepath.to_owned(),
@@ -59,4 +68,11 @@ impl NdjsonFileWriter {
       .write_nuc_error(index, seq_name, errors)
       .wrap_err_with(|| format!("When writing ndjson error entry to file {:#?}", &self.filepath))
   }
+
+  pub fn flush(&mut self) -> Result<(), Report> {
+    self
+      .ndjson_writer
+      .flush()
+      .wrap_err_with(|| format!("When flushing ndjson output file {:#?}", &self.filepath))
+  }
 }
@@ -1,5 +1,6 @@
 use crate::io::csv::{CsvVecFileWriter, CsvVecWriter, VecWriter};
-use crate::io::nextclade_csv_column_config::{CsvColumnConfig, CSV_POSSIBLE_COLUMNS};
+use crate::io::file::OutputFileMode;
+use crate::io::nextclade_csv_column_config::{CsvColumnCategory, CsvColumnConfig, CSV_POSSIBLE_COLUMNS};
 use crate::io::nextclade_csv_row::NextcladeResultsCsvRow;
 use crate::o;
 use crate::tree::tree::{AuspiceRefNodeSearchDesc, AuspiceRefNodesDesc, CladeNodeAttrKeyDesc};
@@ -15,6 +16,8 @@ pub fn prepare_headers(
   phenotype_attr_keys: &[String],
   ref_nodes: &AuspiceRefNodesDesc,
   aa_motifs_keys: &[String],
+  nuc_motifs_keys: &[String],
+  qc_custom_rule_keys: &[String],
   column_config: &CsvColumnConfig,
 ) -> Vec<String> {
   // Get names of enabled columns
@@ -58,6 +61,27 @@ pub fn prepare_headers(
       insert_after(&mut headers, insert_custom_cols_at_index, key.clone());
       insert_custom_cols_at_index += 1;
     });
+
+    nuc_motifs_keys.iter().rev().for_each(|key| {
+      insert_after(&mut headers, insert_custom_cols_at_index, key.clone());
+      insert_custom_cols_at_index += 1;
+    });
+  }
+
+  if column_config.categories.contains_key(&CsvColumnCategory::Qc) {
+    // Insert custom QC rule columns after the last of the builtin QC columns
+    let mut insert_custom_cols_at_index = headers
+      .iter()
+      .rposition(|header| header.starts_with("qc."))
+      .unwrap_or_else(|| headers.len().saturating_sub(1))
+      .clamp(0, headers.len());
+
+    for name in qc_custom_rule_keys {
+      for col in &qc_custom_cols(name) {
+        insert_after(&mut headers, insert_custom_cols_at_index, col.to_owned());
+        insert_custom_cols_at_index += 1;
+      }
+    }
   }
 
   if column_config.include_rel_muts {
@@ -130,6 +154,15 @@ fn clade_founder_cols(name: impl AsRef<str>) -> [String; 5] {
   ]
 }
 
+fn qc_custom_cols(name: impl AsRef<str>) -> [String; 3] {
+  let name = name.as_ref();
+  [
+    format!("qc.custom['{name}'].value"),
+    format!("qc.custom['{name}'].score"),
+    format!("qc.custom['{name}'].status"),
+  ]
+}
+
 fn rel_mut_cols(desc: &AuspiceRefNodeSearchDesc) -> [String; 5] {
   let name = desc.display_name_or_name();
   [
@@ -175,6 +208,10 @@ impl<W: VecWriter> NextcladeResultsCsvWriter<W> {
     Ok(())
   }
 
+  pub fn flush(&mut self) -> Result<(), Report> {
+    self.writer.flush()
+  }
+
   /// Writes the current row and clears it
   fn write_row(&mut self) -> Result<(), Report> {
     self.writer.write(self.row.inner())?;
@@ -196,16 +233,46 @@ impl NextcladeResultsCsvFileWriter {
     phenotype_attr_keys: &[String],
     ref_nodes: &AuspiceRefNodesDesc,
     aa_motifs_keys: &[String],
+    nuc_motifs_keys: &[String],
+    qc_custom_rule_keys: &[String],
     column_config: &CsvColumnConfig,
+  ) -> Result<Self, Report> {
+    Self::with_mode(
+      filepath,
+      delimiter,
+      clade_node_attr_descs,
+      phenotype_attr_keys,
+      ref_nodes,
+      aa_motifs_keys,
+      nuc_motifs_keys,
+      qc_custom_rule_keys,
+      column_config,
+      OutputFileMode::Create,
+    )
+  }
+
+  pub fn with_mode(
+    filepath: impl AsRef<Path>,
+    delimiter: u8,
+    clade_node_attr_descs: &[CladeNodeAttrKeyDesc],
+    phenotype_attr_keys: &[String],
+    ref_nodes: &AuspiceRefNodesDesc,
+    aa_motifs_keys: &[String],
+    nuc_motifs_keys: &[String],
+    qc_custom_rule_keys: &[String],
+    column_config: &CsvColumnConfig,
+    mode: OutputFileMode,
   ) -> Result<Self, Report> {
     let headers: Vec<String> = prepare_headers(
       clade_node_attr_descs,
       phenotype_attr_keys,
       ref_nodes,
       aa_motifs_keys,
+      nuc_motifs_keys,
+      qc_custom_rule_keys,
       column_config,
     );
-    let csv_writer = CsvVecFileWriter::new(filepath, delimiter, &headers)?;
+    let csv_writer = CsvVecFileWriter::with_mode(filepath, delimiter, &headers, mode)?;
     let writer = NextcladeResultsCsvWriter::new(csv_writer, &headers)?;
     Ok(Self { writer })
   }
@@ -218,6 +285,10 @@ impl NextcladeResultsCsvFileWriter {
   pub fn write_nuc_error(&mut self, index: usize, seq_name: &str, errors: &str) -> Result<(), Report> {
     self.writer.write_nuc_error(index, seq_name, errors)
   }
+
+  pub fn flush(&mut self) -> Result<(), Report> {
+    self.writer.flush()
+  }
 }
 
 pub fn results_to_csv_string(
@@ -227,6 +298,8 @@ pub fn results_to_csv_string(
   phenotype_attr_keys: &[String],
   ref_nodes: &AuspiceRefNodesDesc,
   aa_motifs_keys: &[String],
+  nuc_motifs_keys: &[String],
+  qc_custom_rule_keys: &[String],
   delimiter: u8,
   column_config: &CsvColumnConfig,
 ) -> Result<String, Report> {
@@ -238,6 +311,8 @@ pub fn results_to_csv_string(
       phenotype_attr_keys,
       ref_nodes,
       aa_motifs_keys,
+      nuc_motifs_keys,
+      qc_custom_rule_keys,
       column_config,
     );
     let csv_writer = CsvVecWriter::new(&mut buf, delimiter, &headers)?;
@@ -260,7 +335,6 @@ pub fn results_to_csv_string(
 #[cfg(test)]
 mod tests {
   use super::*;
-  use crate::io::nextclade_csv_column_config::CsvColumnCategory;
   use indexmap::indexmap;
 
   #[test]
@@ -285,7 +359,7 @@ mod tests {
       include_clade_founder_muts: false,
     };
 
-    let headers = prepare_headers(&[], &[], &AuspiceRefNodesDesc::default(), &[], &column_config);
+    let headers = prepare_headers(&[], &[], &AuspiceRefNodesDesc::default(), &[], &[], &[], &column_config);
 
     // Verify headers are in canonical order as defined in CSV_COLUMN_CONFIG_MAP_DEFAULT
     let expected_order = vec![
@@ -300,6 +374,73 @@ mod tests {
     assert_eq!(headers, expected_order);
   }
 
+  #[test]
+  fn test_prepare_headers_qc_custom_rules() {
+    let column_config = CsvColumnConfig {
+      categories: indexmap! {
+        CsvColumnCategory::General => indexmap! {
+          o!("index") => true,
+          o!("seqName") => true,
+        },
+        CsvColumnCategory::Qc => indexmap! {
+          o!("qc.overallScore") => true,
+          o!("qc.stopCodons.status") => true,
+        },
+        CsvColumnCategory::ErrsWarns => indexmap! {
+          o!("errors") => true,
+        },
+      },
+      individual: vec![],
+      include_dynamic: false,
+      include_rel_muts: false,
+      include_clade_founder_muts: false,
+    };
+
+    let headers = prepare_headers(
+      &[],
+      &[],
+      &AuspiceRefNodesDesc::default(),
+      &[],
+      &[],
+      &[o!("spikeCoverage")],
+      &column_config,
+    );
+
+    let expected_order = vec![
+      "index",
+      "seqName",
+      "qc.overallScore",
+      "qc.stopCodons.status",
+      "qc.custom['spikeCoverage'].value",
+      "qc.custom['spikeCoverage'].score",
+      "qc.custom['spikeCoverage'].status",
+      "errors",
+    ];
+
+    assert_eq!(headers, expected_order);
+  }
+
+  #[test]
+  fn test_alignment_diagnostics_columns_are_optional() -> Result<(), Report> {
+    let is_diagnostics_column = |header: &String| header.starts_with("alignmentDiagnostics.");
+    let prepare = |column_config: &CsvColumnConfig| {
+      prepare_headers(&[], &[], &AuspiceRefNodesDesc::default(), &[], &[], &[], column_config)
+    };
+
+    let headers = prepare(&CsvColumnConfig::new(&[])?);
+    assert!(!headers.iter().any(is_diagnostics_column));
+
+    let headers = prepare(&CsvColumnConfig::new(&[o!("all"), o!("alignment-diagnostics")])?);
+    assert!(headers.iter().any(is_diagnostics_column));
+    assert!(headers.contains(&o!("substitutions")));
+
+    let headers = prepare(&CsvColumnConfig::new(&[o!("seqName"), o!("alignment-diagnostics")])?);
+    assert_eq!(headers[0], "seqName");
+    assert!(headers[1..].iter().all(is_diagnostics_column));
+
+    Ok(())
+  }
+
   #[test]
   fn test_sort_headers_by_canonical_order() {
     let headers = vec![
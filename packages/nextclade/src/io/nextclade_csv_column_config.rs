@@ -26,6 +26,7 @@ pub enum CsvColumnCategory {
   ErrsWarns,
   Qc,
   Primers,
+  AlignmentDiagnostics,
   Dynamic,
 }
 
@@ -71,7 +72,16 @@ impl CsvColumnConfig {
     })?;
 
     if output_columns_selection.is_empty() || categories.contains(&CsvColumnCategory::All) {
-      Ok(Self::default())
+      // Optional categories are not part of "all" and are only added when requested explicitly
+      let mut config = Self::default();
+      for category in categories {
+        if CSV_OPTIONAL_CATEGORIES.contains(&category) {
+          if let Some(columns) = CSV_COLUMN_CONFIG_MAP_DEFAULT.get(&category) {
+            config.categories.insert(category, columns.clone());
+          }
+        }
+      }
+      Ok(config)
     } else {
       let include_dynamic = categories.contains(&CsvColumnCategory::Dynamic);
 
@@ -101,7 +111,11 @@ impl CsvColumnConfig {
 impl Default for CsvColumnConfig {
   fn default() -> Self {
     Self {
-      categories: CSV_COLUMN_CONFIG_MAP_DEFAULT.clone(),
+      categories: CSV_COLUMN_CONFIG_MAP_DEFAULT
+        .iter()
+        .filter(|(category, _)| !CSV_OPTIONAL_CATEGORIES.contains(category))
+        .map(|(category, columns)| (category.clone(), columns.clone()))
+        .collect(),
       individual: vec![],
       include_dynamic: true,
       include_clade_founder_muts: true,
@@ -125,6 +139,7 @@ lazy_static! {
       o!("totalFrameShifts") => true,
       o!("totalMissing") => true,
       o!("totalNonACGTNs") => true,
+      o!("totalAmbiguousSites") => true,
       o!("totalAminoacidSubstitutions") => true,
       o!("totalAminoacidDeletions") => true,
       o!("totalAminoacidInsertions") => true,
@@ -132,9 +147,14 @@ lazy_static! {
       o!("alignmentScore") => true,
       o!("alignmentStart") => true,
       o!("alignmentEnd") => true,
+      o!("alignmentMode") => true,
       o!("coverage") => true,
       o!("cdsCoverage") => true,
+      o!("placementUncertainty.cladeConfidence") => true,
+      o!("placementUncertainty.alternativeClades") => true,
+      o!("placementUncertainty.isAmbiguous") => true,
       o!("isReverseComplement") => true,
+      o!("circularRotation") => true,
     },
     CsvColumnCategory::RefMuts => indexmap! {
       o!("substitutions") => true,
@@ -144,6 +164,9 @@ lazy_static! {
       o!("aaSubstitutions") => true,
       o!("aaDeletions") => true,
       o!("aaInsertions") => true,
+      o!("proteinAaSubstitutions") => true,
+      o!("proteinAaDeletions") => true,
+      o!("proteinAaInsertions") => true,
     },
     CsvColumnCategory::PrivMuts => indexmap! {
       o!("privateNucMutations.reversionSubstitutions") => true,
@@ -158,6 +181,7 @@ lazy_static! {
       o!("missing") => true,
       o!("unknownAaRanges") => true,
       o!("nonACGTNs") => true,
+      o!("ambiguousSites") => true,
       o!("qc.overallScore") => true,
       o!("qc.overallStatus") => true,
       o!("qc.missingData.missingDataThreshold") => true,
@@ -187,11 +211,29 @@ lazy_static! {
       o!("qc.stopCodons.totalStopCodons") => true,
       o!("qc.stopCodons.score") => true,
       o!("qc.stopCodons.status") => true,
+      o!("qc.recombinants.parents") => true,
+      o!("qc.recombinants.breakpoints") => true,
+      o!("qc.recombinants.support") => true,
+      o!("qc.recombinants.score") => true,
+      o!("qc.recombinants.status") => true,
+      o!("qc.bandBoundary.hitBoundary") => true,
+      o!("qc.bandBoundary.totalAttempts") => true,
+      o!("qc.bandBoundary.score") => true,
+      o!("qc.bandBoundary.status") => true,
     },
     CsvColumnCategory::Primers => indexmap! {
       o!("totalPcrPrimerChanges") => true,
       o!("pcrPrimerChanges") => true,
     },
+    CsvColumnCategory::AlignmentDiagnostics => indexmap! {
+      o!("alignmentDiagnostics.seedCount") => true,
+      o!("alignmentDiagnostics.seedCoverage") => true,
+      o!("alignmentDiagnostics.bandAreas") => true,
+      o!("alignmentDiagnostics.terminalBandwidth") => true,
+      o!("alignmentDiagnostics.excessBandwidth") => true,
+      o!("alignmentDiagnostics.minimalBandwidth") => true,
+      o!("alignmentDiagnostics.hitBoundary") => true,
+    },
     CsvColumnCategory::ErrsWarns => indexmap! {
       o!("failedCdses") => true,
       o!("warnings") => true,
@@ -199,6 +241,9 @@ lazy_static! {
     }
   };
 
+  // Categories of CSV columns which are not written unless requested explicitly
+  pub static ref CSV_OPTIONAL_CATEGORIES: Vec<CsvColumnCategory> = vec![CsvColumnCategory::AlignmentDiagnostics];
+
   pub static ref CSV_POSSIBLE_CATEGORIES: Vec<String> = CsvColumnCategory::VARIANTS.iter()
     .copied()
     .map(String::from)
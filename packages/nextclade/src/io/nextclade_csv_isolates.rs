@@ -0,0 +1,212 @@
+use crate::io::csv::{CsvVecFileWriter, VecWriter};
+use crate::io::nextclade_csv_row::NextcladeResultsCsvRow;
+use crate::types::outputs::NextcladeOutputOrError;
+use crate::{make_internal_error, o};
+use eyre::Report;
+use itertools::{chain, izip, Itertools};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Column names and CSV headers of one segment of a multi-segment dataset
+#[derive(Clone, Debug)]
+pub struct SegmentCsvHeaders {
+  pub name: String,
+  pub headers: Vec<String>,
+}
+
+/// Prepares headers of the per-isolate CSV file: isolate name, followed by columns of each segment, prefixed with the
+/// segment name (e.g. `HA.clade`)
+pub fn prepare_isolate_headers(segments: &[SegmentCsvHeaders]) -> Vec<String> {
+  let segment_headers = segments.iter().flat_map(|segment| {
+    segment
+      .headers
+      .iter()
+      .map(|header| format!("{}.{header}", segment.name))
+  });
+  chain!([o!("isolate")], segment_headers).collect_vec()
+}
+
+/// Rows of CSV and TSV files for multi-segment datasets, one row per isolate. The row consists of the regular
+/// nextclade.csv columns of each segment, prefixed with segment name. Cells of segments for which the isolate has no
+/// sequence are left empty.
+///
+/// Results of each sequence are formatted into cells as soon as they are added, so that only the cell values, and not
+/// the full analysis results, are retained until all sequences are processed.
+pub struct NextcladeIsolatesCsvRows {
+  segment_rows: Vec<NextcladeResultsCsvRow>,
+  isolates: HashMap<String, IsolateCsvRow>,
+}
+
+/// Cells of the row of one isolate
+struct IsolateCsvRow {
+  /// Index of the first sequence of the isolate in the input. Isolates are written in the order of first appearance.
+  first_index: usize,
+  /// Index of the sequence and its formatted cells, for each segment. Empty if the isolate has no sequence for the
+  /// segment.
+  segments: Vec<Option<(usize, Vec<String>)>>,
+}
+
+impl NextcladeIsolatesCsvRows {
+  pub fn new(segments: &[SegmentCsvHeaders]) -> Result<Self, Report> {
+    let segment_rows = segments
+      .iter()
+      .map(|segment| NextcladeResultsCsvRow::new(segment.headers.clone()))
+      .collect::<Result<Vec<_>, Report>>()?;
+    Ok(Self {
+      segment_rows,
+      isolates: HashMap::new(),
+    })
+  }
+
+  /// Adds a sequence of the isolate, given by its index in the input. The record is a pair of segment index and
+  /// analysis results (or error) of the sequence, if it could be assigned to a segment. Sequences can be added in any
+  /// order. If there are multiple sequences for the same segment, only the one which comes first in the input is kept.
+  pub fn add(
+    &mut self,
+    isolate_name: &str,
+    index: usize,
+    record: Option<(usize, &NextcladeOutputOrError)>,
+  ) -> Result<(), Report> {
+    let n_segments = self.segment_rows.len();
+    let isolate = self
+      .isolates
+      .entry(isolate_name.to_owned())
+      .or_insert_with(|| IsolateCsvRow {
+        first_index: index,
+        segments: vec![None; n_segments],
+      });
+    isolate.first_index = isolate.first_index.min(index);
+
+    let Some((segment_index, output_or_error)) = record else {
+      // Sequences which could not be assigned to a segment are not attributed to any of the segment columns
+      return Ok(());
+    };
+
+    let (Some(row), Some(cells)) = (
+      self.segment_rows.get_mut(segment_index),
+      isolate.segments.get_mut(segment_index),
+    ) else {
+      return make_internal_error!("Segment index {segment_index} is out of bounds: there are {n_segments} segments");
+    };
+
+    if cells.as_ref().is_some_and(|(seen_index, _)| *seen_index < index) {
+      return Ok(());
+    }
+
+    match output_or_error {
+      NextcladeOutputOrError::Outputs(output) => {
+        row.format(output)?;
+      }
+      NextcladeOutputOrError::Error(error) => {
+        row.write_nuc_error(error.index, &error.seq_name, &error.errors.join(";"))?;
+      }
+    }
+    *cells = Some((index, row.values().cloned().collect_vec()));
+    row.clear();
+
+    Ok(())
+  }
+
+  /// Writes one row per isolate, in the order of their first appearance in the input
+  pub fn write<W: VecWriter>(&self, writer: &mut W) -> Result<(), Report> {
+    let empty_segments = self
+      .segment_rows
+      .iter()
+      .map(|row| vec![String::new(); row.values().count()])
+      .collect_vec();
+
+    for (isolate_name, isolate) in self.isolates.iter().sorted_by_key(|(_, isolate)| isolate.first_index) {
+      let values = chain!(
+        [isolate_name.as_str()],
+        izip!(&isolate.segments, &empty_segments).flat_map(|(cells, empty)| {
+          cells
+            .as_ref()
+            .map_or(empty, |(_, cells)| cells)
+            .iter()
+            .map(String::as_str)
+        })
+      );
+      writer.write(values)?;
+    }
+
+    Ok(())
+  }
+}
+
+/// Writes per-isolate CSV and TSV files for multi-segment datasets
+pub struct NextcladeIsolatesCsvFileWriter {
+  writer: CsvVecFileWriter,
+}
+
+impl NextcladeIsolatesCsvFileWriter {
+  pub fn new(filepath: impl AsRef<Path>, delimiter: u8, segments: &[SegmentCsvHeaders]) -> Result<Self, Report> {
+    let headers = prepare_isolate_headers(segments);
+    let writer = CsvVecFileWriter::new(filepath, delimiter, &headers)?;
+    Ok(Self { writer })
+  }
+
+  pub fn write(&mut self, rows: &NextcladeIsolatesCsvRows) -> Result<(), Report> {
+    rows.write(&mut self.writer)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::io::csv::CsvVecWriter;
+  use crate::types::outputs::NextcladeErrorOutputs;
+  use pretty_assertions::assert_eq;
+
+  #[test]
+  fn writes_one_row_per_isolate_with_segment_prefixed_columns() -> Result<(), Report> {
+    let segments = vec![
+      SegmentCsvHeaders {
+        name: o!("HA"),
+        headers: vec![o!("seqName"), o!("errors")],
+      },
+      SegmentCsvHeaders {
+        name: o!("NA"),
+        headers: vec![o!("seqName"), o!("errors")],
+      },
+    ];
+
+    let ha = NextcladeOutputOrError::Error(NextcladeErrorOutputs {
+      index: 0,
+      seq_name: o!("iso1|HA"),
+      errors: vec![o!("Sequence is too short")],
+    });
+    let na = NextcladeOutputOrError::Error(NextcladeErrorOutputs {
+      index: 1,
+      seq_name: o!("iso1|NA"),
+      errors: vec![o!("Unable to align")],
+    });
+    let ha_duplicate = NextcladeOutputOrError::Error(NextcladeErrorOutputs {
+      index: 3,
+      seq_name: o!("iso1|HA|duplicate"),
+      errors: vec![o!("Sequence is too short")],
+    });
+
+    let mut rows = NextcladeIsolatesCsvRows::new(&segments)?;
+    rows.add("iso2", 2, Some((1, &na)))?;
+    rows.add("iso1", 1, Some((1, &na)))?;
+    rows.add("iso1", 0, Some((0, &ha)))?;
+    rows.add("iso1", 3, Some((0, &ha_duplicate)))?;
+    rows.add("iso3", 4, None)?;
+
+    let mut buf = Vec::<u8>::new();
+    {
+      let headers = prepare_isolate_headers(&segments);
+      let mut csv_writer = CsvVecWriter::new(&mut buf, b',', &headers)?;
+      rows.write(&mut csv_writer)?;
+    }
+
+    let expected = "\
+isolate,HA.seqName,HA.errors,NA.seqName,NA.errors
+iso1,iso1|HA,Sequence is too short,iso1|NA,Unable to align
+iso2,,,iso1|NA,Unable to align
+iso3,,,,
+";
+    assert_eq!(expected, String::from_utf8(buf)?);
+    Ok(())
+  }
+}
@@ -5,6 +5,7 @@ use crate::analyze::aa_del::AaDel;
 use crate::analyze::aa_sub::AaSub;
 use crate::analyze::find_aa_motifs::AaMotif;
 use crate::analyze::find_clade_founder::CladeNodeAttrFounderInfo;
+use crate::analyze::find_nuc_motifs::NucMotif;
 use crate::analyze::letter_ranges::{CdsAaRange, NucRange};
 use crate::analyze::nuc_del::NucDelRange;
 use crate::analyze::nuc_sub::{NucSub, NucSubLabeled};
@@ -14,6 +15,7 @@ use crate::o;
 use crate::qc::qc_config::StopCodonLocation;
 use crate::qc::qc_rule_snp_clusters::ClusteredSnp;
 use crate::translate::frame_shifts_translate::FrameShift;
+use crate::tree::tree_find_nearest_node::CladeSupport;
 use crate::types::outputs::{NextcladeOutputs, PeptideWarning, PhenotypeValue};
 use crate::utils::num::is_int;
 use eyre::Report;
@@ -49,6 +51,8 @@ impl NextcladeResultsCsvRow {
       total_missing,
       non_acgtns,
       total_non_acgtns,
+      ambiguous_sites,
+      total_ambiguous_sites,
       frame_shifts,
       total_frame_shifts,
       aa_substitutions,
@@ -57,10 +61,15 @@ impl NextcladeResultsCsvRow {
       total_aminoacid_deletions,
       aa_insertions,
       total_aminoacid_insertions,
+      protein_aa_substitutions,
+      protein_aa_deletions,
+      protein_aa_insertions,
       unknown_aa_ranges,
       total_unknown_aa,
       alignment_range,
       alignment_score,
+      alignment_mode,
+      alignment_diagnostics,
       pcr_primer_changes,
       total_pcr_primer_changes,
       clade,
@@ -73,9 +82,12 @@ impl NextcladeResultsCsvRow {
       phenotype_values,
       qc,
       custom_node_attributes,
+      placement_uncertainty,
       is_reverse_complement,
+      circular_rotation,
       warnings,
       aa_motifs,
+      nuc_motifs,
       ref_nodes,
       ref_node_search_results,
       relative_nuc_mutations,
@@ -99,6 +111,10 @@ impl NextcladeResultsCsvRow {
       .iter()
       .try_for_each(|(name, motifs)| self.add_entry(name, &format_aa_motifs(motifs)))?;
 
+    nuc_motifs
+      .iter()
+      .try_for_each(|(name, motifs)| self.add_entry(name, &format_nuc_motifs(motifs)))?;
+
     if let Some(info) = clade_founder_info {
       self.add_clade_founder_cols("clade", info)?;
     }
@@ -196,6 +212,7 @@ impl NextcladeResultsCsvRow {
     self.add_entry("totalUnknownAa", &total_unknown_aa.to_string())?;
     self.add_entry("totalMissing", &total_missing.to_string())?;
     self.add_entry("totalNonACGTNs", &total_non_acgtns.to_string())?;
+    self.add_entry("totalAmbiguousSites", &total_ambiguous_sites.to_string())?;
     self.add_entry("totalPcrPrimerChanges", &total_pcr_primer_changes.to_string())?;
     self.add_entry(
       "substitutions",
@@ -241,12 +258,28 @@ impl NextcladeResultsCsvRow {
       "aaInsertions",
       &format_aa_insertions(aa_insertions, ARRAY_ITEM_DELIMITER),
     )?;
+    self.add_entry(
+      "proteinAaSubstitutions",
+      &format_aa_substitutions(protein_aa_substitutions, ARRAY_ITEM_DELIMITER),
+    )?;
+    self.add_entry(
+      "proteinAaDeletions",
+      &format_aa_deletions(protein_aa_deletions, ARRAY_ITEM_DELIMITER),
+    )?;
+    self.add_entry(
+      "proteinAaInsertions",
+      &format_aa_insertions(protein_aa_insertions, ARRAY_ITEM_DELIMITER),
+    )?;
     self.add_entry(
       "unknownAaRanges",
       &format_unknown_aa_ranges(unknown_aa_ranges, ARRAY_ITEM_DELIMITER),
     )?;
     self.add_entry("missing", &format_missings(missing, ARRAY_ITEM_DELIMITER))?;
     self.add_entry("nonACGTNs", &format_non_acgtns(non_acgtns, ARRAY_ITEM_DELIMITER))?;
+    self.add_entry(
+      "ambiguousSites",
+      &format_nuc_substitutions(ambiguous_sites, ARRAY_ITEM_DELIMITER),
+    )?;
     self.add_entry(
       "pcrPrimerChanges",
       &format_pcr_primer_changes(pcr_primer_changes, ARRAY_ITEM_DELIMITER),
@@ -254,8 +287,45 @@ impl NextcladeResultsCsvRow {
     self.add_entry("alignmentScore", &alignment_score)?;
     self.add_entry("alignmentStart", &(alignment_range.begin + 1).to_string())?;
     self.add_entry("alignmentEnd", &alignment_range.end.to_string())?;
+    self.add_entry("alignmentMode", &alignment_mode.to_string())?;
+    self.add_entry("alignmentDiagnostics.seedCount", &alignment_diagnostics.seed_count)?;
+    self.add_entry(
+      "alignmentDiagnostics.seedCoverage",
+      &alignment_diagnostics.seed_coverage,
+    )?;
+    self.add_entry(
+      "alignmentDiagnostics.bandAreas",
+      &alignment_diagnostics.band_areas.iter().join(ARRAY_ITEM_DELIMITER),
+    )?;
+    self.add_entry(
+      "alignmentDiagnostics.terminalBandwidth",
+      &alignment_diagnostics.terminal_bandwidth,
+    )?;
+    self.add_entry(
+      "alignmentDiagnostics.excessBandwidth",
+      &alignment_diagnostics.excess_bandwidth,
+    )?;
+    self.add_entry(
+      "alignmentDiagnostics.minimalBandwidth",
+      &alignment_diagnostics.minimal_bandwidth,
+    )?;
+    self.add_entry("alignmentDiagnostics.hitBoundary", &alignment_diagnostics.hit_boundary)?;
     self.add_entry("coverage", coverage)?;
     self.add_entry("cdsCoverage", &format_cds_coverage(cds_coverage, ARRAY_ITEM_DELIMITER))?;
+    self.add_entry_maybe(
+      "placementUncertainty.cladeConfidence",
+      placement_uncertainty.as_ref().map(|pu| pu.clade_confidence.to_string()),
+    )?;
+    self.add_entry_maybe(
+      "placementUncertainty.alternativeClades",
+      placement_uncertainty
+        .as_ref()
+        .map(|pu| format_clade_supports(&pu.alternative_clades, ARRAY_ITEM_DELIMITER)),
+    )?;
+    self.add_entry_maybe(
+      "placementUncertainty.isAmbiguous",
+      placement_uncertainty.as_ref().map(|pu| pu.is_ambiguous.to_string()),
+    )?;
     self.add_entry_maybe(
       "qc.missingData.missingDataThreshold",
       qc.missing_data.as_ref().map(|md| md.missing_data_threshold.to_string()),
@@ -374,7 +444,54 @@ impl NextcladeResultsCsvRow {
       "qc.stopCodons.status",
       qc.stop_codons.as_ref().map(|sc| sc.status.to_string()),
     )?;
+    self.add_entry_maybe(
+      "qc.recombinants.parents",
+      qc.recombinants.as_ref().map(|rc| rc.parents.join(ARRAY_ITEM_DELIMITER)),
+    )?;
+    self.add_entry_maybe(
+      "qc.recombinants.breakpoints",
+      qc.recombinants.as_ref().map(|rc| {
+        rc.breakpoints
+          .iter()
+          .map(ToString::to_string)
+          .join(ARRAY_ITEM_DELIMITER)
+      }),
+    )?;
+    self.add_entry_maybe(
+      "qc.recombinants.support",
+      qc.recombinants.as_ref().map(|rc| rc.support.to_string()),
+    )?;
+    self.add_entry_maybe(
+      "qc.recombinants.score",
+      qc.recombinants.as_ref().map(|rc| format_qc_score(rc.score)),
+    )?;
+    self.add_entry_maybe(
+      "qc.recombinants.status",
+      qc.recombinants.as_ref().map(|rc| rc.status.to_string()),
+    )?;
+    self.add_entry_maybe(
+      "qc.bandBoundary.hitBoundary",
+      qc.band_boundary.as_ref().map(|bb| bb.hit_boundary.to_string()),
+    )?;
+    self.add_entry_maybe(
+      "qc.bandBoundary.totalAttempts",
+      qc.band_boundary.as_ref().map(|bb| bb.total_attempts.to_string()),
+    )?;
+    self.add_entry_maybe(
+      "qc.bandBoundary.score",
+      qc.band_boundary.as_ref().map(|bb| format_qc_score(bb.score)),
+    )?;
+    self.add_entry_maybe(
+      "qc.bandBoundary.status",
+      qc.band_boundary.as_ref().map(|bb| bb.status.to_string()),
+    )?;
+    qc.custom.iter().try_for_each(|(name, rule)| {
+      self.add_entry(format!("qc.custom['{name}'].value"), &format_qc_score(rule.value))?;
+      self.add_entry(format!("qc.custom['{name}'].score"), &format_qc_score(rule.score))?;
+      self.add_entry(format!("qc.custom['{name}'].status"), &rule.status.to_string())
+    })?;
     self.add_entry("isReverseComplement", &is_reverse_complement.to_string())?;
+    self.add_entry("circularRotation", circular_rotation)?;
     self.add_entry("failedCdses", &format_failed_cdses(missing_cdses, ARRAY_ITEM_DELIMITER))?;
     self.add_entry(
       "warnings",
@@ -622,6 +739,14 @@ pub fn format_cds_coverage(cds_coverage: &BTreeMap<String, f64>, delimiter: &str
     .join(delimiter)
 }
 
+#[inline]
+pub fn format_clade_supports(clade_supports: &[CladeSupport], delimiter: &str) -> String {
+  clade_supports
+    .iter()
+    .map(|CladeSupport { clade, support }| format!("{clade}:{support}"))
+    .join(delimiter)
+}
+
 #[inline]
 pub fn format_failed_cdses(failed_cdses: &[String], delimiter: &str) -> String {
   failed_cdses.join(delimiter)
@@ -654,3 +779,11 @@ fn format_aa_motifs(motifs: &[AaMotif]) -> String {
     .map(|AaMotif { cds, position, seq, .. }| format!("{}:{}:{seq}", cds, position + 1))
     .join(";")
 }
+
+#[inline]
+fn format_nuc_motifs(motifs: &[NucMotif]) -> String {
+  motifs
+    .iter()
+    .map(|NucMotif { position, seq, .. }| format!("{}:{seq}", position + 1))
+    .join(";")
+}
@@ -0,0 +1,1055 @@
+use crate::align::insertions_strip::{AaIns, Insertion};
+use crate::alphabet::aa::{from_aa, from_aa_seq};
+use crate::alphabet::nuc::{from_nuc, from_nuc_seq, Nuc};
+use crate::analyze::aa_del::AaDel;
+use crate::analyze::aa_sub::AaSub;
+use crate::analyze::find_aa_motifs::AaMotif;
+use crate::analyze::find_clade_founder::CladeNodeAttrFounderInfo;
+use crate::analyze::find_nuc_motifs::NucMotif;
+use crate::analyze::letter_ranges::{CdsAaRange, NucRange};
+use crate::analyze::nuc_del::NucDelRange;
+use crate::analyze::nuc_sub::{NucSub, NucSubLabeled};
+use crate::coord::position::PositionLike;
+use crate::coord::range::Range;
+use crate::io::file::create_file_or_stdout;
+use crate::o;
+use crate::qc::qc_config::StopCodonLocation;
+use crate::qc::qc_rule_snp_clusters::ClusteredSnp;
+use crate::translate::frame_shifts_translate::FrameShift;
+use crate::types::outputs::{NextcladeOutputs, PeptideWarning, PhenotypeValue};
+use crate::utils::error::to_eyre_error;
+use arrow_array::{
+  ArrayRef, BooleanArray, Float64Array, Int64Array, ListArray, RecordBatch, StringArray, StructArray, UInt64Array,
+};
+use arrow_buffer::{NullBuffer, OffsetBuffer};
+use arrow_schema::{DataType, Field, Fields, Schema, SchemaRef};
+use eyre::{Report, WrapErr};
+use itertools::Itertools;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Number of rows accumulated in memory before they are converted to columnar form and passed to the Parquet encoder
+const PARQUET_BATCH_SIZE: usize = 1000;
+
+/// Maximum number of rows in a Parquet row group. Row group is written to the output as soon as it is full, so that
+/// memory consumption stays bounded regardless of the number of sequences.
+const PARQUET_ROW_GROUP_SIZE: usize = 10_000;
+
+/// Writes nextclade.parquet file.
+///
+/// Columns are the same as in nextclade.csv and nextclade.tsv, and are selected using the same configuration. But
+/// unlike in CSV, the values are typed: numbers are stored as numbers and lists of mutations are stored as lists of
+/// structs, rather than as comma-separated strings. Positions and ranges are 1-based, as in CSV.
+pub struct NextcladeResultsParquetWriter<W: Write + Send> {
+  writer: ArrowWriter<W>,
+  schema: SchemaRef,
+  columns: Vec<Vec<Value>>,
+  n_rows: usize,
+}
+
+impl<W: Write + Send> NextcladeResultsParquetWriter<W> {
+  pub fn new(
+    writer: W,
+    headers: &[String],
+    phenotype_attr_keys: &[String],
+    aa_motifs_keys: &[String],
+    nuc_motifs_keys: &[String],
+  ) -> Result<Self, Report> {
+    let fields = headers
+      .iter()
+      .map(|header| {
+        let data_type = column_data_type(header, phenotype_attr_keys, aa_motifs_keys, nuc_motifs_keys);
+        Field::new(header, data_type, true)
+      })
+      .collect_vec();
+    let schema = Arc::new(Schema::new(fields));
+
+    let props = WriterProperties::builder()
+      .set_compression(Compression::SNAPPY)
+      .set_max_row_group_size(PARQUET_ROW_GROUP_SIZE)
+      .build();
+
+    let writer =
+      ArrowWriter::try_new(writer, Arc::clone(&schema), Some(props)).wrap_err("When creating Parquet writer")?;
+
+    Ok(Self {
+      writer,
+      schema,
+      columns: vec![vec![]; headers.len()],
+      n_rows: 0,
+    })
+  }
+
+  /// Writes one row
+  pub fn write(&mut self, nextclade_outputs: &NextcladeOutputs) -> Result<(), Report> {
+    let row = format_row(nextclade_outputs);
+    self.push_row(row)
+  }
+
+  /// Writes one row for the case of error
+  pub fn write_nuc_error(&mut self, index: usize, seq_name: &str, errors: &[String]) -> Result<(), Report> {
+    let row = HashMap::from([
+      ("index".to_owned(), json!(index)),
+      ("seqName".to_owned(), json!(seq_name)),
+      ("errors".to_owned(), json!(errors)),
+    ]);
+    self.push_row(row)
+  }
+
+  /// Writes remaining rows and the file footer. The file is not valid until this is called.
+  pub fn finish(mut self) -> Result<(), Report> {
+    self.write_batch()?;
+    self.writer.close().wrap_err("When finalizing Parquet file")?;
+    Ok(())
+  }
+
+  fn push_row(&mut self, mut row: HashMap<String, Value>) -> Result<(), Report> {
+    for (field, column) in self.schema.fields().iter().zip(self.columns.iter_mut()) {
+      column.push(row.remove(field.name()).unwrap_or(Value::Null));
+    }
+    self.n_rows += 1;
+    if self.n_rows >= PARQUET_BATCH_SIZE {
+      self.write_batch()?;
+    }
+    Ok(())
+  }
+
+  fn write_batch(&mut self) -> Result<(), Report> {
+    if self.n_rows == 0 {
+      return Ok(());
+    }
+
+    let arrays = self
+      .schema
+      .fields()
+      .iter()
+      .zip(self.columns.iter_mut())
+      .map(|(field, column)| {
+        let values = column.drain(..).collect_vec();
+        json_to_array(&values.iter().collect_vec(), field.data_type())
+          .wrap_err_with(|| format!("When converting column '{}'", field.name()))
+      })
+      .collect::<Result<Vec<_>, Report>>()?;
+    self.n_rows = 0;
+
+    let batch =
+      RecordBatch::try_new(Arc::clone(&self.schema), arrays).wrap_err("When creating Parquet record batch")?;
+    self.writer.write(&batch).wrap_err("When writing Parquet record batch")
+  }
+}
+
+/// Writes nextclade.parquet file
+pub struct NextcladeResultsParquetFileWriter {
+  writer: NextcladeResultsParquetWriter<Box<dyn Write + Send>>,
+}
+
+impl NextcladeResultsParquetFileWriter {
+  pub fn new(
+    filepath: impl AsRef<Path>,
+    headers: &[String],
+    phenotype_attr_keys: &[String],
+    aa_motifs_keys: &[String],
+    nuc_motifs_keys: &[String],
+  ) -> Result<Self, Report> {
+    let file = create_file_or_stdout(filepath)?;
+    let writer =
+      NextcladeResultsParquetWriter::new(file, headers, phenotype_attr_keys, aa_motifs_keys, nuc_motifs_keys)?;
+    Ok(Self { writer })
+  }
+
+  pub fn write(&mut self, nextclade_outputs: &NextcladeOutputs) -> Result<(), Report> {
+    self.writer.write(nextclade_outputs)
+  }
+
+  pub fn write_nuc_error(&mut self, index: usize, seq_name: &str, errors: &[String]) -> Result<(), Report> {
+    self.writer.write_nuc_error(index, seq_name, errors)
+  }
+
+  pub fn finish(self) -> Result<(), Report> {
+    self.writer.finish()
+  }
+}
+
+fn struct_type(fields: &[(&str, DataType)]) -> DataType {
+  DataType::Struct(
+    fields
+      .iter()
+      .map(|(name, data_type)| Field::new(*name, data_type.clone(), true))
+      .collect::<Fields>(),
+  )
+}
+
+fn list_type(item: DataType) -> DataType {
+  DataType::List(Arc::new(Field::new("item", item, true)))
+}
+
+fn nuc_sub_type() -> DataType {
+  struct_type(&[
+    ("pos", DataType::UInt64),
+    ("refNuc", DataType::Utf8),
+    ("qryNuc", DataType::Utf8),
+  ])
+}
+
+fn nuc_range_type() -> DataType {
+  struct_type(&[("begin", DataType::UInt64), ("end", DataType::UInt64)])
+}
+
+fn aa_sub_type() -> DataType {
+  struct_type(&[
+    ("cdsName", DataType::Utf8),
+    ("pos", DataType::UInt64),
+    ("refAa", DataType::Utf8),
+    ("qryAa", DataType::Utf8),
+  ])
+}
+
+fn aa_del_type() -> DataType {
+  struct_type(&[
+    ("cdsName", DataType::Utf8),
+    ("pos", DataType::UInt64),
+    ("refAa", DataType::Utf8),
+  ])
+}
+
+fn aa_range_type() -> DataType {
+  struct_type(&[
+    ("cdsName", DataType::Utf8),
+    ("begin", DataType::UInt64),
+    ("end", DataType::UInt64),
+  ])
+}
+
+/// Type of the column, given its name. Names are the same as in CSV output.
+fn column_data_type(
+  header: &str,
+  phenotype_attr_keys: &[String],
+  aa_motifs_keys: &[String],
+  nuc_motifs_keys: &[String],
+) -> DataType {
+  match header {
+    "index"
+    | "totalSubstitutions"
+    | "totalDeletions"
+    | "totalInsertions"
+    | "totalFrameShifts"
+    | "totalMissing"
+    | "totalNonACGTNs"
+    | "totalAmbiguousSites"
+    | "totalAminoacidSubstitutions"
+    | "totalAminoacidDeletions"
+    | "totalAminoacidInsertions"
+    | "totalUnknownAa"
+    | "totalPcrPrimerChanges"
+    | "alignmentStart"
+    | "alignmentEnd"
+    | "circularRotation"
+    | "privateNucMutations.totalReversionSubstitutions"
+    | "privateNucMutations.totalLabeledSubstitutions"
+    | "privateNucMutations.totalUnlabeledSubstitutions"
+    | "privateNucMutations.totalPrivateSubstitutions"
+    | "qc.missingData.totalMissing"
+    | "qc.mixedSites.mixedSitesThreshold"
+    | "qc.mixedSites.totalMixedSites"
+    | "qc.snpClusters.totalSNPs"
+    | "qc.frameShifts.totalFrameShifts"
+    | "qc.frameShifts.totalFrameShiftsIgnored"
+    | "qc.stopCodons.totalStopCodons"
+    | "qc.recombinants.support"
+    | "qc.bandBoundary.totalAttempts"
+    | "alignmentDiagnostics.seedCount"
+    | "alignmentDiagnostics.terminalBandwidth"
+    | "alignmentDiagnostics.excessBandwidth"
+    | "alignmentDiagnostics.minimalBandwidth" => DataType::UInt64,
+    "alignmentScore" => DataType::Int64,
+    "coverage"
+    | "qc.overallScore"
+    | "qc.missingData.missingDataThreshold"
+    | "qc.missingData.score"
+    | "qc.mixedSites.score"
+    | "qc.privateMutations.cutoff"
+    | "qc.privateMutations.excess"
+    | "qc.privateMutations.score"
+    | "qc.privateMutations.total"
+    | "qc.snpClusters.score"
+    | "qc.frameShifts.score"
+    | "qc.stopCodons.score"
+    | "qc.recombinants.score"
+    | "qc.bandBoundary.score"
+    | "alignmentDiagnostics.seedCoverage"
+    | "placementUncertainty.cladeConfidence" => DataType::Float64,
+    "isReverseComplement"
+    | "placementUncertainty.isAmbiguous"
+    | "qc.bandBoundary.hitBoundary"
+    | "alignmentDiagnostics.hitBoundary" => DataType::Boolean,
+    "alignmentDiagnostics.bandAreas" => list_type(DataType::UInt64),
+    "substitutions"
+    | "ambiguousSites"
+    | "privateNucMutations.reversionSubstitutions"
+    | "privateNucMutations.unlabeledSubstitutions" => list_type(nuc_sub_type()),
+    "privateNucMutations.labeledSubstitutions" => list_type(struct_type(&[
+      ("pos", DataType::UInt64),
+      ("refNuc", DataType::Utf8),
+      ("qryNuc", DataType::Utf8),
+      ("labels", list_type(DataType::Utf8)),
+    ])),
+    "deletions" | "missing" => list_type(nuc_range_type()),
+    "nonACGTNs" => list_type(struct_type(&[
+      ("nuc", DataType::Utf8),
+      ("begin", DataType::UInt64),
+      ("end", DataType::UInt64),
+    ])),
+    "insertions" => list_type(struct_type(&[("pos", DataType::UInt64), ("ins", DataType::Utf8)])),
+    "aaSubstitutions" | "proteinAaSubstitutions" => list_type(aa_sub_type()),
+    "aaDeletions" | "proteinAaDeletions" => list_type(aa_del_type()),
+    "aaInsertions" | "proteinAaInsertions" => list_type(struct_type(&[
+      ("cdsName", DataType::Utf8),
+      ("pos", DataType::UInt64),
+      ("ins", DataType::Utf8),
+    ])),
+    "frameShifts" | "qc.frameShifts.frameShifts" | "qc.frameShifts.frameShiftsIgnored" | "unknownAaRanges" => {
+      list_type(aa_range_type())
+    }
+    "pcrPrimerChanges" => list_type(struct_type(&[
+      ("primerName", DataType::Utf8),
+      ("substitutions", list_type(nuc_sub_type())),
+    ])),
+    "cdsCoverage" => list_type(struct_type(&[
+      ("cdsName", DataType::Utf8),
+      ("coverage", DataType::Float64),
+    ])),
+    "qc.snpClusters.clusteredSNPs" => list_type(struct_type(&[
+      ("begin", DataType::UInt64),
+      ("end", DataType::UInt64),
+      ("numberOfSNPs", DataType::UInt64),
+    ])),
+    "qc.stopCodons.stopCodons" => list_type(struct_type(&[("cdsName", DataType::Utf8), ("codon", DataType::UInt64)])),
+    "qc.recombinants.parents" | "failedCdses" | "warnings" | "errors" => list_type(DataType::Utf8),
+    "qc.recombinants.breakpoints" => list_type(nuc_range_type()),
+    "placementUncertainty.alternativeClades" => list_type(struct_type(&[
+      ("clade", DataType::Utf8),
+      ("support", DataType::Float64),
+    ])),
+    _ if header.starts_with("qc.custom[") && !header.ends_with(".status") => DataType::Float64,
+    _ if header.starts_with("relativeMutations[") || header.starts_with("founderMuts[") => {
+      if header.ends_with("].substitutions") {
+        list_type(nuc_sub_type())
+      } else if header.ends_with("].deletions") {
+        list_type(nuc_range_type())
+      } else if header.ends_with("].aaSubstitutions") {
+        list_type(aa_sub_type())
+      } else if header.ends_with("].aaDeletions") {
+        list_type(aa_del_type())
+      } else {
+        DataType::Utf8
+      }
+    }
+    _ if phenotype_attr_keys.iter().any(|key| key == header) => DataType::Float64,
+    _ if aa_motifs_keys.iter().any(|key| key == header) => list_type(struct_type(&[
+      ("cdsName", DataType::Utf8),
+      ("pos", DataType::UInt64),
+      ("seq", DataType::Utf8),
+    ])),
+    _ if nuc_motifs_keys.iter().any(|key| key == header) => {
+      list_type(struct_type(&[("pos", DataType::UInt64), ("seq", DataType::Utf8)]))
+    }
+    _ => DataType::Utf8,
+  }
+}
+
+/// Converts a list of JSON values into an Arrow array of a given type. Missing and null values become nulls.
+fn json_to_array(values: &[&Value], data_type: &DataType) -> Result<ArrayRef, Report> {
+  let array: ArrayRef = match data_type {
+    DataType::Boolean => Arc::new(values.iter().map(|v| v.as_bool()).collect::<BooleanArray>()),
+    DataType::UInt64 => Arc::new(values.iter().map(|v| v.as_u64()).collect::<UInt64Array>()),
+    DataType::Int64 => Arc::new(values.iter().map(|v| v.as_i64()).collect::<Int64Array>()),
+    DataType::Float64 => Arc::new(values.iter().map(|v| v.as_f64()).collect::<Float64Array>()),
+    DataType::Utf8 => Arc::new(
+      values
+        .iter()
+        .map(|v| match v {
+          Value::Null => None,
+          Value::String(s) => Some(s.clone()),
+          v => Some(v.to_string()),
+        })
+        .collect::<StringArray>(),
+    ),
+    DataType::List(item_field) => {
+      let lengths = values.iter().map(|v| v.as_array().map_or(0, Vec::len)).collect_vec();
+      let items = values.iter().filter_map(|v| v.as_array()).flatten().collect_vec();
+      let items = json_to_array(&items, item_field.data_type())?;
+      let nulls = NullBuffer::from(values.iter().map(|v| v.is_array()).collect_vec());
+      Arc::new(to_eyre_error(ListArray::try_new(
+        Arc::clone(item_field),
+        OffsetBuffer::from_lengths(lengths),
+        items,
+        Some(nulls),
+      ))?)
+    }
+    DataType::Struct(fields) => {
+      let children = fields
+        .iter()
+        .map(|field| {
+          let field_values = values
+            .iter()
+            .map(|v| v.get(field.name()).unwrap_or(&Value::Null))
+            .collect_vec();
+          json_to_array(&field_values, field.data_type())
+        })
+        .collect::<Result<Vec<_>, Report>>()?;
+      let nulls = NullBuffer::from(values.iter().map(|v| v.is_object()).collect_vec());
+      Arc::new(to_eyre_error(StructArray::try_new(
+        fields.clone(),
+        children,
+        Some(nulls),
+      ))?)
+    }
+    _ => unreachable!("Unexpected data type in Parquet schema: {data_type}"),
+  };
+  Ok(array)
+}
+
+fn range_to_json<P: PositionLike>(range: &Range<P>) -> Map<String, Value> {
+  let mut map = Map::new();
+  map.insert("begin".to_owned(), json!(range.begin.as_usize() + 1));
+  map.insert("end".to_owned(), json!(range.end.as_usize()));
+  map
+}
+
+fn nuc_subs_to_json(subs: &[NucSub]) -> Value {
+  subs
+    .iter()
+    .map(|sub| {
+      json!({
+        "pos": sub.pos.as_usize() + 1,
+        "refNuc": from_nuc(sub.ref_nuc).to_string(),
+        "qryNuc": from_nuc(sub.qry_nuc).to_string(),
+      })
+    })
+    .collect()
+}
+
+fn nuc_subs_labeled_to_json(subs: &[NucSubLabeled]) -> Value {
+  subs
+    .iter()
+    .map(|NucSubLabeled { substitution, labels }| {
+      json!({
+        "pos": substitution.pos.as_usize() + 1,
+        "refNuc": from_nuc(substitution.ref_nuc).to_string(),
+        "qryNuc": from_nuc(substitution.qry_nuc).to_string(),
+        "labels": labels,
+      })
+    })
+    .collect()
+}
+
+fn nuc_dels_to_json(dels: &[NucDelRange]) -> Value {
+  dels
+    .iter()
+    .map(|del| Value::Object(range_to_json(del.range())))
+    .collect()
+}
+
+fn nuc_ranges_to_json(ranges: &[NucRange]) -> Value {
+  ranges
+    .iter()
+    .map(|range| Value::Object(range_to_json(range.range())))
+    .collect()
+}
+
+fn non_acgtns_to_json(ranges: &[NucRange]) -> Value {
+  ranges
+    .iter()
+    .map(|range| {
+      let mut map = range_to_json(range.range());
+      map.insert("nuc".to_owned(), json!(from_nuc(range.letter).to_string()));
+      Value::Object(map)
+    })
+    .collect()
+}
+
+fn nuc_insertions_to_json(insertions: &[Insertion<Nuc>]) -> Value {
+  insertions
+    .iter()
+    .map(|Insertion { pos, ins }| json!({ "pos": pos + 1, "ins": from_nuc_seq(ins) }))
+    .collect()
+}
+
+fn aa_subs_to_json<'a, I: IntoIterator<Item = &'a AaSub>>(subs: I) -> Value {
+  subs
+    .into_iter()
+    .map(|sub| {
+      json!({
+        "cdsName": sub.cds_name,
+        "pos": sub.pos.as_usize() + 1,
+        "refAa": from_aa(sub.ref_aa).to_string(),
+        "qryAa": from_aa(sub.qry_aa).to_string(),
+      })
+    })
+    .collect()
+}
+
+fn aa_dels_to_json<'a, I: IntoIterator<Item = &'a AaDel>>(dels: I) -> Value {
+  dels
+    .into_iter()
+    .map(|del| {
+      json!({
+        "cdsName": del.cds_name,
+        "pos": del.pos.as_usize() + 1,
+        "refAa": from_aa(del.ref_aa).to_string(),
+      })
+    })
+    .collect()
+}
+
+fn aa_insertions_to_json(insertions: &[AaIns]) -> Value {
+  insertions
+    .iter()
+    .map(|AaIns { cds, pos, ins }| json!({ "cdsName": cds, "pos": pos + 1, "ins": from_aa_seq(ins) }))
+    .collect()
+}
+
+fn unknown_aa_ranges_to_json(ranges: &[CdsAaRange]) -> Value {
+  ranges
+    .iter()
+    .flat_map(|CdsAaRange { cds_name, ranges, .. }| {
+      ranges.iter().map(move |range| {
+        let mut map = range_to_json(range.range());
+        map.insert("cdsName".to_owned(), json!(cds_name));
+        Value::Object(map)
+      })
+    })
+    .collect()
+}
+
+fn frame_shifts_to_json(frame_shifts: &[FrameShift]) -> Value {
+  frame_shifts
+    .iter()
+    .map(|frame_shift| {
+      let mut map = range_to_json(&frame_shift.codon);
+      map.insert("cdsName".to_owned(), json!(frame_shift.cds_name));
+      Value::Object(map)
+    })
+    .collect()
+}
+
+fn clustered_snps_to_json(snps: &[ClusteredSnp]) -> Value {
+  snps
+    .iter()
+    .map(|snp| json!({ "begin": snp.start + 1, "end": snp.end, "numberOfSNPs": snp.number_of_snps }))
+    .collect()
+}
+
+fn stop_codons_to_json(stop_codons: &[StopCodonLocation]) -> Value {
+  stop_codons
+    .iter()
+    .map(|StopCodonLocation { cds_name, codon }| json!({ "cdsName": cds_name, "codon": codon + 1 }))
+    .collect()
+}
+
+fn cds_coverage_to_json(cds_coverage: &BTreeMap<String, f64>) -> Value {
+  cds_coverage
+    .iter()
+    .map(|(cds, coverage)| json!({ "cdsName": cds, "coverage": coverage }))
+    .collect()
+}
+
+fn aa_motifs_to_json(motifs: &[AaMotif]) -> Value {
+  motifs
+    .iter()
+    .map(|AaMotif { cds, position, seq, .. }| json!({ "cdsName": cds, "pos": position.as_usize() + 1, "seq": seq }))
+    .collect()
+}
+
+fn nuc_motifs_to_json(motifs: &[NucMotif]) -> Value {
+  motifs
+    .iter()
+    .map(|NucMotif { position, seq, .. }| json!({ "pos": position.as_usize() + 1, "seq": seq }))
+    .collect()
+}
+
+fn add_mutations_cols(
+  row: &mut HashMap<String, Value>,
+  prefix: &str,
+  node_name: Option<&str>,
+  info: Option<&CladeNodeAttrFounderInfo>,
+) {
+  row.insert(format!("{prefix}.nodeName"), json!(node_name));
+  if let Some(info) = info {
+    let aa_muts = info.aa_mutations.values();
+    row.insert(
+      format!("{prefix}.substitutions"),
+      nuc_subs_to_json(&info.nuc_mutations.private_substitutions),
+    );
+    row.insert(
+      format!("{prefix}.deletions"),
+      nuc_dels_to_json(&info.nuc_mutations.private_deletion_ranges),
+    );
+    row.insert(
+      format!("{prefix}.aaSubstitutions"),
+      aa_subs_to_json(aa_muts.clone().flat_map(|m| &m.private_substitutions)),
+    );
+    row.insert(
+      format!("{prefix}.aaDeletions"),
+      aa_dels_to_json(aa_muts.flat_map(|m| &m.private_deletions)),
+    );
+  }
+}
+
+/// Collects values of all possible columns for one sequence. Only the columns present in the schema are written.
+fn format_row(nextclade_outputs: &NextcladeOutputs) -> HashMap<String, Value> {
+  let NextcladeOutputs {
+    index,
+    seq_name,
+    substitutions,
+    total_substitutions,
+    deletions,
+    total_deletions,
+    insertions,
+    total_insertions,
+    missing,
+    total_missing,
+    non_acgtns,
+    total_non_acgtns,
+    ambiguous_sites,
+    total_ambiguous_sites,
+    frame_shifts,
+    total_frame_shifts,
+    aa_substitutions,
+    total_aminoacid_substitutions,
+    aa_deletions,
+    total_aminoacid_deletions,
+    aa_insertions,
+    total_aminoacid_insertions,
+    protein_aa_substitutions,
+    protein_aa_deletions,
+    protein_aa_insertions,
+    unknown_aa_ranges,
+    total_unknown_aa,
+    alignment_range,
+    alignment_score,
+    alignment_mode,
+    alignment_diagnostics,
+    pcr_primer_changes,
+    total_pcr_primer_changes,
+    clade,
+    private_nuc_mutations,
+    missing_cdses,
+    coverage,
+    cds_coverage,
+    phenotype_values,
+    qc,
+    custom_node_attributes,
+    placement_uncertainty,
+    is_reverse_complement,
+    circular_rotation,
+    warnings,
+    aa_motifs,
+    nuc_motifs,
+    ref_nodes,
+    ref_node_search_results,
+    relative_nuc_mutations,
+    relative_aa_mutations,
+    clade_founder_info,
+    clade_node_attr_founder_info,
+    ..
+  } = nextclade_outputs;
+
+  let mut row = HashMap::<String, Value>::new();
+
+  for (key, val) in custom_node_attributes {
+    row.insert(key.clone(), json!(val));
+  }
+
+  for PhenotypeValue { name, value, .. } in phenotype_values.iter().flatten() {
+    row.insert(name.clone(), json!(value));
+  }
+
+  for (name, motifs) in aa_motifs {
+    row.insert(name.clone(), aa_motifs_to_json(motifs));
+  }
+
+  for (name, motifs) in nuc_motifs {
+    row.insert(name.clone(), nuc_motifs_to_json(motifs));
+  }
+
+  if let Some(info) = clade_founder_info {
+    add_mutations_cols(&mut row, "founderMuts['clade']", Some(&info.node_name), Some(info));
+  }
+  for (name, info) in clade_node_attr_founder_info {
+    add_mutations_cols(
+      &mut row,
+      &format!("founderMuts['{name}']"),
+      Some(&info.node_name),
+      Some(info),
+    );
+  }
+
+  for desc in &ref_nodes.search {
+    let prefix = format!("relativeMutations['{}']", desc.display_name_or_name());
+
+    let node_name = ref_node_search_results
+      .iter()
+      .find(|d| d.search.name == desc.name)
+      .and_then(|r| r.result.as_ref())
+      .and_then(|r| r.r#match.as_ref())
+      .map(|r| r.node_name.as_str());
+    row.insert(format!("{prefix}.nodeName"), json!(node_name));
+
+    if let Some(muts) = relative_nuc_mutations
+      .iter()
+      .find(|rel_nuc_mut| rel_nuc_mut.search.search.name == desc.name)
+      .and_then(|rel_nuc_mut| rel_nuc_mut.result.as_ref())
+      .map(|res| &res.muts)
+    {
+      row.insert(
+        format!("{prefix}.substitutions"),
+        nuc_subs_to_json(&muts.private_substitutions),
+      );
+      row.insert(
+        format!("{prefix}.deletions"),
+        nuc_dels_to_json(&muts.private_deletion_ranges),
+      );
+    }
+
+    if let Some(muts) = relative_aa_mutations
+      .iter()
+      .find(|rel_aa_mut| rel_aa_mut.search.search.name == desc.name)
+      .and_then(|rel_aa_mut| rel_aa_mut.result.as_ref())
+      .map(|res| &res.muts)
+    {
+      row.insert(
+        format!("{prefix}.aaSubstitutions"),
+        aa_subs_to_json(muts.values().flat_map(|m| &m.private_substitutions)),
+      );
+      row.insert(
+        format!("{prefix}.aaDeletions"),
+        aa_dels_to_json(muts.values().flat_map(|m| &m.private_deletions)),
+      );
+    }
+  }
+
+  let entries = [
+    ("index", json!(index)),
+    ("seqName", json!(seq_name)),
+    ("clade", json!(clade)),
+    ("qc.overallScore", json!(qc.overall_score)),
+    ("qc.overallStatus", json!(qc.overall_status.to_string())),
+    ("totalSubstitutions", json!(total_substitutions)),
+    ("totalDeletions", json!(total_deletions)),
+    ("totalInsertions", json!(total_insertions)),
+    ("totalFrameShifts", json!(total_frame_shifts)),
+    ("totalAminoacidSubstitutions", json!(total_aminoacid_substitutions)),
+    ("totalAminoacidDeletions", json!(total_aminoacid_deletions)),
+    ("totalAminoacidInsertions", json!(total_aminoacid_insertions)),
+    ("totalUnknownAa", json!(total_unknown_aa)),
+    ("totalMissing", json!(total_missing)),
+    ("totalNonACGTNs", json!(total_non_acgtns)),
+    ("totalAmbiguousSites", json!(total_ambiguous_sites)),
+    ("totalPcrPrimerChanges", json!(total_pcr_primer_changes)),
+    ("substitutions", nuc_subs_to_json(substitutions)),
+    ("deletions", nuc_dels_to_json(deletions)),
+    ("insertions", nuc_insertions_to_json(insertions)),
+    (
+      "privateNucMutations.reversionSubstitutions",
+      nuc_subs_to_json(&private_nuc_mutations.reversion_substitutions),
+    ),
+    (
+      "privateNucMutations.labeledSubstitutions",
+      nuc_subs_labeled_to_json(&private_nuc_mutations.labeled_substitutions),
+    ),
+    (
+      "privateNucMutations.unlabeledSubstitutions",
+      nuc_subs_to_json(&private_nuc_mutations.unlabeled_substitutions),
+    ),
+    (
+      "privateNucMutations.totalReversionSubstitutions",
+      json!(private_nuc_mutations.total_reversion_substitutions),
+    ),
+    (
+      "privateNucMutations.totalLabeledSubstitutions",
+      json!(private_nuc_mutations.total_labeled_substitutions),
+    ),
+    (
+      "privateNucMutations.totalUnlabeledSubstitutions",
+      json!(private_nuc_mutations.total_unlabeled_substitutions),
+    ),
+    (
+      "privateNucMutations.totalPrivateSubstitutions",
+      json!(private_nuc_mutations.total_private_substitutions),
+    ),
+    ("frameShifts", frame_shifts_to_json(frame_shifts)),
+    ("aaSubstitutions", aa_subs_to_json(aa_substitutions)),
+    ("aaDeletions", aa_dels_to_json(aa_deletions)),
+    ("aaInsertions", aa_insertions_to_json(aa_insertions)),
+    ("proteinAaSubstitutions", aa_subs_to_json(protein_aa_substitutions)),
+    ("proteinAaDeletions", aa_dels_to_json(protein_aa_deletions)),
+    ("proteinAaInsertions", aa_insertions_to_json(protein_aa_insertions)),
+    ("unknownAaRanges", unknown_aa_ranges_to_json(unknown_aa_ranges)),
+    ("missing", nuc_ranges_to_json(missing)),
+    ("nonACGTNs", non_acgtns_to_json(non_acgtns)),
+    ("ambiguousSites", nuc_subs_to_json(ambiguous_sites)),
+    (
+      "pcrPrimerChanges",
+      pcr_primer_changes
+        .iter()
+        .map(|pc| json!({ "primerName": pc.primer.name, "substitutions": nuc_subs_to_json(&pc.substitutions) }))
+        .collect(),
+    ),
+    ("alignmentScore", json!(alignment_score)),
+    ("alignmentStart", json!(alignment_range.begin.as_usize() + 1)),
+    ("alignmentEnd", json!(alignment_range.end.as_usize())),
+    ("alignmentMode", json!(alignment_mode)),
+    (
+      "alignmentDiagnostics.seedCount",
+      json!(alignment_diagnostics.seed_count),
+    ),
+    (
+      "alignmentDiagnostics.seedCoverage",
+      json!(alignment_diagnostics.seed_coverage),
+    ),
+    (
+      "alignmentDiagnostics.bandAreas",
+      json!(alignment_diagnostics.band_areas),
+    ),
+    (
+      "alignmentDiagnostics.terminalBandwidth",
+      json!(alignment_diagnostics.terminal_bandwidth),
+    ),
+    (
+      "alignmentDiagnostics.excessBandwidth",
+      json!(alignment_diagnostics.excess_bandwidth),
+    ),
+    (
+      "alignmentDiagnostics.minimalBandwidth",
+      json!(alignment_diagnostics.minimal_bandwidth),
+    ),
+    (
+      "alignmentDiagnostics.hitBoundary",
+      json!(alignment_diagnostics.hit_boundary),
+    ),
+    ("coverage", json!(coverage)),
+    ("cdsCoverage", cds_coverage_to_json(cds_coverage)),
+    ("isReverseComplement", json!(is_reverse_complement)),
+    ("circularRotation", json!(circular_rotation)),
+    ("failedCdses", json!(missing_cdses)),
+    (
+      "warnings",
+      json!(warnings
+        .iter()
+        .map(|PeptideWarning { warning, .. }| warning)
+        .collect_vec()),
+    ),
+    ("errors", json!([])),
+  ];
+  row.extend(entries.into_iter().map(|(key, val)| (key.to_owned(), val)));
+
+  if let Some(pu) = placement_uncertainty {
+    row.extend([
+      (o!("placementUncertainty.cladeConfidence"), json!(pu.clade_confidence)),
+      (
+        o!("placementUncertainty.alternativeClades"),
+        json!(pu.alternative_clades),
+      ),
+      (o!("placementUncertainty.isAmbiguous"), json!(pu.is_ambiguous)),
+    ]);
+  }
+
+  if let Some(md) = &qc.missing_data {
+    row.extend([
+      (
+        o!("qc.missingData.missingDataThreshold"),
+        json!(md.missing_data_threshold),
+      ),
+      (o!("qc.missingData.score"), json!(md.score)),
+      (o!("qc.missingData.status"), json!(md.status.to_string())),
+      (o!("qc.missingData.totalMissing"), json!(md.total_missing)),
+    ]);
+  }
+  if let Some(ms) = &qc.mixed_sites {
+    row.extend([
+      (o!("qc.mixedSites.mixedSitesThreshold"), json!(ms.mixed_sites_threshold)),
+      (o!("qc.mixedSites.score"), json!(ms.score)),
+      (o!("qc.mixedSites.status"), json!(ms.status.to_string())),
+      (o!("qc.mixedSites.totalMixedSites"), json!(ms.total_mixed_sites)),
+    ]);
+  }
+  if let Some(pm) = &qc.private_mutations {
+    row.extend([
+      (o!("qc.privateMutations.cutoff"), json!(pm.cutoff)),
+      (o!("qc.privateMutations.excess"), json!(pm.excess)),
+      (o!("qc.privateMutations.score"), json!(pm.score)),
+      (o!("qc.privateMutations.status"), json!(pm.status.to_string())),
+      (o!("qc.privateMutations.total"), json!(pm.weighted_total)),
+    ]);
+  }
+  if let Some(sc) = &qc.snp_clusters {
+    row.extend([
+      (
+        o!("qc.snpClusters.clusteredSNPs"),
+        clustered_snps_to_json(&sc.clustered_snps),
+      ),
+      (o!("qc.snpClusters.score"), json!(sc.score)),
+      (o!("qc.snpClusters.status"), json!(sc.status.to_string())),
+      (o!("qc.snpClusters.totalSNPs"), json!(sc.total_snps)),
+    ]);
+  }
+  if let Some(fs) = &qc.frame_shifts {
+    row.extend([
+      (o!("qc.frameShifts.frameShifts"), frame_shifts_to_json(&fs.frame_shifts)),
+      (o!("qc.frameShifts.totalFrameShifts"), json!(fs.total_frame_shifts)),
+      (
+        o!("qc.frameShifts.frameShiftsIgnored"),
+        frame_shifts_to_json(&fs.frame_shifts_ignored),
+      ),
+      (
+        o!("qc.frameShifts.totalFrameShiftsIgnored"),
+        json!(fs.total_frame_shifts_ignored),
+      ),
+      (o!("qc.frameShifts.score"), json!(fs.score)),
+      (o!("qc.frameShifts.status"), json!(fs.status.to_string())),
+    ]);
+  }
+  if let Some(sc) = &qc.stop_codons {
+    row.extend([
+      (o!("qc.stopCodons.stopCodons"), stop_codons_to_json(&sc.stop_codons)),
+      (o!("qc.stopCodons.totalStopCodons"), json!(sc.total_stop_codons)),
+      (o!("qc.stopCodons.score"), json!(sc.score)),
+      (o!("qc.stopCodons.status"), json!(sc.status.to_string())),
+    ]);
+  }
+  if let Some(rc) = &qc.recombinants {
+    row.extend([
+      (o!("qc.recombinants.parents"), json!(rc.parents)),
+      (
+        o!("qc.recombinants.breakpoints"),
+        rc.breakpoints
+          .iter()
+          .map(|range| Value::Object(range_to_json(range)))
+          .collect(),
+      ),
+      (o!("qc.recombinants.support"), json!(rc.support)),
+      (o!("qc.recombinants.score"), json!(rc.score)),
+      (o!("qc.recombinants.status"), json!(rc.status.to_string())),
+    ]);
+  }
+  if let Some(bb) = &qc.band_boundary {
+    row.extend([
+      (o!("qc.bandBoundary.hitBoundary"), json!(bb.hit_boundary)),
+      (o!("qc.bandBoundary.totalAttempts"), json!(bb.total_attempts)),
+      (o!("qc.bandBoundary.score"), json!(bb.score)),
+      (o!("qc.bandBoundary.status"), json!(bb.status.to_string())),
+    ]);
+  }
+  for (name, rule) in &qc.custom {
+    row.extend([
+      (format!("qc.custom['{name}'].value"), json!(rule.value)),
+      (format!("qc.custom['{name}'].score"), json!(rule.score)),
+      (format!("qc.custom['{name}'].status"), json!(rule.status.to_string())),
+    ]);
+  }
+
+  row
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::io::fasta::FastaRecord;
+  use crate::io::nextclade_csv::prepare_headers;
+  use crate::io::nextclade_csv_column_config::CsvColumnConfig;
+  use crate::test_utils::{create_test_nextclade, random_seq};
+  use crate::tree::tree::AuspiceRefNodesDesc;
+  use arrow_array::cast::AsArray;
+  use arrow_array::types::UInt64Type;
+  use arrow_array::Array;
+  use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+  use pretty_assertions::assert_eq;
+  use std::fs::{self, File};
+
+  #[test]
+  #[allow(clippy::string_slice)]
+  fn writes_typed_columns() -> Result<(), Report> {
+    let filepath = std::env::temp_dir().join(format!("nextclade-parquet-test-{}.parquet", std::process::id()));
+
+    let ref_seq = random_seq(1, 1000);
+    let qry_nuc = if &ref_seq[10..11] == "T" { "A" } else { "T" };
+    let qry_seq = format!("{}{qry_nuc}{}{}", &ref_seq[..10], &ref_seq[11..500], &ref_seq[506..]);
+
+    let nextclade = create_test_nextclade("ref", &ref_seq)?;
+    let output = nextclade.run(&FastaRecord {
+      index: 0,
+      seq_name: o!("qry"),
+      seq: qry_seq,
+    })?;
+
+    let headers = prepare_headers(
+      &[],
+      &[],
+      &AuspiceRefNodesDesc::default(),
+      &[],
+      &[],
+      &[],
+      &CsvColumnConfig::default(),
+    );
+    let mut writer = NextcladeResultsParquetWriter::new(File::create(&filepath)?, &headers, &[], &[], &[])?;
+    writer.write(&output.analysis_result)?;
+    writer.write_nuc_error(1, "bad", &[o!("Unable to align")])?;
+    writer.finish()?;
+
+    let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&filepath)?)?.build()?;
+    let batches = reader.collect::<Result<Vec<_>, _>>()?;
+    fs::remove_file(&filepath)?;
+
+    let batch = &batches[0];
+    assert_eq!(batch.num_rows(), 2);
+    assert_eq!(batch.schema().fields().len(), headers.len());
+
+    let total_subs = batch
+      .column_by_name("totalSubstitutions")
+      .unwrap()
+      .as_primitive::<UInt64Type>();
+    assert_eq!(total_subs.value(0), 1);
+    assert!(total_subs.is_null(1));
+
+    let subs = batch.column_by_name("substitutions").unwrap().as_list::<i32>();
+    let sub = subs.value(0);
+    let sub = sub.as_struct();
+    assert_eq!(
+      sub.column_by_name("pos").unwrap().as_primitive::<UInt64Type>().value(0),
+      11
+    );
+    assert_eq!(
+      sub.column_by_name("qryNuc").unwrap().as_string::<i32>().value(0),
+      qry_nuc
+    );
+
+    let dels = batch.column_by_name("deletions").unwrap().as_list::<i32>();
+    let del = dels.value(0);
+    let del = del.as_struct();
+    assert_eq!(
+      del
+        .column_by_name("begin")
+        .unwrap()
+        .as_primitive::<UInt64Type>()
+        .value(0),
+      501
+    );
+    assert_eq!(
+      del.column_by_name("end").unwrap().as_primitive::<UInt64Type>().value(0),
+      506
+    );
+
+    let errors = batch.column_by_name("errors").unwrap().as_list::<i32>();
+    assert_eq!(errors.value(0).len(), 0);
+    assert_eq!(errors.value(1).as_string::<i32>().value(0), "Unable to align");
+
+    Ok(())
+  }
+}
@@ -27,36 +27,54 @@ where
 }
 
 pub fn convert_graph_to_nwk_string<N, E, D>(graph: &Graph<N, E, D>) -> Result<String, Report>
+where
+  N: GraphNode + HasDivergence + HasName,
+  E: GraphEdge,
+{
+  convert_graph_to_nwk_string_with_edge_labels(graph, |_| String::new())
+}
+
+/// Converts graph to Newick string, appending a label to the branch length of every edge. Labels are requested in
+/// postorder (children before their parent), and are identified by the key of the node the edge leads to.
+pub fn convert_graph_to_nwk_string_with_edge_labels<N, E, D>(
+  graph: &Graph<N, E, D>,
+  mut edge_label: impl FnMut(GraphNodeKey) -> String,
+) -> Result<String, Report>
 where
   N: GraphNode + HasDivergence + HasName,
   E: GraphEdge,
 {
   let root_node_key = graph.get_exactly_one_root()?.key();
   let parent_div = 0.0;
-  convert_graph_to_nwk_recursive(graph, root_node_key, parent_div).wrap_err("When converting graph to Newick string")
+  convert_graph_to_nwk_recursive(graph, root_node_key, parent_div, &mut edge_label)
+    .wrap_err("When converting graph to Newick string")
 }
 
-fn convert_graph_to_nwk_recursive<N, E, D>(
+fn convert_graph_to_nwk_recursive<N, E, D, F>(
   graph: &Graph<N, E, D>,
   node_key: GraphNodeKey,
   parent_div: f64,
+  edge_label: &mut F,
 ) -> Result<String, Report>
 where
   N: GraphNode + HasDivergence + HasName,
   E: GraphEdge,
+  F: FnMut(GraphNodeKey) -> String,
 {
   let node = graph.get_node(node_key)?.payload();
   let branch_length = node.divergence() - parent_div;
 
   Ok(if graph.is_leaf_key(node_key) {
     let name = node.name();
-    format!("{name}:{branch_length}")
+    let label = edge_label(node_key);
+    format!("{name}:{branch_length}{label}")
   } else {
     let children = graph
       .iter_child_keys_of_by_key(node_key)
-      .map(|child_key| convert_graph_to_nwk_recursive(graph, child_key, node.divergence()))
+      .map(|child_key| convert_graph_to_nwk_recursive(graph, child_key, node.divergence(), edge_label))
       .collect::<Result<Vec<String>, Report>>()?
       .join(",");
-    format!("({children}):{branch_length}")
+    let label = edge_label(node_key);
+    format!("({children}):{branch_length}{label}")
   })
 }
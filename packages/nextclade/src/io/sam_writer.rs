@@ -0,0 +1,255 @@
+//! Writes pairwise alignments of query sequences to the reference sequence in SAM format.
+//!
+//! See: https://samtools.github.io/hts-specs/SAMv1.pdf
+use crate::align::insertions_strip::Insertion;
+use crate::alphabet::letter::Letter;
+use crate::alphabet::nuc::{from_nuc, Nuc};
+use crate::io::fasta::parse_fasta_header;
+use crate::io::file::{open_file_or_stdout, OutputFileMode};
+use crate::types::outputs::NextcladeOutputs;
+use crate::utils::info::this_package_version_str;
+use eyre::Report;
+use itertools::Itertools;
+use std::fmt::Write as _;
+use std::io::Write;
+use std::path::Path;
+
+/// SAM flag: the read is unmapped
+const SAM_FLAG_UNMAPPED: u16 = 0x4;
+
+/// SAM flag: the sequence is reverse complemented
+const SAM_FLAG_REVERSE: u16 = 0x10;
+
+/// SAM mapping quality: not available
+const SAM_MAPQ_UNAVAILABLE: u8 = 255;
+
+/// Alignment of one query sequence in SAM representation
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SamAlignment {
+  /// 0-based position of the first reference nucleotide aligned to the query
+  pub pos: usize,
+  pub cigar: String,
+  pub seq: String,
+}
+
+/// Reconstructs SAM representation of the pairwise alignment from the aligned query, in which insertions are stripped,
+/// and from the list of stripped insertions.
+///
+/// Reference positions covered by the query produce `M` operations, gaps in the query produce `D` and insertions
+/// produce `I`. Insertions before the first and after the last aligned reference position are the parts of the query
+/// which extend beyond the aligned region; they produce soft clips (`S`). SEQ contains all nucleotides of the query,
+/// in the orientation of the reference.
+///
+/// Returns `None` if the query does not contain any aligned nucleotides.
+pub fn sam_alignment(qry_seq: &[Nuc], insertions: &[Insertion<Nuc>]) -> Option<SamAlignment> {
+  let begin = qry_seq.iter().position(|nuc| !nuc.is_gap())?;
+  let end = qry_seq.iter().rposition(|nuc| !nuc.is_gap())? + 1;
+
+  let mut cigar = CigarBuilder::default();
+  let mut seq = String::with_capacity(qry_seq.len());
+
+  let (leading, rest): (Vec<_>, Vec<_>) = insertions.iter().partition(|ins| ins.pos < begin as i32);
+  let (internal, trailing): (Vec<_>, Vec<_>) = rest.into_iter().partition(|ins| ins.pos < end as i32 - 1);
+
+  for Insertion { ins, .. } in leading {
+    cigar.push('S', ins.len());
+    seq.extend(ins.iter().copied().map(from_nuc));
+  }
+
+  let mut internal = internal.into_iter().peekable();
+  for (pos, &nuc) in qry_seq.iter().enumerate().take(end).skip(begin) {
+    if nuc.is_gap() {
+      cigar.push('D', 1);
+    } else {
+      cigar.push('M', 1);
+      seq.push(from_nuc(nuc));
+    }
+
+    while let Some(Insertion { ins, .. }) = internal.next_if(|ins| ins.pos == pos as i32) {
+      cigar.push('I', ins.len());
+      seq.extend(ins.iter().copied().map(from_nuc));
+    }
+  }
+
+  for Insertion { ins, .. } in trailing {
+    cigar.push('S', ins.len());
+    seq.extend(ins.iter().copied().map(from_nuc));
+  }
+
+  Some(SamAlignment {
+    pos: begin,
+    cigar: cigar.finish(),
+    seq,
+  })
+}
+
+/// Accumulates CIGAR operations, merging consecutive operations of the same kind
+#[derive(Default)]
+struct CigarBuilder {
+  ops: Vec<(char, usize)>,
+}
+
+impl CigarBuilder {
+  fn push(&mut self, op: char, len: usize) {
+    if len == 0 {
+      return;
+    }
+    match self.ops.last_mut() {
+      Some((last_op, last_len)) if *last_op == op => *last_len += len,
+      _ => self.ops.push((op, len)),
+    }
+  }
+
+  fn finish(self) -> String {
+    self.ops.iter().fold(String::new(), |mut cigar, (op, len)| {
+      write!(cigar, "{len}{op}").ok();
+      cigar
+    })
+  }
+}
+
+/// SAM does not allow whitespace and some other characters in QNAME and RNAME, so only the sequence ID (first word of
+/// the FASTA header) is used, with the disallowed characters replaced
+fn sam_name(seq_name: &str) -> String {
+  let (seq_id, _) = parse_fasta_header(seq_name.trim());
+  let name: String = seq_id
+    .chars()
+    .map(|c| if matches!(c, '!'..='?' | 'A'..='~') { c } else { '_' })
+    .collect();
+  if name.is_empty() {
+    "*".to_owned()
+  } else {
+    name
+  }
+}
+
+/// Tab and newline characters are not allowed in values of SAM tags
+fn sam_tag_value(value: &str) -> String {
+  value.replace(['\t', '\n', '\r'], " ")
+}
+
+/// Writes SAM file. Every record is written immediately, so that the memory consumption stays bounded.
+pub struct SamWriter {
+  writer: Box<dyn Write + Send>,
+  ref_name: String,
+}
+
+impl SamWriter {
+  pub fn new(filepath: impl AsRef<Path>, ref_name: &str, ref_len: usize) -> Result<Self, Report> {
+    Self::with_mode(filepath, ref_name, ref_len, OutputFileMode::Create)
+  }
+
+  /// Opens SAM file in a given mode. Header is only written when the file is created, not when it is appended to.
+  pub fn with_mode(
+    filepath: impl AsRef<Path>,
+    ref_name: &str,
+    ref_len: usize,
+    mode: OutputFileMode,
+  ) -> Result<Self, Report> {
+    let mut writer = open_file_or_stdout(filepath, mode)?;
+    let ref_name = sam_name(ref_name);
+    if mode == OutputFileMode::Create {
+      writeln!(writer, "@HD\tVN:1.6\tSO:unsorted")?;
+      writeln!(writer, "@SQ\tSN:{ref_name}\tLN:{ref_len}")?;
+      writeln!(
+        writer,
+        "@PG\tID:nextclade\tPN:nextclade\tVN:{}",
+        this_package_version_str()
+      )?;
+    }
+    Ok(Self { writer, ref_name })
+  }
+
+  /// Writes alignment of one query sequence. The `query` is the aligned query sequence, with insertions stripped.
+  pub fn write(&mut self, query: &[Nuc], outputs: &NextcladeOutputs) -> Result<(), Report> {
+    let NextcladeOutputs {
+      seq_name,
+      insertions,
+      is_reverse_complement,
+      alignment_score,
+      clade,
+      ..
+    } = outputs;
+
+    let Some(SamAlignment { pos, cigar, seq }) = sam_alignment(query, insertions) else {
+      return self.write_nuc_error(seq_name);
+    };
+
+    let flag = if *is_reverse_complement { SAM_FLAG_REVERSE } else { 0 };
+
+    let mut tags = vec![format!("AS:i:{alignment_score}")];
+    if let Some(clade) = clade {
+      tags.push(format!("XC:Z:{}", sam_tag_value(clade)));
+    }
+
+    writeln!(
+      self.writer,
+      "{}\t{flag}\t{}\t{}\t{SAM_MAPQ_UNAVAILABLE}\t{cigar}\t*\t0\t0\t{seq}\t*\t{}",
+      sam_name(seq_name),
+      self.ref_name,
+      pos + 1,
+      tags.iter().join("\t")
+    )?;
+    Ok(())
+  }
+
+  /// Writes unmapped record for a sequence which failed to be analyzed
+  pub fn write_nuc_error(&mut self, seq_name: &str) -> Result<(), Report> {
+    writeln!(
+      self.writer,
+      "{}\t{SAM_FLAG_UNMAPPED}\t*\t0\t0\t*\t*\t0\t0\t*\t*",
+      sam_name(seq_name)
+    )?;
+    Ok(())
+  }
+
+  pub fn flush(&mut self) -> Result<(), Report> {
+    self.writer.flush()?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::align::insertions_strip::insertions_strip;
+  use crate::alphabet::nuc::to_nuc_seq;
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+
+  #[rstest]
+  #[case::identical("ACGTACGT", "ACGTACGT", 0, "8M", "ACGTACGT")]
+  #[case::deletion("ACGTACGT", "ACG--CGT", 0, "3M2D3M", "ACGCGT")]
+  #[case::insertion("ACG--TACGT", "ACGTTTACGT", 0, "3M2I5M", "ACGTTTACGT")]
+  #[case::unaligned_flanks("ACGTACGT", "--GTAC--", 2, "4M", "GTAC")]
+  #[case::terminal_insertions("--ACGTACGT---", "TTACGTACGTCCA", 0, "2S8M3S", "TTACGTACGTCCA")]
+  #[case::terminal_insertion_with_unaligned_flank("ACGTACGT--", "--GTACGTAA", 2, "6M2S", "GTACGTAA")]
+  #[case::mixed("--ACGTAC-GTACGT", "TTAC-TACCGTA---", 0, "2S2M1D3M1I3M", "TTACTACCGTA")]
+  fn converts_alignment_to_cigar(
+    #[case] ref_aln: &str,
+    #[case] qry_aln: &str,
+    #[case] pos: usize,
+    #[case] cigar: &str,
+    #[case] seq: &str,
+  ) -> Result<(), Report> {
+    let stripped = insertions_strip(&to_nuc_seq(qry_aln)?, &to_nuc_seq(ref_aln)?);
+    let actual = sam_alignment(&stripped.qry_seq, &stripped.insertions);
+    assert_eq!(
+      actual,
+      Some(SamAlignment {
+        pos,
+        cigar: cigar.to_owned(),
+        seq: seq.to_owned()
+      })
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[case("seq1 some description", "seq1")]
+  #[case("@seq 1", "_seq")]
+  #[case("", "*")]
+  fn makes_valid_sam_names(#[case] input: &str, #[case] expected: &str) {
+    assert_eq!(sam_name(input), expected);
+  }
+}
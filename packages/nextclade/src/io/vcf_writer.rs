@@ -0,0 +1,599 @@
+//! Writes multi-sample VCF file with nucleotide substitutions, deletions and insertions of every analyzed sequence
+//! relative to the reference sequence.
+//!
+//! See: https://samtools.github.io/hts-specs/VCFv4.2.pdf
+//!
+//! Every sample is haploid. All changes of a sample which are anchored at the same reference position (e.g. a
+//! substitution immediately followed by a deletion) are merged into a single allele. Indels are left-anchored, i.e.
+//! REF and ALT alleles of deletions and insertions include the preceding reference nucleotide. Insertions before the
+//! beginning of the reference are anchored to the first reference nucleotide, and deletions at the beginning of the
+//! reference to the nucleotide following the deleted span, as the spec requires.
+//!
+//! Genotype of a sample is a no-call (`.`) if the sample does not have a variant at the site and the site overlaps
+//! missing or ambiguous nucleotides, a deletion, or the unaligned flanks of the sample.
+use crate::align::insertions_strip::Insertion;
+use crate::alphabet::nuc::{from_nuc, Nuc};
+use crate::analyze::letter_ranges::NucRange;
+use crate::analyze::nuc_del::NucDelRange;
+use crate::analyze::nuc_sub::NucSub;
+use crate::coord::position::PositionLike;
+use crate::io::file::create_file_or_stdout;
+use crate::types::outputs::NextcladeOutputs;
+use crate::utils::info::this_package_version_str;
+use eyre::{Report, WrapErr};
+use indexmap::IndexSet;
+use itertools::Itertools;
+use log::warn;
+use percent_encoding::{percent_encode, AsciiSet, CONTROLS};
+use std::collections::{BTreeMap, HashSet};
+use std::io::Write;
+use std::iter;
+use std::path::{Path, PathBuf};
+
+/// The set of characters to be percent-encoded in VCF FORMAT values
+const VCF_VALUE_ENCODE_SET: &AsciiSet = &CONTROLS.add(b' ').add(b'%').add(b':').add(b';').add(b'=').add(b',');
+
+/// Allele of one sample at one site: REF allele spans reference positions `[pos, end)`
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct VcfSampleAllele {
+  pos: usize,
+  end: usize,
+  alt: String,
+}
+
+/// Per-sample data needed to write VCF. Only the variants and the no-call ranges are kept, not the full results.
+#[derive(Clone, Debug)]
+struct VcfSample {
+  index: usize,
+  name: String,
+  alleles: Vec<VcfSampleAllele>,
+  no_calls: Vec<(usize, usize)>,
+  qc_status: Option<String>,
+  clade: Option<String>,
+}
+
+/// Writes VCF file with variants of all samples.
+///
+/// Multi-sample VCF has one column per sample, so all samples need to be known before the first row can be written.
+/// The samples are accumulated in memory and the file is written when `finish()` is called.
+pub struct VcfFileWriter {
+  filepath: PathBuf,
+  chrom: String,
+  ref_seq: Vec<Nuc>,
+  include_clade: bool,
+  samples: Vec<VcfSample>,
+}
+
+impl VcfFileWriter {
+  pub fn new(filepath: impl AsRef<Path>, ref_name: &str, ref_seq: &[Nuc], include_clade: bool) -> Result<Self, Report> {
+    Ok(Self {
+      filepath: filepath.as_ref().to_owned(),
+      chrom: vcf_chrom_name(ref_name),
+      ref_seq: ref_seq.to_vec(),
+      include_clade,
+      samples: vec![],
+    })
+  }
+
+  pub fn write(&mut self, outputs: &NextcladeOutputs) {
+    let NextcladeOutputs {
+      index,
+      seq_name,
+      substitutions,
+      deletions,
+      insertions,
+      missing,
+      non_acgtns,
+      alignment_range,
+      qc,
+      clade,
+      ..
+    } = outputs;
+
+    let alleles = sample_alleles(seq_name, &self.ref_seq, substitutions, deletions, insertions);
+
+    let no_calls = iter::once((0, alignment_range.begin.as_usize()))
+      .chain(missing.iter().chain(non_acgtns).map(nuc_range_bounds))
+      .chain(
+        deletions
+          .iter()
+          .map(|del| (del.range().begin.as_usize(), del.range().end.as_usize())),
+      )
+      .chain(iter::once((alignment_range.end.as_usize(), self.ref_seq.len())))
+      .filter(|(begin, end)| begin < end)
+      .sorted()
+      .collect_vec();
+
+    self.samples.push(VcfSample {
+      index: *index,
+      name: seq_name.clone(),
+      alleles,
+      no_calls,
+      qc_status: Some(qc.overall_status.to_string()),
+      clade: clade.clone(),
+    });
+  }
+
+  /// Adds a sample which failed to be analyzed. All its genotypes are no-calls.
+  pub fn write_nuc_error(&mut self, index: usize, seq_name: &str) {
+    self.samples.push(VcfSample {
+      index,
+      name: seq_name.to_owned(),
+      alleles: vec![],
+      no_calls: vec![(0, self.ref_seq.len())],
+      qc_status: None,
+      clade: None,
+    });
+  }
+
+  pub fn finish(&mut self) -> Result<(), Report> {
+    let mut writer = create_file_or_stdout(&self.filepath)?;
+    self.samples.sort_by_key(|sample| sample.index);
+    write_vcf(
+      &mut writer,
+      &self.chrom,
+      &self.ref_seq,
+      &self.samples,
+      self.include_clade,
+    )
+    .wrap_err_with(|| format!("When writing VCF file {:#?}", self.filepath))?;
+    writer.flush()?;
+    Ok(())
+  }
+}
+
+/// VCF does not allow whitespace in CHROM, so only the sequence ID (first word of the FASTA header) is used
+fn vcf_chrom_name(ref_name: &str) -> String {
+  ref_name.split_whitespace().next().unwrap_or("reference").to_owned()
+}
+
+/// VCF alleles can only contain A, C, G, T and N
+const fn vcf_base(nuc: Nuc) -> char {
+  if nuc.is_acgtn() {
+    from_nuc(nuc)
+  } else {
+    'N'
+  }
+}
+
+fn vcf_bases(seq: &[Nuc]) -> String {
+  seq.iter().copied().map(vcf_base).collect()
+}
+
+fn vcf_encode_value(s: &str) -> String {
+  percent_encode(s.as_bytes(), VCF_VALUE_ENCODE_SET).to_string()
+}
+
+fn nuc_range_bounds(range: &NucRange) -> (usize, usize) {
+  (range.range().begin.as_usize(), range.range().end.as_usize())
+}
+
+/// Converts changes of one sample into VCF alleles: changes anchored at the same reference position are merged into
+/// one allele, by applying them to the reference sequence in the span of the allele.
+///
+/// An allele with nothing left of it (e.g. when the entire reference sequence is deleted) cannot be represented in
+/// VCF, because there is no nucleotide to anchor it to. Such alleles are skipped with a warning, and the deleted sites
+/// remain no-calls.
+fn sample_alleles(
+  seq_name: &str,
+  ref_seq: &[Nuc],
+  substitutions: &[NucSub],
+  deletions: &[NucDelRange],
+  insertions: &[Insertion<Nuc>],
+) -> Vec<VcfSampleAllele> {
+  let subs: BTreeMap<usize, Nuc> = substitutions
+    .iter()
+    .map(|sub| (sub.pos.as_usize(), sub.qry_nuc))
+    .collect();
+
+  let dels: BTreeMap<usize, usize> = deletions
+    .iter()
+    .map(|del| (del.range().begin.as_usize(), del.range().end.as_usize()))
+    .collect();
+
+  // Insertions before the first nucleotide (position -1) are anchored to the first nucleotide, all others are
+  // anchored to the preceding nucleotide.
+  let mut prefix_insertion: Option<&[Nuc]> = None;
+  let mut inss = BTreeMap::<usize, &[Nuc]>::new();
+  for Insertion { pos, ins } in insertions {
+    if *pos < 0 {
+      prefix_insertion = Some(ins);
+    } else {
+      inss.insert(*pos as usize, ins);
+    }
+  }
+
+  // Span of the allele anchored at each position
+  let mut spans = BTreeMap::<usize, usize>::new();
+  for &pos in subs.keys().chain(inss.keys()) {
+    let end = spans.entry(pos).or_insert(pos + 1);
+    *end = (*end).max(pos + 1);
+  }
+  for (&begin, &end) in &dels {
+    // Deletions at the beginning have no preceding nucleotide, so they are anchored to the following one instead
+    let (anchor, end) = if begin == 0 {
+      (0, (end + 1).min(ref_seq.len()))
+    } else {
+      (begin - 1, end)
+    };
+    let span_end = spans.entry(anchor).or_insert(end);
+    *span_end = (*span_end).max(end);
+  }
+  if prefix_insertion.is_some() {
+    spans.entry(0).or_insert(1);
+  }
+
+  // Merge overlapping spans, e.g. of a deletion at the beginning and of a change of its anchor nucleotide
+  let mut merged_spans = Vec::<(usize, usize)>::with_capacity(spans.len());
+  for (pos, end) in spans {
+    match merged_spans.last_mut() {
+      Some((_, last_end)) if pos < *last_end => *last_end = (*last_end).max(end),
+      _ => merged_spans.push((pos, end)),
+    }
+  }
+
+  merged_spans
+    .into_iter()
+    .filter_map(|(pos, end)| {
+      let mut alt = String::new();
+      if pos == 0 {
+        if let Some(ins) = prefix_insertion {
+          alt.push_str(&vcf_bases(ins));
+        }
+      }
+      let mut i = pos;
+      while i < end {
+        if let Some(&del_end) = dels.get(&i) {
+          i = del_end;
+          continue;
+        }
+        alt.push(vcf_base(subs.get(&i).copied().unwrap_or(ref_seq[i])));
+        if let Some(ins) = inss.get(&i) {
+          alt.push_str(&vcf_bases(ins));
+        }
+        i += 1;
+      }
+      if alt.is_empty() {
+        warn!(
+          "When writing VCF: sequence '{seq_name}': unable to represent deletion of reference positions {}-{}, because it leaves no nucleotide to anchor it to. Writing no-calls for these positions instead.",
+          pos + 1,
+          end
+        );
+        return None;
+      }
+      Some(VcfSampleAllele { pos, end, alt })
+    })
+    .collect_vec()
+}
+
+/// One row of VCF file: alleles of all samples anchored at the same position
+struct VcfSite {
+  pos: usize,
+  end: usize,
+}
+
+fn write_vcf(
+  writer: &mut impl Write,
+  chrom: &str,
+  ref_seq: &[Nuc],
+  samples: &[VcfSample],
+  include_clade: bool,
+) -> Result<(), Report> {
+  writeln!(writer, "##fileformat=VCFv4.2")?;
+  writeln!(writer, "##source=Nextclade {}", this_package_version_str())?;
+  writeln!(writer, "##contig=<ID={chrom},length={}>", ref_seq.len())?;
+  writeln!(writer, r#"##FILTER=<ID=PASS,Description="All filters passed">"#)?;
+  writeln!(
+    writer,
+    r#"##FORMAT=<ID=GT,Number=1,Type=String,Description="Genotype">"#
+  )?;
+  writeln!(
+    writer,
+    r#"##FORMAT=<ID=QC,Number=1,Type=String,Description="Overall QC status of the sequence">"#
+  )?;
+  if include_clade {
+    writeln!(
+      writer,
+      r#"##FORMAT=<ID=CLADE,Number=1,Type=String,Description="Clade assigned to the sequence">"#
+    )?;
+  }
+
+  let sample_names = unique_sample_names(samples);
+  writeln!(
+    writer,
+    "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\t{}",
+    sample_names.join("\t")
+  )?;
+
+  let format = if include_clade { "GT:QC:CLADE" } else { "GT:QC" };
+
+  let sample_infos = samples
+    .iter()
+    .map(|sample| {
+      let qc = sample
+        .qc_status
+        .as_deref()
+        .map_or_else(|| ".".to_owned(), vcf_encode_value);
+      if include_clade {
+        let clade = sample.clade.as_deref().map_or_else(|| ".".to_owned(), vcf_encode_value);
+        format!("{qc}:{clade}")
+      } else {
+        qc
+      }
+    })
+    .collect_vec();
+
+  let mut sites = BTreeMap::<usize, VcfSite>::new();
+  for allele in samples.iter().flat_map(|sample| &sample.alleles) {
+    let site = sites.entry(allele.pos).or_insert(VcfSite {
+      pos: allele.pos,
+      end: allele.end,
+    });
+    site.end = site.end.max(allele.end);
+  }
+
+  // Alleles of every sample and no-call ranges are sorted by position, as are the sites, so a cursor per sample is
+  // enough to find the sample's allele and the overlapping no-call ranges for each site.
+  let mut allele_cursors = vec![0_usize; samples.len()];
+  let mut no_call_cursors = vec![0_usize; samples.len()];
+
+  for site in sites.values() {
+    let ref_allele = vcf_bases(&ref_seq[site.pos..site.end]);
+
+    let mut alts = IndexSet::<String>::new();
+    let mut genotypes = Vec::with_capacity(samples.len());
+    for (i, sample) in samples.iter().enumerate() {
+      let alleles = &sample.alleles;
+      while allele_cursors[i] < alleles.len() && alleles[allele_cursors[i]].pos < site.pos {
+        allele_cursors[i] += 1;
+      }
+
+      let no_calls = &sample.no_calls;
+      while no_call_cursors[i] < no_calls.len() && no_calls[no_call_cursors[i]].1 <= site.pos {
+        no_call_cursors[i] += 1;
+      }
+
+      let allele = alleles.get(allele_cursors[i]).filter(|allele| allele.pos == site.pos);
+      let genotype = if let Some(allele) = allele {
+        // Extend the allele with the reference, so that all alleles of the site have the same span
+        let alt = format!("{}{}", allele.alt, vcf_bases(&ref_seq[allele.end..site.end]));
+        if alt == ref_allele {
+          "0".to_owned()
+        } else {
+          let (alt_index, _) = alts.insert_full(alt);
+          (alt_index + 1).to_string()
+        }
+      } else {
+        let is_no_call = no_calls[no_call_cursors[i]..]
+          .iter()
+          .take_while(|(begin, _)| *begin < site.end)
+          .any(|(_, end)| *end > site.pos);
+        if is_no_call {
+          ".".to_owned()
+        } else {
+          "0".to_owned()
+        }
+      };
+      genotypes.push(genotype);
+    }
+
+    if alts.is_empty() {
+      continue;
+    }
+
+    let samples_columns = genotypes
+      .iter()
+      .zip(&sample_infos)
+      .map(|(genotype, info)| format!("{genotype}:{info}"))
+      .join("\t");
+
+    writeln!(
+      writer,
+      "{chrom}\t{}\t.\t{ref_allele}\t{}\t.\tPASS\t.\t{format}\t{samples_columns}",
+      site.pos + 1,
+      alts.iter().join(","),
+    )?;
+  }
+
+  Ok(())
+}
+
+/// VCF requires sample names to be unique, but sequence names are not guaranteed to be unique. Duplicates are
+/// disambiguated by appending the index of the sequence.
+fn unique_sample_names(samples: &[VcfSample]) -> Vec<String> {
+  let mut seen = HashSet::<String>::new();
+  samples
+    .iter()
+    .map(|sample| {
+      let name = sample.name.replace(['\t', '\n', '\r'], " ");
+      if seen.insert(name.clone()) {
+        name
+      } else {
+        let unique = format!("{name}_{}", sample.index);
+        warn!(
+          "When writing VCF: sequence name '{name}' is not unique. Sample #{} will be named '{unique}'",
+          sample.index
+        );
+        seen.insert(unique.clone());
+        unique
+      }
+    })
+    .collect_vec()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::alphabet::nuc::to_nuc_seq;
+  use crate::io::fasta::FastaRecord;
+  use crate::test_utils::{create_test_nextclade, random_seq};
+  use pretty_assertions::assert_eq;
+
+  #[allow(clippy::string_slice)]
+  fn mutate(seq: &str, pos: usize) -> char {
+    match &seq[pos..=pos] {
+      "A" => 'C',
+      "C" => 'G',
+      "G" => 'T',
+      _ => 'A',
+    }
+  }
+
+  #[test]
+  #[allow(clippy::string_slice)]
+  fn writes_left_anchored_indels_and_no_calls() -> Result<(), Report> {
+    let ref_seq = random_seq(7, 1000);
+    let sub = mutate(&ref_seq, 100);
+
+    // Sample 1: substitution at 101, deletion of 301-303, insertion of "GGG" after 600
+    let qry1 = format!(
+      "{}{sub}{}{}GGG{}",
+      &ref_seq[..100],
+      &ref_seq[101..300],
+      &ref_seq[303..600],
+      &ref_seq[600..]
+    );
+    // Sample 2: same substitution at 101, missing nucleotides in 290-310
+    let qry2 = format!(
+      "{}{sub}{}{}{}",
+      &ref_seq[..100],
+      &ref_seq[101..289],
+      "N".repeat(21),
+      &ref_seq[310..]
+    );
+
+    let nextclade = create_test_nextclade("ref description", &ref_seq)?;
+
+    let run = |index: usize, seq_name: &str, seq: &str| {
+      nextclade.run(&FastaRecord {
+        index,
+        seq_name: seq_name.to_owned(),
+        seq: seq.to_owned(),
+      })
+    };
+    let out1 = run(0, "one", &qry1)?.analysis_result;
+    let out2 = run(1, "two", &qry2)?.analysis_result;
+
+    let mut writer = VcfFileWriter::new("unused", "ref description", &to_nuc_seq(&ref_seq)?, false)?;
+    writer.write(&out2);
+    writer.write(&out1);
+    writer.write_nuc_error(2, "one");
+    writer.samples.sort_by_key(|sample| sample.index);
+
+    let mut buf = Vec::<u8>::new();
+    write_vcf(&mut buf, &writer.chrom, &writer.ref_seq, &writer.samples, false)?;
+    let actual = String::from_utf8(buf)?;
+    let rows = actual.lines().filter(|line| !line.starts_with("##")).collect_vec();
+
+    let r = |begin: usize, end: usize| &ref_seq[begin..end];
+    assert_eq!(
+      rows,
+      vec![
+        "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tone\ttwo\tone_2".to_owned(),
+        format!(
+          "ref\t101\t.\t{}\t{sub}\t.\tPASS\t.\tGT:QC\t1:good\t1:good\t.:.",
+          r(100, 101)
+        ),
+        format!(
+          "ref\t300\t.\t{}\t{}\t.\tPASS\t.\tGT:QC\t1:good\t.:good\t.:.",
+          r(299, 303),
+          r(299, 300)
+        ),
+        format!(
+          "ref\t600\t.\t{}\t{}GGG\t.\tPASS\t.\tGT:QC\t1:good\t0:good\t.:.",
+          r(599, 600),
+          r(599, 600)
+        ),
+      ]
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn merges_changes_anchored_at_the_same_position() -> Result<(), Report> {
+    let ref_seq = to_nuc_seq("ACGTACGTAC")?;
+    let subs = [NucSub {
+      pos: 2.into(),
+      ref_nuc: Nuc::G,
+      qry_nuc: Nuc::A,
+    }];
+    let dels = [NucDelRange::from_usize(3, 5)];
+    let inss = [
+      Insertion {
+        pos: -1,
+        ins: to_nuc_seq("TT")?,
+      },
+      Insertion {
+        pos: 7,
+        ins: to_nuc_seq("CRC")?,
+      },
+    ];
+
+    let actual = sample_alleles("sample", &ref_seq, &subs, &dels, &inss);
+
+    let allele = |pos: usize, end: usize, alt: &str| VcfSampleAllele {
+      pos,
+      end,
+      alt: alt.to_owned(),
+    };
+    assert_eq!(
+      actual,
+      vec![allele(0, 1, "TTA"), allele(2, 5, "A"), allele(7, 8, "TCNC")]
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn anchors_deletion_at_the_beginning_to_the_following_nucleotide() -> Result<(), Report> {
+    let ref_seq = to_nuc_seq("ACGTACGTAC")?;
+    let dels = [NucDelRange::from_usize(0, 2)];
+
+    let allele = |pos: usize, end: usize, alt: &str| VcfSampleAllele {
+      pos,
+      end,
+      alt: alt.to_owned(),
+    };
+
+    let actual = sample_alleles("sample", &ref_seq, &[], &dels, &[]);
+    assert_eq!(actual, vec![allele(0, 3, "G")]);
+
+    // Substitution of the anchor nucleotide and insertion after it are merged into the same allele
+    let subs = [NucSub {
+      pos: 2.into(),
+      ref_nuc: Nuc::G,
+      qry_nuc: Nuc::A,
+    }];
+    let inss = [Insertion {
+      pos: 2,
+      ins: to_nuc_seq("TT")?,
+    }];
+    let actual = sample_alleles("sample", &ref_seq, &subs, &dels, &inss);
+    assert_eq!(actual, vec![allele(0, 3, "ATT")]);
+    Ok(())
+  }
+
+  #[test]
+  fn skips_deletion_of_the_entire_reference_sequence() -> Result<(), Report> {
+    let ref_seq = to_nuc_seq("ACGTACGTAC")?;
+    let dels = [NucDelRange::from_usize(0, 10)];
+
+    let actual = sample_alleles("sample", &ref_seq, &[], &dels, &[]);
+    assert_eq!(actual, Vec::<VcfSampleAllele>::new());
+
+    // Insertion in place of the deleted sequence still leaves something to write
+    let inss = [Insertion {
+      pos: -1,
+      ins: to_nuc_seq("TT")?,
+    }];
+    let actual = sample_alleles("sample", &ref_seq, &[], &dels, &inss);
+    assert_eq!(
+      actual,
+      vec![VcfSampleAllele {
+        pos: 0,
+        end: 10,
+        alt: "TT".to_owned(),
+      }]
+    );
+    Ok(())
+  }
+}
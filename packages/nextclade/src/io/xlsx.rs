@@ -24,6 +24,8 @@ pub fn results_to_excel_sheet(
     &initial_data.phenotype_attr_keys,
     &initial_data.ref_nodes,
     &initial_data.aa_motif_keys,
+    &initial_data.nuc_motif_keys,
+    &initial_data.qc_custom_rule_keys,
     column_config,
   );
 
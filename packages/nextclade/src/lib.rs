@@ -15,6 +15,9 @@ pub mod tree;
 pub mod types;
 pub mod utils;
 
+#[cfg(test)]
+mod test_utils;
+
 #[cfg(test)]
 mod tests {
   use crate::utils::global_init::{global_init, GlobalInitConfig};
@@ -1,9 +1,13 @@
 use crate::coord::range::AaRefRange;
 use crate::io::fs::read_file_to_string;
 use crate::io::json::json_parse;
+use crate::make_error;
+use crate::qc::qc_custom_expression::QcExpression;
+use crate::qc::qc_rule_custom::validate_rule_custom;
 use eyre::{Report, WrapErr};
 use ordered_float::OrderedFloat;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
 use std::path::Path;
 use std::str::FromStr;
 use validator::Validate;
@@ -67,6 +71,65 @@ pub struct QcRulesConfigSnpClusters {
   pub score_weight: OrderedFloat<f64>,
 }
 
+/// Detection of recombinants: sequences which are explained by a mosaic of two nodes of the reference tree better
+/// than by any single node. The score is the number of sites additionally explained by the mosaic, multiplied by the
+/// weight.
+///
+/// The search parameters apply whenever the reference tree is available, regardless of whether the rule is enabled.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, schemars::JsonSchema, Validate)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct QcRulesConfigRecombinants {
+  /// Whether the result of the search contributes to the QC score
+  pub enabled: bool,
+
+  /// Cost of each switch between parents, in number of mismatching sites
+  pub breakpoint_penalty: usize,
+
+  /// Minimum number of sites which are explained by the mosaic of parents, but not by any single node
+  pub min_support: usize,
+
+  /// Minimum number of sites supporting each of the segments
+  pub min_segment_sites: usize,
+
+  /// Maximum number of candidate parental nodes to compare pairwise
+  pub max_candidates: usize,
+
+  pub score_weight: OrderedFloat<f64>,
+}
+
+impl Default for QcRulesConfigRecombinants {
+  fn default() -> Self {
+    Self {
+      enabled: false,
+      breakpoint_penalty: 3,
+      min_support: 3,
+      min_segment_sites: 3,
+      max_candidates: 20,
+      score_weight: OrderedFloat(10.0),
+    }
+  }
+}
+
+/// Flags sequences which still hit the boundary of the alignment band after all attempts to widen it. The alignment of
+/// such sequences may be suboptimal. The score is the weight if the boundary is hit, and zero otherwise.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, schemars::JsonSchema, Validate)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct QcRulesConfigBandBoundary {
+  pub enabled: bool,
+  pub score_weight: OrderedFloat<f64>,
+}
+
+impl Default for QcRulesConfigBandBoundary {
+  fn default() -> Self {
+    Self {
+      enabled: false,
+      score_weight: OrderedFloat(50.0),
+    }
+  }
+}
+
 #[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize, schemars::JsonSchema, Validate)]
 #[serde(rename_all = "camelCase")]
 #[serde(default)]
@@ -122,6 +185,68 @@ impl Default for QcRulesConfigStopCodons {
   }
 }
 
+/// User-defined QC rule. The score is computed by evaluating the expression over fields of analysis outputs and
+/// multiplying the result by the weight. Negative scores are clamped to zero.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, schemars::JsonSchema, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct QcRulesConfigCustom {
+  pub name: String,
+
+  #[serde(default)]
+  pub enabled: bool,
+
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub description: Option<String>,
+
+  pub expression: QcExpression,
+
+  #[serde(default = "one")]
+  pub score_weight: OrderedFloat<f64>,
+}
+
+/// Score thresholds which divide QC scores into status bands. A score below `mediocre` is "good", a score at or
+/// above `mediocre` is "mediocre" and a score at or above `bad` is "bad". If `fail` is set, then scores at or above it
+/// receive the additional "fail" status.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, schemars::JsonSchema, Validate)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct QcStatusThresholds {
+  pub mediocre: OrderedFloat<f64>,
+  pub bad: OrderedFloat<f64>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub fail: Option<OrderedFloat<f64>>,
+}
+
+impl Default for QcStatusThresholds {
+  fn default() -> Self {
+    Self {
+      mediocre: OrderedFloat(30.0),
+      bad: OrderedFloat(100.0),
+      fail: None,
+    }
+  }
+}
+
+impl QcStatusThresholds {
+  /// Checks that the thresholds are in non-decreasing order, so that each status band starts where the previous ends
+  pub fn validate(&self) -> Result<(), Report> {
+    let Self { mediocre, bad, fail } = self;
+    if mediocre > bad {
+      return make_error!(
+        "QC status thresholds (qc.statusThresholds) are expected to satisfy 'mediocre <= bad', but found: mediocre={mediocre}, bad={bad}"
+      );
+    }
+    if let Some(fail) = fail {
+      if bad > fail {
+        return make_error!(
+          "QC status thresholds (qc.statusThresholds) are expected to satisfy 'bad <= fail', but found: bad={bad}, fail={fail}"
+        );
+      }
+    }
+    Ok(())
+  }
+}
+
 #[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize, schemars::JsonSchema, Validate)]
 #[serde(rename_all = "camelCase")]
 #[serde(default)]
@@ -133,6 +258,11 @@ pub struct QcConfig {
   pub snp_clusters: QcRulesConfigSnpClusters,
   pub frame_shifts: QcRulesConfigFrameShifts,
   pub stop_codons: QcRulesConfigStopCodons,
+  pub recombinants: QcRulesConfigRecombinants,
+  pub band_boundary: QcRulesConfigBandBoundary,
+  pub status_thresholds: QcStatusThresholds,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub custom_rules: Vec<QcRulesConfigCustom>,
 }
 
 impl FromStr for QcConfig {
@@ -144,9 +274,112 @@ impl FromStr for QcConfig {
 }
 
 impl QcConfig {
+  /// Names of enabled custom QC rules, in the order of declaration
+  pub fn custom_rule_keys(&self) -> Vec<String> {
+    self
+      .custom_rules
+      .iter()
+      .filter(|rule| rule.enabled)
+      .map(|rule| rule.name.clone())
+      .collect()
+  }
+
   pub fn from_path(filepath: impl AsRef<Path>) -> Result<Self, Report> {
     let filepath = filepath.as_ref();
     let data = read_file_to_string(filepath).wrap_err_with(|| format!("When reading QC config file {filepath:#?}"))?;
     Self::from_str(&data).wrap_err_with(|| format!("When parsing QC config file {filepath:#?}"))
   }
+
+  pub fn validate(&self) -> Result<(), Report> {
+    self.status_thresholds.validate()?;
+
+    // Results of custom rules are keyed by rule name, so rules with the same name would overwrite each other
+    let mut names = BTreeSet::new();
+    for rule in &self.custom_rules {
+      if !names.insert(rule.name.as_str()) {
+        return make_error!(
+          "Custom QC rules (qc.customRules) are expected to have unique names, but found multiple rules named '{}'",
+          rule.name
+        );
+      }
+      validate_rule_custom(rule).wrap_err_with(|| format!("When validating custom QC rule '{}'", rule.name))?;
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+
+  fn custom_rule(name: &str) -> QcRulesConfigCustom {
+    QcRulesConfigCustom {
+      name: name.to_owned(),
+      enabled: true,
+      description: None,
+      expression: QcExpression::from_str("totalSubstitutions").unwrap(),
+      score_weight: OrderedFloat(1.0),
+    }
+  }
+
+  #[rstest]
+  #[case(30.0, 100.0, None, true)]
+  #[case(30.0, 100.0, Some(200.0), true)]
+  #[case(50.0, 50.0, Some(50.0), true)]
+  #[case(100.0, 30.0, None, false)]
+  #[case(30.0, 100.0, Some(50.0), false)]
+  fn validates_qc_status_thresholds_order(
+    #[case] mediocre: f64,
+    #[case] bad: f64,
+    #[case] fail: Option<f64>,
+    #[case] is_valid: bool,
+  ) {
+    let config = QcConfig {
+      status_thresholds: QcStatusThresholds {
+        mediocre: OrderedFloat(mediocre),
+        bad: OrderedFloat(bad),
+        fail: fail.map(OrderedFloat),
+      },
+      ..QcConfig::default()
+    };
+    assert_eq!(config.validate().is_ok(), is_valid);
+  }
+
+  #[test]
+  fn accepts_custom_rules_with_unique_names() {
+    let config = QcConfig {
+      custom_rules: vec![custom_rule("foo"), custom_rule("bar")],
+      ..QcConfig::default()
+    };
+    assert!(config.validate().is_ok());
+  }
+
+  #[test]
+  fn rejects_custom_rules_with_duplicate_names() {
+    let config = QcConfig {
+      custom_rules: vec![custom_rule("foo"), custom_rule("bar"), custom_rule("foo")],
+      ..QcConfig::default()
+    };
+    let error = config.validate().unwrap_err();
+    assert!(error.to_string().contains("'foo'"));
+  }
+
+  #[rstest]
+  #[case("totalSubstitutions + qc.missingData.totalMissing", true)]
+  #[case("count(deletions[range.begin > 100 && foo == 1])", true)]
+  #[case("totalSubstitutionz", false)]
+  #[case("qc.overallScore", false)]
+  #[case("qc.custom.foo.value", false)]
+  fn validates_custom_rule_field_paths(#[case] expression: &str, #[case] is_valid: bool) {
+    let config = QcConfig {
+      custom_rules: vec![QcRulesConfigCustom {
+        expression: QcExpression::from_str(expression).unwrap(),
+        ..custom_rule("foo")
+      }],
+      ..QcConfig::default()
+    };
+    assert_eq!(config.validate().is_ok(), is_valid);
+  }
 }
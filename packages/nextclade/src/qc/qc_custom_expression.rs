@@ -0,0 +1,676 @@
+use crate::make_error;
+use crate::utils::error::to_eyre_error;
+use eyre::{Report, WrapErr};
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// Expression which computes a numeric value from fields of Nextclade analysis outputs.
+///
+/// Used by custom QC rules (`customRules` in qc.json). The expression is evaluated against the JSON representation
+/// of `NextcladeOutputs` (the same as in the NDJSON output), so field names are the same as in the output files.
+///
+/// Syntax:
+///  - numbers: `42`, `0.95`
+///  - strings: `'S'`, `"ORF1a"`
+///  - field paths: `totalSubstitutions`, `qc.missingData.totalMissing`, `cdsCoverage.S`, `cdsCoverage['ORF1a']`
+///  - filters on arrays, evaluated relative to each array element: `deletions[range.begin >= 21562 && range.end <= 25384]`
+///  - arithmetic: `+`, `-`, `*`, `/`, unary `-`, parentheses
+///  - comparisons (yield 1 or 0): `==`, `!=`, `<`, `<=`, `>`, `>=`
+///  - logic (yield 1 or 0): `&&`, `||`, `!`
+///  - functions: `count(path)`, `sum(...)`, `min(...)`, `max(...)`, `abs(x)`
+///
+/// Arrays encountered along a path are expanded into their elements, so that `unknownAaRanges[cdsName == 'S'].length`
+/// yields one value per matching element. Such multi-valued paths need to be aggregated with `count()`, `sum()`,
+/// `min()` or `max()`. Fields which are not present in the outputs evaluate to 0.
+///
+/// Custom rules are evaluated after the built-in QC rules, so results of the built-in rules are available under `qc`
+/// (e.g. `qc.missingData.totalMissing`). Results of other custom rules and the overall QC score are not.
+#[derive(Clone, Debug, Default)]
+pub struct QcExpression {
+  source: String,
+  root: Expr,
+}
+
+impl QcExpression {
+  #[inline]
+  pub fn as_str(&self) -> &str {
+    &self.source
+  }
+
+  /// Evaluates the expression against JSON representation of analysis outputs
+  pub fn eval(&self, root: &Value) -> Result<f64, Report> {
+    eval(&self.root, root)
+      .and_then(|val| val.to_num())
+      .wrap_err_with(|| format!("When evaluating QC expression '{}'", self.source))
+  }
+
+  /// Field paths which are evaluated against the root of analysis outputs, up to the first filter. Paths inside of
+  /// filters are relative to array elements and are not included.
+  pub fn root_paths(&self) -> Vec<Vec<&str>> {
+    let mut paths = vec![];
+    collect_root_paths(&self.root, &mut paths);
+    paths
+  }
+}
+
+impl FromStr for QcExpression {
+  type Err = Report;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let tokens = tokenize(s).wrap_err_with(|| format!("When parsing QC expression '{s}'"))?;
+    let mut parser = Parser { tokens, pos: 0 };
+    let root = parser
+      .parse_expression()
+      .and_then(|expr| match parser.peek() {
+        None => Ok(expr),
+        Some(token) => make_error!("Unexpected token '{token}'"),
+      })
+      .wrap_err_with(|| format!("When parsing QC expression '{s}'"))?;
+    Ok(Self {
+      source: s.to_owned(),
+      root,
+    })
+  }
+}
+
+impl Display for QcExpression {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", self.source)
+  }
+}
+
+impl PartialEq for QcExpression {
+  fn eq(&self, other: &Self) -> bool {
+    self.source == other.source
+  }
+}
+
+impl Eq for QcExpression {}
+
+impl<'de> Deserialize<'de> for QcExpression {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    QcExpression::from_str(&s).map_err(|report| Error::custom(format!("{report:#}")))
+  }
+}
+
+impl Serialize for QcExpression {
+  fn serialize<Ser>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error>
+  where
+    Ser: Serializer,
+  {
+    serializer.serialize_str(&self.source)
+  }
+}
+
+impl schemars::JsonSchema for QcExpression {
+  fn schema_name() -> String {
+    "QcExpression".to_owned()
+  }
+
+  fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+    gen.subschema_for::<String>()
+  }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum BinaryOp {
+  Add,
+  Sub,
+  Mul,
+  Div,
+  Eq,
+  Ne,
+  Lt,
+  Le,
+  Gt,
+  Ge,
+  And,
+  Or,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Func {
+  Count,
+  Sum,
+  Min,
+  Max,
+  Abs,
+}
+
+impl FromStr for Func {
+  type Err = Report;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "count" => Ok(Func::Count),
+      "sum" => Ok(Func::Sum),
+      "min" => Ok(Func::Min),
+      "max" => Ok(Func::Max),
+      "abs" => Ok(Func::Abs),
+      _ => make_error!("Unknown function '{s}'. Possible functions: count, sum, min, max, abs"),
+    }
+  }
+}
+
+#[derive(Clone, Debug)]
+enum PathSegment {
+  Field(String),
+  Filter(Expr),
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+  Num(f64),
+  Str(String),
+  Path(Vec<PathSegment>),
+  Neg(Box<Expr>),
+  Not(Box<Expr>),
+  Binary(BinaryOp, Box<Expr>, Box<Expr>),
+  Call(Func, Vec<Expr>),
+}
+
+impl Default for Expr {
+  fn default() -> Self {
+    Expr::Num(0.0)
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+  Num(f64),
+  Str(String),
+  Ident(String),
+  Op(&'static str),
+}
+
+impl Display for Token {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Token::Num(num) => write!(f, "{num}"),
+      Token::Str(s) => write!(f, "'{s}'"),
+      Token::Ident(ident) => write!(f, "{ident}"),
+      Token::Op(op) => write!(f, "{op}"),
+    }
+  }
+}
+
+const OPERATORS: &[&str] = &[
+  "&&", "||", "==", "!=", "<=", ">=", "<", ">", "!", "+", "-", "*", "/", "(", ")", "[", "]", ".", ",",
+];
+
+fn tokenize(s: &str) -> Result<Vec<Token>, Report> {
+  let chars: Vec<char> = s.chars().collect();
+  let mut tokens = vec![];
+  let mut i = 0;
+  while i < chars.len() {
+    let c = chars[i];
+    if c.is_whitespace() {
+      i += 1;
+    } else if c.is_ascii_digit() {
+      let begin = i;
+      while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+        i += 1;
+      }
+      let num: String = chars[begin..i].iter().collect();
+      let num = to_eyre_error(num.parse::<f64>()).wrap_err_with(|| format!("Unable to parse number '{num}'"))?;
+      tokens.push(Token::Num(num));
+    } else if c == '\'' || c == '"' {
+      let begin = i + 1;
+      i = begin;
+      while i < chars.len() && chars[i] != c {
+        i += 1;
+      }
+      if i >= chars.len() {
+        return make_error!("Unterminated string literal starting at position {begin}");
+      }
+      tokens.push(Token::Str(chars[begin..i].iter().collect()));
+      i += 1;
+    } else if c.is_alphabetic() || c == '_' {
+      let begin = i;
+      while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+        i += 1;
+      }
+      tokens.push(Token::Ident(chars[begin..i].iter().collect()));
+    } else {
+      let rest: String = chars[i..].iter().take(2).collect();
+      match OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+        Some(op) => {
+          tokens.push(Token::Op(op));
+          i += op.len();
+        }
+        None => return make_error!("Unexpected character '{c}' at position {i}"),
+      }
+    }
+  }
+  Ok(tokens)
+}
+
+/// Recursive descent parser. Precedence, from lowest to highest: `||`, `&&`, comparisons, `+ -`, `* /`, unary.
+struct Parser {
+  tokens: Vec<Token>,
+  pos: usize,
+}
+
+impl Parser {
+  fn peek(&self) -> Option<&Token> {
+    self.tokens.get(self.pos)
+  }
+
+  fn next(&mut self) -> Option<Token> {
+    let token = self.tokens.get(self.pos).cloned();
+    self.pos += 1;
+    token
+  }
+
+  fn eat_op(&mut self, ops: &[&'static str]) -> Option<&'static str> {
+    match self.peek() {
+      Some(Token::Op(op)) if ops.contains(op) => {
+        let op = *op;
+        self.pos += 1;
+        Some(op)
+      }
+      _ => None,
+    }
+  }
+
+  fn expect_op(&mut self, expected: &'static str) -> Result<(), Report> {
+    match self.next() {
+      Some(Token::Op(op)) if op == expected => Ok(()),
+      Some(token) => make_error!("Expected '{expected}', but found '{token}'"),
+      None => make_error!("Expected '{expected}', but reached the end of expression"),
+    }
+  }
+
+  fn parse_binary(
+    &mut self,
+    ops: &[&'static str],
+    next: fn(&mut Self) -> Result<Expr, Report>,
+  ) -> Result<Expr, Report> {
+    let mut lhs = next(self)?;
+    while let Some(op) = self.eat_op(ops) {
+      let rhs = next(self)?;
+      let op = match op {
+        "||" => BinaryOp::Or,
+        "&&" => BinaryOp::And,
+        "==" => BinaryOp::Eq,
+        "!=" => BinaryOp::Ne,
+        "<" => BinaryOp::Lt,
+        "<=" => BinaryOp::Le,
+        ">" => BinaryOp::Gt,
+        ">=" => BinaryOp::Ge,
+        "+" => BinaryOp::Add,
+        "-" => BinaryOp::Sub,
+        "*" => BinaryOp::Mul,
+        _ => BinaryOp::Div,
+      };
+      lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+    }
+    Ok(lhs)
+  }
+
+  fn parse_expression(&mut self) -> Result<Expr, Report> {
+    self.parse_binary(&["||"], Self::parse_and)
+  }
+
+  fn parse_and(&mut self) -> Result<Expr, Report> {
+    self.parse_binary(&["&&"], Self::parse_comparison)
+  }
+
+  fn parse_comparison(&mut self) -> Result<Expr, Report> {
+    self.parse_binary(&["==", "!=", "<=", ">=", "<", ">"], Self::parse_additive)
+  }
+
+  fn parse_additive(&mut self) -> Result<Expr, Report> {
+    self.parse_binary(&["+", "-"], Self::parse_multiplicative)
+  }
+
+  fn parse_multiplicative(&mut self) -> Result<Expr, Report> {
+    self.parse_binary(&["*", "/"], Self::parse_unary)
+  }
+
+  fn parse_unary(&mut self) -> Result<Expr, Report> {
+    if self.eat_op(&["-"]).is_some() {
+      return Ok(Expr::Neg(Box::new(self.parse_unary()?)));
+    }
+    if self.eat_op(&["!"]).is_some() {
+      return Ok(Expr::Not(Box::new(self.parse_unary()?)));
+    }
+    self.parse_primary()
+  }
+
+  fn parse_primary(&mut self) -> Result<Expr, Report> {
+    match self.next() {
+      Some(Token::Num(num)) => Ok(Expr::Num(num)),
+      Some(Token::Str(s)) => Ok(Expr::Str(s)),
+      Some(Token::Op("(")) => {
+        let expr = self.parse_expression()?;
+        self.expect_op(")")?;
+        Ok(expr)
+      }
+      Some(Token::Ident(ident)) => {
+        if self.eat_op(&["("]).is_some() {
+          let func = Func::from_str(&ident)?;
+          let mut args = vec![];
+          if self.eat_op(&[")"]).is_none() {
+            loop {
+              args.push(self.parse_expression()?);
+              if self.eat_op(&[","]).is_none() {
+                break;
+              }
+            }
+            self.expect_op(")")?;
+          }
+          if args.is_empty() || ((func == Func::Count || func == Func::Abs) && args.len() != 1) {
+            return make_error!("Wrong number of arguments for function '{ident}'");
+          }
+          Ok(Expr::Call(func, args))
+        } else {
+          self.parse_path(ident)
+        }
+      }
+      Some(token) => make_error!("Unexpected token '{token}'"),
+      None => make_error!("Unexpected end of expression"),
+    }
+  }
+
+  fn parse_path(&mut self, first: String) -> Result<Expr, Report> {
+    let mut segments = vec![PathSegment::Field(first)];
+    while let Some(op) = self.eat_op(&[".", "["]) {
+      if op == "." {
+        match self.next() {
+          Some(Token::Ident(ident)) => segments.push(PathSegment::Field(ident)),
+          Some(token) => return make_error!("Expected field name after '.', but found '{token}'"),
+          None => return make_error!("Expected field name after '.', but reached the end of expression"),
+        }
+      } else {
+        let segment = match self.peek() {
+          Some(Token::Str(s)) if self.tokens.get(self.pos + 1) == Some(&Token::Op("]")) => {
+            let field = PathSegment::Field(s.clone());
+            self.pos += 1;
+            field
+          }
+          _ => PathSegment::Filter(self.parse_expression()?),
+        };
+        self.expect_op("]")?;
+        segments.push(segment);
+      }
+    }
+    Ok(Expr::Path(segments))
+  }
+}
+
+fn collect_root_paths<'a>(expr: &'a Expr, paths: &mut Vec<Vec<&'a str>>) {
+  match expr {
+    Expr::Num(_) | Expr::Str(_) => {}
+    Expr::Path(segments) => paths.push(
+      segments
+        .iter()
+        .map_while(|segment| match segment {
+          PathSegment::Field(field) => Some(field.as_str()),
+          PathSegment::Filter(_) => None,
+        })
+        .collect(),
+    ),
+    Expr::Neg(expr) | Expr::Not(expr) => collect_root_paths(expr, paths),
+    Expr::Binary(_, lhs, rhs) => {
+      collect_root_paths(lhs, paths);
+      collect_root_paths(rhs, paths);
+    }
+    Expr::Call(_, args) => args.iter().for_each(|arg| collect_root_paths(arg, paths)),
+  }
+}
+
+/// Intermediate result of evaluation
+enum Val<'a> {
+  Num(f64),
+  Str(String),
+  Nodes(Vec<&'a Value>),
+}
+
+impl Val<'_> {
+  fn to_num(&self) -> Result<f64, Report> {
+    match self {
+      Val::Num(num) => Ok(*num),
+      Val::Str(s) => make_error!("Expected a number, but found string '{s}'"),
+      Val::Nodes(nodes) => match nodes.as_slice() {
+        [] => Ok(0.0),
+        [node] => json_to_num(node),
+        _ => make_error!(
+          "Expected a single value, but the field path yields {} values. Consider aggregating them using count(), sum(), min() or max()",
+          nodes.len()
+        ),
+      },
+    }
+  }
+
+  fn to_nums(&self) -> Result<Vec<f64>, Report> {
+    match self {
+      Val::Nodes(nodes) => nodes.iter().map(|node| json_to_num(node)).collect(),
+      _ => Ok(vec![self.to_num()?]),
+    }
+  }
+
+  fn to_str(&self) -> Option<&str> {
+    match self {
+      Val::Str(s) => Some(s),
+      Val::Nodes(nodes) => match nodes.as_slice() {
+        [Value::String(s)] => Some(s),
+        _ => None,
+      },
+      Val::Num(_) => None,
+    }
+  }
+}
+
+fn json_to_num(value: &Value) -> Result<f64, Report> {
+  match value {
+    Value::Null => Ok(0.0),
+    Value::Bool(b) => Ok(if *b { 1.0 } else { 0.0 }),
+    Value::Number(num) => Ok(num.as_f64().unwrap_or_default()),
+    Value::String(s) => make_error!("Expected a number, but found string '{s}'"),
+    Value::Array(_) | Value::Object(_) => make_error!("Expected a number, but found a compound value: '{value}'"),
+  }
+}
+
+const fn bool_to_num(b: bool) -> f64 {
+  if b {
+    1.0
+  } else {
+    0.0
+  }
+}
+
+/// Pushes node into the output, expanding arrays into their elements
+fn push_expanded<'a>(nodes: &mut Vec<&'a Value>, node: &'a Value) {
+  match node {
+    Value::Array(elems) => elems.iter().for_each(|elem| push_expanded(nodes, elem)),
+    _ => nodes.push(node),
+  }
+}
+
+fn eval_path<'a>(segments: &[PathSegment], root: &'a Value) -> Result<Vec<&'a Value>, Report> {
+  let mut nodes = vec![root];
+  for segment in segments {
+    let mut next = vec![];
+    match segment {
+      PathSegment::Field(field) => {
+        for node in nodes {
+          if let Some(child) = node.get(field) {
+            push_expanded(&mut next, child);
+          }
+        }
+      }
+      PathSegment::Filter(filter) => {
+        for node in nodes {
+          if eval(filter, node)?.to_num()? != 0.0 {
+            next.push(node);
+          }
+        }
+      }
+    }
+    nodes = next;
+  }
+  Ok(nodes)
+}
+
+#[allow(clippy::float_cmp)]
+fn eval<'a>(expr: &Expr, root: &'a Value) -> Result<Val<'a>, Report> {
+  Ok(match expr {
+    Expr::Num(num) => Val::Num(*num),
+    Expr::Str(s) => Val::Str(s.clone()),
+    Expr::Path(segments) => Val::Nodes(eval_path(segments, root)?),
+    Expr::Neg(expr) => Val::Num(-eval(expr, root)?.to_num()?),
+    Expr::Not(expr) => Val::Num(bool_to_num(eval(expr, root)?.to_num()? == 0.0)),
+    Expr::Binary(op, lhs, rhs) => {
+      let lhs = eval(lhs, root)?;
+      let rhs = eval(rhs, root)?;
+
+      if let (BinaryOp::Eq | BinaryOp::Ne, Some(l), Some(r)) = (op, lhs.to_str(), rhs.to_str()) {
+        return Ok(Val::Num(bool_to_num((l == r) == (*op == BinaryOp::Eq))));
+      }
+
+      let l = lhs.to_num()?;
+      let r = rhs.to_num()?;
+      Val::Num(match op {
+        BinaryOp::Add => l + r,
+        BinaryOp::Sub => l - r,
+        BinaryOp::Mul => l * r,
+        BinaryOp::Div => {
+          if r == 0.0 {
+            0.0
+          } else {
+            l / r
+          }
+        }
+        BinaryOp::Eq => bool_to_num(l == r),
+        BinaryOp::Ne => bool_to_num(l != r),
+        BinaryOp::Lt => bool_to_num(l < r),
+        BinaryOp::Le => bool_to_num(l <= r),
+        BinaryOp::Gt => bool_to_num(l > r),
+        BinaryOp::Ge => bool_to_num(l >= r),
+        BinaryOp::And => bool_to_num(l != 0.0 && r != 0.0),
+        BinaryOp::Or => bool_to_num(l != 0.0 || r != 0.0),
+      })
+    }
+    Expr::Call(func, args) => {
+      let args = args
+        .iter()
+        .map(|arg| eval(arg, root))
+        .collect::<Result<Vec<_>, Report>>()?;
+      match func {
+        Func::Count => Val::Num(match &args[0] {
+          Val::Nodes(nodes) => nodes.len() as f64,
+          _ => 1.0,
+        }),
+        Func::Abs => Val::Num(args[0].to_num()?.abs()),
+        Func::Sum | Func::Min | Func::Max => {
+          let nums: Vec<f64> = args
+            .iter()
+            .map(Val::to_nums)
+            .collect::<Result<Vec<_>, Report>>()?
+            .into_iter()
+            .flatten()
+            .collect();
+          Val::Num(match func {
+            Func::Sum => nums.iter().sum(),
+            Func::Min => nums.into_iter().reduce(f64::min).unwrap_or_default(),
+            _ => nums.into_iter().reduce(f64::max).unwrap_or_default(),
+          })
+        }
+      }
+    }
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  #![allow(clippy::float_cmp)]
+
+  use super::*;
+  use crate::utils::error::report_to_string;
+  use eyre::Report;
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+  use serde_json::json;
+
+  fn outputs() -> Value {
+    json!({
+      "totalSubstitutions": 12,
+      "isReverseComplement": false,
+      "cdsCoverage": { "S": 0.75, "ORF1a": 1.0 },
+      "deletions": [
+        { "range": { "begin": 100, "end": 103 } },
+        { "range": { "begin": 21600, "end": 21606 } },
+        { "range": { "begin": 22000, "end": 22003 } }
+      ],
+      "unknownAaRanges": [
+        { "cdsName": "S", "length": 5 },
+        { "cdsName": "E", "length": 2 },
+        { "cdsName": "S", "length": 3 }
+      ],
+      "qc": { "missingData": { "totalMissing": 250 } }
+    })
+  }
+
+  #[rstest]
+  #[case("42", 42.0)]
+  #[case("1 + 2 * 3", 7.0)]
+  #[case("(1 + 2) * 3", 9.0)]
+  #[case("-totalSubstitutions + 2", -10.0)]
+  #[case("totalSubstitutions / 0", 0.0)]
+  #[case("qc.missingData.totalMissing", 250.0)]
+  #[case("cdsCoverage.S", 0.75)]
+  #[case("cdsCoverage['ORF1a']", 1.0)]
+  #[case("cdsCoverage.nonExistent", 0.0)]
+  #[case("(1 - cdsCoverage.S) * 100", 25.0)]
+  #[case("isReverseComplement", 0.0)]
+  #[case("count(deletions)", 3.0)]
+  #[case("count(deletions[range.begin >= 21562 && range.end <= 25384])", 2.0)]
+  #[case("sum(unknownAaRanges[cdsName == 'S'].length)", 8.0)]
+  #[case("max(unknownAaRanges.length)", 5.0)]
+  #[case("min(unknownAaRanges.length, 1)", 1.0)]
+  #[case("abs(-3)", 3.0)]
+  #[case("totalSubstitutions > 10 || !isReverseComplement", 1.0)]
+  fn evaluates_qc_expression(#[case] input: &str, #[case] expected: f64) -> Result<(), Report> {
+    let expr = QcExpression::from_str(input)?;
+    assert_eq!(expr.eval(&outputs())?, expected);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case("1 +")]
+  #[case("foo(1)")]
+  #[case("count(1, 2)")]
+  #[case("(1 + 2")]
+  #[case("'unterminated")]
+  #[case("1 # 2")]
+  fn rejects_invalid_qc_expression(#[case] input: &str) {
+    assert2::assert!(QcExpression::from_str(input).is_err());
+  }
+
+  #[test]
+  fn lists_root_paths_of_qc_expression() -> Result<(), Report> {
+    let expr = QcExpression::from_str("count(deletions[range.begin > 10]) + qc.missingData.score * cdsCoverage['S']")?;
+    assert_eq!(
+      expr.root_paths(),
+      vec![
+        vec!["deletions"],
+        vec!["qc", "missingData", "score"],
+        vec!["cdsCoverage", "S"]
+      ]
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn rejects_multi_valued_path_without_aggregation() -> Result<(), Report> {
+    let expr = QcExpression::from_str("unknownAaRanges.length")?;
+    let report = expr.eval(&outputs()).unwrap_err();
+    assert!(report_to_string(&report).contains("the field path yields 3 values"));
+    Ok(())
+  }
+}
@@ -0,0 +1,40 @@
+use crate::align::backtrace::AlignmentDiagnostics;
+use crate::qc::qc_config::{QcRulesConfigBandBoundary, QcStatusThresholds};
+use crate::qc::qc_run::{QcRule, QcStatus};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct QcResultBandBoundary {
+  pub score: f64,
+  pub status: QcStatus,
+  pub hit_boundary: bool,
+  pub total_attempts: usize,
+}
+
+impl QcRule for QcResultBandBoundary {
+  fn score(&self) -> f64 {
+    self.score
+  }
+}
+
+pub fn rule_band_boundary(
+  diagnostics: &AlignmentDiagnostics,
+  config: &QcRulesConfigBandBoundary,
+  thresholds: &QcStatusThresholds,
+) -> Option<QcResultBandBoundary> {
+  if !config.enabled {
+    return None;
+  }
+
+  let hit_boundary = diagnostics.hit_boundary;
+  let score = if hit_boundary { *config.score_weight } else { 0.0 };
+  let status = QcStatus::from_score(score, thresholds);
+
+  Some(QcResultBandBoundary {
+    score,
+    status,
+    hit_boundary,
+    total_attempts: diagnostics.band_areas.len(),
+  })
+}
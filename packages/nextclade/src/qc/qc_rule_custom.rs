@@ -0,0 +1,105 @@
+use crate::make_error;
+use crate::qc::qc_config::{QcRulesConfigCustom, QcStatusThresholds};
+use crate::qc::qc_run::{QcResult, QcRule, QcStatus};
+use crate::types::outputs::NextcladeOutputs;
+use crate::utils::error::report_to_string;
+use eyre::Report;
+use itertools::Itertools;
+use num::traits::clamp_min;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeSet;
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct QcResultCustom {
+  pub score: f64,
+  pub status: QcStatus,
+  pub value: f64,
+
+  /// Error which occurred when evaluating the rule's expression for this sequence. Such rules do not contribute to
+  /// the overall QC score.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub error: Option<String>,
+}
+
+impl QcRule for QcResultCustom {
+  fn score(&self) -> f64 {
+    self.score
+  }
+}
+
+/// Evaluates a user-defined QC rule against JSON representation of analysis outputs.
+///
+/// Errors of evaluation (e.g. a field path which yields multiple values for this particular sequence) are reported in
+/// the result of the rule, rather than failing the analysis of the sequence.
+pub fn rule_custom(
+  outputs: &Value,
+  config: &QcRulesConfigCustom,
+  thresholds: &QcStatusThresholds,
+) -> Option<QcResultCustom> {
+  if !config.enabled {
+    return None;
+  }
+
+  let result = match config.expression.eval(outputs) {
+    Ok(value) => {
+      let score = clamp_min(value * *config.score_weight, 0.0);
+      let status = QcStatus::from_score(score, thresholds);
+      QcResultCustom {
+        score,
+        status,
+        value,
+        error: None,
+      }
+    }
+    Err(report) => QcResultCustom {
+      error: Some(report_to_string(&report)),
+      ..QcResultCustom::default()
+    },
+  };
+
+  Some(result)
+}
+
+/// Checks that the expression of a user-defined QC rule refers only to the existing fields of analysis outputs, and,
+/// under `qc`, only to the results of the built-in QC rules, which are computed before the custom rules.
+pub fn validate_rule_custom(config: &QcRulesConfigCustom) -> Result<(), Report> {
+  let output_fields = schema_properties::<NextcladeOutputs>();
+
+  let mut qc_fields = schema_properties::<QcResult>();
+  for field in ["custom", "overallScore", "overallStatus"] {
+    qc_fields.remove(field);
+  }
+
+  for path in config.expression.root_paths() {
+    match path.as_slice() {
+      [field, ..] if !output_fields.contains(*field) => {
+        return make_error!(
+          "Expression '{}' refers to unknown field '{field}'. Fields are named the same as in the JSON output",
+          config.expression
+        );
+      }
+      ["qc", field, ..] if !qc_fields.contains(*field) => {
+        return make_error!(
+          "Expression '{}' refers to 'qc.{field}', but only results of the built-in QC rules are available to custom QC rules: {}",
+          config.expression,
+          qc_fields.iter().map(|field| format!("'qc.{field}'")).join(", ")
+        );
+      }
+      _ => {}
+    }
+  }
+
+  Ok(())
+}
+
+/// Names of fields of a struct, as they appear in its JSON representation
+fn schema_properties<T: JsonSchema>() -> BTreeSet<String> {
+  schemars::schema_for!(T)
+    .schema
+    .object
+    .map(|object| object.properties.keys().cloned().collect())
+    .unwrap_or_default()
+}
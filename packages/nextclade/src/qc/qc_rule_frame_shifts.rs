@@ -1,4 +1,4 @@
-use crate::qc::qc_config::QcRulesConfigFrameShifts;
+use crate::qc::qc_config::{QcRulesConfigFrameShifts, QcStatusThresholds};
 use crate::qc::qc_run::{QcRule, QcStatus};
 use crate::translate::frame_shifts_translate::FrameShift;
 use serde::{Deserialize, Serialize};
@@ -32,6 +32,7 @@ impl QcRule for QcResultFrameShifts {
 pub fn rule_frame_shifts(
   all_frame_shifts: &[FrameShift],
   config: &QcRulesConfigFrameShifts,
+  thresholds: &QcStatusThresholds,
 ) -> Option<QcResultFrameShifts> {
   if !config.enabled {
     return None;
@@ -46,7 +47,7 @@ pub fn rule_frame_shifts(
   let total_frame_shifts_ignored = frame_shifts_ignored.len();
 
   let score = total_frame_shifts as f64 * *config.score_weight;
-  let status = QcStatus::from_score(score);
+  let status = QcStatus::from_score(score, thresholds);
 
   Some(QcResultFrameShifts {
     score,
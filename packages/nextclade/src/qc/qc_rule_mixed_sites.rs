@@ -1,6 +1,6 @@
 use crate::alphabet::letter::Letter;
 use crate::alphabet::nuc::Nuc;
-use crate::qc::qc_config::{QcRulesConfigMixedSites};
+use crate::qc::qc_config::{QcRulesConfigMixedSites, QcStatusThresholds};
 use crate::qc::qc_run::{QcRule, QcStatus};
 use num::traits::clamp_min;
 use serde::{Deserialize, Serialize};
@@ -24,6 +24,7 @@ impl QcRule for QcResultMixedSites {
 pub fn rule_mixed_sites(
   nucleotide_composition: &BTreeMap<Nuc, usize>,
   config: &QcRulesConfigMixedSites,
+  thresholds: &QcStatusThresholds,
 ) -> Option<QcResultMixedSites> {
   if !config.enabled {
     return None;
@@ -40,7 +41,7 @@ pub fn rule_mixed_sites(
     100.0 * (total_mixed_sites as f64 / config.mixed_sites_threshold as f64),
     0.0,
   );
-  let status = QcStatus::from_score(score);
+  let status = QcStatus::from_score(score, thresholds);
 
   Some(QcResultMixedSites {
     score,
@@ -0,0 +1,51 @@
+use crate::coord::range::NucRefGlobalRange;
+use crate::qc::qc_config::{QcRulesConfigRecombinants, QcStatusThresholds};
+use crate::qc::qc_run::{QcRule, QcStatus};
+use crate::tree::tree_find_recombinant_parents::Recombination;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct QcResultRecombinants {
+  pub score: f64,
+  pub status: QcStatus,
+  pub support: usize,
+  pub parents: Vec<String>,
+  pub breakpoints: Vec<NucRefGlobalRange>,
+}
+
+impl QcRule for QcResultRecombinants {
+  fn score(&self) -> f64 {
+    self.score
+  }
+}
+
+pub fn rule_recombinants(
+  recombination: Option<&Recombination>,
+  config: &QcRulesConfigRecombinants,
+  thresholds: &QcStatusThresholds,
+) -> Option<QcResultRecombinants> {
+  if !config.enabled {
+    return None;
+  }
+
+  let Some(recombination) = recombination else {
+    return Some(QcResultRecombinants::default());
+  };
+
+  let score = recombination.support as f64 * *config.score_weight;
+  let status = QcStatus::from_score(score, thresholds);
+
+  Some(QcResultRecombinants {
+    score,
+    status,
+    support: recombination.support,
+    parents: recombination
+      .parents
+      .iter()
+      .map(|parent| parent.node_name.clone())
+      .collect_vec(),
+    breakpoints: recombination.breakpoints.clone(),
+  })
+}
@@ -1,7 +1,7 @@
 use crate::analyze::find_private_nuc_mutations::PrivateNucMutations;
 use crate::analyze::nuc_sub::NucSub;
 use crate::coord::position::PositionLike;
-use crate::qc::qc_config::QcRulesConfigSnpClusters;
+use crate::qc::qc_config::{QcRulesConfigSnpClusters, QcStatusThresholds};
 use crate::qc::qc_run::{QcRule, QcStatus};
 use itertools::Itertools;
 use num::traits::clamp_min;
@@ -37,6 +37,7 @@ impl QcRule for QcResultSnpClusters {
 pub fn rule_snp_clusters(
   private_nuc_mutations: &PrivateNucMutations,
   config: &QcRulesConfigSnpClusters,
+  thresholds: &QcStatusThresholds,
 ) -> Option<QcResultSnpClusters> {
   if !config.enabled {
     return None;
@@ -53,7 +54,7 @@ pub fn rule_snp_clusters(
   let total_snps = clustered_snps.iter().map(|cluster| cluster.number_of_snps).sum();
 
   let score = clamp_min(total_clusters as f64 * *config.score_weight, 0.0);
-  let status = QcStatus::from_score(score);
+  let status = QcStatus::from_score(score, thresholds);
 
   Some(QcResultSnpClusters {
     score,
@@ -1,19 +1,21 @@
-use crate::alphabet::nuc::Nuc;
-use crate::analyze::find_private_nuc_mutations::PrivateNucMutations;
-use crate::qc::qc_config::QcConfig;
+use crate::qc::qc_config::{QcConfig, QcStatusThresholds};
+use crate::qc::qc_rule_band_boundary::{rule_band_boundary, QcResultBandBoundary};
+use crate::qc::qc_rule_custom::{rule_custom, QcResultCustom};
 use crate::qc::qc_rule_frame_shifts::{rule_frame_shifts, QcResultFrameShifts};
 use crate::qc::qc_rule_missing_data::{rule_missing_data, QcResultMissingData};
 use crate::qc::qc_rule_mixed_sites::{rule_mixed_sites, QcResultMixedSites};
 use crate::qc::qc_rule_private_mutations::{rule_private_mutations, QcResultPrivateMutations};
+use crate::qc::qc_rule_recombinants::{rule_recombinants, QcResultRecombinants};
 use crate::qc::qc_rule_snp_clusters::{rule_snp_clusters, QcResultSnpClusters};
 use crate::qc::qc_rule_stop_codons::{rule_stop_codons, QcResultStopCodons};
-use crate::translate::frame_shifts_translate::FrameShift;
 use crate::translate::translate_genes::Translation;
+use crate::types::outputs::NextcladeOutputs;
+use eyre::{Report, WrapErr};
 use num::traits::Pow;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 
-#[derive(Clone, Debug, Serialize, Deserialize, schemars::JsonSchema)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, schemars::JsonSchema)]
 #[serde(rename_all = "lowercase")]
 #[derive(Default)]
 pub enum QcStatus {
@@ -21,6 +23,7 @@ pub enum QcStatus {
   Good,
   Mediocre,
   Bad,
+  Fail,
 }
 
 impl ToString for QcStatus {
@@ -29,16 +32,19 @@ impl ToString for QcStatus {
       QcStatus::Good => "good".to_owned(),
       QcStatus::Mediocre => "mediocre".to_owned(),
       QcStatus::Bad => "bad".to_owned(),
+      QcStatus::Fail => "fail".to_owned(),
     }
   }
 }
 
 impl QcStatus {
-  pub fn from_score(score: f64) -> QcStatus {
-    if (30.0..100.0).contains(&score) {
-      QcStatus::Mediocre
-    } else if score >= 100.0 {
+  pub fn from_score(score: f64, thresholds: &QcStatusThresholds) -> QcStatus {
+    if thresholds.fail.is_some_and(|fail| score >= *fail) {
+      QcStatus::Fail
+    } else if score >= *thresholds.bad {
       QcStatus::Bad
+    } else if score >= *thresholds.mediocre {
+      QcStatus::Mediocre
     } else {
       QcStatus::Good
     }
@@ -54,6 +60,10 @@ pub struct QcResult {
   pub snp_clusters: Option<QcResultSnpClusters>,
   pub frame_shifts: Option<QcResultFrameShifts>,
   pub stop_codons: Option<QcResultStopCodons>,
+  pub recombinants: Option<QcResultRecombinants>,
+  pub band_boundary: Option<QcResultBandBoundary>,
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub custom: BTreeMap<String, QcResultCustom>,
   pub overall_score: f64,
   pub overall_status: QcStatus,
 }
@@ -62,41 +72,170 @@ pub trait QcRule {
   fn score(&self) -> f64;
 }
 
-pub fn qc_run(
-  private_nuc_mutations: &PrivateNucMutations,
-  nucleotide_composition: &BTreeMap<Nuc, usize>,
-  total_missing: usize,
-  translation: &Translation,
-  frame_shifts: &[FrameShift],
-  config: &QcConfig,
-) -> QcResult {
+pub fn qc_run(outputs: &NextcladeOutputs, translation: &Translation, config: &QcConfig) -> Result<QcResult, Report> {
+  let NextcladeOutputs {
+    private_nuc_mutations,
+    nucleotide_composition,
+    total_missing,
+    frame_shifts,
+    recombination,
+    alignment_diagnostics,
+    ..
+  } = outputs;
+
+  let thresholds = &config.status_thresholds;
+
   let mut result = QcResult {
-    missing_data: rule_missing_data(total_missing, &config.missing_data),
-    mixed_sites: rule_mixed_sites(nucleotide_composition, &config.mixed_sites),
-    private_mutations: rule_private_mutations(private_nuc_mutations, &config.private_mutations),
-    snp_clusters: rule_snp_clusters(private_nuc_mutations, &config.snp_clusters),
-    frame_shifts: rule_frame_shifts(frame_shifts, &config.frame_shifts),
-    stop_codons: rule_stop_codons(translation, &config.stop_codons),
+    missing_data: rule_missing_data(*total_missing, &config.missing_data, thresholds),
+    mixed_sites: rule_mixed_sites(nucleotide_composition, &config.mixed_sites, thresholds),
+    private_mutations: rule_private_mutations(private_nuc_mutations, &config.private_mutations, thresholds),
+    snp_clusters: rule_snp_clusters(private_nuc_mutations, &config.snp_clusters, thresholds),
+    frame_shifts: rule_frame_shifts(frame_shifts, &config.frame_shifts, thresholds),
+    stop_codons: rule_stop_codons(translation, &config.stop_codons, thresholds),
+    recombinants: rule_recombinants(recombination.as_ref(), &config.recombinants, thresholds),
+    band_boundary: rule_band_boundary(alignment_diagnostics, &config.band_boundary, thresholds),
+    custom: BTreeMap::new(),
     overall_score: 0.0,
     overall_status: QcStatus::Good,
   };
 
-  result.overall_score += add_score(&result.missing_data);
-  result.overall_score += add_score(&result.mixed_sites);
-  result.overall_score += add_score(&result.private_mutations);
-  result.overall_score += add_score(&result.snp_clusters);
-  result.overall_score += add_score(&result.frame_shifts);
-  result.overall_score += add_score(&result.stop_codons);
+  // Custom rules are expressions over output fields, so they need outputs in JSON form. Only pay for the conversion
+  // if there are any custom rules enabled, and only once for all of them. The outputs don't contain QC results yet, so
+  // the results of the built-in rules, computed above, are added for the custom rules to refer to.
+  if config.custom_rules.iter().any(|rule| rule.enabled) {
+    let mut outputs_json = serde_json::to_value(outputs).wrap_err("When preparing outputs for custom QC rules")?;
+    outputs_json["qc"] = serde_json::to_value(&result).wrap_err("When preparing QC results for custom QC rules")?;
+    for rule in &config.custom_rules {
+      if let Some(rule_result) = rule_custom(&outputs_json, rule, thresholds) {
+        result.custom.insert(rule.name.clone(), rule_result);
+      }
+    }
+  }
+
+  result.overall_score += add_score(result.missing_data.as_ref());
+  result.overall_score += add_score(result.mixed_sites.as_ref());
+  result.overall_score += add_score(result.private_mutations.as_ref());
+  result.overall_score += add_score(result.snp_clusters.as_ref());
+  result.overall_score += add_score(result.frame_shifts.as_ref());
+  result.overall_score += add_score(result.stop_codons.as_ref());
+  result.overall_score += add_score(result.recombinants.as_ref());
+  result.overall_score += add_score(result.band_boundary.as_ref());
+  for rule_result in result.custom.values() {
+    result.overall_score += add_score(Some(rule_result));
+  }
 
-  result.overall_status = QcStatus::from_score(result.overall_score);
+  result.overall_status = QcStatus::from_score(result.overall_score, thresholds);
 
-  result
+  Ok(result)
 }
 
-fn add_score<R: QcRule>(rule_result: &Option<R>) -> f64 {
+fn add_score<R: QcRule>(rule_result: Option<&R>) -> f64 {
   if let Some(rule_result) = rule_result {
     rule_result.score().pow(2.0) * 0.01
   } else {
     0.0
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::io::fasta::FastaRecord;
+  use crate::qc::qc_config::{QcRulesConfigCustom, QcRulesConfigMissingData};
+  use crate::qc::qc_custom_expression::QcExpression;
+  use crate::run::nextclade_wasm::AnalysisOutput;
+  use crate::test_utils::{create_test_nextclade, random_seq};
+  use ordered_float::OrderedFloat;
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+  use std::str::FromStr;
+
+  #[rstest]
+  #[case(0.0, None, QcStatus::Good)]
+  #[case(29.9, None, QcStatus::Good)]
+  #[case(30.0, None, QcStatus::Mediocre)]
+  #[case(99.9, None, QcStatus::Mediocre)]
+  #[case(100.0, None, QcStatus::Bad)]
+  #[case(1000.0, None, QcStatus::Bad)]
+  #[case(199.9, Some(200.0), QcStatus::Bad)]
+  #[case(200.0, Some(200.0), QcStatus::Fail)]
+  fn assigns_qc_status_from_score(#[case] score: f64, #[case] fail: Option<f64>, #[case] expected: QcStatus) {
+    let thresholds = QcStatusThresholds {
+      fail: fail.map(OrderedFloat),
+      ..QcStatusThresholds::default()
+    };
+    assert_eq!(QcStatus::from_score(score, &thresholds), expected);
+  }
+
+  #[test]
+  fn uses_custom_qc_status_thresholds() {
+    let thresholds = QcStatusThresholds {
+      mediocre: OrderedFloat(5.0),
+      bad: OrderedFloat(10.0),
+      fail: Some(OrderedFloat(50.0)),
+    };
+    assert_eq!(QcStatus::from_score(4.0, &thresholds), QcStatus::Good);
+    assert_eq!(QcStatus::from_score(5.0, &thresholds), QcStatus::Mediocre);
+    assert_eq!(QcStatus::from_score(10.0, &thresholds), QcStatus::Bad);
+    assert_eq!(QcStatus::from_score(50.0, &thresholds), QcStatus::Fail);
+  }
+
+  fn custom_rule(name: &str, expression: &str) -> Result<QcRulesConfigCustom, Report> {
+    Ok(QcRulesConfigCustom {
+      name: name.to_owned(),
+      enabled: true,
+      description: None,
+      expression: QcExpression::from_str(expression)?,
+      score_weight: OrderedFloat(1.0),
+    })
+  }
+
+  #[test]
+  #[allow(clippy::float_cmp, clippy::string_slice)]
+  fn runs_custom_qc_rules_on_results_of_builtin_rules() -> Result<(), Report> {
+    let ref_seq = random_seq(11, 1000);
+    let qry_seq = format!("{}{}{}", &ref_seq[..400], "N".repeat(25), &ref_seq[425..]);
+
+    let nextclade = create_test_nextclade("ref", &ref_seq)?;
+    let AnalysisOutput {
+      analysis_result,
+      translation,
+      ..
+    } = nextclade.run(&FastaRecord {
+      index: 0,
+      seq_name: "qry".to_owned(),
+      seq: qry_seq,
+    })?;
+
+    let config = QcConfig {
+      missing_data: QcRulesConfigMissingData {
+        enabled: true,
+        missing_data_threshold: OrderedFloat(1000.0),
+        score_bias: OrderedFloat(0.0),
+      },
+      custom_rules: vec![
+        custom_rule("missing", "qc.missingData.totalMissing")?,
+        // Comparison of a string with a number cannot be evaluated
+        custom_rule("broken", "seqName > 1")?,
+      ],
+      ..QcConfig::default()
+    };
+
+    let result = qc_run(&analysis_result, &translation, &config)?;
+
+    let missing = &result.custom["missing"];
+    assert_eq!(missing.value, 25.0);
+    assert_eq!(missing.error, None);
+
+    let broken = &result.custom["broken"];
+    assert_eq!(broken.score, 0.0);
+    assert!(broken.error.as_ref().is_some_and(|error| error.contains("seqName > 1")));
+
+    // The broken rule does not contribute to the overall score
+    assert_eq!(
+      result.overall_score,
+      add_score(result.missing_data.as_ref()) + add_score(Some(missing))
+    );
+    Ok(())
+  }
+}
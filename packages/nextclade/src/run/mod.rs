@@ -1,4 +1,6 @@
+pub mod nextclade_cache;
 pub mod nextclade_run_one;
+pub mod nextclade_segmented;
 pub mod nextclade_wasm;
 pub mod params;
 pub mod params_general;
@@ -0,0 +1,364 @@
+use crate::alphabet::nuc::{from_nuc_seq, Nuc};
+use crate::gene::gene_map::GeneMap;
+use crate::io::fasta::parse_fasta_header;
+use crate::io::file::{create_file_or_stdout, open_file_or_stdin};
+use crate::io::fs::read_file_to_string;
+use crate::io::json::{json_parse, json_write_impl, JsonPretty};
+use crate::o;
+use crate::run::nextclade_wasm::{AnalysisOutput, Nextclade};
+use crate::utils::datetime::date_iso_now;
+use crate::utils::hash::{hash_json_sha256, hash_sha256};
+use crate::utils::info::this_package_version_str;
+use eyre::{Report, WrapErr};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fs;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::SystemTime;
+
+/// Name of the file describing the analysis context, in every context directory of the cache
+pub const CACHE_CONTEXT_FILENAME: &str = "context.json";
+
+/// Extension of cache entry files. Entries are stored in CBOR format, compressed with zstd. Unlike JSON, CBOR stores
+/// floating point numbers in binary form, so that the results read from the cache are exactly the same as computed.
+pub const CACHE_ENTRY_EXT: &str = "cbor.zst";
+
+/// Describes everything, except the query sequence itself, that analysis results depend on.
+///
+/// Results computed within one context are stored in a separate directory of the cache, named after the hash of the
+/// context. Changing any of the inputs or parameters therefore results in a fresh, empty cache.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct AnalysisCacheContext {
+  pub nextclade_version: String,
+  pub dataset_name: String,
+  pub dataset_tag: Option<String>,
+  pub params_hash: String,
+  #[serde(default)]
+  pub created_at: String,
+}
+
+impl AnalysisCacheContext {
+  pub fn from_nextclade(nextclade: &Nextclade) -> Result<Self, Report> {
+    // Dataset version tag alone is not sufficient: any of the dataset files can be overridden from command line.
+    // So all the inputs are hashed as well.
+    let params_hash = hash_json_sha256(&json!({
+      "refSeq": from_nuc_seq(&nextclade.ref_seq),
+      "geneMap": nextclade.gene_map,
+      "tree": nextclade.graph,
+      "virusProperties": nextclade.virus_properties,
+      "primers": nextclade.primers,
+      "params": nextclade.params,
+    }))
+    .wrap_err("When calculating hash of analysis parameters")?;
+
+    Ok(Self {
+      nextclade_version: this_package_version_str().to_owned(),
+      dataset_name: nextclade.dataset_name.clone(),
+      dataset_tag: nextclade
+        .virus_properties
+        .version
+        .as_ref()
+        .map(|version| version.tag.clone()),
+      params_hash,
+      created_at: date_iso_now(),
+    })
+  }
+
+  /// Hash identifying the context. Creation time is not part of the identity.
+  pub fn hash(&self) -> Result<String, Report> {
+    hash_json_sha256(&json!({
+      "nextcladeVersion": self.nextclade_version,
+      "datasetName": self.dataset_name,
+      "datasetTag": self.dataset_tag,
+      "paramsHash": self.params_hash,
+    }))
+  }
+}
+
+/// Content of a cache entry file
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnalysisCacheEntry {
+  pub seq_hash: String,
+  pub output: AnalysisOutput,
+}
+
+/// On-disk cache of analysis results, keyed by hash of the query sequence.
+///
+/// Cache failures are never fatal: they are reported as warnings and the analysis is recomputed.
+#[derive(Clone, Debug)]
+pub struct AnalysisCache {
+  dir: PathBuf,
+}
+
+impl AnalysisCache {
+  /// Opens cache for the given analysis context, creating the context directory if necessary
+  pub fn open(cache_dir: impl AsRef<Path>, context: &AnalysisCacheContext) -> Result<Self, Report> {
+    let cache_dir = cache_dir.as_ref();
+    let dir = cache_dir.join(context.hash()?);
+
+    let context_file = dir.join(CACHE_CONTEXT_FILENAME);
+    if !context_file.exists() {
+      fs::create_dir_all(&dir).wrap_err_with(|| format!("When creating cache directory {dir:#?}"))?;
+      write_atomic(&context_file, |writer| {
+        json_write_impl(writer, context, JsonPretty(false))
+      })?;
+    }
+
+    Ok(Self { dir })
+  }
+
+  pub fn dir(&self) -> &Path {
+    &self.dir
+  }
+
+  /// Retrieves results for a sequence from the cache, if present.
+  ///
+  /// Note that the stored results contain index and name of the sequence they were originally computed for. Use
+  /// `cache_output_rename()` to adjust them.
+  pub fn get(&self, qry_seq: &[Nuc]) -> Option<AnalysisOutput> {
+    let seq_hash = hash_seq(qry_seq);
+    let filepath = cache_entry_path(&self.dir, &seq_hash);
+    if !filepath.exists() {
+      return None;
+    }
+
+    match read_cache_entry(&filepath) {
+      Ok(entry) if entry.seq_hash == seq_hash => {
+        // Update modification time, such that pruning by age removes least recently used entries
+        File::options()
+          .write(true)
+          .open(&filepath)
+          .and_then(|file| file.set_modified(SystemTime::now()))
+          .ok();
+        Some(entry.output)
+      }
+      Ok(_) => {
+        warn!("Analysis cache entry {filepath:#?} does not match its sequence hash. Ignoring it.");
+        None
+      }
+      Err(report) => {
+        warn!("Unable to read analysis cache entry {filepath:#?}. Ignoring it. {report:#}");
+        None
+      }
+    }
+  }
+
+  /// Stores results for a sequence in the cache
+  pub fn put(&self, qry_seq: &[Nuc], output: &AnalysisOutput) {
+    let seq_hash = hash_seq(qry_seq);
+    let filepath = cache_entry_path(&self.dir, &seq_hash);
+    let entry = AnalysisCacheEntry {
+      seq_hash,
+      output: output.clone(),
+    };
+    let result = write_atomic(&filepath, |writer| {
+      ciborium::into_writer(&entry, writer).wrap_err("When serializing cache entry")
+    });
+    if let Err(report) = result {
+      warn!("Unable to write analysis cache entry {filepath:#?}. {report:#}");
+    }
+  }
+}
+
+pub fn hash_seq(qry_seq: &[Nuc]) -> String {
+  hash_sha256(from_nuc_seq(qry_seq))
+}
+
+/// Path to the cache entry file. Entries are spread across subdirectories, to avoid too many files in one directory.
+pub fn cache_entry_path(context_dir: impl AsRef<Path>, seq_hash: &str) -> PathBuf {
+  let prefix = seq_hash.get(0..2).unwrap_or(seq_hash);
+  context_dir
+    .as_ref()
+    .join(prefix)
+    .join(format!("{seq_hash}.{CACHE_ENTRY_EXT}"))
+}
+
+pub fn read_cache_entry(filepath: impl AsRef<Path>) -> Result<AnalysisCacheEntry, Report> {
+  let filepath = filepath.as_ref();
+  let reader = open_file_or_stdin(&Some(filepath))?;
+  ciborium::from_reader(reader).wrap_err_with(|| format!("When parsing cache entry {filepath:#?}"))
+}
+
+pub fn read_cache_context(context_dir: impl AsRef<Path>) -> Result<AnalysisCacheContext, Report> {
+  let filepath = context_dir.as_ref().join(CACHE_CONTEXT_FILENAME);
+  json_parse(read_file_to_string(&filepath)?).wrap_err_with(|| format!("When parsing cache context {filepath:#?}"))
+}
+
+/// File found in a context directory of the cache
+#[derive(Clone, Debug)]
+pub struct AnalysisCacheFile {
+  pub path: PathBuf,
+  pub size: u64,
+  pub modified: SystemTime,
+}
+
+/// Lists context directories of the cache
+pub fn cache_context_dirs(cache_dir: impl AsRef<Path>) -> Result<Vec<PathBuf>, Report> {
+  let cache_dir = cache_dir.as_ref();
+  let mut dirs = vec![];
+  for entry in fs::read_dir(cache_dir).wrap_err_with(|| format!("When reading cache directory {cache_dir:#?}"))? {
+    let path = entry?.path();
+    if path.is_dir() {
+      dirs.push(path);
+    }
+  }
+  dirs.sort();
+  Ok(dirs)
+}
+
+/// Lists entry files (including temporary files) of a context directory of the cache
+pub fn cache_context_files(context_dir: impl AsRef<Path>) -> Result<Vec<AnalysisCacheFile>, Report> {
+  let context_dir = context_dir.as_ref();
+  let mut files = vec![];
+  for prefix_dir in
+    fs::read_dir(context_dir).wrap_err_with(|| format!("When reading cache directory {context_dir:#?}"))?
+  {
+    let prefix_dir = prefix_dir?.path();
+    if !prefix_dir.is_dir() {
+      continue;
+    }
+    for entry in fs::read_dir(&prefix_dir).wrap_err_with(|| format!("When reading cache directory {prefix_dir:#?}"))? {
+      let entry = entry?;
+      let metadata = entry.metadata()?;
+      if metadata.is_file() {
+        files.push(AnalysisCacheFile {
+          path: entry.path(),
+          size: metadata.len(),
+          modified: metadata.modified()?,
+        });
+      }
+    }
+  }
+  files.sort_by(|a, b| a.path.cmp(&b.path));
+  Ok(files)
+}
+
+/// Whether the file is a temporary file of an unfinished (e.g. interrupted) write
+pub fn is_cache_temp_file(filepath: impl AsRef<Path>) -> bool {
+  filepath
+    .as_ref()
+    .file_name()
+    .and_then(|name| name.to_str())
+    .map_or(false, |name| name.contains(".tmp-"))
+}
+
+/// Writes a file such that concurrent readers never observe partially written content: the data is written into a
+/// temporary file in the same directory, which is then renamed into place.
+fn write_atomic(filepath: &Path, write: impl FnOnce(&mut dyn Write) -> Result<(), Report>) -> Result<(), Report> {
+  static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+  let filename = filepath.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+  let (stem, ext) = filename.split_once('.').unwrap_or((filename, ""));
+  let tmp_path = filepath.with_file_name(format!(
+    "{stem}.tmp-{}-{}.{ext}",
+    std::process::id(),
+    COUNTER.fetch_add(1, Ordering::Relaxed)
+  ));
+
+  let result = (|| {
+    let mut writer = create_file_or_stdout(&tmp_path)?;
+    write(&mut writer)?;
+    writer.flush()?;
+    drop(writer);
+    fs::rename(&tmp_path, filepath).wrap_err_with(|| format!("When renaming {tmp_path:#?} to {filepath:#?}"))
+  })();
+
+  if result.is_err() {
+    fs::remove_file(&tmp_path).ok();
+  }
+  result
+}
+
+/// Adjusts results retrieved from the cache to the sequence they are now used for: the same sequence might appear
+/// under a different name and at a different position in the input.
+pub fn cache_output_rename(output: &mut AnalysisOutput, index: usize, seq_name: &str) {
+  let result = &mut output.analysis_result;
+
+  let old_prefix = format!("#{} '{}'", result.index, result.seq_name);
+  let new_prefix = format!("#{index} '{seq_name}'");
+  for warning in &mut result.warnings {
+    warning.warning = warning.warning.replace(&old_prefix, &new_prefix);
+  }
+
+  let (seq_id, seq_desc) = parse_fasta_header(seq_name);
+  annotation_rename(&mut result.annotation, index, &seq_id);
+
+  result.index = index;
+  result.seq_name = seq_name.to_owned();
+  result.seq_id = seq_id;
+  result.seq_desc = seq_desc;
+}
+
+/// Rewrites the parts of the query annotation which depend on sequence index and ID.
+/// See `calculate_qry_annotation()`.
+fn annotation_rename(gene_map: &mut GeneMap, index: usize, seq_id: &str) {
+  let seq_index = vec![index.to_string()];
+  for gene in &mut gene_map.genes {
+    let gene_id = format!("Gene-{}-{}", index, gene.id);
+    gene.gff_seqid = Some(seq_id.to_owned());
+    gene.attributes.insert(o!("seq_index"), seq_index.clone());
+    gene.attributes.insert(o!("ID"), vec![gene_id.clone()]);
+
+    for cds in &mut gene.cdses {
+      cds.attributes.insert(o!("seq_index"), seq_index.clone());
+
+      for seg in &mut cds.segments {
+        seg.gff_seqid = Some(seq_id.to_owned());
+        seg
+          .attributes
+          .insert(o!("ID"), vec![format!("CDS-{}-{}", index, seg.id)]);
+        seg.attributes.insert(o!("seq_index"), seq_index.clone());
+        seg.attributes.insert(o!("Parent"), vec![gene_id.clone()]);
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::io::fasta::FastaRecord;
+  use crate::io::json::json_stringify;
+  use crate::test_utils::{create_test_nextclade, random_seq};
+  use pretty_assertions::assert_eq;
+
+  #[test]
+  #[allow(clippy::string_slice)]
+  fn returns_cached_results_for_the_same_sequence_under_different_name() -> Result<(), Report> {
+    let cache_dir = std::env::temp_dir().join(format!("nextclade-cache-test-{}", std::process::id()));
+
+    let ref_seq = random_seq(1, 1000);
+    let qry_seq = format!("{}TT{}", &ref_seq[..400], &ref_seq[402..]);
+    let record = |index: usize, seq_name: &str| FastaRecord {
+      index,
+      seq_name: seq_name.to_owned(),
+      seq: qry_seq.clone(),
+    };
+
+    let mut nextclade = create_test_nextclade("ref", &ref_seq)?;
+    let context = AnalysisCacheContext::from_nextclade(&nextclade)?;
+    let cache = AnalysisCache::open(&cache_dir, &context)?;
+    let entry_path = cache_entry_path(cache.dir(), &hash_sha256(&qry_seq));
+    nextclade.cache = Some(cache);
+
+    nextclade.run(&record(0, "first"))?;
+    assert!(entry_path.exists());
+
+    let actual = nextclade.run(&record(5, "second description"))?;
+    let expected = create_test_nextclade("ref", &ref_seq)?.run(&record(5, "second description"))?;
+
+    fs::remove_dir_all(&cache_dir)?;
+
+    assert_eq!(
+      json_stringify(&actual, JsonPretty(true))?,
+      json_stringify(&expected, JsonPretty(true))?
+    );
+    Ok(())
+  }
+}
@@ -14,6 +14,8 @@ use crate::analyze::find_aa_motifs_changes::find_aa_motifs_changes;
 use crate::analyze::find_clade_founder::{
   find_clade_founder, find_clade_node_attrs_founders, CladeNodeAttrFounderInfo,
 };
+use crate::analyze::find_nuc_motifs::find_nuc_motifs;
+use crate::analyze::find_nuc_motifs_changes::find_nuc_motifs_changes;
 use crate::analyze::find_private_aa_mutations::{
   find_private_aa_mutations, FindPrivateAaMutationsParams, PrivateAaMutations,
 };
@@ -26,11 +28,13 @@ use crate::analyze::letter_composition::get_letter_composition;
 use crate::analyze::letter_ranges::{
   find_aa_letter_ranges, find_letter_ranges, find_letter_ranges_by, CdsAaRange, NucRange,
 };
+use crate::analyze::mutational_spectrum::find_mutational_spectra;
 use crate::analyze::nuc_alignment::NucAlignment;
 use crate::analyze::nuc_changes::{find_nuc_changes, FindNucChangesOutput};
 use crate::analyze::nuc_del::NucDelRange;
 use crate::analyze::pcr_primer_changes::get_pcr_primer_changes;
 use crate::analyze::phenotype::calculate_phenotype;
+use crate::analyze::protein_changes::{find_protein_changes, FindProteinChangesOutput};
 use crate::analyze::virus_properties::PhenotypeData;
 use crate::coord::coord_map_global::CoordMapGlobal;
 use crate::coord::position::PositionLike;
@@ -42,14 +46,19 @@ use crate::graph::node::GraphNodeKey;
 use crate::io::fasta::parse_fasta_header;
 use crate::io::gff3_writer::GFF_ATTRIBUTES_TO_REMOVE;
 use crate::o;
-use crate::qc::qc_run::qc_run;
+use crate::qc::qc_config::QcRulesConfigRecombinants;
+use crate::qc::qc_run::{qc_run, QcResult};
+use crate::run::nextclade_cache::cache_output_rename;
 use crate::run::nextclade_wasm::{AnalysisOutput, Nextclade};
 use crate::translate::aa_alignment_ranges::{gather_aa_alignment_ranges, GatherAaAlignmentRangesResult};
 use crate::translate::frame_shifts_flatten::frame_shifts_flatten;
 use crate::translate::frame_shifts_translate::FrameShift;
 use crate::translate::translate_genes::{translate_genes, Translation};
 use crate::tree::tree_find_ancestors_of_interest::{graph_find_ancestors_of_interest, AncestralSearchResult};
-use crate::tree::tree_find_nearest_node::graph_find_nearest_nodes;
+use crate::tree::tree_find_nearest_node::{
+  graph_find_nearest_nodes, graph_find_placement_uncertainty, PlacementUncertainty,
+};
+use crate::tree::tree_find_recombinant_parents::{graph_find_recombinant_parents, Recombination};
 use crate::types::outputs::{NextcladeOutputs, PeptideWarning, PhenotypeValue};
 use crate::utils::num::float_collapse_zero;
 use eyre::Report;
@@ -66,6 +75,9 @@ struct NextcladeResultWithAa {
   total_aminoacid_substitutions: usize,
   total_aminoacid_deletions: usize,
   total_aminoacid_insertions: usize,
+  protein_aa_substitutions: Vec<AaSub>,
+  protein_aa_deletions: Vec<AaDel>,
+  protein_aa_insertions: Vec<AaIns>,
   nuc_to_aa_muts: BTreeMap<String, Vec<AaSub>>,
   missing_genes: Vec<String>,
   warnings: Vec<PeptideWarning>,
@@ -90,6 +102,8 @@ struct NextcladeResultWithGraph {
   nearest_node_id: GraphNodeKey,
   nearest_node_name: String,
   nearest_nodes: Option<Vec<String>>,
+  placement_uncertainty: Option<PlacementUncertainty>,
+  recombination: Option<Recombination>,
   ref_node_search_results: Vec<AncestralSearchResult>,
   relative_nuc_mutations: Vec<RelativeNucMutations>,
   relative_aa_mutations: Vec<RelativeAaMutations>,
@@ -102,6 +116,26 @@ pub fn nextclade_run_one(
   seq_name: &str,
   qry_seq: &[Nuc],
   state: &Nextclade,
+) -> Result<AnalysisOutput, Report> {
+  let Some(cache) = &state.cache else {
+    return nextclade_run_one_impl(index, seq_name, qry_seq, state);
+  };
+
+  if let Some(mut output) = cache.get(qry_seq) {
+    cache_output_rename(&mut output, index, seq_name);
+    return Ok(output);
+  }
+
+  let output = nextclade_run_one_impl(index, seq_name, qry_seq, state)?;
+  cache.put(qry_seq, &output);
+  Ok(output)
+}
+
+fn nextclade_run_one_impl(
+  index: usize,
+  seq_name: &str,
+  qry_seq: &[Nuc],
+  state: &Nextclade,
 ) -> Result<AnalysisOutput, Report> {
   let Nextclade {
     dataset_name,
@@ -111,6 +145,7 @@ pub fn nextclade_run_one(
     gap_open_close_nuc,
     virus_properties,
     params,
+    nuc_motifs_ref,
     gene_map,
     gap_open_close_aa,
     ref_translation,
@@ -135,13 +170,24 @@ pub fn nextclade_run_one(
 
   let stripped = insertions_strip(&alignment.qry_seq, &alignment.ref_seq);
   let alignment_score = alignment.alignment_score;
+  let alignment_mode = alignment.alignment_mode;
+  let alignment_diagnostics = alignment.diagnostics.clone();
 
   let FindNucChangesOutput {
     substitutions,
     deletions,
+    ambiguous_sites,
     alignment_range,
   } = find_nuc_changes(&stripped.qry_seq, ref_seq);
 
+  // Ambiguous sites are only reported, and taken into account in tree placement, in ambiguity-aware mode
+  let ambiguous_sites = if params.general.ambiguity_aware {
+    ambiguous_sites
+  } else {
+    vec![]
+  };
+  let total_ambiguous_sites = ambiguous_sites.len();
+
   let aln = NucAlignment::new(ref_seq, &stripped.qry_seq, &alignment_range);
 
   let total_substitutions = substitutions.len();
@@ -161,6 +207,15 @@ pub fn nextclade_run_one(
   let pcr_primer_changes = get_pcr_primer_changes(&substitutions, primers);
   let total_pcr_primer_changes = pcr_primer_changes.iter().map(|pc| pc.substitutions.len()).sum();
 
+  let nuc_motifs = find_nuc_motifs(&virus_properties.nuc_motifs, &stripped.qry_seq, &alignment_range)?;
+  let nuc_motifs_changes = find_nuc_motifs_changes(
+    nuc_motifs_ref,
+    &nuc_motifs,
+    ref_seq,
+    &stripped.qry_seq,
+    &alignment_range,
+  );
+
   let total_aligned_nucs = alignment_range.len();
   let total_covered_nucs = total_aligned_nucs - total_missing - total_non_acgtns;
   let coverage = total_covered_nucs as f64 / ref_seq.len() as f64;
@@ -175,6 +230,9 @@ pub fn nextclade_run_one(
     total_aminoacid_substitutions,
     total_aminoacid_deletions,
     total_aminoacid_insertions,
+    protein_aa_substitutions,
+    protein_aa_deletions,
+    protein_aa_insertions,
     nuc_to_aa_muts,
     missing_genes,
     warnings,
@@ -223,6 +281,14 @@ pub fn nextclade_run_one(
         });
       }
 
+      if alignment.circular_rotation > 0 {
+        let circular_rotation = alignment.circular_rotation;
+        warnings.push(PeptideWarning {
+          cds_name: "nuc".to_owned(),
+          warning: format!("When processing sequence #{index} '{seq_name}': Sequence is rotated: The sequence starts at a different origin than the circular reference, so its first {circular_rotation} nucleotides were moved to its end before alignment. Aligned sequence is derived from the rotated sequence. Query annotation is relative to the input sequence, and features which cross its origin end past its end. All other coordinates are relative to the reference."),
+        });
+      }
+
       warnings
     };
 
@@ -242,6 +308,12 @@ pub fn nextclade_run_one(
     let total_aminoacid_deletions = aa_deletions.len();
     let total_aminoacid_insertions = aa_insertions.len();
 
+    let FindProteinChangesOutput {
+      protein_aa_substitutions,
+      protein_aa_deletions,
+      protein_aa_insertions,
+    } = find_protein_changes(gene_map, &aa_substitutions, &aa_deletions, &aa_insertions);
+
     let unknown_aa_ranges = find_aa_letter_ranges(&translation, Aa::X);
     let total_unknown_aa = unknown_aa_ranges.iter().map(|r| r.length).sum();
 
@@ -295,6 +367,9 @@ pub fn nextclade_run_one(
       total_aminoacid_substitutions,
       total_aminoacid_deletions,
       total_aminoacid_insertions,
+      protein_aa_substitutions,
+      protein_aa_deletions,
+      protein_aa_insertions,
       nuc_to_aa_muts,
       missing_genes,
       warnings,
@@ -326,8 +401,11 @@ pub fn nextclade_run_one(
     nearest_node_id,
     nearest_node_name,
     nearest_nodes,
+    placement_uncertainty,
+    recombination,
   } = if let Some(graph) = graph {
-    let nearest_node_candidates = graph_find_nearest_nodes(graph, &substitutions, &missing, &alignment_range)?;
+    let nearest_node_candidates =
+      graph_find_nearest_nodes(graph, &substitutions, &missing, &ambiguous_sites, &alignment_range)?;
     let nearest_node_id = nearest_node_candidates[0].node_key;
     let nearest_node = graph.get_node(nearest_node_id)?.payload();
     let nearest_node_name = nearest_node.name.clone();
@@ -336,11 +414,34 @@ pub fn nextclade_run_one(
       nearest_node_candidates
         .iter()
         // Choose all nodes with distance equal to the distance of the nearest node
-        .filter(|n| n.distance == nearest_node_candidates[0].distance)
+        .filter(|n| n.distance.total_cmp(&nearest_node_candidates[0].distance).is_eq())
         .map(|n| Ok(graph.get_node(n.node_key)?.payload().name.clone()))
         .collect::<Result<Vec<String>, Report>>()?,
     );
 
+    let placement_uncertainty = Some(graph_find_placement_uncertainty(
+      graph,
+      &nearest_node_candidates,
+      *params.general.placement_mismatch_likelihood,
+      *params.general.clade_confidence_threshold,
+    )?);
+
+    // Search parameters of the recombinants QC rule are used even if the rule itself is disabled: the rule only scores
+    // the result of the search
+    let default_recombinants_config = QcRulesConfigRecombinants::default();
+    let recombinants_config = virus_properties
+      .qc
+      .as_ref()
+      .map_or(&default_recombinants_config, |qc| &qc.recombinants);
+    let recombination = graph_find_recombinant_parents(
+      graph,
+      &substitutions,
+      &missing,
+      &ambiguous_sites,
+      &alignment_range,
+      recombinants_config,
+    )?;
+
     let clade = nearest_node.clade();
 
     let clade_node_attr_descs = graph.data.meta.clade_node_attr_descs();
@@ -418,6 +519,8 @@ pub fn nextclade_run_one(
       nearest_node_id,
       nearest_node_name,
       nearest_nodes,
+      placement_uncertainty,
+      recombination,
     }
   } else {
     NextcladeResultWithGraph::default()
@@ -426,22 +529,11 @@ pub fn nextclade_run_one(
   let aa_motifs = find_aa_motifs(&virus_properties.aa_motifs, &translation)?;
   let aa_motifs_changes = find_aa_motifs_changes(aa_motifs_ref, &aa_motifs, ref_translation, &translation)?;
 
-  let qc = virus_properties
-    .qc
-    .as_ref()
-    .map(|qc_config| {
-      qc_run(
-        &private_nuc_mutations,
-        &nucleotide_composition,
-        total_missing,
-        &translation,
-        &frame_shifts,
-        qc_config,
-      )
-    })
-    .unwrap_or_default();
+  let mutational_spectra =
+    find_mutational_spectra(ref_seq, &substitutions, &private_nuc_mutations.private_substitutions);
 
   let is_reverse_complement = alignment.is_reverse_complement;
+  let circular_rotation = alignment.circular_rotation;
 
   let len_unaligned = qry_seq.len();
   let len_aligned = alignment.qry_seq.len();
@@ -455,75 +547,95 @@ pub fn nextclade_run_one(
     &coord_map_global,
     &alignment_range,
     is_reverse_complement,
+    circular_rotation,
   )?;
 
+  let mut analysis_result = NextcladeOutputs {
+    index,
+    seq_name: seq_name.to_owned(),
+    seq_id,
+    seq_desc,
+    len_unaligned,
+    len_aligned,
+    len_stripped,
+    ref_name: ref_record.seq_name.clone(),
+    dataset_name: dataset_name.clone(),
+    substitutions,
+    total_substitutions,
+    deletions,
+    total_deletions,
+    insertions,
+    total_insertions,
+    missing,
+    total_missing,
+    non_acgtns,
+    total_non_acgtns,
+    ambiguous_sites,
+    total_ambiguous_sites,
+    nucleotide_composition,
+    frame_shifts,
+    total_frame_shifts,
+    aa_substitutions,
+    total_aminoacid_substitutions,
+    aa_deletions,
+    total_aminoacid_deletions,
+    aa_insertions,
+    total_aminoacid_insertions,
+    protein_aa_substitutions,
+    protein_aa_deletions,
+    protein_aa_insertions,
+    unknown_aa_ranges,
+    total_unknown_aa,
+    aa_changes_groups,
+    nuc_to_aa_muts,
+    alignment_range,
+    alignment_score,
+    alignment_mode,
+    alignment_diagnostics,
+    aa_alignment_ranges,
+    aa_unsequenced_ranges,
+    pcr_primer_changes,
+    total_pcr_primer_changes,
+    warnings,
+    missing_cdses: missing_genes,
+    coverage,
+    cds_coverage,
+    aa_motifs,
+    aa_motifs_changes,
+    nuc_motifs,
+    nuc_motifs_changes,
+    mutational_spectra,
+    recombination,
+    qc: QcResult::default(),
+    clade,
+    private_nuc_mutations,
+    private_aa_mutations,
+    clade_founder_info,
+    clade_node_attr_founder_info,
+    ref_nodes: ref_nodes.to_owned(),
+    ref_node_search_results,
+    relative_nuc_mutations,
+    relative_aa_mutations,
+    phenotype_values,
+    divergence,
+    custom_node_attributes,
+    nearest_node_id,
+    nearest_node_name,
+    nearest_nodes,
+    placement_uncertainty,
+    is_reverse_complement,
+    circular_rotation,
+    annotation,
+  };
+
+  if let Some(qc_config) = &virus_properties.qc {
+    analysis_result.qc = qc_run(&analysis_result, &translation, qc_config)?;
+  }
+
   Ok(AnalysisOutput {
     query: stripped.qry_seq,
     translation,
-    analysis_result: NextcladeOutputs {
-      index,
-      seq_name: seq_name.to_owned(),
-      seq_id,
-      seq_desc,
-      len_unaligned,
-      len_aligned,
-      len_stripped,
-      ref_name: ref_record.seq_name.clone(),
-      dataset_name: dataset_name.clone(),
-      substitutions,
-      total_substitutions,
-      deletions,
-      total_deletions,
-      insertions,
-      total_insertions,
-      missing,
-      total_missing,
-      non_acgtns,
-      total_non_acgtns,
-      nucleotide_composition,
-      frame_shifts,
-      total_frame_shifts,
-      aa_substitutions,
-      total_aminoacid_substitutions,
-      aa_deletions,
-      total_aminoacid_deletions,
-      aa_insertions,
-      total_aminoacid_insertions,
-      unknown_aa_ranges,
-      total_unknown_aa,
-      aa_changes_groups,
-      nuc_to_aa_muts,
-      alignment_range,
-      alignment_score,
-      aa_alignment_ranges,
-      aa_unsequenced_ranges,
-      pcr_primer_changes,
-      total_pcr_primer_changes,
-      warnings,
-      missing_cdses: missing_genes,
-      coverage,
-      cds_coverage,
-      aa_motifs,
-      aa_motifs_changes,
-      qc,
-      clade,
-      private_nuc_mutations,
-      private_aa_mutations,
-      clade_founder_info,
-      clade_node_attr_founder_info,
-      ref_nodes: ref_nodes.to_owned(),
-      ref_node_search_results,
-      relative_nuc_mutations,
-      relative_aa_mutations,
-      phenotype_values,
-      divergence,
-      custom_node_attributes,
-      nearest_node_id,
-      nearest_node_name,
-      nearest_nodes,
-      is_reverse_complement,
-      annotation,
-    },
+    analysis_result,
   })
 }
 
@@ -536,6 +648,7 @@ pub fn calculate_qry_annotation(
   coord_map_global: &CoordMapGlobal,
   alignment_range: &NucRefGlobalRange,
   is_reverse_complement: bool,
+  circular_rotation: usize,
 ) -> Result<GeneMap, Report> {
   let mut gene_map = gene_map.clone();
 
@@ -547,6 +660,10 @@ pub fn calculate_qry_annotation(
     additional_attributes.insert(o!("is_reverse_complement"), vec![o!("true")]);
   }
 
+  if circular_rotation > 0 {
+    additional_attributes.insert(o!("circular_rotation"), vec![circular_rotation.to_string()]);
+  }
+
   for gene in &mut gene_map.genes {
     let gene_id = format!("Gene-{}-{}", index, gene.id);
 
@@ -575,13 +692,8 @@ pub fn calculate_qry_annotation(
     let included_range = intersect(alignment_range, &gene.range);
 
     // Convert included segment range from reference to query coordinates
-    let mut range = coord_map_global.ref_to_qry_range(&included_range);
-    if is_reverse_complement {
-      let begin = seq_len.saturating_sub(range.end.as_usize()).into();
-      let end = seq_len.saturating_sub(range.begin.as_usize()).into();
-      range = NucRefGlobalRange::new(begin, end);
-    }
-    gene.range = range;
+    let range = coord_map_global.ref_to_qry_range(&included_range);
+    gene.range = qry_range_to_input_coords(&range, seq_len, is_reverse_complement, circular_rotation);
 
     for cds in &mut gene.cdses {
       cds.attributes.extend(additional_attributes.clone());
@@ -617,21 +729,54 @@ pub fn calculate_qry_annotation(
         calculate_truncation(&included_range, seg)?;
 
         // Convert included segment range from reference to query coordinates
-        let mut range = coord_map_global.ref_to_qry_range(&included_range);
+        let range = coord_map_global.ref_to_qry_range(&included_range);
+        seg.range = qry_range_to_input_coords(&range, seq_len, is_reverse_complement, circular_rotation);
         if is_reverse_complement {
-          let begin = seq_len.saturating_sub(range.end.as_usize()).into();
-          let end = seq_len.saturating_sub(range.begin.as_usize()).into();
-          range = NucRefGlobalRange::new(begin, end);
           seg.strand = seg.strand.inverted();
         }
-        seg.range = range;
       }
 
       // Remove empty CDS segments
       cds.segments.retain(|seg| !seg.is_empty());
 
-      // TODO: once we support proteins, modify protein coordinates as well
-      // for protein in &mut cds.proteins {}
+      // Link proteins to the first segment of their parent CDS
+      let cds_segment_id = cds.segments.first().map(|seg| format!("CDS-{}-{}", index, seg.id));
+
+      for protein in &mut cds.proteins {
+        for seg in &mut protein.segments {
+          let segment_id = format!("Protein-{}-{}", index, seg.id);
+          seg.attributes.insert(o!("ID"), vec![segment_id]);
+
+          seg.gff_seqid = Some(seq_id.to_owned());
+          seg.attributes.extend(additional_attributes.clone());
+
+          GFF_ATTRIBUTES_TO_REMOVE.iter().for_each(|attr| {
+            seg.attributes.remove(*attr);
+          });
+
+          if let Some(cds_segment_id) = &cds_segment_id {
+            seg.attributes.insert(o!("Parent"), vec![cds_segment_id.clone()]);
+          }
+
+          // Add Name if not present
+          if !seg.attributes.contains_key("Name") {
+            seg.attributes.insert(o!("Name"), vec![seg.name.clone()]);
+          }
+
+          // Take only the part of the segment which is within the alignment range
+          let included_range = intersect(alignment_range, &seg.range);
+
+          // Convert included segment range from reference to query coordinates
+          let range = coord_map_global.ref_to_qry_range(&included_range);
+          seg.range = qry_range_to_input_coords(&range, seq_len, is_reverse_complement, circular_rotation);
+        }
+
+        // Remove empty protein segments
+        protein.segments.retain(|seg| !seg.is_empty());
+      }
+
+      // Remove empty proteins
+      cds.proteins.retain(|protein| !protein.is_empty());
     }
 
     // Remove empty CDS
@@ -644,6 +789,26 @@ pub fn calculate_qry_annotation(
   Ok(gene_map)
 }
 
+/// Converts range in coordinates of the query sequence as it was aligned, i.e. possibly reverse-complemented and then
+/// rotated, back into coordinates of the input query sequence. Ranges of a rotated circular query which cross the
+/// origin of the input sequence end past the end of the sequence, as in GFF3 for circular genomes.
+fn qry_range_to_input_coords(
+  range: &NucRefGlobalRange,
+  seq_len: usize,
+  is_reverse_complement: bool,
+  circular_rotation: usize,
+) -> NucRefGlobalRange {
+  if seq_len == 0 {
+    return range.clone();
+  }
+  let len = range.len();
+  let mut begin = (range.begin.as_usize() + circular_rotation) % seq_len;
+  if is_reverse_complement {
+    begin = (2 * seq_len).saturating_sub(begin + len) % seq_len;
+  }
+  NucRefGlobalRange::from_usize(begin, begin + len)
+}
+
 fn calculate_truncation(included_range: &NucRefGlobalRange, seg: &mut CdsSegment) -> Result<(), Report> {
   let included_range = included_range.to_std();
   let seg_range = seg.range.to_std();
@@ -686,3 +851,67 @@ fn calculate_truncation(included_range: &NucRefGlobalRange, seg: &mut CdsSegment
 
   Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::alphabet::nuc::to_nuc_seq;
+  use crate::test_utils::random_seq;
+  use eyre::Report;
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+
+  #[rstest]
+  #[case(false, 0, (100, 200), (100, 200))]
+  #[case(true, 0, (100, 200), (800, 900))]
+  #[case(false, 300, (100, 200), (400, 500))]
+  #[case(false, 300, (650, 800), (950, 1100))]
+  #[case(true, 300, (650, 800), (900, 1050))]
+  #[case(true, 300, (800, 950), (750, 900))]
+  fn converts_qry_range_to_input_coords(
+    #[case] is_reverse_complement: bool,
+    #[case] circular_rotation: usize,
+    #[case] range: (usize, usize),
+    #[case] expected: (usize, usize),
+  ) {
+    let range = NucRefGlobalRange::from_usize(range.0, range.1);
+    let actual = qry_range_to_input_coords(&range, 1000, is_reverse_complement, circular_rotation);
+    assert_eq!(actual, NucRefGlobalRange::from_usize(expected.0, expected.1));
+  }
+
+  #[rstest]
+  fn annotates_feature_crossing_origin_of_rotated_query() -> Result<(), Report> {
+    let gene_map = GeneMap::from_str(
+      r#"##gff-version 3
+##sequence-region ref 1 3000
+ref	feature	gene	901	1200	.	+	.	Name=G;ID=1
+ref	feature	CDS	901	1200	.	+	.	Name=G;Parent=1
+"#,
+    )?;
+
+    // The input query starts 1000 nucleotides into the reference, so it is rotated by 2000 nucleotides before the
+    // alignment, which then matches the reference exactly
+    let ref_seq = to_nuc_seq(&random_seq(5, 3000))?;
+    let coord_map_global = CoordMapGlobal::new(&ref_seq, &ref_seq);
+    let alignment_range = NucRefGlobalRange::from_usize(0, 3000);
+
+    let annotation = calculate_qry_annotation(
+      0,
+      "qry",
+      3000,
+      &gene_map,
+      &coord_map_global,
+      &alignment_range,
+      false,
+      2000,
+    )?;
+
+    let gene = &annotation.genes[0];
+    assert_eq!(gene.range, NucRefGlobalRange::from_usize(2900, 3200));
+    assert_eq!(
+      gene.cdses[0].segments[0].range,
+      NucRefGlobalRange::from_usize(2900, 3200)
+    );
+    Ok(())
+  }
+}
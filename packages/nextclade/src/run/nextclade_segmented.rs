@@ -0,0 +1,200 @@
+use crate::align::seed_match::get_seed_cover;
+use crate::alphabet::nuc::{to_nuc_seq, to_nuc_seq_replacing};
+use crate::io::fasta::FastaRecord;
+use crate::make_error;
+use crate::run::nextclade_run_one::nextclade_run_one;
+use crate::run::nextclade_wasm::{AnalysisOutput, Nextclade, NextcladeParams};
+use crate::run::params::NextcladeInputParamsOptional;
+use eyre::{Report, WrapErr};
+use itertools::Itertools;
+use regex::Regex;
+
+/// Inputs for one segment of a multi-segment dataset
+#[derive(Clone, Debug)]
+pub struct NextcladeSegmentParams {
+  pub name: String,
+  pub params: NextcladeParams,
+}
+
+/// Analysis state for one segment of a multi-segment dataset
+pub struct NextcladeSegment {
+  pub name: String,
+  pub nextclade: Nextclade,
+}
+
+/// Result of the analysis of one sequence of a multi-segment dataset
+pub struct SegmentedAnalysisOutput {
+  /// Index of the segment the sequence has been assigned to
+  pub segment_index: usize,
+  pub output: AnalysisOutput,
+}
+
+/// Runs analysis for multi-segment datasets (e.g. influenza).
+///
+/// Each segment is analyzed as if it was a separate dataset, against its own reference sequence, annotation and tree.
+/// Each query sequence is first assigned to the segment which reference it matches best, as measured by the cover of
+/// seed matches, and is then analyzed against that segment only.
+pub struct NextcladeSegmented {
+  segments: Vec<NextcladeSegment>,
+  isolate_name_regex: Regex,
+}
+
+impl NextcladeSegmented {
+  pub fn new(segments: Vec<NextcladeSegmentParams>, params: &NextcladeInputParamsOptional) -> Result<Self, Report> {
+    if segments.is_empty() {
+      return make_error!("Multi-segment dataset is expected to have at least one segment, but found none");
+    }
+
+    let duplicates = segments.iter().map(|segment| &segment.name).duplicates().collect_vec();
+    if !duplicates.is_empty() {
+      return make_error!(
+        "Segment names in multi-segment dataset are expected to be unique, but found duplicates: {}",
+        duplicates.iter().join(", ")
+      );
+    }
+
+    let segments = segments
+      .into_iter()
+      .map(|NextcladeSegmentParams { name, params: inputs }| {
+        let nextclade = Nextclade::new(inputs, vec![], params)
+          .wrap_err_with(|| format!("When preparing segment '{name}' of multi-segment dataset"))?;
+        Ok(NextcladeSegment { name, nextclade })
+      })
+      .collect::<Result<Vec<_>, Report>>()?;
+
+    let isolate_name_pattern = &segments[0].nextclade.params.general.isolate_name_pattern;
+    let isolate_name_regex = Regex::new(isolate_name_pattern)
+      .wrap_err_with(|| format!("When compiling isolate name pattern '{isolate_name_pattern}'"))?;
+
+    Ok(Self {
+      segments,
+      isolate_name_regex,
+    })
+  }
+
+  pub fn segments(&self) -> &[NextcladeSegment] {
+    &self.segments
+  }
+
+  pub fn segment_names(&self) -> impl Iterator<Item = &str> {
+    self.segments.iter().map(|segment| segment.name.as_str())
+  }
+
+  /// Extracts isolate name from a sequence name. Sequences with the same isolate name belong to the same isolate.
+  pub fn isolate_name<'a>(&self, seq_name: &'a str) -> &'a str {
+    self
+      .isolate_name_regex
+      .captures(seq_name)
+      .and_then(|captures| captures.get(1).or_else(|| captures.get(0)))
+      .map_or(seq_name, |m| m.as_str())
+  }
+
+  pub fn run(&self, input: &FastaRecord) -> Result<SegmentedAnalysisOutput, Report> {
+    let general_params = &self.segments[0].nextclade.params.general;
+    let qry_seq = if general_params.replace_unknown {
+      Ok(to_nuc_seq_replacing(&input.seq))
+    } else {
+      to_nuc_seq(&input.seq)
+    }?;
+
+    let (segment_index, seed_cover) = self
+      .segments
+      .iter()
+      .map(|NextcladeSegment { nextclade, .. }| {
+        get_seed_cover(
+          &qry_seq,
+          &nextclade.ref_seq,
+          &nextclade.seed_index,
+          &nextclade.params.alignment,
+        )
+      })
+      .enumerate()
+      .max_by_key(|(_, seed_cover)| *seed_cover)
+      .unwrap_or_default();
+
+    if seed_cover == 0 {
+      return make_error!(
+        "Unable to assign sequence to any of the segments of the dataset: no seed matches found against references of segments: {}",
+        self.segment_names().join(", ")
+      );
+    }
+
+    let segment = &self.segments[segment_index];
+    let output = nextclade_run_one(input.index, &input.seq_name, &qry_seq, &segment.nextclade)
+      .wrap_err_with(|| format!("When analyzing sequence against segment '{}'", segment.name))?;
+
+    Ok(SegmentedAnalysisOutput { segment_index, output })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::analyze::virus_properties::VirusProperties;
+  use crate::gene::gene_map::GeneMap;
+  use crate::test_utils::random_seq;
+  use pretty_assertions::assert_eq;
+
+  fn segment(name: &str, seq: &str) -> NextcladeSegmentParams {
+    NextcladeSegmentParams {
+      name: name.to_owned(),
+      params: NextcladeParams {
+        dataset_name: name.to_owned(),
+        ref_record: FastaRecord {
+          index: 0,
+          seq_name: name.to_owned(),
+          seq: seq.to_owned(),
+        },
+        gene_map: GeneMap::new(),
+        tree: None,
+        virus_properties: VirusProperties::default(),
+      },
+    }
+  }
+
+  #[test]
+  #[allow(clippy::string_slice)]
+  fn assigns_sequences_to_segments() -> Result<(), Report> {
+    let ref_a = random_seq(1, 1200);
+    let ref_b = random_seq(2, 900);
+    let nextclade = NextcladeSegmented::new(
+      vec![segment("A", &ref_a), segment("B", &ref_b)],
+      &NextcladeInputParamsOptional::default(),
+    )?;
+
+    let record = |index: usize, seq_name: &str, seq: &str| FastaRecord {
+      index,
+      seq_name: seq_name.to_owned(),
+      seq: seq.to_owned(),
+    };
+
+    let result = nextclade.run(&record(0, "iso1|B", &ref_b[50..850]))?;
+    assert_eq!(result.segment_index, 1);
+
+    let result = nextclade.run(&record(1, "iso1|A", &ref_a[100..1100]))?;
+    assert_eq!(result.segment_index, 0);
+
+    Ok(())
+  }
+
+  #[test]
+  fn extracts_isolate_names() -> Result<(), Report> {
+    let nextclade = NextcladeSegmented::new(
+      vec![segment("A", &random_seq(1, 300))],
+      &NextcladeInputParamsOptional::default(),
+    )?;
+    assert_eq!(nextclade.isolate_name("A/Texas/1/2024|HA|2024-01-01"), "A/Texas/1/2024");
+    assert_eq!(nextclade.isolate_name("|HA"), "|HA");
+    Ok(())
+  }
+
+  #[test]
+  fn rejects_duplicate_segment_names() {
+    let seq = random_seq(1, 300);
+    let result = NextcladeSegmented::new(
+      vec![segment("A", &seq), segment("A", &seq)],
+      &NextcladeInputParamsOptional::default(),
+    );
+    assert2::assert!(result.is_err());
+  }
+}
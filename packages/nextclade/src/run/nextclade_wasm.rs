@@ -4,14 +4,19 @@ use crate::alphabet::letter::{serde_deserialize_seq, serde_serialize_seq};
 use crate::alphabet::nuc::{to_nuc_seq, to_nuc_seq_replacing, Nuc};
 use crate::analyze::find_aa_motifs::find_aa_motifs;
 use crate::analyze::find_aa_motifs_changes::AaMotifsMap;
+use crate::analyze::find_nuc_motifs::find_nuc_motifs;
+use crate::analyze::find_nuc_motifs_changes::NucMotifsMap;
 use crate::analyze::pcr_primers::PcrPrimer;
 use crate::analyze::phenotype::get_phenotype_attr_descs;
-use crate::analyze::virus_properties::{AaMotifsDesc, PhenotypeAttrDesc, VirusProperties};
+use crate::analyze::virus_properties::{AaMotifsDesc, NucMotifsDesc, PhenotypeAttrDesc, VirusProperties};
+use crate::coord::range::NucRefGlobalRange;
 use crate::gene::gene_map::{filter_gene_map, GeneMap};
 use crate::graph::graph::Graph;
 use crate::io::fasta::{read_one_fasta_from_str, FastaRecord};
 use crate::io::nextclade_csv_column_config::CsvColumnConfig;
 use crate::io::nwk_writer::convert_graph_to_nwk_string;
+use crate::qc::qc_config::QcConfig;
+use crate::run::nextclade_cache::AnalysisCache;
 use crate::run::nextclade_run_one::nextclade_run_one;
 use crate::run::params::{NextcladeInputParams, NextcladeInputParamsOptional};
 use crate::run::validate_ref_seq::validate_ref_seq;
@@ -253,6 +258,12 @@ pub struct AnalysisInitialData {
   pub ref_nodes: AuspiceRefNodesDesc,
   pub aa_motifs_descs: Vec<AaMotifsDesc>,
   pub aa_motif_keys: Vec<String>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub nuc_motifs_descs: Vec<NucMotifsDesc>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub nuc_motif_keys: Vec<String>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub qc_custom_rule_keys: Vec<String>,
   pub csv_column_config_default: CsvColumnConfig,
 }
 
@@ -287,6 +298,9 @@ pub struct Nextclade {
   pub virus_properties: VirusProperties,
   pub primers: Vec<PcrPrimer>,
   pub params: NextcladeInputParams,
+  pub nuc_motifs_ref: NucMotifsMap,
+  pub nuc_motifs_descs: Vec<NucMotifsDesc>,
+  pub nuc_motifs_keys: Vec<String>,
 
   // If genome annotation is provided
   pub gene_map: GeneMap,
@@ -301,6 +315,9 @@ pub struct Nextclade {
   pub clade_attr_descs: Vec<CladeNodeAttrKeyDesc>,
   pub phenotype_attr_descs: Vec<PhenotypeAttrDesc>,
   pub ref_nodes: AuspiceRefNodesDesc,
+
+  // If analysis cache is enabled
+  pub cache: Option<AnalysisCache>,
 }
 
 pub struct InitialStateWithAa {
@@ -342,6 +359,17 @@ impl Nextclade {
 
     validate_ref_seq(&ref_record.seq_name, &ref_seq)?;
 
+    if let Some(qc_config) = &virus_properties.qc {
+      qc_config.validate().wrap_err("When validating QC config")?;
+    }
+
+    let nuc_motifs_ref = find_nuc_motifs(
+      &virus_properties.nuc_motifs,
+      &ref_seq,
+      &NucRefGlobalRange::from_usize(0, ref_seq.len()),
+    )
+    .wrap_err("When searching nucleotide motifs in reference sequence")?;
+
     // If genome annotation is present, calculate AA-related parameters
     let InitialStateWithAa {
       gap_open_close_nuc,
@@ -395,6 +423,9 @@ impl Nextclade {
     let aa_motifs_descs = virus_properties.aa_motifs.clone();
     let aa_motifs_keys = aa_motifs_descs.iter().map(|desc| desc.name.clone()).collect_vec();
 
+    let nuc_motifs_descs = virus_properties.nuc_motifs.clone();
+    let nuc_motifs_keys = nuc_motifs_descs.iter().map(|desc| desc.name.clone()).collect_vec();
+
     let ref_nodes = graph
       .as_ref()
       .map(|graph| graph.data.meta.reference_nodes())
@@ -410,6 +441,9 @@ impl Nextclade {
       virus_properties,
       primers,
       params,
+      nuc_motifs_ref,
+      nuc_motifs_descs,
+      nuc_motifs_keys,
       gene_map,
       gap_open_close_aa,
       ref_translation,
@@ -420,6 +454,7 @@ impl Nextclade {
       clade_attr_descs,
       phenotype_attr_descs,
       ref_nodes,
+      cache: None,
     })
   }
 
@@ -437,6 +472,14 @@ impl Nextclade {
       ref_nodes: self.ref_nodes.clone(),
       aa_motifs_descs: self.aa_motifs_descs.clone(),
       aa_motif_keys: self.aa_motifs_keys.clone(),
+      nuc_motifs_descs: self.nuc_motifs_descs.clone(),
+      nuc_motif_keys: self.nuc_motifs_keys.clone(),
+      qc_custom_rule_keys: self
+        .virus_properties
+        .qc
+        .as_ref()
+        .map(QcConfig::custom_rule_keys)
+        .unwrap_or_default(),
       csv_column_config_default: CsvColumnConfig::default(),
     }
   }
@@ -1,5 +1,8 @@
+use crate::{make_error, o};
 use clap::Parser;
+use eyre::Report;
 use optfield::optfield;
+use ordered_float::OrderedFloat;
 use serde::{Deserialize, Serialize};
 
 #[allow(clippy::struct_excessive_bools)]
@@ -39,9 +42,33 @@ pub struct NextcladeGeneralParams {
   #[clap(long)]
   #[clap(num_args=0..=1, default_missing_value = "true")]
   pub replace_unknown: bool,
+
+  /// Take ambiguous nucleotides into account when placing sequences on the reference tree.
+  ///
+  /// By default, ambiguous nucleotides (IUPAC codes other than 'N', e.g. 'R' or 'Y') do not count as substitutions, but a site of a reference tree node which differs from the reference sequence counts as a mismatch if the query sequence has an ambiguous nucleotide at this site. With this flag, an ambiguous nucleotide which is compatible with the reference nucleotide or with the nucleotide of the tree node counts as a partial match: the distance is increased by the probability of a mismatch, if one of the possible nucleotides was chosen at random (e.g. 0.5 for 'R' against 'A'). An incompatible ambiguous nucleotide counts as a mismatch. The ambiguous sites are reported separately in the outputs.
+  ///
+  /// This is useful for low-coverage data, such as amplicon sequencing, where ambiguous nucleotides are frequent.
+  #[clap(long)]
+  #[clap(num_args=0..=1, default_missing_value = "true")]
+  pub ambiguity_aware: bool,
+
+  /// Regular expression which extracts isolate name from sequence name. Only used with multi-segment datasets.
+  ///
+  /// Sequences of different segments with the same isolate name are combined into one row of the output CSV and TSV files. If the expression contains a capture group, the first group is used as isolate name, otherwise the whole match is used. If the expression does not match, the full sequence name is used as isolate name. By default, the part of the sequence name before the first '|' character is used.
+  #[clap(long)]
+  pub isolate_name_pattern: String,
+
+  /// Relative likelihood of a placement on a reference tree node which has one more mismatch with the query sequence than the nearest node.
+  ///
+  /// Together with placement priors of the nodes, this determines the posterior probabilities of placement of the query sequence onto each of the nodes, from which the confidence of the clade assignment is computed. Must be in range (0, 1]. Smaller values make near-tie placements less likely.
+  #[clap(long)]
+  pub placement_mismatch_likelihood: OrderedFloat<f64>,
+
+  /// Minimum confidence of the clade assignment, below which the placement is flagged as ambiguous. Must be in range [0, 1].
+  #[clap(long)]
+  pub clade_confidence_threshold: OrderedFloat<f64>,
 }
 
-#[allow(clippy::derivable_impls)]
 impl Default for NextcladeGeneralParams {
   fn default() -> Self {
     Self {
@@ -49,6 +76,28 @@ impl Default for NextcladeGeneralParams {
       include_nearest_node_info: false,
       in_order: false,
       replace_unknown: false,
+      ambiguity_aware: false,
+      isolate_name_pattern: o!(r"^([^|]+)"),
+      placement_mismatch_likelihood: OrderedFloat(0.1),
+      clade_confidence_threshold: OrderedFloat(0.95),
+    }
+  }
+}
+
+impl NextcladeGeneralParams {
+  pub fn validate(&self) -> Result<(), Report> {
+    let likelihood = *self.placement_mismatch_likelihood;
+    if !(likelihood > 0.0 && likelihood <= 1.0) {
+      return make_error!(
+        "Placement mismatch likelihood (--placement-mismatch-likelihood) is expected to be in range (0, 1], but found: {likelihood}"
+      );
+    }
+    let threshold = *self.clade_confidence_threshold;
+    if !(0.0..=1.0).contains(&threshold) {
+      return make_error!(
+        "Clade confidence threshold (--clade-confidence-threshold) is expected to be in range [0, 1], but found: {threshold}"
+      );
     }
+    Ok(())
   }
 }
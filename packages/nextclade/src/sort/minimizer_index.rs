@@ -1,6 +1,9 @@
+use crate::io::fasta::FastaRecord;
 use crate::io::fs::read_file_to_string;
 use crate::io::json::json_parse;
 use crate::io::schema_version::{SchemaVersion, SchemaVersionParams};
+use crate::make_error;
+use crate::sort::minimizer_search::get_ref_search_minimizers;
 use eyre::{Report, WrapErr};
 use log::warn;
 use schemars::JsonSchema;
@@ -14,6 +17,12 @@ pub const MINIMIZER_INDEX_SCHEMA_VERSION_FROM: &str = "3.0.0";
 pub const MINIMIZER_INDEX_SCHEMA_VERSION_TO: &str = "3.0.0";
 pub const MINIMIZER_INDEX_ALGO_VERSION: &str = "1";
 
+/// Default length of k-mers, as used in the official minimizer index
+pub const MINIMIZER_INDEX_DEFAULT_K: i64 = 17;
+
+/// Default maximum value of minimizer hashes, as used in the official minimizer index
+pub const MINIMIZER_INDEX_DEFAULT_CUTOFF: i64 = 1 << 28;
+
 pub type MinimizerMap = BTreeMap<u64, Vec<usize>>;
 
 /// Contains external configuration and data specific for a particular pathogen
@@ -121,4 +130,121 @@ impl MinimizerIndexJson {
 
     json_parse(s).wrap_err("When parsing minimizer index")
   }
+
+  /// Builds minimizer index from reference sequences. Each reference is given along with its name, which is the name
+  /// of the corresponding dataset. Order of references in the index is the same as the order of inputs.
+  pub fn build<'a>(
+    references: impl IntoIterator<Item = (&'a str, &'a FastaRecord)>,
+    params: MinimizerIndexParams,
+  ) -> Result<Self, Report> {
+    let mut minimizers = MinimizerMap::new();
+    let mut ref_infos = vec![];
+    let mut normalization = vec![];
+
+    for (ri, (name, reference)) in references.into_iter().enumerate() {
+      let ref_minimizers = get_ref_search_minimizers(reference, &params);
+      if ref_minimizers.is_empty() {
+        return make_error!("When building minimizer index: reference sequence of '{name}' contains no minimizers. The sequence is either too short or contains too many ambiguous nucleotides.");
+      }
+
+      for m in &ref_minimizers {
+        minimizers.entry(*m).or_default().push(ri);
+      }
+
+      let length = reference.seq.len() as i64;
+      let n_minimizers = ref_minimizers.len() as i64;
+      normalization.push(length as f64 / n_minimizers as f64);
+      ref_infos.push(MinimizerIndexRefInfo {
+        length,
+        name: name.to_owned(),
+        n_minimizers,
+        other: serde_json::Value::default(),
+      });
+    }
+
+    if ref_infos.is_empty() {
+      return make_error!("When building minimizer index: no reference sequences provided");
+    }
+
+    Ok(Self {
+      schema_version: MINIMIZER_INDEX_SCHEMA_VERSION_TO.to_owned(),
+      version: MINIMIZER_INDEX_ALGO_VERSION.to_owned(),
+      params,
+      minimizers,
+      references: ref_infos,
+      normalization,
+      other: serde_json::Value::default(),
+    })
+  }
+}
+
+impl Default for MinimizerIndexParams {
+  fn default() -> Self {
+    Self {
+      k: MINIMIZER_INDEX_DEFAULT_K,
+      cutoff: MINIMIZER_INDEX_DEFAULT_CUTOFF,
+      other: serde_json::Value::default(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::io::json::{json_stringify, JsonPretty};
+  use crate::sort::minimizer_index_binary::MinimizerIndexBinary;
+  use crate::sort::minimizer_search::run_minimizer_search;
+  use crate::sort::params::NextcladeSeqSortParams;
+  use crate::test_utils::random_seq;
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+
+  fn record(seq_name: &str, seq: String) -> FastaRecord {
+    FastaRecord {
+      seq_name: seq_name.to_owned(),
+      seq,
+      index: 0,
+    }
+  }
+
+  #[rstest]
+  fn builds_index_usable_for_search() -> Result<(), Report> {
+    let ref_a = record("a", random_seq(1, 3000));
+    let ref_b = record("b", random_seq(2, 5000));
+
+    let index = MinimizerIndexJson::build(
+      [("org/virus-a", &ref_a), ("org/virus-b", &ref_b)],
+      MinimizerIndexParams::default(),
+    )?;
+
+    // Index goes through serialization, to make sure it is readable the same way as the prebuilt indices
+    let index = MinimizerIndexJson::from_str(json_stringify(&index, JsonPretty(false))?)?;
+
+    assert_eq!(
+      index
+        .references
+        .iter()
+        .map(|r| (r.name.as_str(), r.length))
+        .collect::<Vec<_>>(),
+      vec![("org/virus-a", 3000), ("org/virus-b", 5000)]
+    );
+    assert_eq!(index.normalization.len(), 2);
+
+    #[allow(clippy::string_slice)]
+    let qry = record("qry", ref_b.seq[1000..2000].to_owned());
+    let index = MinimizerIndexBinary::from_json(&index)?;
+    let result = run_minimizer_search(&qry, &index, &NextcladeSeqSortParams::default())?;
+    assert_eq!(
+      result.datasets.iter().map(|d| d.name.as_str()).collect::<Vec<_>>(),
+      vec!["org/virus-b"]
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  fn rejects_references_without_minimizers() {
+    let reference = record("a", "N".repeat(100));
+    let result = MinimizerIndexJson::build([("a", &reference)], MinimizerIndexParams::default());
+    assert2::assert!(result.is_err());
+  }
 }
@@ -0,0 +1,415 @@
+//! Compact binary representation of the minimizer index.
+//!
+//! Layout of the binary index (all integers are little-endian `u64`):
+//!
+//!   magic                  8 bytes "NXCMZIDX"
+//!   format version
+//!   length of metadata     in bytes, including padding
+//!   metadata               `MinimizerIndexJson` without minimizers, as JSON, padded with spaces to a multiple of 8 bytes
+//!   number of minimizers
+//!   number of words        in each reference bitset
+//!   minimizers             sorted in ascending order
+//!   reference bitsets      for each minimizer, a bitset where bit `i` is set if reference `i` contains the minimizer
+//!
+//! All sections are aligned to 8 bytes. The data is used directly, without parsing, so the file can be memory-mapped.
+use crate::io::file::{create_file_or_stdout, open_file_or_stdin};
+use crate::make_error;
+use crate::sort::minimizer_index::{MinimizerIndexJson, MinimizerIndexParams, MinimizerIndexRefInfo, MinimizerMap};
+use eyre::{Report, WrapErr};
+use std::io::{Read, Write};
+use std::ops::Deref;
+use std::path::Path;
+
+pub const MINIMIZER_INDEX_BINARY_MAGIC: &[u8; 8] = b"NXCMZIDX";
+pub const MINIMIZER_INDEX_BINARY_FORMAT_VERSION: u64 = 1;
+
+const WORD_SIZE: usize = std::mem::size_of::<u64>();
+const BITS_PER_WORD: usize = u64::BITS as usize;
+
+/// Bytes of the binary index: either read into memory or memory-mapped from a file
+enum MinimizerIndexBytes {
+  Owned(Vec<u8>),
+  #[cfg(not(target_arch = "wasm32"))]
+  Mapped(memmap2::Mmap),
+}
+
+impl Deref for MinimizerIndexBytes {
+  type Target = [u8];
+
+  fn deref(&self) -> &Self::Target {
+    match self {
+      Self::Owned(bytes) => bytes,
+      #[cfg(not(target_arch = "wasm32"))]
+      Self::Mapped(mmap) => mmap,
+    }
+  }
+}
+
+/// Minimizer index in compact binary representation, used for the search.
+///
+/// Can be created from the binary index file, which is memory-mapped when possible, or from the JSON index.
+pub struct MinimizerIndexBinary {
+  meta: MinimizerIndexJson,
+  bytes: MinimizerIndexBytes,
+  n_minimizers: usize,
+  n_words: usize,
+  minimizers_offset: usize,
+  bitsets_offset: usize,
+}
+
+impl MinimizerIndexBinary {
+  /// Reads minimizer index file, in either binary or JSON format. Uncompressed binary index is memory-mapped.
+  pub fn from_path(filepath: impl AsRef<Path>) -> Result<Self, Report> {
+    let filepath = filepath.as_ref();
+
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(index) = Self::try_mmap(filepath)? {
+      return Ok(index);
+    }
+
+    let mut bytes = vec![];
+    open_file_or_stdin(&Some(filepath))?
+      .read_to_end(&mut bytes)
+      .wrap_err_with(|| format!("When reading minimizer index file: {filepath:#?}"))?;
+    Self::from_bytes(bytes).wrap_err_with(|| format!("When reading minimizer index file: {filepath:#?}"))
+  }
+
+  /// Creates index from contents of a minimizer index file, in either binary or JSON format
+  pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, Report> {
+    if bytes.starts_with(MINIMIZER_INDEX_BINARY_MAGIC) {
+      Self::parse(MinimizerIndexBytes::Owned(bytes))
+    } else {
+      let index = MinimizerIndexJson::from_str(String::from_utf8(bytes)?)?;
+      Self::from_json(&index)
+    }
+  }
+
+  pub fn from_json(index: &MinimizerIndexJson) -> Result<Self, Report> {
+    Self::parse(MinimizerIndexBytes::Owned(Self::json_to_bytes(index)?))
+  }
+
+  #[cfg(not(target_arch = "wasm32"))]
+  fn try_mmap(filepath: &Path) -> Result<Option<Self>, Report> {
+    if crate::io::file::is_path_stdin(filepath) {
+      return Ok(None);
+    }
+
+    let mut file =
+      std::fs::File::open(filepath).wrap_err_with(|| format!("When opening minimizer index file: {filepath:#?}"))?;
+
+    let mut magic = [0_u8; 8];
+    if file.read_exact(&mut magic).is_err() || &magic != MINIMIZER_INDEX_BINARY_MAGIC {
+      return Ok(None);
+    }
+
+    #[allow(unsafe_code)]
+    // SAFETY: the mapped file is only read. It is expected to not be modified by other processes while in use,
+    // same as any other input file.
+    let mmap = unsafe { memmap2::Mmap::map(&file) }
+      .wrap_err_with(|| format!("When memory-mapping minimizer index file: {filepath:#?}"))?;
+
+    Self::parse(MinimizerIndexBytes::Mapped(mmap))
+      .wrap_err_with(|| format!("When reading minimizer index file: {filepath:#?}"))
+      .map(Some)
+  }
+
+  fn parse(bytes: MinimizerIndexBytes) -> Result<Self, Report> {
+    if !bytes.starts_with(MINIMIZER_INDEX_BINARY_MAGIC) {
+      return make_error!("Binary minimizer index is expected to start with the magic bytes, but they are not found");
+    }
+
+    let format_version = read_u64(&bytes, WORD_SIZE)?;
+    if format_version != MINIMIZER_INDEX_BINARY_FORMAT_VERSION {
+      return make_error!("Format version of the binary minimizer index ({format_version}) is not supported by this version of Nextclade (supported: {MINIMIZER_INDEX_BINARY_FORMAT_VERSION}). Please try to update your version of Nextclade and/or rebuild the index.");
+    }
+
+    let meta_offset = 3 * WORD_SIZE;
+    let meta_len = read_u64(&bytes, 2 * WORD_SIZE)? as usize;
+    let Some(counts_offset) = meta_offset.checked_add(meta_len) else {
+      return make_error!("Binary minimizer index is corrupted: metadata length ({meta_len} bytes) is too large");
+    };
+    let Some(meta_bytes) = bytes.get(meta_offset..counts_offset) else {
+      return make_error!("Binary minimizer index is truncated: metadata is incomplete");
+    };
+    let meta = MinimizerIndexJson::from_str(std::str::from_utf8(meta_bytes)?)?;
+
+    let n_minimizers = read_u64(&bytes, counts_offset)? as usize;
+    let n_words = read_u64(&bytes, counts_offset + WORD_SIZE)? as usize;
+    if n_words != meta.references.len().div_ceil(BITS_PER_WORD) {
+      return make_error!(
+        "Binary minimizer index is inconsistent: bitsets of {n_words} words cannot hold {} references",
+        meta.references.len()
+      );
+    }
+
+    // Counts are read from the file, so the sizes computed from them can overflow if the file is corrupted
+    let minimizers_offset = counts_offset + 2 * WORD_SIZE;
+    let offsets = (|| {
+      let bitsets_offset = minimizers_offset.checked_add(n_minimizers.checked_mul(WORD_SIZE)?)?;
+      let bitsets_len = n_minimizers.checked_mul(n_words)?.checked_mul(WORD_SIZE)?;
+      Some((bitsets_offset, bitsets_offset.checked_add(bitsets_len)?))
+    })();
+    let Some((bitsets_offset, expected_len)) = offsets else {
+      return make_error!(
+        "Binary minimizer index is corrupted: size of {n_minimizers} minimizers with bitsets of {n_words} words is too large"
+      );
+    };
+    if bytes.len() != expected_len {
+      return make_error!(
+        "Binary minimizer index is expected to be {expected_len} bytes long, but it is {} bytes long",
+        bytes.len()
+      );
+    }
+
+    Ok(Self {
+      meta,
+      bytes,
+      n_minimizers,
+      n_words,
+      minimizers_offset,
+      bitsets_offset,
+    })
+  }
+
+  /// Converts JSON index into the bytes of binary index
+  #[allow(clippy::little_endian_bytes)]
+  pub fn json_to_bytes(index: &MinimizerIndexJson) -> Result<Vec<u8>, Report> {
+    let n_refs = index.references.len();
+    let n_words = n_refs.div_ceil(BITS_PER_WORD);
+
+    let meta = MinimizerIndexJson {
+      minimizers: MinimizerMap::new(),
+      ..index.clone()
+    };
+    let mut meta = serde_json::to_vec(&meta)?;
+    meta.resize(meta.len().next_multiple_of(WORD_SIZE), b' ');
+
+    let mut bytes = Vec::with_capacity(meta.len() + index.minimizers.len() * (1 + n_words) * WORD_SIZE + 64);
+    bytes.extend_from_slice(MINIMIZER_INDEX_BINARY_MAGIC);
+    bytes.extend_from_slice(&MINIMIZER_INDEX_BINARY_FORMAT_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&(meta.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(&meta);
+    bytes.extend_from_slice(&(index.minimizers.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(&(n_words as u64).to_le_bytes());
+
+    // Minimizer map is ordered by key, so the minimizers are sorted
+    for minimizer in index.minimizers.keys() {
+      bytes.extend_from_slice(&minimizer.to_le_bytes());
+    }
+
+    for (minimizer, ref_indices) in &index.minimizers {
+      let mut bitset = vec![0_u64; n_words];
+      for &ri in ref_indices {
+        if ri >= n_refs {
+          return make_error!("Minimizer index is inconsistent: minimizer {minimizer} refers to reference #{ri}, but there are only {n_refs} references");
+        }
+        bitset[ri / BITS_PER_WORD] |= 1 << (ri % BITS_PER_WORD);
+      }
+      for word in bitset {
+        bytes.extend_from_slice(&word.to_le_bytes());
+      }
+    }
+
+    Ok(bytes)
+  }
+
+  /// Converts the index back into the JSON representation
+  pub fn to_json(&self) -> MinimizerIndexJson {
+    let minimizers = (0..self.n_minimizers)
+      .map(|i| (self.minimizer_at(i), self.ref_indices_at(i).collect()))
+      .collect();
+    MinimizerIndexJson {
+      minimizers,
+      ..self.meta.clone()
+    }
+  }
+
+  pub fn write(&self, filepath: impl AsRef<Path>) -> Result<(), Report> {
+    let filepath = filepath.as_ref();
+    create_file_or_stdout(filepath)?
+      .write_all(&self.bytes)
+      .wrap_err_with(|| format!("When writing binary minimizer index to {filepath:#?}"))
+  }
+
+  pub const fn params(&self) -> &MinimizerIndexParams {
+    &self.meta.params
+  }
+
+  pub fn references(&self) -> &[MinimizerIndexRefInfo] {
+    &self.meta.references
+  }
+
+  pub const fn n_minimizers(&self) -> usize {
+    self.n_minimizers
+  }
+
+  /// Counts, for each reference, how many of the given minimizers it contains. This is the size of intersection of the
+  /// set of query minimizers with the set of reference minimizers, which is computed by walking the set bits of the
+  /// reference bitsets of the query minimizers found in the index.
+  pub fn count_hits(&self, minimizers: &[u64]) -> Vec<u64> {
+    let mut hit_counts = vec![0; self.references().len()];
+    for &minimizer in minimizers {
+      let Some(i) = self.find(minimizer) else {
+        continue;
+      };
+      for w in 0..self.n_words {
+        let mut word = self.bitset_word_at(i, w);
+        while word != 0 {
+          let bit = word.trailing_zeros() as usize;
+          hit_counts[w * BITS_PER_WORD + bit] += 1;
+          word &= word - 1;
+        }
+      }
+    }
+    hit_counts
+  }
+
+  /// Finds position of a minimizer in the sorted array of minimizers, using binary search
+  fn find(&self, minimizer: u64) -> Option<usize> {
+    let (mut lo, mut hi) = (0, self.n_minimizers);
+    while lo < hi {
+      let mid = lo + (hi - lo) / 2;
+      match self.minimizer_at(mid).cmp(&minimizer) {
+        std::cmp::Ordering::Less => lo = mid + 1,
+        std::cmp::Ordering::Greater => hi = mid,
+        std::cmp::Ordering::Equal => return Some(mid),
+      }
+    }
+    None
+  }
+
+  fn ref_indices_at(&self, i: usize) -> impl Iterator<Item = usize> + '_ {
+    (0..self.references().len())
+      .filter(move |ri| self.bitset_word_at(i, ri / BITS_PER_WORD) & (1 << (ri % BITS_PER_WORD)) != 0)
+  }
+
+  #[inline]
+  fn minimizer_at(&self, i: usize) -> u64 {
+    word_at(&self.bytes, self.minimizers_offset + i * WORD_SIZE)
+  }
+
+  #[inline]
+  fn bitset_word_at(&self, i: usize, w: usize) -> u64 {
+    word_at(&self.bytes, self.bitsets_offset + (i * self.n_words + w) * WORD_SIZE)
+  }
+}
+
+/// Reads a word at a given offset. The offset is expected to be validated beforehand.
+#[inline]
+#[allow(clippy::little_endian_bytes)]
+fn word_at(bytes: &[u8], offset: usize) -> u64 {
+  let mut word = [0_u8; WORD_SIZE];
+  word.copy_from_slice(&bytes[offset..offset + WORD_SIZE]);
+  u64::from_le_bytes(word)
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Result<u64, Report> {
+  if bytes.len() < offset + WORD_SIZE {
+    return make_error!(
+      "Binary minimizer index is truncated: expected at least {} bytes, but found {}",
+      offset + WORD_SIZE,
+      bytes.len()
+    );
+  }
+  Ok(word_at(bytes, offset))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::io::json::{json_stringify, JsonPretty};
+  use crate::o;
+  use maplit::btreemap;
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+
+  fn create_index(n_refs: usize, minimizers: MinimizerMap) -> MinimizerIndexJson {
+    MinimizerIndexJson {
+      schema_version: o!("3.0.0"),
+      version: o!("1"),
+      params: MinimizerIndexParams::default(),
+      minimizers,
+      references: (0..n_refs)
+        .map(|i| MinimizerIndexRefInfo {
+          length: 1000,
+          name: format!("ref{i}"),
+          n_minimizers: 10,
+          other: serde_json::Value::default(),
+        })
+        .collect(),
+      normalization: vec![100.0; n_refs],
+      other: serde_json::Value::default(),
+    }
+  }
+
+  #[rstest]
+  fn counts_hits_across_multiple_bitset_words() -> Result<(), Report> {
+    let index = create_index(
+      70,
+      btreemap! {
+        5 => vec![0, 69],
+        17 => vec![1, 64, 69],
+        100 => vec![69],
+      },
+    );
+    let index = MinimizerIndexBinary::from_json(&index)?;
+
+    let hits = index.count_hits(&[17, 100, 3, 5]);
+
+    let mut expected = vec![0; 70];
+    expected[0] = 1;
+    expected[1] = 1;
+    expected[64] = 1;
+    expected[69] = 3;
+    assert_eq!(hits, expected);
+    Ok(())
+  }
+
+  #[rstest]
+  fn round_trips_json_and_binary() -> Result<(), Report> {
+    let json = create_index(3, btreemap! { 1 => vec![0], 2 => vec![0, 2], 1_u64 << 40 => vec![1] });
+
+    let binary = MinimizerIndexBinary::from_json(&json)?;
+    let bytes = MinimizerIndexBinary::json_to_bytes(&json)?;
+    assert_eq!(bytes.len() % WORD_SIZE, 0);
+
+    let from_binary = MinimizerIndexBinary::from_bytes(bytes)?;
+    let from_json_str = MinimizerIndexBinary::from_bytes(json_stringify(&json, JsonPretty(false))?.into_bytes())?;
+
+    for index in [&binary, &from_binary, &from_json_str] {
+      assert_eq!(
+        json_stringify(&index.to_json(), JsonPretty(false))?,
+        json_stringify(&json, JsonPretty(false))?
+      );
+    }
+    Ok(())
+  }
+
+  #[rstest]
+  #[allow(clippy::little_endian_bytes)]
+  fn rejects_binary_index_with_overflowing_metadata_length() -> Result<(), Report> {
+    let mut bytes = MinimizerIndexBinary::json_to_bytes(&create_index(2, btreemap! { 1 => vec![0, 1] }))?;
+    bytes[2 * WORD_SIZE..3 * WORD_SIZE].copy_from_slice(&u64::MAX.to_le_bytes());
+    assert2::assert!(MinimizerIndexBinary::from_bytes(bytes).is_err());
+    Ok(())
+  }
+
+  #[rstest]
+  #[allow(clippy::little_endian_bytes)]
+  fn rejects_binary_index_with_overflowing_number_of_minimizers() -> Result<(), Report> {
+    let mut bytes = MinimizerIndexBinary::json_to_bytes(&create_index(2, btreemap! { 1 => vec![0, 1] }))?;
+    let meta_len = word_at(&bytes, 2 * WORD_SIZE) as usize;
+    let counts_offset = 3 * WORD_SIZE + meta_len;
+    bytes[counts_offset..counts_offset + WORD_SIZE].copy_from_slice(&(u64::MAX / 2).to_le_bytes());
+    assert2::assert!(MinimizerIndexBinary::from_bytes(bytes).is_err());
+    Ok(())
+  }
+
+  #[rstest]
+  fn rejects_truncated_binary_index() -> Result<(), Report> {
+    let mut bytes = MinimizerIndexBinary::json_to_bytes(&create_index(2, btreemap! { 1 => vec![0, 1] }))?;
+    bytes.truncate(bytes.len() - WORD_SIZE);
+    assert2::assert!(MinimizerIndexBinary::from_bytes(bytes).is_err());
+    Ok(())
+  }
+}
@@ -1,5 +1,6 @@
 use crate::io::fasta::FastaRecord;
-use crate::sort::minimizer_index::{MinimizerIndexJson, MinimizerIndexParams};
+use crate::sort::minimizer_index::MinimizerIndexParams;
+use crate::sort::minimizer_index_binary::MinimizerIndexBinary;
 use crate::sort::params::NextcladeSeqSortParams;
 use crate::utils::indexmap::reorder_indexmap;
 use crate::utils::map::key_of_max_value;
@@ -39,27 +40,18 @@ pub struct MinimizerSearchRecord {
 #[allow(clippy::string_slice)]
 pub fn run_minimizer_search(
   fasta_record: &FastaRecord,
-  index: &MinimizerIndexJson,
+  index: &MinimizerIndexBinary,
   search_params: &NextcladeSeqSortParams,
 ) -> Result<MinimizerSearchResult, Report> {
-  let n_refs = index.references.len();
-
-  let minimizers = get_ref_search_minimizers(fasta_record, &index.params);
-  let mut hit_counts = vec![0; n_refs];
-  for m in minimizers {
-    if let Some(mz) = index.minimizers.get(&m) {
-      for (ri, hit_count) in hit_counts.iter_mut().enumerate() {
-        if mz.contains(&ri) {
-          *hit_count += 1;
-        }
-      }
-    }
-  }
+  let n_refs = index.references().len();
+
+  let minimizers = get_ref_search_minimizers(fasta_record, index.params());
+  let hit_counts = index.count_hits(&minimizers);
 
   // we expect hits to be proportional to the length of the sequence and the number of minimizers per reference
   let mut scores: Vec<f64> = vec![0.0; hit_counts.len()];
   for i in 0..n_refs {
-    let reff = &index.references[i];
+    let reff = &index.references()[i];
 
     let qry_hits = hit_counts[i] as f64;
     let ref_minimizers = reff.n_minimizers as f64;
@@ -71,7 +63,7 @@ pub fn run_minimizer_search(
   let max_score = scores.iter().copied().fold(0.0, f64::max);
   let total_hits: u64 = hit_counts.iter().sum();
 
-  let mut datasets = izip!(&index.references, hit_counts, scores)
+  let mut datasets = izip!(index.references(), hit_counts, scores)
     .filter_map(|(ref_info, n_hits, score)| {
       (n_hits >= search_params.min_hits && score >= search_params.min_score).then_some(MinimizerSearchDatasetResult {
         name: ref_info.name.clone(),
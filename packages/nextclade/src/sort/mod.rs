@@ -1,3 +1,4 @@
 pub mod minimizer_index;
+pub mod minimizer_index_binary;
 pub mod minimizer_search;
 pub mod params;
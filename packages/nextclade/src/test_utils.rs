@@ -0,0 +1,62 @@
+//! Helpers shared by unit tests of different modules
+use crate::analyze::virus_properties::VirusProperties;
+use crate::gene::gene_map::GeneMap;
+use crate::io::fasta::FastaRecord;
+use crate::o;
+use crate::run::nextclade_wasm::{Nextclade, NextcladeParams};
+use crate::run::params::NextcladeInputParamsOptional;
+use eyre::Report;
+
+/// Generates deterministic pseudo-random nucleotide sequence, using a linear congruential generator. Sequences are
+/// random enough for seed matching to find unique hits, and are the same on every run for a given seed.
+pub fn random_seq(seed: u64, len: usize) -> String {
+  let mut state = seed;
+  (0..len)
+    .map(|_| {
+      state = state
+        .wrapping_mul(6_364_136_223_846_793_005)
+        .wrapping_add(1_442_695_040_888_963_407);
+      ['A', 'C', 'G', 'T'][(state >> 62) as usize]
+    })
+    .collect()
+}
+
+/// Creates a minimal Nextclade instance, with only the reference sequence and default parameters
+pub fn create_test_nextclade(ref_name: &str, ref_seq: &str) -> Result<Nextclade, Report> {
+  Nextclade::new(
+    NextcladeParams {
+      dataset_name: o!("test"),
+      ref_record: FastaRecord {
+        index: 0,
+        seq_name: ref_name.to_owned(),
+        seq: ref_seq.to_owned(),
+      },
+      gene_map: GeneMap::new(),
+      tree: None,
+      virus_properties: VirusProperties::default(),
+    },
+    vec![],
+    &NextcladeInputParamsOptional::default(),
+  )
+}
+
+/// Deterministic pseudo-random number generator (xorshift64), for tests which need random choices other than a random
+/// nucleotide sequence, e.g. random mutations or other alphabets
+pub struct Xorshift64 {
+  state: u64,
+}
+
+impl Xorshift64 {
+  /// Creates the generator. Seed must be non-zero.
+  pub const fn new(seed: u64) -> Self {
+    Self { state: seed }
+  }
+
+  /// Returns next pseudo-random number in range `[0, n)`
+  pub fn next_below(&mut self, n: usize) -> usize {
+    self.state ^= self.state << 13;
+    self.state ^= self.state >> 7;
+    self.state ^= self.state << 17;
+    (self.state % n as u64) as usize
+  }
+}
@@ -0,0 +1,355 @@
+use crate::alphabet::aa::{to_aa, Aa};
+use crate::alphabet::letter::Letter;
+use crate::alphabet::nuc::{is_nuc_match, to_nuc_seq, Nuc};
+use crate::make_error;
+use crate::translate::translate::decode;
+use eyre::{Report, WrapErr};
+use itertools::Itertools;
+use lazy_static::lazy_static;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// Genetic code (codon table) used for translation of nucleotide sequences into peptides.
+///
+/// Identified by the number of the NCBI translation table, same as in `transl_table` qualifier of GenBank and GFF
+/// files. See: https://www.ncbi.nlm.nih.gov/Taxonomy/Utils/wprintgc.cgi
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(try_from = "u8", into = "u8")]
+#[schemars(transparent)]
+pub struct GeneticCode(u8);
+
+impl GeneticCode {
+  pub const STANDARD: GeneticCode = GeneticCode(1);
+
+  pub const fn id(self) -> u8 {
+    self.0
+  }
+
+  pub fn name(self) -> &'static str {
+    find_table(self.0).map_or("", |table| table.name)
+  }
+
+  /// Translates a nucleotide triplet into amino acid.
+  ///
+  /// Triplets containing ambiguous nucleotides are translated into the amino acid which all of the possible
+  /// codons encode, or into `X` if they encode different amino acids.
+  pub fn decode(self, triplet: &[Nuc]) -> Aa {
+    if self == Self::STANDARD {
+      return decode(triplet);
+    }
+    match (triplet, GENETIC_CODE_TRIPLET_TABLES.get(&self.0)) {
+      ([n1, n2, n3], Some(table)) => table[triplet_index(*n1, *n2, *n3)],
+      _ => Aa::X,
+    }
+  }
+}
+
+impl Default for GeneticCode {
+  fn default() -> Self {
+    Self::STANDARD
+  }
+}
+
+impl TryFrom<u8> for GeneticCode {
+  type Error = Report;
+
+  fn try_from(id: u8) -> Result<Self, Self::Error> {
+    if find_table(id).is_none() {
+      let known = NCBI_GENETIC_CODE_TABLES.iter().map(|table| table.id).join(", ");
+      return make_error!("Unknown genetic code: {id}. Known NCBI translation tables are: {known}");
+    }
+    Ok(Self(id))
+  }
+}
+
+impl From<GeneticCode> for u8 {
+  fn from(code: GeneticCode) -> Self {
+    code.0
+  }
+}
+
+impl FromStr for GeneticCode {
+  type Err = Report;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let id = s.trim().parse::<u8>().wrap_err_with(|| {
+      format!("When parsing genetic code: expected a number of NCBI translation table, but got '{s}'")
+    })?;
+    Self::try_from(id)
+  }
+}
+
+impl Display for GeneticCode {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", self.0)
+  }
+}
+
+struct GeneticCodeTable {
+  id: u8,
+  name: &'static str,
+  /// Amino acids encoded by the 64 codons, in the order TTT, TTC, TTA, TTG, TCT, ..., GGG
+  amino_acids: &'static str,
+}
+
+const NCBI_GENETIC_CODE_TABLES: &[GeneticCodeTable] = &[
+  GeneticCodeTable {
+    id: 1,
+    name: "Standard",
+    amino_acids: "FFLLSSSSYY**CC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+  },
+  GeneticCodeTable {
+    id: 2,
+    name: "Vertebrate Mitochondrial",
+    amino_acids: "FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIMMTTTTNNKKSS**VVVVAAAADDEEGGGG",
+  },
+  GeneticCodeTable {
+    id: 3,
+    name: "Yeast Mitochondrial",
+    amino_acids: "FFLLSSSSYY**CCWWTTTTPPPPHHQQRRRRIIMMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+  },
+  GeneticCodeTable {
+    id: 4,
+    name: "Mold, Protozoan, and Coelenterate Mitochondrial and Mycoplasma/Spiroplasma",
+    amino_acids: "FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+  },
+  GeneticCodeTable {
+    id: 5,
+    name: "Invertebrate Mitochondrial",
+    amino_acids: "FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIMMTTTTNNKKSSSSVVVVAAAADDEEGGGG",
+  },
+  GeneticCodeTable {
+    id: 6,
+    name: "Ciliate, Dasycladacean and Hexamita Nuclear",
+    amino_acids: "FFLLSSSSYYQQCC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+  },
+  GeneticCodeTable {
+    id: 9,
+    name: "Echinoderm and Flatworm Mitochondrial",
+    amino_acids: "FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIIMTTTTNNNKSSSSVVVVAAAADDEEGGGG",
+  },
+  GeneticCodeTable {
+    id: 10,
+    name: "Euplotid Nuclear",
+    amino_acids: "FFLLSSSSYY**CCCWLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+  },
+  GeneticCodeTable {
+    id: 11,
+    name: "Bacterial, Archaeal and Plant Plastid",
+    amino_acids: "FFLLSSSSYY**CC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+  },
+  GeneticCodeTable {
+    id: 12,
+    name: "Alternative Yeast Nuclear",
+    amino_acids: "FFLLSSSSYY**CC*WLLLSPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+  },
+  GeneticCodeTable {
+    id: 13,
+    name: "Ascidian Mitochondrial",
+    amino_acids: "FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIMMTTTTNNKKSSGGVVVVAAAADDEEGGGG",
+  },
+  GeneticCodeTable {
+    id: 14,
+    name: "Alternative Flatworm Mitochondrial",
+    amino_acids: "FFLLSSSSYYY*CCWWLLLLPPPPHHQQRRRRIIIMTTTTNNNKSSSSVVVVAAAADDEEGGGG",
+  },
+  GeneticCodeTable {
+    id: 15,
+    name: "Blepharisma Nuclear",
+    amino_acids: "FFLLSSSSYY*QCC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+  },
+  GeneticCodeTable {
+    id: 16,
+    name: "Chlorophycean Mitochondrial",
+    amino_acids: "FFLLSSSSYY*LCC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+  },
+  GeneticCodeTable {
+    id: 21,
+    name: "Trematode Mitochondrial",
+    amino_acids: "FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIMMTTTTNNNKSSSSVVVVAAAADDEEGGGG",
+  },
+  GeneticCodeTable {
+    id: 22,
+    name: "Scenedesmus obliquus Mitochondrial",
+    amino_acids: "FFLLSS*SYY*LCC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+  },
+  GeneticCodeTable {
+    id: 23,
+    name: "Thraustochytrium Mitochondrial",
+    amino_acids: "FF*LSSSSYY**CC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+  },
+  GeneticCodeTable {
+    id: 24,
+    name: "Rhabdopleuridae Mitochondrial",
+    amino_acids: "FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSSKVVVVAAAADDEEGGGG",
+  },
+  GeneticCodeTable {
+    id: 25,
+    name: "Candidate Division SR1 and Gracilibacteria",
+    amino_acids: "FFLLSSSSYY**CCGWLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+  },
+  GeneticCodeTable {
+    id: 26,
+    name: "Pachysolen tannophilus Nuclear",
+    amino_acids: "FFLLSSSSYY**CC*WLLLAPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+  },
+  GeneticCodeTable {
+    id: 27,
+    name: "Karyorelict Nuclear",
+    amino_acids: "FFLLSSSSYYQQCCWWLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+  },
+  GeneticCodeTable {
+    id: 28,
+    name: "Condylostoma Nuclear",
+    amino_acids: "FFLLSSSSYYQQCCWWLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+  },
+  GeneticCodeTable {
+    id: 29,
+    name: "Mesodinium Nuclear",
+    amino_acids: "FFLLSSSSYYYYCC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+  },
+  GeneticCodeTable {
+    id: 30,
+    name: "Peritrich Nuclear",
+    amino_acids: "FFLLSSSSYYEECC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+  },
+  GeneticCodeTable {
+    id: 31,
+    name: "Blastocrithidia Nuclear",
+    amino_acids: "FFLLSSSSYYEECCWWLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+  },
+  GeneticCodeTable {
+    id: 32,
+    name: "Balanophoraceae Plastid",
+    amino_acids: "FFLLSSSSYY*WCC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+  },
+  GeneticCodeTable {
+    id: 33,
+    name: "Cephalodiscidae Mitochondrial",
+    amino_acids: "FFLLSSSSYYY*CCWWLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSSKVVVVAAAADDEEGGGG",
+  },
+];
+
+/// Canonical nucleotides in the order used in NCBI codon tables
+const CODON_TABLE_NUCS: [Nuc; 4] = [Nuc::T, Nuc::C, Nuc::A, Nuc::G];
+
+/// Number of possible nucleotide letters, including ambiguous nucleotides and gap
+const NUM_NUCS: usize = 16;
+
+fn find_table(id: u8) -> Option<&'static GeneticCodeTable> {
+  NCBI_GENETIC_CODE_TABLES.iter().find(|table| table.id == id)
+}
+
+const fn triplet_index(n1: Nuc, n2: Nuc, n3: Nuc) -> usize {
+  (n1 as usize * NUM_NUCS + n2 as usize) * NUM_NUCS + n3 as usize
+}
+
+/// Indices of canonical nucleotides (in codon table order) which a given, possibly ambiguous, nucleotide represents
+fn codon_table_indices(nuc: Nuc) -> impl Iterator<Item = usize> {
+  (0..CODON_TABLE_NUCS.len()).filter(move |&i| !nuc.is_gap() && is_nuc_match(nuc, CODON_TABLE_NUCS[i]))
+}
+
+/// Translates every possible triplet, including triplets with ambiguous nucleotides and gaps
+fn make_triplet_table(amino_acids: &[Aa]) -> Result<Vec<Aa>, Report> {
+  let nucs = to_nuc_seq("ACGTRYSWKMBDHVN-")?;
+  let mut table = vec![Aa::X; NUM_NUCS * NUM_NUCS * NUM_NUCS];
+  for &n1 in &nucs {
+    for &n2 in &nucs {
+      for &n3 in &nucs {
+        table[triplet_index(n1, n2, n3)] = translate_ambiguous_triplet(amino_acids, n1, n2, n3);
+      }
+    }
+  }
+  Ok(table)
+}
+
+fn translate_ambiguous_triplet(amino_acids: &[Aa], n1: Nuc, n2: Nuc, n3: Nuc) -> Aa {
+  if [n1, n2, n3] == [Nuc::Gap; 3] {
+    return Aa::Gap;
+  }
+
+  let mut result = None;
+  for i1 in codon_table_indices(n1) {
+    for i2 in codon_table_indices(n2) {
+      for i3 in codon_table_indices(n3) {
+        let aa = amino_acids[i1 * 16 + i2 * 4 + i3];
+        match result {
+          None => result = Some(aa),
+          Some(prev) if prev != aa => return Aa::X,
+          Some(_) => {}
+        }
+      }
+    }
+  }
+  result.unwrap_or(Aa::X)
+}
+
+lazy_static! {
+  static ref GENETIC_CODE_TRIPLET_TABLES: BTreeMap<u8, Vec<Aa>> = NCBI_GENETIC_CODE_TABLES
+    .iter()
+    .map(|table| {
+      let triplet_table = table
+        .amino_acids
+        .chars()
+        .map(to_aa)
+        .collect::<Result<Vec<Aa>, Report>>()
+        .and_then(|amino_acids| make_triplet_table(&amino_acids))
+        .wrap_err_with(|| format!("When preparing genetic code table {}", table.id))
+        .unwrap();
+      (table.id, triplet_table)
+    })
+    .collect();
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::alphabet::nuc::to_nuc_seq;
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+
+  #[rstest]
+  fn standard_table_is_consistent_with_builtin_decoder() -> Result<(), Report> {
+    let nucs = to_nuc_seq("ACGTRYSWKMBDHVN-")?;
+    let table = &GENETIC_CODE_TRIPLET_TABLES[&1];
+    for &n1 in &nucs {
+      for &n2 in &nucs {
+        for &n3 in &nucs {
+          assert_eq!(table[triplet_index(n1, n2, n3)], decode(&[n1, n2, n3]), "{n1}{n2}{n3}");
+        }
+      }
+    }
+    Ok(())
+  }
+
+  #[rstest]
+  #[case::standard_tga(1, "TGA", Aa::Stop)]
+  #[case::standard_tgr(1, "TGR", Aa::X)]
+  #[case::vertebrate_mito_tga(2, "TGA", Aa::W)]
+  #[case::vertebrate_mito_tgr(2, "TGR", Aa::W)]
+  #[case::vertebrate_mito_aga(2, "AGA", Aa::Stop)]
+  #[case::vertebrate_mito_agr(2, "AGR", Aa::Stop)]
+  #[case::vertebrate_mito_ata(2, "ATA", Aa::M)]
+  #[case::vertebrate_mito_gap(2, "---", Aa::Gap)]
+  #[case::vertebrate_mito_partial_gap(2, "AT-", Aa::X)]
+  #[case::mycoplasma_tga(4, "TGA", Aa::W)]
+  #[case::yeast_mito_ctn(3, "CTN", Aa::T)]
+  #[case::bacterial_tga(11, "TGA", Aa::Stop)]
+  fn decodes_triplets(#[case] id: u8, #[case] triplet: &str, #[case] expected: Aa) -> Result<(), Report> {
+    let code = GeneticCode::try_from(id)?;
+    assert_eq!(code.decode(&to_nuc_seq(triplet)?), expected);
+    Ok(())
+  }
+
+  #[rstest]
+  fn parses_genetic_code() -> Result<(), Report> {
+    assert_eq!(GeneticCode::from_str("2")?, GeneticCode(2));
+    assert_eq!(GeneticCode::from_str("2")?.name(), "Vertebrate Mitochondrial");
+    assert2::assert!(GeneticCode::from_str("7").is_err());
+    assert2::assert!(GeneticCode::from_str("mito").is_err());
+    Ok(())
+  }
+}
@@ -204,11 +204,14 @@ pub fn translate(gene_nuc_seq: &[Nuc], cds: &Cds, params: &AlignPairwiseParams)
   // NOTE: rounds the result to the multiple of 3 (floor) so that translation does not overrun the buffer
   let peptide_length = gene_nuc_seq.len() / 3;
 
+  // Genetic code of the CDS, if specified in genome annotation, takes precedence over the dataset-wide one
+  let genetic_code = cds.genetic_code.unwrap_or(params.genetic_code);
+
   let mut peptide = Vec::<Aa>::with_capacity(peptide_length);
   for i_aa in 0..peptide_length {
     let i_nuc = i_aa * 3;
     let triplet: &[Nuc] = &gene_nuc_seq[i_nuc..(i_nuc + 3)];
-    let aminoacid = decode(triplet);
+    let aminoacid = genetic_code.decode(triplet);
     peptide.push(aminoacid);
     if params.no_translate_past_stop && aminoacid == Aa::Stop {
       break;
@@ -7,4 +7,5 @@ pub mod tree_builder;
 pub mod tree_find_ancestors_of_interest;
 pub mod tree_find_clade_founder;
 pub mod tree_find_nearest_node;
+pub mod tree_find_recombinant_parents;
 pub mod tree_preprocess;
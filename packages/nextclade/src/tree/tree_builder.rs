@@ -55,6 +55,28 @@ pub fn graph_attach_new_node_in_place(
   ref_seq_len: usize,
   params: &TreeBuilderParams,
 ) -> Result<(), Report> {
+  // Check if new seq is in between nearest node and a neighbor of nearest node
+  let mutations_seq = query_private_mutations(result);
+
+  let (nearest_node_key, private_mutations) = if params.without_greedy_tree_builder {
+    // Skip tree fine-tuning
+    (result.nearest_node_id, mutations_seq)
+  } else {
+    // for the attachment on the reference tree ('result') fine tune the position
+    // on the updated graph to minimize the number of private mutations
+    finetune_nearest_node(graph, result.nearest_node_id, &mutations_seq)?
+  };
+
+  // add the new node at the fine-tuned position while accounting for shared mutations
+  // on the branch leading to the nearest node.
+  knit_into_graph(graph, nearest_node_key, result, &private_mutations, ref_seq_len, params)?;
+
+  Ok(())
+}
+
+/// Collects private mutations of a query sequence, relative to its nearest node, in the form used by the tree builder.
+/// Deletions are treated as substitutions to gap.
+pub fn query_private_mutations(result: &NextcladeOutputs) -> BranchMutations {
   let mut private_aa_mutations = BTreeMap::<String, Vec<AaSub>>::new();
   for key in result.private_aa_mutations.keys() {
     let subs = result.private_aa_mutations[key].private_substitutions.clone();
@@ -78,26 +100,10 @@ pub fn graph_attach_new_node_in_place(
       .collect_vec(),
   );
 
-  // Check if new seq is in between nearest node and a neighbor of nearest node
-  let mutations_seq = BranchMutations {
+  BranchMutations {
     nuc_muts: nuc_subs,
     aa_muts: private_aa_mutations,
-  };
-
-  let (nearest_node_key, private_mutations) = if params.without_greedy_tree_builder {
-    // Skip tree fine-tuning
-    (result.nearest_node_id, mutations_seq)
-  } else {
-    // for the attachment on the reference tree ('result') fine tune the position
-    // on the updated graph to minimize the number of private mutations
-    finetune_nearest_node(graph, result.nearest_node_id, &mutations_seq)?
-  };
-
-  // add the new node at the fine-tuned position while accounting for shared mutations
-  // on the branch leading to the nearest node.
-  knit_into_graph(graph, nearest_node_key, result, &private_mutations, ref_seq_len, params)?;
-
-  Ok(())
+  }
 }
 
 /// Moves the new sequences, defined by its set of private mutations
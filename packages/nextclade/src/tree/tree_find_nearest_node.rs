@@ -1,4 +1,4 @@
-use crate::alphabet::nuc::Nuc;
+use crate::alphabet::nuc::{is_nuc_match, Nuc};
 use crate::analyze::is_sequenced::is_nuc_sequenced;
 use crate::analyze::letter_ranges::NucRange;
 use crate::analyze::nuc_sub::NucSub;
@@ -7,12 +7,17 @@ use crate::graph::node::GraphNodeKey;
 use crate::tree::tree::{AuspiceGraph, AuspiceGraphNodePayload};
 use eyre::Report;
 use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use traversal::DftPre;
 
+/// Minimum posterior probability for a node or a clade to be reported as a placement alternative
+const MIN_REPORTED_SUPPORT: f64 = 0.001;
+
 /// Distance and placement prior for a ref tree node
 pub struct TreePlacementInfo {
   pub node_key: GraphNodeKey,
-  pub distance: i64,
+  pub distance: f64,
   pub prior: f64, // prior in non-log scale
 }
 
@@ -21,6 +26,7 @@ pub fn graph_find_nearest_nodes(
   graph: &AuspiceGraph,
   qry_nuc_subs: &[NucSub],
   qry_missing: &[NucRange],
+  qry_ambiguous: &[NucSub],
   aln_range: &NucRefGlobalRange,
 ) -> Result<Vec<TreePlacementInfo>, Report> {
   let masked_ranges = graph.data.meta.placement_mask_ranges();
@@ -29,7 +35,14 @@ pub fn graph_find_nearest_nodes(
   let nodes_by_placement_score = DftPre::new(graph.get_exactly_one_root()?, |node| graph.iter_children_of(node))
     .map(|(_, node)| {
       let node_payload = node.payload();
-      let distance = tree_calculate_node_distance(node_payload, qry_nuc_subs, qry_missing, aln_range, masked_ranges);
+      let distance = tree_calculate_node_distance(
+        node_payload,
+        qry_nuc_subs,
+        qry_missing,
+        qry_ambiguous,
+        aln_range,
+        masked_ranges,
+      );
       let prior = get_prior(node_payload);
       TreePlacementInfo {
         node_key: node.key(),
@@ -37,14 +50,14 @@ pub fn graph_find_nearest_nodes(
         prior,
       }
     })
-    .sorted_by(|a, b| a.distance.cmp(&b.distance).then(b.prior.total_cmp(&a.prior)))
+    .sorted_by(|a, b| a.distance.total_cmp(&b.distance).then(b.prior.total_cmp(&a.prior)))
     .collect_vec();
 
   Ok(if nodes_by_placement_score.is_empty() {
     // Unlikely case: if there's no nodes, return parent
     vec![TreePlacementInfo {
       node_key: graph.get_exactly_one_root()?.key(),
-      distance: 0,
+      distance: 0.0,
       prior: 1.0,
     }]
   } else {
@@ -52,6 +65,138 @@ pub fn graph_find_nearest_nodes(
   })
 }
 
+/// Node of the reference tree onto which the query sample could be placed
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PlacementCandidate {
+  pub node_name: String,
+  pub clade: Option<String>,
+  pub distance: f64,
+
+  /// Posterior probability of placement onto this node
+  pub support: f64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CladeSupport {
+  pub clade: String,
+
+  /// Posterior probability of placement onto any of the nodes of this clade
+  pub support: f64,
+}
+
+/// Uncertainty of placement of the query sample on the reference tree and of the resulting clade assignment
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PlacementUncertainty {
+  /// Nodes with non-negligible support, sorted by decreasing support
+  pub candidates: Vec<PlacementCandidate>,
+
+  /// Posterior probability of the assigned clade, i.e. of the clade of the nearest node
+  pub clade_confidence: f64,
+
+  /// Clades other than the assigned clade with non-negligible support, sorted by decreasing support
+  pub alternative_clades: Vec<CladeSupport>,
+
+  /// Whether the clade confidence is below the threshold
+  pub is_ambiguous: bool,
+}
+
+/// Computes posterior probabilities of placement of the query sample onto each of the nodes of the reference tree,
+/// given the distances and the placement priors. The likelihood of a placement onto a node decreases by a factor of
+/// `mismatch_likelihood` with each mismatch in excess of the distance to the nearest node. Masked sites are not
+/// counted as mismatches, same as for the distance itself.
+///
+/// `placements` are expected to be sorted by distance, as returned by `graph_find_nearest_nodes()`.
+pub fn graph_find_placement_uncertainty(
+  graph: &AuspiceGraph,
+  placements: &[TreePlacementInfo],
+  mismatch_likelihood: f64,
+  clade_confidence_threshold: f64,
+) -> Result<PlacementUncertainty, Report> {
+  let candidates = placements
+    .iter()
+    .map(|placement| {
+      let node = graph.get_node(placement.node_key)?.payload();
+      Ok((node.name.clone(), node.clade(), placement.distance, placement.prior))
+    })
+    .collect::<Result<Vec<_>, Report>>()?;
+
+  Ok(placement_uncertainty(
+    &candidates,
+    mismatch_likelihood,
+    clade_confidence_threshold,
+  ))
+}
+
+/// Computes placement posterior from a list of `(node name, clade, distance, prior)`, where the first entry is the
+/// nearest node
+fn placement_uncertainty(
+  candidates: &[(String, Option<String>, f64, f64)],
+  mismatch_likelihood: f64,
+  clade_confidence_threshold: f64,
+) -> PlacementUncertainty {
+  let Some((_, assigned_clade, _, _)) = candidates.first() else {
+    return PlacementUncertainty::default();
+  };
+
+  let min_distance = candidates
+    .iter()
+    .map(|(_, _, distance, _)| *distance)
+    .fold(f64::INFINITY, f64::min);
+  let max_prior = candidates.iter().map(|(_, _, _, prior)| *prior).fold(0.0, f64::max);
+
+  // Weights are relative to the best possible weight, to avoid underflow for small priors
+  let weights = candidates
+    .iter()
+    .map(|(_, _, distance, prior)| prior / max_prior * mismatch_likelihood.powf(distance - min_distance))
+    .collect_vec();
+  let total_weight: f64 = weights.iter().sum();
+
+  let mut clade_supports = BTreeMap::<Option<&String>, f64>::new();
+  let mut reported = vec![];
+  for ((node_name, clade, distance, _), weight) in candidates.iter().zip(weights) {
+    let support = weight / total_weight;
+    *clade_supports.entry(clade.as_ref()).or_default() += support;
+    if support >= MIN_REPORTED_SUPPORT {
+      reported.push(PlacementCandidate {
+        node_name: node_name.clone(),
+        clade: clade.clone(),
+        distance: *distance,
+        support,
+      });
+    }
+  }
+
+  let clade_confidence = clade_supports
+    .get(&assigned_clade.as_ref())
+    .copied()
+    .unwrap_or_default();
+
+  let alternative_clades = clade_supports
+    .into_iter()
+    .filter_map(|(clade, support)| match clade {
+      Some(clade) if Some(clade) != assigned_clade.as_ref() && support >= MIN_REPORTED_SUPPORT => Some(CladeSupport {
+        clade: clade.clone(),
+        support,
+      }),
+      _ => None,
+    })
+    .sorted_by(|a, b| b.support.total_cmp(&a.support))
+    .collect_vec();
+
+  PlacementUncertainty {
+    candidates: reported
+      .into_iter()
+      .sorted_by(|a, b| b.support.total_cmp(&a.support))
+      .collect_vec(),
+    clade_confidence,
+    alternative_clades,
+    is_ambiguous: clade_confidence < clade_confidence_threshold,
+  }
+}
+
 /// Gets non-log scale prior from node attributes
 fn get_prior(node: &AuspiceGraphNodePayload) -> f64 {
   10.0_f64.powf(
@@ -65,13 +210,17 @@ fn get_prior(node: &AuspiceGraphNodePayload) -> f64 {
 }
 
 /// Calculates distance metric between a given query sample and a tree node
+///
+/// Ambiguous nucleotides of the query (`qry_ambiguous`) are only taken into account if provided: each of them adds the
+/// probability of a mismatch with the node's nucleotide at that site. Pass an empty slice to disable this.
 fn tree_calculate_node_distance(
   node: &AuspiceGraphNodePayload,
   qry_nuc_subs: &[NucSub],
   qry_missing: &[NucRange],
+  qry_ambiguous: &[NucSub],
   aln_range: &NucRefGlobalRange,
   masked_ranges: &[NucRefGlobalRange],
-) -> i64 {
+) -> f64 {
   let mut shared_differences = 0_i64;
   let mut shared_sites = 0_i64;
 
@@ -117,12 +266,40 @@ fn tree_calculate_node_distance(
   let total_node_muts = node.tmp.substitutions.len() as i64;
   let total_seq_muts = masked_qry_nuc_subs.len() as i64;
 
+  // Ambiguous sites are sequenced, so a site mutated in the node has been counted as a full mismatch above.
+  // Replace it with the probability of a mismatch with the node's nucleotide (or with the reference nucleotide, if
+  // the site is not mutated in the node).
+  let mut ambiguous_distance = 0.0;
+  for qamb in qry_ambiguous
+    .iter()
+    .filter(|amb| !masked_ranges.iter().any(|range| range.contains(amb.pos)))
+  {
+    let node_nuc = node.tmp.substitutions.get(&qamb.pos);
+    if node_nuc.is_some() {
+      ambiguous_distance -= 1.0;
+    }
+    ambiguous_distance += ambiguous_site_distance(qamb.qry_nuc, node_nuc.copied().unwrap_or(qamb.ref_nuc));
+  }
+
   // calculate distance from set overlaps.
-  total_node_muts + total_seq_muts - 2 * shared_differences - shared_sites - undetermined_sites
+  let distance = total_node_muts + total_seq_muts - 2 * shared_differences - shared_sites - undetermined_sites;
+  distance as f64 + ambiguous_distance
+}
+
+/// Probability that an ambiguous nucleotide does not match a given nucleotide, assuming that each of the
+/// nucleotides it represents is equally likely. Incompatible nucleotides never match.
+fn ambiguous_site_distance(qry_nuc: Nuc, node_nuc: Nuc) -> f64 {
+  if is_nuc_match(qry_nuc, node_nuc) {
+    1.0 - 1.0 / qry_nuc.num_possible_nucs() as f64
+  } else {
+    1.0
+  }
 }
 
 #[cfg(test)]
 mod tests {
+  #![allow(clippy::float_cmp)]
+
   use std::collections::BTreeMap;
 
   use crate::alphabet::nuc::Nuc;
@@ -234,9 +411,9 @@ mod tests {
     let aln_range = NucRefGlobalRange::from_usize(0, 100);
     let masked_ranges = vec![];
 
-    let result = tree_calculate_node_distance(&node, &qry_nuc_subs, &qry_missing, &aln_range, &masked_ranges);
+    let result = tree_calculate_node_distance(&node, &qry_nuc_subs, &qry_missing, &[], &aln_range, &masked_ranges);
 
-    assert_eq!(result, 0);
+    assert_eq!(result, 0.0);
 
     Ok(())
   }
@@ -249,9 +426,9 @@ mod tests {
     let aln_range = NucRefGlobalRange::from_usize(0, 100);
     let masked_ranges = vec![];
 
-    let result = tree_calculate_node_distance(&node, &qry_nuc_subs, &qry_missing, &aln_range, &masked_ranges);
+    let result = tree_calculate_node_distance(&node, &qry_nuc_subs, &qry_missing, &[], &aln_range, &masked_ranges);
 
-    assert_eq!(result, 3);
+    assert_eq!(result, 3.0);
 
     Ok(())
   }
@@ -264,9 +441,9 @@ mod tests {
     let aln_range = NucRefGlobalRange::from_usize(0, 100);
     let masked_ranges = vec![];
 
-    let result = tree_calculate_node_distance(&node, &qry_nuc_subs, &qry_missing, &aln_range, &masked_ranges);
+    let result = tree_calculate_node_distance(&node, &qry_nuc_subs, &qry_missing, &[], &aln_range, &masked_ranges);
 
-    assert_eq!(result, 5);
+    assert_eq!(result, 5.0);
 
     Ok(())
   }
@@ -279,9 +456,9 @@ mod tests {
     let aln_range = NucRefGlobalRange::from_usize(0, 100);
     let masked_ranges = vec![];
 
-    let result = tree_calculate_node_distance(&node, &qry_nuc_subs, &qry_missing, &aln_range, &masked_ranges);
+    let result = tree_calculate_node_distance(&node, &qry_nuc_subs, &qry_missing, &[], &aln_range, &masked_ranges);
 
-    assert_eq!(result, 5);
+    assert_eq!(result, 5.0);
 
     Ok(())
   }
@@ -294,9 +471,9 @@ mod tests {
     let aln_range = NucRefGlobalRange::from_usize(0, 100);
     let masked_ranges = vec![];
 
-    let result = tree_calculate_node_distance(&node, &qry_nuc_subs, &qry_missing, &aln_range, &masked_ranges);
+    let result = tree_calculate_node_distance(&node, &qry_nuc_subs, &qry_missing, &[], &aln_range, &masked_ranges);
 
-    assert_eq!(result, 4);
+    assert_eq!(result, 4.0);
 
     Ok(())
   }
@@ -309,9 +486,9 @@ mod tests {
     let aln_range = NucRefGlobalRange::from_usize(0, 20);
     let masked_ranges = vec![];
 
-    let result = tree_calculate_node_distance(&node, &qry_nuc_subs, &qry_missing, &aln_range, &masked_ranges);
+    let result = tree_calculate_node_distance(&node, &qry_nuc_subs, &qry_missing, &[], &aln_range, &masked_ranges);
 
-    assert_eq!(result, 3);
+    assert_eq!(result, 3.0);
 
     Ok(())
   }
@@ -324,9 +501,9 @@ mod tests {
     let aln_range = NucRefGlobalRange::from_usize(0, 100);
     let masked_ranges = vec![NucRefGlobalRange::from_usize(0, 100)];
 
-    let result = tree_calculate_node_distance(&node, &qry_nuc_subs, &qry_missing, &aln_range, &masked_ranges);
+    let result = tree_calculate_node_distance(&node, &qry_nuc_subs, &qry_missing, &[], &aln_range, &masked_ranges);
 
-    assert_eq!(result, 0);
+    assert_eq!(result, 0.0);
 
     Ok(())
   }
@@ -343,9 +520,9 @@ mod tests {
       NucRefGlobalRange::from_usize(30, 50),
     ];
 
-    let result = tree_calculate_node_distance(&node, &qry_nuc_subs, &qry_missing, &aln_range, &masked_ranges);
+    let result = tree_calculate_node_distance(&node, &qry_nuc_subs, &qry_missing, &[], &aln_range, &masked_ranges);
 
-    assert_eq!(result, 3);
+    assert_eq!(result, 3.0);
 
     Ok(())
   }
@@ -358,10 +535,131 @@ mod tests {
     let aln_range = NucRefGlobalRange::from_usize(0, 30);
     let masked_ranges = vec![NucRefGlobalRange::from_usize(12, 13)];
 
-    let result = tree_calculate_node_distance(&node, &qry_nuc_subs, &qry_missing, &aln_range, &masked_ranges);
+    let result = tree_calculate_node_distance(&node, &qry_nuc_subs, &qry_missing, &[], &aln_range, &masked_ranges);
 
-    assert_eq!(result, 3);
+    assert_eq!(result, 3.0);
 
     Ok(())
   }
+
+  #[rstest]
+  #[case::compatible_with_node(15, Nuc::A, Nuc::Y, 4.5)]
+  #[case::incompatible_with_node(15, Nuc::A, Nuc::R, 5.0)]
+  #[case::compatible_with_ref(40, Nuc::A, Nuc::R, 5.5)]
+  #[case::incompatible_with_ref(40, Nuc::A, Nuc::Y, 6.0)]
+  #[case::three_way_ambiguity(15, Nuc::A, Nuc::H, 4.0 + 2.0 / 3.0)]
+  fn ambiguous_sites_partial_match(
+    #[case] pos: usize,
+    #[case] ref_nuc: Nuc,
+    #[case] qry_nuc: Nuc,
+    #[case] expected: f64,
+  ) -> Result<(), Report> {
+    let node = node_with_simple_nuc_subs();
+    let qry_nuc_subs: Vec<NucSub> = vec![];
+    let qry_missing: Vec<NucRange> = vec![];
+    let qry_ambiguous = vec![NucSub {
+      ref_nuc,
+      pos: pos.into(),
+      qry_nuc,
+    }];
+    let aln_range = NucRefGlobalRange::from_usize(0, 100);
+    let masked_ranges = vec![];
+
+    let result = tree_calculate_node_distance(
+      &node,
+      &qry_nuc_subs,
+      &qry_missing,
+      &qry_ambiguous,
+      &aln_range,
+      &masked_ranges,
+    );
+
+    assert_eq!(result, expected);
+
+    Ok(())
+  }
+
+  #[rstest]
+  /// Masked node mutation is excluded together with the ambiguous site -> distance 4
+  fn ambiguous_sites_masked() -> Result<(), Report> {
+    let node = node_with_simple_nuc_subs();
+    let qry_nuc_subs: Vec<NucSub> = vec![];
+    let qry_missing: Vec<NucRange> = vec![];
+    let qry_ambiguous = vec![NucSub {
+      ref_nuc: Nuc::A,
+      pos: 15.into(),
+      qry_nuc: Nuc::Y,
+    }];
+    let aln_range = NucRefGlobalRange::from_usize(0, 100);
+    let masked_ranges = vec![NucRefGlobalRange::from_usize(15, 16)];
+
+    let result = tree_calculate_node_distance(
+      &node,
+      &qry_nuc_subs,
+      &qry_missing,
+      &qry_ambiguous,
+      &aln_range,
+      &masked_ranges,
+    );
+
+    assert_eq!(result, 4.0);
+
+    Ok(())
+  }
+
+  fn candidate(name: &str, clade: &str, distance: f64, prior: f64) -> (String, Option<String>, f64, f64) {
+    (name.to_owned(), Some(clade.to_owned()), distance, prior)
+  }
+
+  fn round(x: f64) -> f64 {
+    (x * 1e4).round() / 1e4
+  }
+
+  #[rstest]
+  fn placement_uncertainty_splits_support_between_near_ties() {
+    let candidates = vec![
+      candidate("a", "X", 2.0, 1.0),
+      candidate("b", "Y", 2.0, 1.0),
+      candidate("c", "X", 3.0, 1.0),
+    ];
+
+    let actual = placement_uncertainty(&candidates, 0.1, 0.95);
+
+    assert_eq!(round(actual.clade_confidence), 0.5238);
+    assert_eq!(
+      actual
+        .alternative_clades
+        .iter()
+        .map(|c| (c.clade.as_str(), round(c.support)))
+        .collect_vec(),
+      vec![("Y", 0.4762)]
+    );
+    assert_eq!(
+      actual.candidates.iter().map(|c| c.node_name.as_str()).collect_vec(),
+      vec!["a", "b", "c"]
+    );
+    assert!(actual.is_ambiguous);
+  }
+
+  #[rstest]
+  fn placement_uncertainty_is_confident_for_clear_placement() {
+    let candidates = vec![candidate("a", "X", 0.0, 1.0), candidate("b", "Y", 3.0, 1.0)];
+
+    let actual = placement_uncertainty(&candidates, 0.1, 0.95);
+
+    assert_eq!(round(actual.clade_confidence), 0.999);
+    assert_eq!(actual.alternative_clades, vec![]);
+    assert_eq!(actual.candidates.len(), 1);
+    assert!(!actual.is_ambiguous);
+  }
+
+  #[rstest]
+  fn placement_uncertainty_takes_priors_into_account() {
+    let candidates = vec![candidate("a", "X", 1.0, 1.0), candidate("b", "Y", 1.0, 1e-10)];
+
+    let actual = placement_uncertainty(&candidates, 0.1, 0.95);
+
+    assert_eq!(round(actual.clade_confidence), 1.0);
+    assert!(!actual.is_ambiguous);
+  }
 }
@@ -0,0 +1,434 @@
+use crate::alphabet::nuc::Nuc;
+use crate::analyze::is_sequenced::is_nuc_sequenced;
+use crate::analyze::letter_ranges::NucRange;
+use crate::analyze::nuc_sub::NucSub;
+use crate::coord::position::NucRefGlobalPosition;
+use crate::coord::range::NucRefGlobalRange;
+use crate::graph::node::GraphNodeKey;
+use crate::qc::qc_config::QcRulesConfigRecombinants;
+use crate::tree::tree::AuspiceGraph;
+use eyre::Report;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use traversal::DftPre;
+
+/// Parental node of a recombinant sequence, as found on the reference tree
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RecombinantParent {
+  pub node_id: GraphNodeKey,
+  pub node_name: String,
+  pub clade: Option<String>,
+}
+
+/// Part of the genome which is attributed to one of the parents
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RecombinantSegment {
+  /// Index of the parent in the list of parents
+  pub parent: usize,
+
+  /// Range from the first to the last informative site attributed to the parent
+  pub range: NucRefGlobalRange,
+
+  /// Number of sites in the segment where the parents differ and the query agrees with this parent
+  pub supporting_sites: usize,
+
+  /// Number of sites in the segment where the parents differ and the query agrees with the other parent
+  pub conflicting_sites: usize,
+}
+
+/// Result of the search for recombinant parents of a query sequence
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Recombination {
+  pub parents: Vec<RecombinantParent>,
+
+  pub segments: Vec<RecombinantSegment>,
+
+  /// Intervals between consecutive segments, in which the switch from one parent to the other happened
+  pub breakpoints: Vec<NucRefGlobalRange>,
+
+  /// Number of sites where the query disagrees with the best matching single node of the tree
+  pub mismatches_single: usize,
+
+  /// Number of sites where the query disagrees with the mosaic of the two parents
+  pub mismatches_recombinant: usize,
+
+  /// Number of sites which are explained by the mosaic of the two parents, but not by any single node of the tree
+  pub support: usize,
+}
+
+/// Sites of a given node which disagree with the query
+struct NodeMismatches {
+  node_key: GraphNodeKey,
+  positions: Vec<NucRefGlobalPosition>,
+}
+
+impl NodeMismatches {
+  /// Number of mismatches before a given position
+  fn count_before(&self, pos: NucRefGlobalPosition) -> usize {
+    self.positions.partition_point(|p| *p < pos)
+  }
+}
+
+/// Searches the reference tree for two parental nodes, such that the query is explained by a mosaic of these nodes
+/// along the genome significantly better than by any single node.
+///
+/// A site is a mismatch between the query and a node if either of them has a substitution which the other does not
+/// share. Masked, missing and ambiguous sites of the query are disregarded, same as during placement. For each
+/// candidate breakpoint, the nodes best explaining the query before and after the breakpoint are taken as candidate
+/// parents. Then each pair of candidates is aligned to the query along the genome, where each switch between parents
+/// costs `breakpoint_penalty` mismatches. The best scoring pair is reported if it explains at least `min_support` more
+/// sites than the best single node, and if each of its segments is supported by at least `min_segment_sites` sites.
+pub fn graph_find_recombinant_parents(
+  graph: &AuspiceGraph,
+  qry_nuc_subs: &[NucSub],
+  qry_missing: &[NucRange],
+  qry_ambiguous: &[NucSub],
+  aln_range: &NucRefGlobalRange,
+  config: &QcRulesConfigRecombinants,
+) -> Result<Option<Recombination>, Report> {
+  let masked_ranges = graph.data.meta.placement_mask_ranges();
+  let is_masked = |pos: NucRefGlobalPosition| masked_ranges.iter().any(|range| range.contains(pos));
+
+  let qry_subs: BTreeMap<NucRefGlobalPosition, Nuc> = qry_nuc_subs
+    .iter()
+    .filter(|sub| !is_masked(sub.pos))
+    .map(|sub| (sub.pos, sub.qry_nuc))
+    .collect();
+
+  let is_determined = |pos: NucRefGlobalPosition| {
+    !is_masked(pos)
+      && is_nuc_sequenced(pos, qry_missing, aln_range)
+      && qry_ambiguous.binary_search_by_key(&pos, |amb| amb.pos).is_err()
+  };
+
+  let nodes = DftPre::new(graph.get_exactly_one_root()?, |node| graph.iter_children_of(node))
+    .map(|(_, node)| {
+      let node_subs = &node.payload().tmp.substitutions;
+
+      let qry_only = qry_subs
+        .iter()
+        .filter(|(pos, qry_nuc)| node_subs.get(pos) != Some(qry_nuc))
+        .map(|(pos, _)| *pos);
+
+      let node_only = node_subs
+        .keys()
+        .filter(|pos| !qry_subs.contains_key(pos) && is_determined(**pos))
+        .copied();
+
+      NodeMismatches {
+        node_key: node.key(),
+        positions: qry_only.chain(node_only).sorted().dedup().collect_vec(),
+      }
+    })
+    .collect_vec();
+
+  let Some(best_single) = nodes.iter().min_by_key(|node| node.positions.len()) else {
+    return Ok(None);
+  };
+
+  let mismatches_single = best_single.positions.len();
+  if mismatches_single < config.min_support {
+    return Ok(None);
+  }
+
+  let candidates = find_candidate_parents(&nodes, best_single, config.max_candidates);
+
+  let Some(mosaic) = candidates
+    .iter()
+    .tuple_combinations()
+    .filter_map(|(left, right)| find_mosaic(left, right, config.breakpoint_penalty))
+    .min_by_key(|mosaic| mosaic.cost)
+  else {
+    return Ok(None);
+  };
+
+  let support = mismatches_single.saturating_sub(mosaic.mismatches);
+  let segments_supported = mosaic
+    .segments
+    .iter()
+    .all(|segment| segment.supporting_sites >= config.min_segment_sites);
+  if support < config.min_support || !segments_supported {
+    return Ok(None);
+  }
+
+  let parents = mosaic
+    .parents
+    .iter()
+    .map(|node_key| {
+      let node = graph.get_node(*node_key)?.payload();
+      Ok(RecombinantParent {
+        node_id: *node_key,
+        node_name: node.name.clone(),
+        clade: node.clade(),
+      })
+    })
+    .collect::<Result<Vec<_>, Report>>()?;
+
+  let breakpoints = mosaic
+    .segments
+    .iter()
+    .tuple_windows()
+    .map(|(prev, next)| NucRefGlobalRange::new(prev.range.end, next.range.begin))
+    .collect_vec();
+
+  Ok(Some(Recombination {
+    parents,
+    segments: mosaic.segments,
+    breakpoints,
+    mismatches_single,
+    mismatches_recombinant: mosaic.mismatches,
+    support,
+  }))
+}
+
+/// Selects nodes which best explain the query on either side of each of the possible breakpoints. Breakpoints are
+/// only considered at the sites where the best single node disagrees with the query. Among the nodes equally good on
+/// one side, the one with the fewest mismatches overall is preferred.
+fn find_candidate_parents<'n>(
+  nodes: &'n [NodeMismatches],
+  best_single: &'n NodeMismatches,
+  max_candidates: usize,
+) -> Vec<&'n NodeMismatches> {
+  let best_left = |pos: NucRefGlobalPosition| {
+    nodes
+      .iter()
+      .min_by_key(|node| (node.count_before(pos), node.positions.len()))
+  };
+
+  let best_right = |pos: NucRefGlobalPosition| {
+    nodes.iter().min_by_key(|node| {
+      let after = node.positions.len() - node.count_before(pos);
+      (after, node.positions.len())
+    })
+  };
+
+  // Breakpoint is placed right after a mismatched site
+  let breakpoints = best_single.positions.iter().map(|pos| *pos + 1);
+
+  std::iter::once(best_single)
+    .chain(breakpoints.flat_map(|pos| [best_left(pos), best_right(pos)]).flatten())
+    .unique_by(|node| node.node_key)
+    .take(max_candidates)
+    .collect_vec()
+}
+
+/// Mosaic of two nodes which best explains the query
+struct Mosaic {
+  parents: [GraphNodeKey; 2],
+  segments: Vec<RecombinantSegment>,
+  mismatches: usize,
+  cost: usize,
+}
+
+/// Finds the optimal attribution of the sites of the query to one of the two nodes, where each switch between the
+/// nodes costs `breakpoint_penalty`. Returns `None` if no switches are necessary, i.e. if one of the nodes alone
+/// explains the query best.
+fn find_mosaic(a: &NodeMismatches, b: &NodeMismatches, breakpoint_penalty: usize) -> Option<Mosaic> {
+  // Sites where both nodes disagree with the query are mismatches regardless of attribution
+  let shared_mismatches = a
+    .positions
+    .iter()
+    .filter(|pos| b.positions.binary_search(pos).is_ok())
+    .count();
+
+  // Informative sites are where exactly one of the nodes disagrees with the query. Value is whether `a` disagrees.
+  let sites = a
+    .positions
+    .iter()
+    .filter(|pos| b.positions.binary_search(pos).is_err())
+    .map(|pos| (*pos, true))
+    .chain(
+      b.positions
+        .iter()
+        .filter(|pos| a.positions.binary_search(pos).is_err())
+        .map(|pos| (*pos, false)),
+    )
+    .sorted()
+    .collect_vec();
+
+  if sites.is_empty() {
+    return None;
+  }
+
+  // Viterbi algorithm with 2 states: site attributed to `a` (0) or to `b` (1)
+  let site_cost = |state: usize, a_mismatch: bool| usize::from((state == 0) == a_mismatch);
+
+  let mut costs = [site_cost(0, sites[0].1), site_cost(1, sites[0].1)];
+  let mut back = Vec::with_capacity(sites.len());
+  back.push([0, 1]);
+  for (_, a_mismatch) in &sites[1..] {
+    let mut next_costs = [0; 2];
+    let mut next_back = [0; 2];
+    for state in 0..2 {
+      let stay = costs[state];
+      let switch = costs[1 - state] + breakpoint_penalty;
+      (next_costs[state], next_back[state]) = if stay <= switch {
+        (stay, state)
+      } else {
+        (switch, 1 - state)
+      };
+      next_costs[state] += site_cost(state, *a_mismatch);
+    }
+    costs = next_costs;
+    back.push(next_back);
+  }
+
+  let mut state = usize::from(costs[1] < costs[0]);
+  let cost = costs[state] + shared_mismatches;
+  let mut path = vec![0; sites.len()];
+  for i in (0..sites.len()).rev() {
+    path[i] = state;
+    state = back[i][state];
+  }
+
+  let runs = path
+    .iter()
+    .zip(&sites)
+    .group_by(|(state, _)| **state)
+    .into_iter()
+    .map(|(state, run)| (state, run.map(|(_, site)| *site).collect_vec()))
+    .collect_vec();
+
+  if runs.len() < 2 {
+    return None;
+  }
+
+  // Parents are listed in the order of their first segment
+  let first_state = runs[0].0;
+  let parents = if first_state == 0 {
+    [a.node_key, b.node_key]
+  } else {
+    [b.node_key, a.node_key]
+  };
+
+  let segments = runs
+    .into_iter()
+    .map(|(state, run)| {
+      let conflicting_sites = run
+        .iter()
+        .filter(|(_, a_mismatch)| site_cost(state, *a_mismatch) > 0)
+        .count();
+      RecombinantSegment {
+        parent: usize::from(state != first_state),
+        range: NucRefGlobalRange::new(run[0].0, run[run.len() - 1].0 + 1),
+        supporting_sites: run.len() - conflicting_sites,
+        conflicting_sites,
+      }
+    })
+    .collect_vec();
+
+  let mismatches = shared_mismatches + segments.iter().map(|s| s.conflicting_sites).sum::<usize>();
+
+  Some(Mosaic {
+    parents,
+    segments,
+    mismatches,
+    cost,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::coord::position::PositionLike;
+  use crate::tree::tree::{AuspiceGraphEdgePayload, AuspiceGraphMeta, AuspiceGraphNodePayload};
+  use pretty_assertions::assert_eq;
+  use rstest::rstest;
+  use std::str::FromStr;
+
+  fn node(name: &str, subs: &[&str]) -> Result<AuspiceGraphNodePayload, Report> {
+    let mut node = AuspiceGraphNodePayload::new(name);
+    node.tmp.substitutions = subs
+      .iter()
+      .map(|sub| NucSub::from_str(sub).map(|sub| (sub.pos, sub.qry_nuc)))
+      .collect::<Result<_, Report>>()?;
+    Ok(node)
+  }
+
+  //       root
+  //      /    \
+  //     A      B
+  //
+  // Mutations of A and B alternate along the genome
+  fn graph() -> Result<AuspiceGraph, Report> {
+    let mut graph = AuspiceGraph::new(AuspiceGraphMeta::default());
+    let root = graph.add_node(node("root", &[])?);
+    let a = graph.add_node(node("A", &["C100T", "G300A", "A500G", "T700C", "C900T"])?);
+    let b = graph.add_node(node("B", &["A200G", "C400T", "G600A", "T800C"])?);
+    graph.add_edge(root, a, AuspiceGraphEdgePayload::new())?;
+    graph.add_edge(root, b, AuspiceGraphEdgePayload::new())?;
+    graph.build()
+  }
+
+  fn config() -> QcRulesConfigRecombinants {
+    QcRulesConfigRecombinants {
+      enabled: true,
+      ..QcRulesConfigRecombinants::default()
+    }
+  }
+
+  fn find(graph: &AuspiceGraph, subs: &[&str]) -> Result<Option<Recombination>, Report> {
+    let subs = subs
+      .iter()
+      .map(|sub| NucSub::from_str(sub))
+      .collect::<Result<Vec<_>, _>>()?;
+    graph_find_recombinant_parents(
+      graph,
+      &subs,
+      &[],
+      &[],
+      &NucRefGlobalRange::from_usize(0, 1000),
+      &config(),
+    )
+  }
+
+  #[rstest]
+  fn finds_recombinant_parents_and_breakpoint() -> Result<(), Report> {
+    let graph = graph()?;
+    let actual = find(&graph, &["C100T", "G300A", "G600A", "T800C"])?.expect("recombinant");
+
+    assert_eq!(
+      actual.parents.iter().map(|p| p.node_name.as_str()).collect_vec(),
+      vec!["A", "B"]
+    );
+    assert_eq!(
+      actual
+        .segments
+        .iter()
+        .map(|s| (
+          s.parent,
+          s.range.begin.as_usize(),
+          s.range.end.as_usize(),
+          s.supporting_sites
+        ))
+        .collect_vec(),
+      vec![(0, 99, 400, 4), (1, 499, 900, 5)]
+    );
+    assert_eq!(
+      actual
+        .breakpoints
+        .iter()
+        .map(|r| (r.begin.as_usize(), r.end.as_usize()))
+        .collect_vec(),
+      vec![(400, 499)]
+    );
+    assert_eq!(actual.mismatches_single, 4);
+    assert_eq!(actual.mismatches_recombinant, 0);
+    assert_eq!(actual.support, 4);
+    Ok(())
+  }
+
+  #[rstest]
+  fn does_not_report_non_recombinant() -> Result<(), Report> {
+    let graph = graph()?;
+    assert_eq!(
+      find(&graph, &["C100T", "G300A", "A500G", "T700C", "C900T", "G950A"])?,
+      None
+    );
+    Ok(())
+  }
+}
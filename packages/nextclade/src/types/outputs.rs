@@ -1,3 +1,4 @@
+use crate::align::backtrace::{AlignmentDiagnostics, AlignmentMode};
 use crate::align::insertions_strip::{AaIns, Insertion};
 use crate::alphabet::nuc::Nuc;
 use crate::analyze::aa_changes_group::AaChangesGroup;
@@ -5,11 +6,13 @@ use crate::analyze::aa_del::AaDel;
 use crate::analyze::aa_sub::AaSub;
 use crate::analyze::find_aa_motifs_changes::{AaMotifsChangesMap, AaMotifsMap};
 use crate::analyze::find_clade_founder::CladeNodeAttrFounderInfo;
+use crate::analyze::find_nuc_motifs_changes::{NucMotifsChangesMap, NucMotifsMap};
 use crate::analyze::find_private_aa_mutations::PrivateAaMutations;
 use crate::analyze::find_private_nuc_mutations::PrivateNucMutations;
 use crate::analyze::find_relative_aa_mutations::RelativeAaMutations;
 use crate::analyze::find_relative_nuc_mutations::RelativeNucMutations;
 use crate::analyze::letter_ranges::{CdsAaRange, NucRange};
+use crate::analyze::mutational_spectrum::MutationalSpectra;
 use crate::analyze::nuc_del::NucDelRange;
 use crate::analyze::nuc_sub::NucSub;
 use crate::analyze::pcr_primer_changes::PcrPrimerChange;
@@ -21,6 +24,8 @@ use crate::qc::qc_run::QcResult;
 use crate::translate::frame_shifts_translate::FrameShift;
 use crate::tree::tree::AuspiceRefNodesDesc;
 use crate::tree::tree_find_ancestors_of_interest::AncestralSearchResult;
+use crate::tree::tree_find_nearest_node::PlacementUncertainty;
+use crate::tree::tree_find_recombinant_parents::Recombination;
 use eyre::{Report, WrapErr};
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
@@ -64,6 +69,10 @@ pub struct NextcladeOutputs {
   pub non_acgtns: Vec<NucRange>,
   #[serde(rename = "totalNonACGTNs")]
   pub total_non_acgtns: usize,
+  #[serde(default)]
+  pub ambiguous_sites: Vec<NucSub>,
+  #[serde(default)]
+  pub total_ambiguous_sites: usize,
   pub nucleotide_composition: BTreeMap<Nuc, usize>,
   pub frame_shifts: Vec<FrameShift>,
   pub total_frame_shifts: usize,
@@ -73,12 +82,22 @@ pub struct NextcladeOutputs {
   pub total_aminoacid_deletions: usize,
   pub aa_insertions: Vec<AaIns>,
   pub total_aminoacid_insertions: usize,
+  #[serde(default)]
+  pub protein_aa_substitutions: Vec<AaSub>,
+  #[serde(default)]
+  pub protein_aa_deletions: Vec<AaDel>,
+  #[serde(default)]
+  pub protein_aa_insertions: Vec<AaIns>,
   pub unknown_aa_ranges: Vec<CdsAaRange>,
   pub total_unknown_aa: usize,
   pub aa_changes_groups: Vec<AaChangesGroup>,
   pub nuc_to_aa_muts: BTreeMap<String, Vec<AaSub>>,
   pub alignment_range: NucRefGlobalRange,
   pub alignment_score: i32,
+  #[serde(default)]
+  pub alignment_mode: AlignmentMode,
+  #[serde(default)]
+  pub alignment_diagnostics: AlignmentDiagnostics,
   pub aa_alignment_ranges: BTreeMap<String, Vec<AaRefRange>>,
   pub aa_unsequenced_ranges: BTreeMap<String, Vec<AaRefRange>>,
   pub pcr_primer_changes: Vec<PcrPrimerChange>,
@@ -104,10 +123,23 @@ pub struct NextcladeOutputs {
   pub nearest_node_name: String,
   #[serde(skip_serializing_if = "Option::is_none")]
   pub nearest_nodes: Option<Vec<String>>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub placement_uncertainty: Option<PlacementUncertainty>,
   pub is_reverse_complement: bool,
+  #[serde(default)]
+  pub circular_rotation: usize,
   pub phenotype_values: Option<Vec<PhenotypeValue>>,
   pub aa_motifs: AaMotifsMap,
   pub aa_motifs_changes: AaMotifsChangesMap,
+  #[serde(default)]
+  pub nuc_motifs: NucMotifsMap,
+  #[serde(default)]
+  pub nuc_motifs_changes: NucMotifsChangesMap,
+  #[serde(default)]
+  pub mutational_spectra: MutationalSpectra,
+
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub recombination: Option<Recombination>,
 
   #[serde(default, skip_serializing_if = "GeneMap::is_empty")]
   pub annotation: GeneMap,
@@ -0,0 +1,60 @@
+use eyre::{Report, WrapErr};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+
+/// Calculates SHA-256 hash of a byte string, as a hex string
+pub fn hash_sha256(data: impl AsRef<[u8]>) -> String {
+  format!("{:x}", Sha256::digest(data.as_ref()))
+}
+
+/// Calculates SHA-256 hash of JSON representation of a value, as a hex string.
+///
+/// Object keys are sorted before hashing, so that the hash does not depend on the order of entries in hash maps.
+pub fn hash_json_sha256(value: &impl Serialize) -> Result<String, Report> {
+  let value = serde_json::to_value(value).wrap_err("When serializing value for hashing")?;
+  let mut hasher = Sha256::new();
+  serde_json::to_writer(&mut hasher, &sort_keys(value)).wrap_err("When hashing value")?;
+  Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn sort_keys(value: Value) -> Value {
+  match value {
+    Value::Object(map) => {
+      let mut entries = map.into_iter().collect::<Vec<_>>();
+      entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+      Value::Object(
+        entries
+          .into_iter()
+          .map(|(k, v)| (k, sort_keys(v)))
+          .collect::<Map<_, _>>(),
+      )
+    }
+    Value::Array(values) => Value::Array(values.into_iter().map(sort_keys).collect()),
+    value => value,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use pretty_assertions::assert_eq;
+  use serde_json::json;
+
+  #[test]
+  fn hashes_bytes() {
+    assert_eq!(
+      hash_sha256("abc"),
+      "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+  }
+
+  #[test]
+  fn json_hash_does_not_depend_on_key_order() -> Result<(), Report> {
+    let a = json!({ "x": 1, "y": { "a": [1, 2], "b": null } });
+    let b = json!({ "y": { "b": null, "a": [1, 2] }, "x": 1 });
+    assert_eq!(hash_json_sha256(&a)?, hash_json_sha256(&b)?);
+    assert_ne!(hash_json_sha256(&a)?, hash_json_sha256(&json!({ "x": 2 }))?);
+    Ok(())
+  }
+}
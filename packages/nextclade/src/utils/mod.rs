@@ -8,6 +8,7 @@ pub mod eyre;
 pub mod fs;
 pub mod getenv;
 pub mod global_init;
+pub mod hash;
 pub mod indexmap;
 pub mod info;
 pub mod iter;